@@ -0,0 +1,99 @@
+/*
+ * Accessibility.
+ * Limits of the effects that move or flash, for viewers sensitive to motion or flashing light. They are
+ * set with the `[accessibility]` table of the render settings and applied where the effects are computed:
+ * the camera cuts to views instead of flying there, animated lights change their brightness and color at
+ * most a few times per second and flicker less, and the post-processing passes are toned down. Without
+ * the table nothing is limited:
+ *
+ *     [accessibility]
+ *     reduced_motion = true
+ *     max_flash_rate = 3.0
+ *     max_flicker = 0.1
+ *     post_intensity = 0.5
+ */
+use crate::depth_of_field::DepthOfFieldSettings;
+use crate::exposure::AutoExposureSettings;
+use crate::light_animation::{ColorCycle, Flicker};
+use crate::stylize::StylizeSettings;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessibilitySettings {
+    /// Camera transitions to views and focused nodes cut instead of easing the camera there.
+    pub reduced_motion: bool,
+    /// Most changes of brightness or color per second of animated lights. Guidelines for photosensitive
+    /// viewers, e.g. WCAG 2.3.1, allow three flashes per second.
+    pub max_flash_rate: f32,
+    /// Largest share of the intensity a light flicker takes away, see [`Flicker::amount`].
+    pub max_flicker: f32,
+    /// Strength of the post-processing passes from 0, which leaves the frame as it is, to 1.
+    pub post_intensity: f32,
+}
+
+impl Default for AccessibilitySettings {
+    fn default() -> Self {
+        Self {
+            reduced_motion: false,
+            max_flash_rate: f32::INFINITY,
+            max_flicker: 1.0,
+            post_intensity: 1.0,
+        }
+    }
+}
+
+impl AccessibilitySettings {
+    /// Seconds of a camera transition that would take `seconds`.
+    pub fn transition_time(&self, seconds: f32) -> f32 {
+        if self.reduced_motion {
+            0.0
+        } else {
+            seconds
+        }
+    }
+
+    /// `flicker` no faster and deeper than allowed.
+    pub fn flicker(&self, flicker: Flicker) -> Flicker {
+        Flicker {
+            amount: flicker.amount.min(self.max_flicker),
+            speed: flicker.speed.min(self.max_flash_rate),
+        }
+    }
+
+    /// Period of `cycle` that blends to the next color at most `max_flash_rate` times per second.
+    pub fn color_period(&self, cycle: &ColorCycle) -> f32 {
+        let shortest = cycle.colors.len() as f32 / self.max_flash_rate.max(f32::EPSILON);
+        cycle.period.max(shortest)
+    }
+
+    fn post_intensity(&self) -> f32 {
+        self.post_intensity.clamp(0.0, 1.0)
+    }
+
+    /// `settings` with the exposure range narrowed by the post intensity, 0 keeps the exposure at 1.
+    pub fn exposure(&self, settings: &AutoExposureSettings) -> AutoExposureSettings {
+        let intensity = self.post_intensity();
+        AutoExposureSettings {
+            min_exposure: settings.min_exposure.powf(intensity),
+            max_exposure: settings.max_exposure.powf(intensity),
+            ..*settings
+        }
+    }
+
+    /// `settings` with the blur limited by the post intensity, 0 keeps the frame sharp.
+    pub fn depth_of_field(&self, settings: &DepthOfFieldSettings) -> DepthOfFieldSettings {
+        DepthOfFieldSettings {
+            max_blur: settings.max_blur * self.post_intensity(),
+            ..*settings
+        }
+    }
+
+    /// `settings` blended with the unchanged frame by the post intensity.
+    pub fn stylize(&self, settings: &StylizeSettings) -> StylizeSettings {
+        StylizeSettings {
+            intensity: settings.intensity.min(self.post_intensity()),
+            ..*settings
+        }
+    }
+}
@@ -0,0 +1,126 @@
+/*
+ * Screen-space anchors for HTML overlays on the web build.
+ * The hosting page registers node names and a callback; every frame the callback receives an array of
+ * `{ name, x, y, depth, visible }` objects in CSS pixels relative to the canvas, so tooltips and labels
+ * can be positioned over 3D objects.
+ */
+use crate::camera::Camera;
+use crate::scenegraph::SceneGraph;
+use glam::Vec4;
+use std::cell::RefCell;
+use wasm_bindgen::prelude::wasm_bindgen;
+use wasm_bindgen::JsValue;
+use winit::dpi::PhysicalSize;
+
+#[derive(Debug, Clone)]
+pub struct ScreenAnchor {
+    pub name: String,
+    pub x: f32,
+    pub y: f32,
+    pub depth: f32,
+    pub visible: bool,
+}
+
+#[derive(Default)]
+pub struct AnchorTracker {
+    names: Vec<String>,
+}
+
+impl AnchorTracker {
+    pub fn track(&mut self, name: &str) {
+        if !self.names.iter().any(|tracked| tracked == name) {
+            self.names.push(name.to_string());
+        }
+    }
+
+    pub fn untrack(&mut self, name: &str) {
+        self.names.retain(|tracked| tracked != name);
+    }
+
+    /// Projects all tracked nodes onto a viewport of the given size (in CSS pixels).
+    /// Nodes that no longer exist in the scene graph are skipped.
+    pub fn project(
+        &self,
+        scene_graph: &SceneGraph,
+        camera: &Camera,
+        width: f32,
+        height: f32,
+    ) -> Vec<ScreenAnchor> {
+        let view_proj = camera.calculate_matrix();
+        self.names
+            .iter()
+            .filter_map(|name| {
+                let position = scene_graph.world_position(name)?;
+                let clip = view_proj * Vec4::new(position.x, position.y, position.z, 1.0);
+                let ndc = clip.truncate() / clip.w;
+                let visible = clip.w > 0.0
+                    && ndc.x.abs() <= 1.0
+                    && ndc.y.abs() <= 1.0
+                    && (0.0..=1.0).contains(&ndc.z);
+
+                Some(ScreenAnchor {
+                    name: name.clone(),
+                    x: (ndc.x * 0.5 + 0.5) * width,
+                    y: (0.5 - ndc.y * 0.5) * height,
+                    depth: ndc.z,
+                    visible,
+                })
+            })
+            .collect()
+    }
+}
+
+thread_local! {
+    static TRACKER: RefCell<AnchorTracker> = RefCell::new(AnchorTracker::default());
+    static CALLBACK: RefCell<Option<js_sys::Function>> = const { RefCell::new(None) };
+}
+
+#[wasm_bindgen]
+pub fn track_node(name: &str) {
+    TRACKER.with(|tracker| tracker.borrow_mut().track(name));
+}
+
+#[wasm_bindgen]
+pub fn untrack_node(name: &str) {
+    TRACKER.with(|tracker| tracker.borrow_mut().untrack(name));
+}
+
+#[wasm_bindgen]
+pub fn set_anchor_callback(callback: Option<js_sys::Function>) {
+    CALLBACK.with(|cell| *cell.borrow_mut() = callback);
+}
+
+/// Called once per frame by the application, after the frame has been presented.
+pub fn emit_anchors(
+    scene_graph: &SceneGraph,
+    camera: &Camera,
+    size: PhysicalSize<u32>,
+    scale_factor: f64,
+) {
+    CALLBACK.with(|cell| {
+        let Some(callback) = cell.borrow().clone() else {
+            return;
+        };
+        let css_size = size.to_logical::<f32>(scale_factor);
+        let anchors = TRACKER.with(|tracker| {
+            tracker
+                .borrow()
+                .project(scene_graph, camera, css_size.width, css_size.height)
+        });
+
+        let array = js_sys::Array::new();
+        for anchor in anchors {
+            let object = js_sys::Object::new();
+            let _ = js_sys::Reflect::set(&object, &"name".into(), &anchor.name.into());
+            let _ = js_sys::Reflect::set(&object, &"x".into(), &anchor.x.into());
+            let _ = js_sys::Reflect::set(&object, &"y".into(), &anchor.y.into());
+            let _ = js_sys::Reflect::set(&object, &"depth".into(), &anchor.depth.into());
+            let _ = js_sys::Reflect::set(&object, &"visible".into(), &anchor.visible.into());
+            array.push(&object);
+        }
+
+        if let Err(e) = callback.call1(&JsValue::NULL, &array) {
+            web_sys::console::error_1(&e);
+        }
+    });
+}
@@ -3,19 +3,55 @@
  * Reason is that it's tricky to set up a WGPU pipeline using the latest version of WGPU and Winit, especially when targeting the web.
  *
  */
-use crate::light::ShadowMap;
-use crate::renderer::{rotate_sun, RenderProxy, Renderer};
-use crate::scenegraph::{DrawScenegraph, SceneGraphLightNodeIterator};
+use crate::asset_reload::AssetWatcher;
+use crate::bind_groups;
+use crate::camera::{CameraUniform, CanonicalView, RenderMode};
+use crate::clock::FrameClock;
+use crate::custom_pass::{PassContext, PassStage};
+#[cfg(feature = "debug-ui")]
+use crate::debug_ui::DebugUi;
+use crate::depth_view::DepthView;
+use crate::error::RendererError;
+use crate::frame_stats::FrameStats;
+#[cfg(feature = "gamepad")]
+use crate::gamepad::GamepadInput;
+use crate::gltf_export::export_glb;
+use crate::golden::{self, GoldenRun, GoldenStatus, GOLDEN_HEIGHT, GOLDEN_VIEWS, GOLDEN_WIDTH};
+use crate::hdr::HDR_FORMAT;
+use crate::input::{Action, Binding, InputState};
+use crate::labels;
+use crate::layered_shadow::{LayeredShadowPass, MAX_VIEWS};
+use crate::light::{LightKind, ShadowStats};
+use crate::msaa::MsaaPass;
+use crate::offscreen::OffscreenTarget;
+use crate::panorama::{PanoramaCapture, FACE_COUNT};
+use crate::pass_capture::PassCapture;
+use crate::pick::PickReadout;
+use crate::renderer::{GaussianPass, PipelineVariants, RenderProxy, Renderer};
+use crate::resources::{with_resource_cache, ResourceCache};
+use crate::scenegraph::{
+    DrawLayer, DrawScenegraph, DrawStats, DrawView, NodeStats, SceneGraphLightNodeIterator,
+};
+use crate::stereo::EYE_COUNT;
 use crate::texture::Texture;
-use std::time::{Duration, Instant};
+use crate::time_of_day::{self, TimeOfDay};
+use bytemuck::Zeroable;
+#[cfg(feature = "gamepad")]
+use gilrs::Button;
+use glam::Mat4;
+#[cfg(feature = "gamepad")]
+use glam::Vec3;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Instant;
 #[allow(unused_imports)]
 use wasm_bindgen::{prelude::wasm_bindgen, throw_str, JsCast, UnwrapThrowExt};
 use wgpu::hal::DynCommandEncoder;
-use winit::event::{DeviceEvent, DeviceId, ElementState, KeyEvent, MouseButton};
-use winit::keyboard::{KeyCode, PhysicalKey};
+use winit::event::{DeviceEvent, DeviceId, ElementState, KeyEvent};
+use winit::keyboard::{KeyCode, ModifiersState, PhysicalKey};
 use winit::{
     application::ApplicationHandler,
-    dpi::PhysicalSize,
+    dpi::{PhysicalPosition, PhysicalSize},
     event::WindowEvent,
     event_loop::{ActiveEventLoop, EventLoop},
     window::WindowId,
@@ -28,141 +64,1347 @@ pub enum MaybeRenderer {
 
 pub struct App {
     pub renderer: MaybeRenderer,
-    start_time: instant::Instant,
-    target_frame_time: Duration,
+    clock: FrameClock,
+    frame_stats: FrameStats,
+    capture_panorama: bool,
+    /// Set by F9, the targets of the next frame are saved, see [`PassCapture`].
+    capture_passes: bool,
+    modifiers: ModifiersState,
+    cursor_position: Option<PhysicalPosition<f64>>,
+    /// Pixel under the cursor while Alt is held, shown in the HUD one frame late.
+    pick_readout: Option<PickReadout>,
+    /// Set by `--compare-golden`, the app compares the golden views and exits once the renderer is ready.
+    golden: Option<GoldenRun>,
+    pub golden_passed: Option<bool>,
+    /// Set by `--watch-assets`, see asset_reload.rs.
+    asset_watcher: Option<AssetWatcher>,
+    #[cfg(feature = "gamepad")]
+    gamepad: GamepadInput,
+    input: InputState,
+    /// Index into [`Action::ALL`] of the action that the next key or mouse button is bound to, see F4.
+    rebinding: Option<usize>,
+    /// Created with the renderer of a window, see F5.
+    #[cfg(feature = "debug-ui")]
+    debug_ui: Option<DebugUi>,
+}
+
+const PANORAMA_FACE_SIZE: u32 = 1024;
+/// Render nodes with the most triangles listed on the HUD.
+const HUD_EXPENSIVE_NODES: usize = 3;
+
+/// What a gamepad button does, see [`gamepad_action`].
+/// The gamepad button mapping. The left stick moves the camera, the triggers move it down and up and
+/// the right stick looks around.
+#[cfg(feature = "gamepad")]
+fn gamepad_action(button: Button) -> Option<Action> {
+    let action = match button {
+        Button::DPadDown => Action::SnapFront,
+        Button::DPadRight => Action::SnapRight,
+        Button::DPadUp => Action::SnapTop,
+        Button::DPadLeft => Action::ToggleOrthographic,
+        Button::RightTrigger => Action::FasterCamera,
+        Button::LeftTrigger => Action::SlowerCamera,
+        Button::Select => Action::ToggleHud,
+        Button::Start => Action::ToggleDepthView,
+        Button::North => Action::CapturePanorama,
+        _ => return None,
+    };
+    Some(action)
 }
 
 impl App {
-    pub fn new(event_loop: &EventLoop<Renderer>) -> Self {
+    pub fn new(
+        event_loop: &EventLoop<Renderer>,
+        golden: Option<GoldenRun>,
+        settings_file: Option<String>,
+        scene_file: Option<String>,
+        deterministic: bool,
+        print_frame_stats: bool,
+        watch_assets: bool,
+    ) -> Self {
         Self {
-            renderer: MaybeRenderer::Proxy(RenderProxy::new(event_loop.create_proxy())),
-            start_time: Instant::now(),
-            target_frame_time: Duration::from_secs_f64(1.0 / 60.0),
+            renderer: MaybeRenderer::Proxy(RenderProxy::new(
+                event_loop.create_proxy(),
+                settings_file,
+                scene_file,
+            )),
+            clock: FrameClock::new(deterministic),
+            frame_stats: FrameStats::new(print_frame_stats),
+            capture_panorama: false,
+            capture_passes: false,
+            modifiers: ModifiersState::empty(),
+            cursor_position: None,
+            pick_readout: None,
+            golden,
+            golden_passed: None,
+            asset_watcher: watch_assets.then(AssetWatcher::new),
+            #[cfg(feature = "gamepad")]
+            gamepad: GamepadInput::new(),
+            input: InputState::default(),
+            rebinding: None,
+            #[cfg(feature = "debug-ui")]
+            debug_ui: None,
+        }
+    }
+
+    /// Draws a frame and exits on errors the renderer can't recover from.
+    fn draw_or_exit(&mut self, event_loop: &ActiveEventLoop) {
+        if let Err(e) = self.draw() {
+            println!("Rendering failed: {e}");
+            event_loop.exit();
         }
     }
 
-    pub fn draw(&mut self) {
+    pub fn draw(&mut self) -> Result<(), RendererError> {
+        #[cfg(feature = "gamepad")]
+        self.apply_gamepad();
         let MaybeRenderer::Renderer(renderer) = &mut self.renderer else {
-            return;
+            return Ok(());
         };
+        if let Some(reason) = renderer.device_lost.lock().unwrap().take() {
+            with_resource_cache(&renderer.device, ResourceCache::clear);
+            return Err(RendererError::DeviceLost(reason));
+        }
 
-        let frame = renderer.surface.get_current_texture().unwrap_throw();
-        let view = frame.texture.create_view(&Default::default());
-        let mut encoder = renderer.device.create_command_encoder(&Default::default());
-
-        let now = Instant::now();
-
-        rotate_sun(&renderer.device, &mut renderer.scene_graph, (now - self.start_time).as_secs_f32());
+        // headless renderers read their frames back instead, see Renderer::read_back_frame
+        let (Some(window), Some(surface)) = (renderer.window.clone(), &renderer.surface) else {
+            return Ok(());
+        };
 
-        // shadow pass
-        {
-            render_shadow_pass(renderer, &mut encoder);
+        renderer.watchdog.begin_frame();
+        let frame = match surface.get_current_texture() {
+            Ok(frame) => frame,
+            Err(wgpu::SurfaceError::Timeout) => return Ok(()),
+            // e.g. after minimizing the window or a GPU reset, the surface and the textures of its size
+            // are recreated and the next frame is drawn into them
+            Err(e @ (wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost)) => {
+                println!("Recreating the surface: {e}");
+                self.resized(window.inner_size());
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        };
+        renderer.watchdog.lap("acquire");
+        let view = frame.texture.create_view(&wgpu::TextureViewDescriptor {
+            format: Some(renderer.frame_format()),
+            ..Default::default()
+        });
+        let mut encoder = renderer
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("frame_encoder"),
+            });
+        if let Some(gpu_timer) = &mut renderer.gpu_timer {
+            gpu_timer.begin_frame(&renderer.device);
         }
 
-        unsafe {
-            render_gaussian_pass(renderer, &mut encoder, true);
-            render_gaussian_pass(renderer, &mut encoder, false);
-        }
+        let (time, frame_time) = self.clock.tick(renderer.settings.target_frame_time());
 
+        renderer.scene_graph.animate_paths(time);
+        renderer.custom_materials.set_time(&renderer.queue, time);
+        renderer
+            .scene_graph
+            .animate_lights(time, &renderer.settings.accessibility);
+        #[cfg(target_arch = "wasm32")]
+        if let Some((preset, seconds)) = time_of_day::take_requested() {
+            renderer.time_of_day.switch_to(preset, seconds);
+        }
+        renderer.time_of_day.update(&mut renderer.scene_graph, time);
+        renderer.trails.record(&renderer.scene_graph);
         renderer
             .camera_state
             .camera_controller
-            .update_camera(&mut renderer.camera_state.camera);
+            .update_camera(&mut renderer.camera_state.camera, frame_time);
+        let camera = &renderer.camera_state.camera;
         renderer
-            .camera_state
-            .camera_uniform
-            .update(&renderer.camera_state.camera);
-        renderer.queue.write_buffer(
-            &renderer.camera_state.camera_buffer,
-            0,
-            bytemuck::cast_slice(&[renderer.camera_state.camera_uniform]),
+            .scene_graph
+            .apply_constraints(camera.eye, camera.target);
+        renderer.reload_changed_shaders();
+        if let Some(watcher) = &mut self.asset_watcher {
+            renderer.reload_changed_assets(watcher);
+        }
+        renderer.sync_scene();
+        renderer.watchdog.lap("update");
+        if std::mem::take(&mut self.capture_passes) {
+            let dir = format!("passes_{}", unix_timestamp());
+            renderer.pass_capture = Some(PassCapture::new(dir));
+        }
+        let forward_draw_stats = render_scene(renderer, &mut encoder, &frame.texture, &view);
+        let mut hud_lines = self.frame_stats.hud_lines();
+        hud_lines.push(format!(
+            "{} draws, {} material switches, {} culled",
+            forward_draw_stats.draws,
+            forward_draw_stats.material_switches,
+            forward_draw_stats.culled
+        ));
+        if renderer.depth_view.enabled && !renderer.stereo.is_enabled() {
+            hud_lines.extend(DepthView::annotations(&renderer.camera_state.camera));
+        }
+        hud_lines.extend(renderer.shadow_stats.iter().map(ShadowStats::hud_line));
+        // the nodes worth optimizing first
+        hud_lines.extend(
+            renderer
+                .node_stats
+                .iter()
+                .filter(|node| !node.culled)
+                .take(HUD_EXPENSIVE_NODES)
+                .map(NodeStats::hud_line),
+        );
+        if let Some(readout) = &self.pick_readout {
+            hud_lines.extend(readout.hud_lines());
+        }
+        renderer.hud.set_lines(hud_lines);
+        renderer.hud.prepare(&renderer.device, &renderer.queue);
+        renderer.hud.render(
+            &mut encoder,
+            &view,
+            renderer.surface_config.width,
+            renderer.surface_config.height,
+        );
+        renderer.watchdog.lap("hud");
+        render_custom_passes(
+            renderer,
+            PassStage::Overlay,
+            &mut encoder,
+            &frame.texture,
+            &view,
         );
+        if let Some(capture) = &mut renderer.pass_capture {
+            capture.capture(&renderer.device, &mut encoder, "frame", &frame.texture);
+        }
 
-        // forward pass
-        {
-            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.1,
-                            g: 0.2,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &renderer.depth_texture.view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: wgpu::StoreOp::Discard,
-                    }),
-                    stencil_ops: None,
-                }),
-                ..Default::default()
-            });
+        #[cfg(feature = "debug-ui")]
+        if let Some(debug_ui) = &mut self.debug_ui {
+            debug_ui.render(renderer, &mut encoder, &view, frame_time);
+            renderer.watchdog.lap("debug ui");
+        }
 
-            rpass.set_pipeline(&renderer.render_pipeline.pipeline);
-            rpass.set_bind_group(0, &renderer.camera_state.camera_bind_group, &[]);
-            rpass.set_bind_group(1, &renderer.model_matrix_bind_group, &[]);
-            rpass.set_bind_group(3, &renderer.scene_graph.light_bind_group, &[]);
-            rpass.draw_scenegraph(
-                &renderer.scene_graph,
-                &renderer.queue,
-                2,
-                &renderer.model_matrix_buffer,
-                &renderer.camera_state.camera.eye,
-            );
+        let (width, height) = (
+            renderer.surface_config.width,
+            renderer.surface_config.height,
+        );
+        let pick_pixel = self
+            .cursor_position
+            .filter(|_| self.modifiers.alt_key() && !renderer.stereo.is_enabled())
+            .map(|position| (position.x as u32, position.y as u32))
+            .filter(|&(x, y)| x < width && y < height);
+        if let Some(pixel) = pick_pixel {
+            render_pick_pass(renderer, &mut encoder, pixel);
+            renderer.watchdog.lap("pick");
         }
 
+        let panorama = if std::mem::take(&mut self.capture_panorama) {
+            let capture = PanoramaCapture::new(
+                &renderer.device,
+                &renderer
+                    .camera_state
+                    .camera
+                    .relative_to(renderer.scene_graph.render_origin()),
+                renderer.frame_format(),
+                PANORAMA_FACE_SIZE,
+            );
+            encoder.push_debug_group("panorama");
+            for face in 0..FACE_COUNT as usize {
+                render_forward_pass(
+                    renderer,
+                    &mut encoder,
+                    ForwardTarget::new(&capture.face_views[face], &capture.face_depth_views[face]),
+                    &renderer.render_pipeline,
+                    &capture.face_camera_bind_groups[face],
+                    // the faces together cover every direction
+                    &DrawView::unculled(renderer.camera_state.camera.eye),
+                );
+            }
+            capture.encode_conversion(&mut encoder);
+            encoder.pop_debug_group();
+            renderer.watchdog.lap("panorama");
+            Some(capture)
+        } else {
+            None
+        };
+
+        if let Some(gpu_timer) = &mut renderer.gpu_timer {
+            gpu_timer.end_frame(&mut encoder);
+        }
         renderer.queue.submit(Some(encoder.finish()));
+        if let Some(gpu_timer) = &mut renderer.gpu_timer {
+            gpu_timer.submitted();
+        }
+        renderer.watchdog.lap("submit");
+        renderer.save_pass_capture();
         frame.present();
+        renderer.watchdog.lap("present");
+        let scene_stats = renderer.scene_graph.stats();
+        renderer.watchdog.end_frame(scene_stats, forward_draw_stats);
+        self.frame_stats
+            .record(frame_time, renderer.last_pass_timings());
+
+        self.pick_readout = pick_pixel.map(|pixel| {
+            renderer.pick.read(
+                &renderer.device,
+                &renderer.camera_state.camera,
+                pixel,
+                width,
+                height,
+            )
+        });
+
+        if let Some(capture) = panorama {
+            let path = format!("panorama_{}.png", unix_timestamp());
+            match capture.save(&renderer.device, &path) {
+                Ok(()) => println!("Saved panorama to {path}"),
+                Err(e) => println!("Failed to save panorama: {e}"),
+            }
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        crate::anchor::emit_anchors(
+            &renderer.scene_graph,
+            &renderer.camera_state.camera,
+            window.inner_size(),
+            window.scale_factor(),
+        );
 
         renderer.scene_graph.on_frame_update();
+        Ok(())
+    }
+
+    /// Renders the golden views into an offscreen target and compares them with the references of `run`,
+    /// or saves them as the references with `--update-golden`. Returns whether all views passed.
+    fn compare_golden(&mut self, run: &GoldenRun) -> bool {
+        let MaybeRenderer::Renderer(renderer) = &mut self.renderer else {
+            return false;
+        };
+        // the references are 8 bit sRGB images
+        if renderer.frame_format() == HDR_FORMAT {
+            println!("Golden images can't be compared with HDR output, remove the [hdr] settings");
+            return false;
+        }
+        if let Some(window) = &renderer.window {
+            window.set_visible(false);
+        }
+        self.resized(PhysicalSize::new(GOLDEN_WIDTH, GOLDEN_HEIGHT));
+        let MaybeRenderer::Renderer(renderer) = &mut self.renderer else {
+            return false;
+        };
+
+        // nodes with a path or animated lights stay at their start, like the sun
+        renderer.scene_graph.animate_paths(0.0);
+        renderer
+            .scene_graph
+            .animate_lights(0.0, &renderer.settings.accessibility);
+        let camera = &renderer.camera_state.camera;
+        renderer
+            .scene_graph
+            .apply_constraints(camera.eye, camera.target);
+
+        let target = OffscreenTarget::new(
+            &renderer.device,
+            renderer.frame_format(),
+            GOLDEN_WIDTH,
+            GOLDEN_HEIGHT,
+        );
+        let mut passed = true;
+        for view in &GOLDEN_VIEWS {
+            renderer.camera_state.camera.eye = view.eye;
+            renderer.camera_state.camera.target = view.target;
+            renderer.sync_scene();
+            let label = format!("golden_{}_encoder", view.name);
+            let descriptor = wgpu::CommandEncoderDescriptor {
+                label: Some(&label),
+            };
+            let mut encoder = renderer.device.create_command_encoder(&descriptor);
+            render_scene(renderer, &mut encoder, &target.texture, &target.view);
+            target.encode_readback(&mut encoder);
+            renderer.queue.submit(Some(encoder.finish()));
+
+            let status = target
+                .read(&renderer.device)
+                .and_then(|image| golden::compare(&run.dir, view.name, &image, run.update));
+            match status {
+                Ok(GoldenStatus::Updated) => println!("Golden {}: updated reference", view.name),
+                Ok(GoldenStatus::Passed { diff_pixels }) => {
+                    println!("Golden {}: passed, {diff_pixels} pixels differ", view.name)
+                }
+                Ok(GoldenStatus::Failed { diff_pixels }) => {
+                    passed = false;
+                    println!(
+                        "Golden {}: FAILED, {diff_pixels} pixels differ, see {}.diff.png",
+                        view.name, view.name
+                    );
+                }
+                Err(e) => {
+                    passed = false;
+                    println!("Golden {}: {e}", view.name);
+                }
+            }
+        }
+        passed
+    }
+
+    /// Snaps the camera to `view` of the scene bounds.
+    fn snap_camera(&mut self, view: CanonicalView) {
+        let MaybeRenderer::Renderer(renderer) = &mut self.renderer else {
+            return;
+        };
+        let camera_state = &mut renderer.camera_state;
+        let camera = &mut camera_state.camera;
+        if let Some((min, max)) = renderer.scene_graph.bounds() {
+            let from = camera.pose();
+            camera.snap_to_view(view, min, max);
+            camera_state.camera_controller.begin_transition(camera, from);
+        }
+    }
+
+    fn toggle_orthographic(&mut self) {
+        if let MaybeRenderer::Renderer(renderer) = &mut self.renderer {
+            renderer.camera_state.camera.toggle_orthographic();
+        }
+    }
+
+    /// Changes the HUD scale by `step`, None resets it.
+    fn scale_hud(&mut self, step: Option<f32>) {
+        let MaybeRenderer::Renderer(renderer) = &mut self.renderer else {
+            return;
+        };
+        let ui_scale = step.map_or(1.0, |step| renderer.hud.ui_scale() + step);
+        renderer.hud.set_ui_scale(ui_scale);
+    }
+
+    /// Runs the actions of the pressed gamepad buttons and passes the sticks and triggers to the camera.
+    #[cfg(feature = "gamepad")]
+    fn apply_gamepad(&mut self) {
+        let state = self.gamepad.poll();
+        for action in state.pressed.into_iter().filter_map(gamepad_action) {
+            self.run_action(action, false);
+        }
+        if let MaybeRenderer::Renderer(renderer) = &mut self.renderer {
+            let movement = Vec3::new(
+                state.left_stick.x,
+                state.right_trigger - state.left_trigger,
+                state.left_stick.y,
+            );
+            renderer
+                .camera_state
+                .camera_controller
+                .set_analog_input(movement, state.right_stick);
+        }
+    }
+
+    fn toggle_hud(&mut self) {
+        if let MaybeRenderer::Renderer(renderer) = &mut self.renderer {
+            renderer.hud.visible = !renderer.hud.visible;
+        }
+    }
+
+    #[cfg(feature = "debug-ui")]
+    fn toggle_debug_ui(&mut self) {
+        if let Some(debug_ui) = &mut self.debug_ui {
+            debug_ui.visible = !debug_ui.visible;
+        }
+    }
+
+    fn toggle_depth_view(&mut self) {
+        if let MaybeRenderer::Renderer(renderer) = &mut self.renderer {
+            renderer.depth_view.toggle(&renderer.device);
+        }
+    }
+
+    fn toggle_trails(&mut self) {
+        if let MaybeRenderer::Renderer(renderer) = &mut self.renderer {
+            let trails = &mut renderer.trails;
+            trails.enabled = !trails.enabled;
+            println!("Trails {}", if trails.enabled { "on" } else { "off" });
+        }
+    }
+
+    fn next_time_of_day(&mut self) {
+        if let MaybeRenderer::Renderer(renderer) = &mut self.renderer {
+            let day_cycle = &mut renderer.time_of_day;
+            let preset = day_cycle.current().map_or(TimeOfDay::Dawn, TimeOfDay::next);
+            day_cycle.switch_to(preset, time_of_day::TRANSITION_SECONDS);
+        }
+    }
+
+    fn toggle_debug_lines(&mut self) {
+        if let MaybeRenderer::Renderer(renderer) = &mut self.renderer {
+            let debug_lines = &mut renderer.debug_lines;
+            debug_lines.enabled = !debug_lines.enabled;
+            println!(
+                "Debug lines {}",
+                if debug_lines.enabled { "on" } else { "off" }
+            );
+        }
+    }
+
+    fn cycle_debug_view(&mut self) {
+        if let MaybeRenderer::Renderer(renderer) = &mut self.renderer {
+            renderer.debug_view = renderer.debug_view.next();
+            println!("Debug view: {:?}", renderer.debug_view);
+        }
+    }
+
+    fn select_render_mode(&mut self, mode: RenderMode) {
+        if let MaybeRenderer::Renderer(renderer) = &mut self.renderer {
+            renderer.set_render_mode(mode);
+            println!("Render mode: {:?}", renderer.render_mode);
+        }
+    }
+
+    /// Saves the scene graph as a scene file that `--scene` loads.
+    fn save_scene(&self) {
+        let MaybeRenderer::Renderer(renderer) = &self.renderer else {
+            return;
+        };
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let path = format!("scene_{timestamp}.json");
+        let camera = &renderer.camera_state.camera;
+        match renderer.scene_graph.save(Path::new(&path), camera) {
+            Ok(()) => println!("Saved scene to {path}"),
+            Err(e) => println!("Failed to save scene: {e}"),
+        }
+    }
+
+    /// Exports the scene graph and the camera as a binary glTF file, see gltf_export.rs.
+    fn export_gltf(&self) {
+        let MaybeRenderer::Renderer(renderer) = &self.renderer else {
+            return;
+        };
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let path = format!("scene_{timestamp}.glb");
+        let camera = &renderer.camera_state.camera;
+        match export_glb(&renderer.scene_graph, camera, Path::new(&path)) {
+            Ok(()) => println!("Exported scene to {path}"),
+            Err(e) => println!("Failed to export scene: {e}"),
+        }
+    }
+
+    /// Multiplies the movement speed by `factor`.
+    fn adjust_camera_speed(&mut self, factor: f32) {
+        if let MaybeRenderer::Renderer(renderer) = &mut self.renderer {
+            let controller = &mut renderer.camera_state.camera_controller;
+            controller.set_speed(controller.speed() * factor);
+            println!("Camera speed {:.1} units per second", controller.speed());
+        }
+    }
+
+    /// Multiplies the mouse sensitivity by `factor`.
+    fn adjust_sensitivity(&mut self, factor: f32) {
+        if let MaybeRenderer::Renderer(renderer) = &mut self.renderer {
+            let controller = &mut renderer.camera_state.camera_controller;
+            controller.set_sensitivity(controller.sensitivity() * factor);
+            println!(
+                "Mouse sensitivity {:.3} degrees per pixel",
+                controller.sensitivity()
+            );
+        }
+    }
+
+    fn toggle_msaa(&mut self) {
+        if let MaybeRenderer::Renderer(renderer) = &mut self.renderer {
+            let msaa = &mut renderer.msaa;
+            if msaa.sample_count > 1 {
+                msaa.enabled = !msaa.enabled;
+                println!("MSAA {}", if msaa.enabled { "on" } else { "off" });
+            }
+        }
+    }
+
+    /// Runs `action` if it is one of the app's hotkeys, returns whether it was. Only the speed, sensitivity
+    /// and HUD scale steps repeat while their key is held.
+    fn run_action(&mut self, action: Action, repeat: bool) -> bool {
+        match action {
+            Action::FasterCamera => self.adjust_camera_speed(1.5),
+            Action::SlowerCamera => self.adjust_camera_speed(1.0 / 1.5),
+            Action::MoreSensitive => self.adjust_sensitivity(1.5),
+            Action::LessSensitive => self.adjust_sensitivity(1.0 / 1.5),
+            Action::LargerHud => self.scale_hud(Some(0.25)),
+            Action::SmallerHud => self.scale_hud(Some(-0.25)),
+            _ if repeat => return false,
+            Action::ResetHudScale => self.scale_hud(None),
+            Action::CapturePanorama => self.capture_panorama = true,
+            Action::SaveScene => self.save_scene(),
+            Action::ExportGltf => self.export_gltf(),
+            Action::ToggleHud => self.toggle_hud(),
+            Action::ToggleDepthView => self.toggle_depth_view(),
+            Action::ToggleMsaa => self.toggle_msaa(),
+            #[cfg(feature = "debug-ui")]
+            Action::ToggleDebugUi => self.toggle_debug_ui(),
+            Action::CycleDebugView => self.cycle_debug_view(),
+            Action::ToggleDebugLines => self.toggle_debug_lines(),
+            Action::ToggleTrails => self.toggle_trails(),
+            Action::SnapFront => self.snap_camera(CanonicalView::Front),
+            Action::SnapBack => self.snap_camera(CanonicalView::Back),
+            Action::SnapRight => self.snap_camera(CanonicalView::Right),
+            Action::SnapLeft => self.snap_camera(CanonicalView::Left),
+            Action::SnapTop => self.snap_camera(CanonicalView::Top),
+            Action::SnapBottom => self.snap_camera(CanonicalView::Bottom),
+            Action::ToggleOrthographic => self.toggle_orthographic(),
+            Action::RenderLit => self.select_render_mode(RenderMode::Lit),
+            Action::RenderUnlit => self.select_render_mode(RenderMode::Unlit),
+            Action::RenderClay => self.select_render_mode(RenderMode::Clay),
+            Action::RenderNormals => self.select_render_mode(RenderMode::Normals),
+            Action::RenderUvChecker => self.select_render_mode(RenderMode::UvChecker),
+            Action::NextTimeOfDay => self.next_time_of_day(),
+            Action::CapturePasses => self.capture_passes = true,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Binds `binding` to the action being rebound, or keeps its bindings without one, and asks for the
+    /// binding of the next action. F4 starts with the first action.
+    fn rebind(&mut self, binding: Option<Binding>) {
+        if let (Some(index), Some(binding)) = (self.rebinding, binding) {
+            let action = Action::ALL[index];
+            self.input.action_map.rebind(action, binding);
+            println!("{action:?} bound to {binding:?}");
+        }
+        let next = self.rebinding.map_or(0, |index| index + 1);
+        let Some(&action) = Action::ALL.get(next) else {
+            println!("Rebinding done");
+            self.rebinding = None;
+            return;
+        };
+        self.rebinding = Some(next);
+        println!(
+            "Press a key or mouse button for {action:?} (F4 keeps {:?})",
+            self.input.action_map.bindings(action)
+        );
     }
 
     fn resized(&mut self, size: PhysicalSize<u32>) {
         let MaybeRenderer::Renderer(renderer) = &mut self.renderer else {
             return;
         };
+        // minimized, the surface can't be configured with a zero size
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
         renderer.surface_config.width = size.width;
         renderer.surface_config.height = size.height;
-        renderer
-            .surface
-            .configure(&renderer.device, &renderer.surface_config);
+        if let Some(surface) = &renderer.surface {
+            surface.configure(&renderer.device, &renderer.surface_config);
+        }
         renderer
             .camera_state
             .camera
             .resize(size.width as f32, size.height as f32);
+        renderer
+            .camera_state
+            .camera_controller
+            .resize(size.width as f64, size.height as f64);
 
         renderer.depth_texture = Texture::create_depth_texture(
             &renderer.device,
             &renderer.surface_config,
             "depth_texture",
         );
+        renderer
+            .depth_view
+            .set_depth_texture(&renderer.device, &renderer.depth_texture.view);
+        renderer
+            .refraction
+            .resize(&renderer.device, &renderer.queue, size.width, size.height);
+        renderer
+            .msaa
+            .resize(&renderer.device, size.width, size.height);
     }
 }
 
-fn render_shadow_pass(renderer: &Renderer, encoder: &mut wgpu::CommandEncoder) {
+/// Attachments of a forward pass, multisampled color is resolved into `resolve`.
+/// Records the shadow, blur and forward passes of a frame into `view`, with the camera as it is.
+pub fn render_scene(
+    renderer: &mut Renderer,
+    encoder: &mut wgpu::CommandEncoder,
+    texture: &wgpu::Texture,
+    view: &wgpu::TextureView,
+) -> DrawStats {
+    renderer.frame_count += 1;
+    // without shadows the shadow maps are neither drawn nor blurred
+    if renderer.scene_graph.shadows_enabled() {
+        render_shadows(renderer, encoder);
+    } else {
+        renderer.shadow_stats.clear();
+        // the scene may change while the shadow maps aren't drawn
+        renderer.invalidate_shadow_layers();
+    }
+    render_custom_passes(renderer, PassStage::AfterShadows, encoder, texture, view);
+
+    let origin = renderer.scene_graph.render_origin();
+    // forward pass
+    encoder.push_debug_group("forward");
+    renderer.begin_gpu_pass(encoder, "forward");
+    let stats = if renderer.stereo.is_enabled() {
+        let (eye_width, eye_height) = renderer.stereo.eye_size(
+            renderer.surface_config.width,
+            renderer.surface_config.height,
+        );
+        renderer
+            .stereo
+            .ensure_target(&renderer.device, eye_width, eye_height);
+        renderer.stereo.update_cameras(
+            &renderer.queue,
+            &renderer.camera_state.camera.relative_to(origin),
+        );
+        let stats = render_stereo_pass(renderer, encoder);
+        render_stereo_composite(renderer, encoder, view);
+        // the eyes draw unculled
+        let draw_view = DrawView::unculled(renderer.camera_state.camera.eye);
+        renderer.node_stats = renderer.scene_graph.node_stats(&draw_view);
+        stats
+    } else {
+        let draw_view = DrawView::new(
+            renderer.camera_state.camera.eye,
+            renderer.camera_state.camera.calculate_matrix(),
+        );
+        renderer.node_stats = renderer.scene_graph.node_stats(&draw_view);
+        let stats = if renderer.scene_graph.has_glass() {
+            render_refraction_passes(renderer, encoder, view, draw_view)
+        } else {
+            let (target, pipelines) = match msaa_pass(renderer) {
+                Some(msaa) => (
+                    ForwardTarget::multisampled(msaa, view),
+                    &msaa.forward_pipeline,
+                ),
+                None => (
+                    ForwardTarget::new(view, &renderer.depth_texture.view),
+                    &renderer.render_pipeline,
+                ),
+            };
+            render_forward_pass(
+                renderer,
+                encoder,
+                target,
+                pipelines,
+                &renderer.camera_state.camera_bind_group,
+                &draw_view,
+            )
+        };
+        if renderer.depth_view.enabled {
+            renderer.depth_view.render(
+                encoder,
+                &renderer.queue,
+                view,
+                &renderer.camera_state.camera,
+            );
+        }
+        renderer.debug_lines.render(
+            &renderer.device,
+            &renderer.queue,
+            encoder,
+            view,
+            &renderer.scene_graph,
+            &renderer.camera_state.camera_bind_group,
+        );
+        renderer.trails.render(
+            &renderer.device,
+            &renderer.queue,
+            encoder,
+            view,
+            renderer.camera_state.camera.eye,
+            origin,
+            &renderer.camera_state.camera_bind_group,
+        );
+        stats
+    };
+    renderer.end_gpu_pass(encoder);
+    encoder.pop_debug_group();
+    renderer.watchdog.lap("forward");
+    // the depth of multisampled and stereo frames is in their own targets
+    let single_depth = !renderer.stereo.is_enabled() && msaa_pass(renderer).is_none();
+    if let Some(capture) = &mut renderer.pass_capture {
+        capture.capture(&renderer.device, encoder, "forward", texture);
+        if single_depth {
+            let depth = &renderer.depth_texture.texture;
+            capture.capture(&renderer.device, encoder, "forward_depth", depth);
+        }
+    }
+    render_custom_passes(renderer, PassStage::AfterForward, encoder, texture, view);
+    stats
+}
+
+/// Adds the shadow maps of the light `kinds` to the pass capture of the frame, if there is one.
+fn capture_shadow_maps(
+    renderer: &mut Renderer,
+    encoder: &mut wgpu::CommandEncoder,
+    kinds: &[LightKind],
+    suffix: &str,
+) {
+    let Some(capture) = &mut renderer.pass_capture else {
+        return;
+    };
+    for &kind in kinds {
+        let name = format!("{kind:?}_shadow_map{suffix}").to_lowercase();
+        let texture = &renderer.scene_graph.shadow_map_for(kind).texture;
+        capture.capture(&renderer.device, encoder, &name, texture);
+    }
+}
+
+/// Seconds since the Unix epoch, for the names of saved captures.
+fn unix_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Runs the custom passes of `stage`, see [`CustomPass`].
+fn render_custom_passes(
+    renderer: &mut Renderer,
+    stage: PassStage,
+    encoder: &mut wgpu::CommandEncoder,
+    texture: &wgpu::Texture,
+    view: &wgpu::TextureView,
+) {
+    if renderer.custom_passes.is_empty() {
+        return;
+    }
+    // the passes see the renderer, so they are taken out of it while they run
+    let mut passes = std::mem::take(&mut renderer.custom_passes);
+    // a handle of the view, the passes get the renderer while the captures below change it
+    let (depth, depth_sample_count) = match msaa_pass(renderer) {
+        Some(pass) => (pass.depth_texture.view.clone(), renderer.msaa.sample_count),
+        None => (renderer.depth_texture.view.clone(), 1),
+    };
+    let depth =
+        (stage == PassStage::AfterForward && !renderer.stereo.is_enabled()).then_some(depth);
+    encoder.push_debug_group(&format!("custom {stage:?}"));
+    for (index, pass) in passes
+        .iter_mut()
+        .filter(|pass| pass.stage() == stage)
+        .enumerate()
+    {
+        pass.render(PassContext {
+            renderer,
+            encoder,
+            color: view,
+            color_texture: texture,
+            depth: depth.as_ref(),
+            depth_sample_count,
+        });
+        if let Some(capture) = &mut renderer.pass_capture {
+            let name = format!("{stage:?}_pass_{index}").to_lowercase();
+            capture.capture(&renderer.device, encoder, &name, texture);
+        }
+    }
+    encoder.pop_debug_group();
+    renderer.custom_passes = passes;
+}
+
+struct ForwardTarget<'a> {
+    color: &'a wgpu::TextureView,
+    resolve: Option<&'a wgpu::TextureView>,
+    depth: &'a wgpu::TextureView,
+    sample_count: u32,
+    /// The skybox has no multiview pipeline, multiview targets keep the clear color.
+    skybox: bool,
+    /// Neither have custom materials, multiview targets skip the nodes drawn with them.
+    custom_materials: bool,
+}
+
+impl<'a> ForwardTarget<'a> {
+    fn new(color: &'a wgpu::TextureView, depth: &'a wgpu::TextureView) -> Self {
+        Self {
+            color,
+            resolve: None,
+            depth,
+            sample_count: 1,
+            skybox: true,
+            custom_materials: true,
+        }
+    }
+
+    /// Renders into the multisampled target of `msaa` and resolves into `view`.
+    fn multisampled(msaa: &'a MsaaPass, view: &'a wgpu::TextureView) -> Self {
+        Self {
+            color: &msaa.color_view,
+            resolve: Some(view),
+            depth: &msaa.depth_texture.view,
+            sample_count: msaa.depth_texture.texture.sample_count(),
+            skybox: true,
+            custom_materials: true,
+        }
+    }
+
+    fn multiview(color: &'a wgpu::TextureView, depth: &'a wgpu::TextureView) -> Self {
+        Self {
+            skybox: false,
+            custom_materials: false,
+            ..Self::new(color, depth)
+        }
+    }
+}
+
+/// The multisampled pass for the mono forward pass, if MSAA is on. The depth visualization samples
+/// the single sampled depth texture, so MSAA is skipped while it is shown.
+fn msaa_pass(renderer: &Renderer) -> Option<&MsaaPass> {
+    renderer
+        .msaa
+        .active_pass()
+        .filter(|_| !renderer.depth_view.enabled)
+}
+
+fn render_forward_pass(
+    renderer: &Renderer,
+    encoder: &mut wgpu::CommandEncoder,
+    target: ForwardTarget,
+    pipelines: &PipelineVariants,
+    camera_bind_group: &wgpu::BindGroup,
+    draw_view: &DrawView,
+) -> DrawStats {
+    let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("forward_pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target.color,
+            resolve_target: target.resolve,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(renderer.settings.clear_color()),
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+            view: target.depth,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(1.0),
+                // kept for the depth visualization
+                store: wgpu::StoreOp::Store,
+            }),
+            stencil_ops: None,
+        }),
+        ..Default::default()
+    });
+
+    rpass.set_bind_group(bind_groups::CAMERA, camera_bind_group, &[]);
+    if let Some(skybox) = renderer.skybox.as_ref().filter(|_| target.skybox) {
+        skybox.draw(&mut rpass, target.sample_count);
+    }
+    rpass.set_bind_group(
+        bind_groups::LIGHTS,
+        &renderer.scene_graph.light_bind_group,
+        &[],
+    );
+    let mut stats = rpass.draw_scenegraph(
+        &renderer.scene_graph,
+        pipelines,
+        bind_groups::MODEL,
+        bind_groups::MATERIAL,
+        draw_view,
+        renderer.forward_sort_policy,
+    );
+    if target.custom_materials {
+        stats += rpass.draw_custom_materials(
+            &renderer.scene_graph,
+            &renderer.custom_materials,
+            target.sample_count,
+            bind_groups::MODEL,
+            bind_groups::MATERIAL,
+            draw_view,
+            renderer.forward_sort_policy,
+        );
+    }
+    stats
+}
+
+/// Renders the opaque nodes offscreen and copies them into `view`, then draws the glass over them,
+/// refracting the offscreen copy. With MSAA the opaque nodes are resolved into the offscreen texture
+/// and the glass is drawn over the multisampled target, which is resolved into `view`.
+fn render_refraction_passes(
+    renderer: &Renderer,
+    encoder: &mut wgpu::CommandEncoder,
+    view: &wgpu::TextureView,
+    draw_view: DrawView,
+) -> DrawStats {
+    let refraction = &renderer.refraction;
+    let msaa = msaa_pass(renderer);
+    let (opaque_target, forward_pipeline) = match msaa {
+        Some(msaa) => (
+            ForwardTarget::multisampled(msaa, &refraction.opaque_view),
+            &msaa.forward_pipeline,
+        ),
+        None => (
+            ForwardTarget::new(&refraction.opaque_view, &renderer.depth_texture.view),
+            &renderer.render_pipeline,
+        ),
+    };
+    let mut stats = render_forward_pass(
+        renderer,
+        encoder,
+        opaque_target,
+        forward_pipeline,
+        &renderer.camera_state.camera_bind_group,
+        &draw_view.clone().with_layer(DrawLayer::Opaque),
+    );
+    // the multisampled target still holds the opaque nodes
+    let (glass_target, glass_pipeline) = match msaa {
+        Some(msaa) => (
+            ForwardTarget::multisampled(msaa, view),
+            &msaa.glass_pipeline,
+        ),
+        None => {
+            refraction.copy_opaque(encoder, view);
+            (
+                ForwardTarget::new(view, &renderer.depth_texture.view),
+                &refraction.glass_pipeline,
+            )
+        }
+    };
+
+    let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("glass_pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: glass_target.color,
+            resolve_target: glass_target.resolve,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Load,
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+            view: glass_target.depth,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Load,
+                store: wgpu::StoreOp::Store,
+            }),
+            stencil_ops: None,
+        }),
+        ..Default::default()
+    });
+    rpass.set_bind_group(
+        bind_groups::CAMERA,
+        &renderer.camera_state.camera_bind_group,
+        &[],
+    );
+    rpass.set_bind_group(bind_groups::LIGHTS, &refraction.bind_group, &[]);
+    stats += rpass.draw_scenegraph(
+        &renderer.scene_graph,
+        glass_pipeline,
+        bind_groups::MODEL,
+        bind_groups::MATERIAL,
+        &draw_view.with_layer(DrawLayer::Glass),
+        renderer.forward_sort_policy,
+    );
+    stats
+}
+
+/// Renders the pixel of the frame at `pixel` into the pick targets and copies them for readback.
+fn render_pick_pass(renderer: &Renderer, encoder: &mut wgpu::CommandEncoder, pixel: (u32, u32)) {
+    let pick = &renderer.pick;
+    let draw_view = pick.update_camera(
+        &renderer.queue,
+        &renderer.camera_state.camera,
+        renderer.scene_graph.render_origin(),
+        pixel,
+        renderer.surface_config.width,
+        renderer.surface_config.height,
+    );
+    {
+        let mut rpass = pick.begin_pass(encoder);
+        rpass.set_bind_group(
+            bind_groups::LIGHTS,
+            &renderer.scene_graph.light_bind_group,
+            &[],
+        );
+        rpass.draw_scenegraph(
+            &renderer.scene_graph,
+            &pick.pipeline,
+            bind_groups::MODEL,
+            bind_groups::MATERIAL,
+            &draw_view,
+            renderer.forward_sort_policy,
+        );
+    }
+    pick.encode_readback(encoder);
+}
+
+/// Renders the scene into both layers of the stereo target, in a single pass if multiview is available.
+fn render_stereo_pass(renderer: &Renderer, encoder: &mut wgpu::CommandEncoder) -> DrawStats {
+    let stereo = &renderer.stereo;
+    let Some(target) = &stereo.target else {
+        return DrawStats::default();
+    };
+    // the eyes are not culled, the camera frustum misses the outer edge of each eye's view
+    let draw_view = DrawView::unculled(renderer.camera_state.camera.eye);
+
+    if let Some(multiview_pipeline) = &stereo.multiview_pipeline {
+        return render_forward_pass(
+            renderer,
+            encoder,
+            ForwardTarget::multiview(&target.color_view, &target.depth_view),
+            multiview_pipeline,
+            &stereo.multiview_bind_group,
+            &draw_view,
+        );
+    }
+
+    let mut stats = DrawStats::default();
+    for eye in 0..EYE_COUNT as usize {
+        stats += render_forward_pass(
+            renderer,
+            encoder,
+            ForwardTarget::new(&target.color_eye_views[eye], &target.depth_eye_views[eye]),
+            &renderer.render_pipeline,
+            &stereo.eye_bind_groups[eye],
+            &draw_view,
+        );
+    }
+    stats
+}
+
+fn render_stereo_composite(
+    renderer: &Renderer,
+    encoder: &mut wgpu::CommandEncoder,
+    view: &wgpu::TextureView,
+) {
+    let Some(target) = &renderer.stereo.target else {
+        return;
+    };
+
+    let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("stereo_composite_pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        ..Default::default()
+    });
+    rpass.set_pipeline(&renderer.stereo.composite_pipeline().pipeline);
+    rpass.set_bind_group(0, &target.composite_bind_group, &[]);
+    rpass.draw(0..3, 0..1);
+}
+
+/// Records the shadow pass and the blur of the shadow maps, after the shadow pass and before the
+/// forward pass reads them.
+fn render_shadows(renderer: &mut Renderer, encoder: &mut wgpu::CommandEncoder) {
+    let moved = renderer.moved_shadow_layers();
+    encoder.push_debug_group("shadows");
+    renderer.begin_gpu_pass(encoder, "shadows");
+    let mut shadow_stats = render_shadow_pass(renderer, encoder, moved.as_deref());
+    renderer.end_gpu_pass(encoder);
+    renderer.watchdog.lap("shadows");
+    capture_shadow_maps(renderer, encoder, &[LightKind::Spot, LightKind::Point], "");
+
+    let blur_start = instant::Instant::now();
+    if renderer.gaussian_pass.is_some() {
+        renderer.begin_gpu_pass(encoder, "blur");
+    }
+    if let Some(gaussian_pass) = &renderer.gaussian_pass {
+        unsafe {
+            render_gaussian_pass(renderer, gaussian_pass, encoder, true, moved.as_deref());
+            render_gaussian_pass(renderer, gaussian_pass, encoder, false, moved.as_deref());
+        }
+    }
+    renderer.end_gpu_pass(encoder);
+    encoder.pop_debug_group();
+    renderer.watchdog.lap("blur");
+    // only the spot light shadow map is blurred
+    if renderer.gaussian_pass.is_some() {
+        capture_shadow_maps(renderer, encoder, &[LightKind::Spot], "_blurred");
+    }
+    let blur_time = renderer
+        .gaussian_pass
+        .is_some()
+        .then(|| blur_start.elapsed());
+    for stats in &mut shadow_stats {
+        stats.blur_time = blur_time;
+    }
+    renderer.shadow_stats = shadow_stats;
+}
+
+/// Renders the shadow maps of the lights and returns what was drawn for each light with a shadow map.
+/// With `moved` only the spot lights of those layers are drawn, the others keep their shadow map and
+/// stats of the frame they were last drawn in.
+fn render_shadow_pass(
+    renderer: &Renderer,
+    encoder: &mut wgpu::CommandEncoder,
+    moved: Option<&[u32]>,
+) -> Vec<ShadowStats> {
+    if let Some(layered_shadows) = &renderer.layered_shadows {
+        return render_layered_shadow_pass(renderer, layered_shadows, encoder);
+    }
     let scene_graph = &renderer.scene_graph;
+    let mut shadow_stats = Vec::new();
 
-    for light_node in SceneGraphLightNodeIterator::new(&renderer.scene_graph) {
+    for (index, light_node) in SceneGraphLightNodeIterator::new(&renderer.scene_graph).enumerate() {
         let light = &light_node.0.light;
         let model = light_node.1;
-        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &light.target_view,
+        // lights without a shadow map layer don't cast shadows
+        let Some(layer) = light.shadow_layer.filter(|_| light.cast_shadows) else {
+            continue;
+        };
+        let shadow_size = scene_graph.shadow_size(light);
+        let mut stats = ShadowStats {
+            light: index,
+            kind: light.kind,
+            resolution: shadow_size,
+            draws: DrawStats::default(),
+            blur_time: None,
+            last_update: renderer.frame_count,
+        };
+        if light.kind == LightKind::Spot && moved.is_some_and(|moved| !moved.contains(&layer)) {
+            let last_stats = renderer
+                .shadow_stats
+                .iter()
+                .find(|last| last.light == index && last.kind == light.kind);
+            shadow_stats.push(last_stats.copied().unwrap_or(stats));
+            continue;
+        }
+        let (moments_depth_texture, first_camera) = match light.kind {
+            LightKind::Spot => (&renderer.shadow_depth_texture, layer),
+            LightKind::Point => (
+                &renderer.point_shadow_depth_texture,
+                scene_graph.shadow_map.layers + layer,
+            ),
+        };
+
+        // a point light renders one pass per cube face
+        for (face, (target_view, camera_uniform)) in light
+            .target_views
+            .iter()
+            .zip(light.to_camera_uniforms(model, scene_graph.render_origin()))
+            .enumerate()
+        {
+            let camera_index = first_camera as usize + face;
+            // depth shadow maps are the depth attachment of the depth-only pipeline, moments are
+            // written to a color target
+            let (color_attachments, depth_view) = match moments_depth_texture {
+                None => (Vec::new(), target_view),
+                Some(moments_depth_texture) => {
+                    let color_attachment = wgpu::RenderPassColorAttachment {
+                        view: target_view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    };
+                    (vec![Some(color_attachment)], &moments_depth_texture.view)
+                }
+            };
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(&labels::light(index, &format!("shadow_pass face {face}"))),
+                color_attachments: &color_attachments,
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                ..Default::default()
+            });
+
+            // lights with a lower resolution render into the corner of their layers
+            rpass.set_viewport(0.0, 0.0, shadow_size as f32, shadow_size as f32, 0.0, 1.0);
+
+            rpass.set_bind_group(
+                bind_groups::CAMERA,
+                &renderer.sp_camera_bind_groups[camera_index],
+                &[],
+            );
+
+            stats.draws += rpass.draw_scenegraph_vertices(
+                scene_graph,
+                &renderer.shadow_pipeline,
+                bind_groups::MODEL,
+                // culled in world space
+                &DrawView::new(
+                    model.transform_point3(light.pos),
+                    Mat4::from_cols_array_2d(&camera_uniform.view_proj)
+                        * Mat4::from_translation(-scene_graph.render_origin()),
+                ),
+                renderer.shadow_sort_policy,
+            );
+        }
+        shadow_stats.push(stats);
+    }
+    shadow_stats
+}
+
+/// Renders the shadow map layers in chunks of up to [`MAX_VIEWS`] layers per multiview pass.
+fn render_layered_shadow_pass(
+    renderer: &Renderer,
+    layered_shadows: &LayeredShadowPass,
+    encoder: &mut wgpu::CommandEncoder,
+) -> Vec<ShadowStats> {
+    let scene_graph = &renderer.scene_graph;
+    let mut shadow_stats = Vec::new();
+    // camera, light position and index into shadow_stats of each used layer
+    let mut layer_cameras = HashMap::new();
+    for (index, (light_node, model)) in SceneGraphLightNodeIterator::new(scene_graph).enumerate() {
+        let light = &light_node.light;
+        let Some(layer) = light.shadow_layer.filter(|_| light.cast_shadows) else {
+            continue;
+        };
+        let position = model.transform_point3(light.pos);
+        let uniforms = light.to_camera_uniforms(model, scene_graph.render_origin());
+        for (face, camera_uniform) in uniforms.into_iter().enumerate() {
+            layer_cameras.insert(
+                (light.kind, layer + face as u32),
+                (camera_uniform, position, shadow_stats.len()),
+            );
+        }
+        shadow_stats.push(ShadowStats {
+            light: index,
+            kind: light.kind,
+            resolution: scene_graph.shadow_map_for(light.kind).size,
+            draws: DrawStats::default(),
+            blur_time: None,
+            last_update: renderer.frame_count,
+        });
+    }
+
+    for chunk in &layered_shadows.chunks {
+        let used = chunk
+            .layers
+            .clone()
+            .map(|layer| layer_cameras.get(&(chunk.kind, layer)))
+            .collect::<Vec<_>>();
+        let Some(&&(_, position, _)) = used.iter().flatten().next() else {
+            continue;
+        };
+        // unused layers get a zero matrix, which collapses every triangle
+        let mut cameras = [CameraUniform::zeroed(); MAX_VIEWS as usize];
+        for (camera, layer_camera) in cameras.iter_mut().zip(&used) {
+            if let Some((camera_uniform, _, _)) = layer_camera {
+                *camera = *camera_uniform;
+            }
+        }
+        renderer
+            .queue
+            .write_buffer(&chunk.camera_buffer, 0, bytemuck::cast_slice(&cameras));
+
+        let color_attachments = match &chunk.color_view {
+            Some(view) => vec![Some(wgpu::RenderPassColorAttachment {
+                view,
                 resolve_target: None,
                 ops: wgpu::Operations {
                     load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
                     store: wgpu::StoreOp::Store,
                 },
             })],
+            None => Vec::new(),
+        };
+        let shadow_map = scene_graph.shadow_map_for(chunk.kind);
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(&labels::shadow_layer(
+                &format!("layered_shadow_pass {:?}", chunk.kind),
+                chunk.layers.start,
+            )),
+            color_attachments: &color_attachments,
             depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                view: &renderer.shadow_depth_texture.view,
+                view: &chunk.depth_view,
                 depth_ops: Some(wgpu::Operations {
                     load: wgpu::LoadOp::Clear(1.0),
                     store: wgpu::StoreOp::Store,
@@ -171,55 +1413,78 @@ fn render_shadow_pass(renderer: &Renderer, encoder: &mut wgpu::CommandEncoder) {
             }),
             ..Default::default()
         });
-
         rpass.set_viewport(
             0.0,
             0.0,
-            ShadowMap::SHADOW_MAP_SIZE as f32,
-            ShadowMap::SHADOW_MAP_SIZE as f32,
+            shadow_map.size as f32,
+            shadow_map.size as f32,
             0.0,
             1.0,
         );
-
-        rpass.set_pipeline(&renderer.shadow_pipeline.pipeline);
-        rpass.set_bind_group(1, &renderer.model_matrix_bind_group, &[]);
-
-        let temp_camera_uniform = light.to_camera_uniform(model);
-        renderer.queue.write_buffer(
-            &renderer.sp_camera_buffer,
-            0,
-            bytemuck::cast_slice(&[temp_camera_uniform]),
+        rpass.set_bind_group(bind_groups::CAMERA, &chunk.camera_bind_group, &[]);
+        let draws = rpass.draw_scenegraph_vertices(
+            scene_graph,
+            layered_shadows.pipeline(chunk.views()),
+            bind_groups::MODEL,
+            &DrawView::unculled(position),
+            renderer.shadow_sort_policy,
         );
-        rpass.set_bind_group(0, &renderer.sp_camera_bind_group, &[]);
-
-        rpass.draw_scenegraph_vertices(scene_graph, &renderer.queue, &renderer.model_matrix_buffer);
+        // the layers of a light are consecutive
+        let mut lights = used
+            .iter()
+            .flatten()
+            .map(|&&(_, _, light)| light)
+            .collect::<Vec<_>>();
+        lights.dedup();
+        for light in lights {
+            shadow_stats[light].draws += draws;
+        }
     }
+    shadow_stats
 }
 
+/// Blurs the spot light shadow map in one direction, all layers at once or only the `layers` given.
 unsafe fn render_gaussian_pass(
     renderer: &Renderer,
+    gaussian_pass: &GaussianPass,
     encoder: &mut wgpu::CommandEncoder,
     vertical: bool,
+    layers: Option<&[u32]>,
 ) {
-    let gaussian_pass = &renderer.gaussian_pass;
-
+    if layers.is_some_and(|layers| layers.is_empty()) {
+        return;
+    }
     let bind_group = if vertical {
         &gaussian_pass.vertical_blur_bind_group
     } else {
         &gaussian_pass.horizontal_blur_bind_group
     };
 
-    let size = ShadowMap::SHADOW_MAP_SIZE;
-    let dispatch_x = (size + 15) / 16;
-    let dispatch_y = (size + 15) / 16;
+    let size = renderer.scene_graph.shadow_map.size;
+    let dispatch_x = size.div_ceil(gaussian_pass.workgroup_size);
+    let dispatch_y = size.div_ceil(gaussian_pass.workgroup_size);
 
     let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
         label: Some("gaussian_pass"),
         timestamp_writes: None,
     });
     cpass.set_pipeline(&gaussian_pass.blur_pipeline);
-    cpass.set_bind_group(0, bind_group, &[]);
-    cpass.dispatch_workgroups(dispatch_x, dispatch_y, 3);
+    match layers {
+        None => {
+            cpass.set_bind_group(0, bind_group, &[0]);
+            cpass.dispatch_workgroups(
+                dispatch_x,
+                dispatch_y,
+                renderer.scene_graph.shadow_map.layers,
+            );
+        }
+        Some(layers) => {
+            for &layer in layers {
+                cpass.set_bind_group(0, bind_group, &[layer * gaussian_pass.layer_stride]);
+                cpass.dispatch_workgroups(dispatch_x, dispatch_y, 1);
+            }
+        }
+    }
 }
 
 impl ApplicationHandler<Renderer> for App {
@@ -229,8 +1494,19 @@ impl ApplicationHandler<Renderer> for App {
         }
     }
 
-    fn user_event(&mut self, _event_loop: &ActiveEventLoop, graphics: Renderer) {
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, graphics: Renderer) {
+        #[cfg(feature = "debug-ui")]
+        {
+            self.debug_ui = graphics
+                .window
+                .clone()
+                .map(|window| DebugUi::new(&graphics.device, graphics.frame_format(), window));
+        }
         self.renderer = MaybeRenderer::Renderer(graphics);
+        if let Some(run) = self.golden.clone() {
+            self.golden_passed = Some(self.compare_golden(&run));
+            event_loop.exit();
+        }
     }
 
     fn window_event(
@@ -239,23 +1515,31 @@ impl ApplicationHandler<Renderer> for App {
         _window_id: WindowId,
         event: WindowEvent,
     ) {
+        #[cfg(feature = "debug-ui")]
+        if let Some(debug_ui) = &mut self.debug_ui {
+            if debug_ui.on_window_event(&event) {
+                return;
+            }
+        }
         match event {
             WindowEvent::Resized(size) => self.resized(size),
             WindowEvent::RedrawRequested => {
                 let frame_start = Instant::now();
 
-                self.draw();
-
-                let elapsed = frame_start.elapsed();
-                if elapsed < self.target_frame_time {
-                    let wait_duration = self.target_frame_time - elapsed;
-                    std::thread::sleep(wait_duration);
-                }
+                self.draw_or_exit(event_loop);
 
                 let MaybeRenderer::Renderer(renderer) = &mut self.renderer else {
                     return;
                 };
-                renderer.window.request_redraw();
+                let elapsed = frame_start.elapsed();
+                let target_frame_time = renderer.settings.target_frame_time();
+                if elapsed < target_frame_time {
+                    let wait_duration = target_frame_time - elapsed;
+                    std::thread::sleep(wait_duration);
+                }
+                if let Some(window) = &renderer.window {
+                    window.request_redraw();
+                }
             },
             WindowEvent::CloseRequested
             | WindowEvent::KeyboardInput {
@@ -267,39 +1551,76 @@ impl ApplicationHandler<Renderer> for App {
                     },
                 ..
             } => event_loop.exit(),
-            WindowEvent::KeyboardInput { .. } => {
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::F4),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => self.rebind(None),
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(keycode),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } if self.rebinding.is_some() => self.rebind(Some(self.input.key_binding(keycode))),
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button,
+                ..
+            } if self.rebinding.is_some() => self.rebind(Some(Binding::Mouse(button))),
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
                 if let MaybeRenderer::Renderer(renderer) = &mut self.renderer {
-                    let state_changed = renderer
-                        .camera_state
-                        .camera_controller
-                        .process_events(&event);
-                    if state_changed {
-                        self.draw();
-                    }
+                    renderer.hud.set_scale_factor(scale_factor);
                 }
             }
-            WindowEvent::MouseInput {
-                button: MouseButton::Left,
-                ..
-            } => {
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+                self.input.set_modifiers(self.modifiers);
+            }
+            WindowEvent::KeyboardInput { .. } | WindowEvent::MouseInput { .. } => {
+                let pressed = InputState::is_press(&event);
+                let repeat = InputState::is_repeat(&event);
+                let Some((action, active)) = self.input.process_window_event(&event) else {
+                    return;
+                };
+                if (pressed || repeat) && self.run_action(action, repeat) {
+                    return;
+                }
                 if let MaybeRenderer::Renderer(renderer) = &mut self.renderer {
+                    if renderer.stereo.process_action(action, pressed) {
+                        return;
+                    }
                     let state_changed = renderer
                         .camera_state
                         .camera_controller
-                        .process_events(&event);
+                        .process_action(action, active);
                     if state_changed {
-                        self.draw();
+                        self.draw_or_exit(event_loop);
                     }
                 }
             }
-            WindowEvent::CursorMoved { .. } => {
+            WindowEvent::CursorLeft { .. } => self.cursor_position = None,
+            WindowEvent::CursorMoved { .. }
+            | WindowEvent::MouseWheel { .. }
+            | WindowEvent::PinchGesture { .. } => {
+                if let WindowEvent::CursorMoved { position, .. } = event {
+                    self.cursor_position = Some(position);
+                }
                 if let MaybeRenderer::Renderer(renderer) = &mut self.renderer {
                     let state_changed = renderer
                         .camera_state
                         .camera_controller
                         .process_events(&event);
                     if state_changed {
-                        self.draw();
+                        self.draw_or_exit(event_loop);
                     }
                 }
             }
@@ -313,8 +1634,11 @@ impl ApplicationHandler<Renderer> for App {
         _: DeviceId,
         event: DeviceEvent,
     ) {
-        match event {
-            _ => (),
+        let Some((dx, dy)) = self.input.process_device_event(&event) else {
+            return;
+        };
+        if let MaybeRenderer::Renderer(renderer) = &mut self.renderer {
+            renderer.camera_state.camera_controller.look(dx, dy);
         }
     }
 }
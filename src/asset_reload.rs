@@ -0,0 +1,55 @@
+/*
+ * Asset hot reload.
+ * With `--watch-assets` the models of the scene are loaded again when one of their OBJ, MTL or texture
+ * files is saved, so an asset can be edited in another program while the demo runs. The files of each
+ * model group are polled for their modification time like the shader sources, see shader_reload.rs.
+ * A changed file is evicted from the resource cache, the models using it are loaded again and their
+ * meshes and materials swapped into the render nodes of the group, which keep their transforms. A model
+ * that fails to load keeps what it had.
+ */
+use crate::resources::ResourceConfig;
+use instant::Instant;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+/// Time between two checks of the modification times.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Polls the files of the models in the scene for changes.
+pub struct AssetWatcher {
+    modified: HashMap<String, Option<SystemTime>>,
+    last_poll: Instant,
+}
+
+impl AssetWatcher {
+    pub fn new() -> Self {
+        println!("Watching the model and texture files for changes");
+        Self {
+            modified: HashMap::new(),
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// Those of `files` that were saved since the last call, files seen for the first time are only
+    /// remembered.
+    pub fn changed<'a>(&mut self, files: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+        let mut changed = Vec::new();
+        for file in files {
+            let modified = ResourceConfig::get()
+                .path(file)
+                .and_then(std::fs::metadata)
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            // a file used twice is compared to itself the second time
+            let last_modified = self.modified.insert(file.to_string(), modified);
+            if last_modified.is_some_and(|last| modified.is_some() && modified != last) {
+                changed.push(file.to_string());
+            }
+        }
+        changed
+    }
+}
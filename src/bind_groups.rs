@@ -0,0 +1,61 @@
+/*
+ * Bind group indices of the scene shaders.
+ * The forward, glass, pick, shadow and custom material pipelines share the group numbering below. The
+ * shaders use the constants of `wgsl()` in their `@group` attributes, which shader.wgsl and shadow.wgsl
+ * import as the `bind_groups` snippet (see shader_compose.rs), and the pipeline layouts are put together with `layouts`, so
+ * the Rust side and the shaders can't disagree about which group holds what. Passes with shaders of their
+ * own, like the skybox or the blur, number their groups themselves.
+ */
+
+/// Camera uniform, the light camera in the shadow passes.
+pub const CAMERA: u32 = 0;
+/// Model matrix, with a dynamic offset per render node.
+pub const MODEL: u32 = 1;
+/// Textures and factors of the material.
+pub const MATERIAL: u32 = 2;
+/// Lights and shadow maps, the refraction inputs in the glass pass.
+pub const LIGHTS: u32 = 3;
+pub const COUNT: usize = 4;
+
+const _: () = assert!(
+    (1 << CAMERA | 1 << MODEL | 1 << MATERIAL | 1 << LIGHTS) == (1 << COUNT) - 1,
+    "the bind group indices must be distinct and cover 0..COUNT"
+);
+// the shadow pipelines only have the camera and model groups
+const _: () = assert!(CAMERA < 2 && MODEL < 2);
+
+/// The indices as WGSL constants, e.g. `const CAMERA_GROUP: u32 = 0u;`.
+pub fn wgsl() -> String {
+    format!(
+        "const CAMERA_GROUP: u32 = {CAMERA}u;\n\
+         const MODEL_GROUP: u32 = {MODEL}u;\n\
+         const MATERIAL_GROUP: u32 = {MATERIAL}u;\n\
+         const LIGHTS_GROUP: u32 = {LIGHTS}u;\n"
+    )
+}
+
+/// The layouts at their group indices, for the layout of a pipeline that uses all groups.
+pub fn layouts(
+    camera: &wgpu::BindGroupLayout,
+    model: &wgpu::BindGroupLayout,
+    material: &wgpu::BindGroupLayout,
+    lights: &wgpu::BindGroupLayout,
+) -> [wgpu::BindGroupLayout; COUNT] {
+    let mut layouts = [const { None }; COUNT];
+    layouts[CAMERA as usize] = Some(camera.clone());
+    layouts[MODEL as usize] = Some(model.clone());
+    layouts[MATERIAL as usize] = Some(material.clone());
+    layouts[LIGHTS as usize] = Some(lights.clone());
+    layouts.map(|layout| layout.unwrap())
+}
+
+/// The layouts of the shadow pipelines at their group indices.
+pub fn shadow_layouts(
+    camera: &wgpu::BindGroupLayout,
+    model: &wgpu::BindGroupLayout,
+) -> [wgpu::BindGroupLayout; 2] {
+    let mut layouts = [const { None }; 2];
+    layouts[CAMERA as usize] = Some(camera.clone());
+    layouts[MODEL as usize] = Some(model.clone());
+    layouts.map(|layout| layout.unwrap())
+}
@@ -1,7 +1,9 @@
-use glam::{Mat3, Mat4, Vec3};
+use crate::input::Action;
+use crate::reflection::ReflectDevice;
+use crate::scenegraph::MaterialOverride;
+use glam::{Mat3, Mat4, Vec2, Vec3};
 use std::clone::Clone;
-use winit::event::{ElementState, KeyEvent, WindowEvent};
-use winit::keyboard::{KeyCode, PhysicalKey};
+use winit::event::{MouseScrollDelta, WindowEvent};
 
 pub struct Camera {
     pub eye: Vec3,
@@ -11,6 +13,149 @@ pub struct Camera {
     pub fovy: f32,
     pub znear: f32,
     pub zfar: f32,
+    pub projection: Projection,
+}
+
+/// Position and orientation of a camera, without its projection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraPose {
+    pub eye: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+}
+
+impl CameraPose {
+    pub fn lerp(&self, other: &CameraPose, t: f32) -> CameraPose {
+        CameraPose {
+            eye: self.eye.lerp(other.eye, t),
+            target: self.target.lerp(other.target, t),
+            up: self.up.lerp(other.up, t).normalize_or(other.up),
+        }
+    }
+
+    // Move the camera by a specified vector
+    pub fn move_by(&mut self, delta: Vec3) {
+        self.eye += delta;
+        self.target += delta;
+    }
+
+    /// Moves the eye by `delta`, the target only follows sideways, so moving forward brings the eye closer
+    /// to the target. The eye stops `min_distance` in front of the target instead of passing through it.
+    pub fn zoom_by(&mut self, delta: Vec3, min_distance: f32) {
+        let to_target = self.target - self.eye;
+        let forward = to_target.normalize();
+        let max_along = (to_target.length() - min_distance).max(0.0);
+        let along = delta.dot(forward);
+        let delta = if along > max_along {
+            delta * (max_along / along)
+        } else {
+            delta
+        };
+        self.eye += delta;
+        self.target += delta.reject_from_normalized(forward);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    Perspective,
+    /// Parallel projection showing `height` world units vertically.
+    Orthographic { height: f32 },
+}
+
+/// Axis-aligned views used when inspecting models, named after the side of the scene they show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CanonicalView {
+    Front,
+    Back,
+    Right,
+    Left,
+    Top,
+    Bottom,
+}
+
+impl CanonicalView {
+    /// View direction and up vector of the view.
+    fn orientation(self) -> (Vec3, Vec3) {
+        match self {
+            CanonicalView::Front => (Vec3::NEG_Z, Vec3::Y),
+            CanonicalView::Back => (Vec3::Z, Vec3::Y),
+            CanonicalView::Right => (Vec3::NEG_X, Vec3::Y),
+            CanonicalView::Left => (Vec3::X, Vec3::Y),
+            CanonicalView::Top => (Vec3::NEG_Y, Vec3::NEG_Z),
+            CanonicalView::Bottom => (Vec3::Y, Vec3::Z),
+        }
+    }
+}
+
+/// What the forward pass shows instead of the lit scene, to diagnose meshes with bad normals or
+/// texture coordinates. Cycled with F6.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DebugView {
+    #[default]
+    Off = 0,
+    /// World space normals, xyz mapped from -1..1 to RGB.
+    Normals = 1,
+    /// World space tangents like the normals.
+    Tangents = 2,
+    /// Distance to the camera, white close by.
+    Depth = 3,
+    /// Texture coordinates in red and green, repeating outside of 0..1.
+    Uvs = 4,
+    /// The diffuse texture or color of the material without lighting.
+    Albedo = 5,
+    /// A checker pattern in texture space, shows stretched and flipped texture coordinates.
+    UvChecker = 6,
+}
+
+impl DebugView {
+    pub fn next(self) -> Self {
+        match self {
+            DebugView::Off => DebugView::Normals,
+            DebugView::Normals => DebugView::Tangents,
+            DebugView::Tangents => DebugView::Depth,
+            DebugView::Depth => DebugView::Uvs,
+            DebugView::Uvs => DebugView::Albedo,
+            DebugView::Albedo => DebugView::UvChecker,
+            DebugView::UvChecker => DebugView::Off,
+        }
+    }
+}
+
+/// How the scene is shown for reviewing assets, selected with the keys 1 to 5. Clay draws every render
+/// node with a plain gray material, the other modes are debug views.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RenderMode {
+    #[default]
+    Lit,
+    Unlit,
+    Clay,
+    Normals,
+    UvChecker,
+}
+
+impl RenderMode {
+    pub const ALL: [RenderMode; 5] = [
+        RenderMode::Lit,
+        RenderMode::Unlit,
+        RenderMode::Clay,
+        RenderMode::Normals,
+        RenderMode::UvChecker,
+    ];
+
+    pub fn debug_view(self) -> DebugView {
+        match self {
+            RenderMode::Lit | RenderMode::Clay => DebugView::Off,
+            RenderMode::Unlit => DebugView::Albedo,
+            RenderMode::Normals => DebugView::Normals,
+            RenderMode::UvChecker => DebugView::UvChecker,
+        }
+    }
+
+    /// The material every render node is drawn with instead of its own.
+    pub fn material_override(self) -> Option<MaterialOverride> {
+        (self == RenderMode::Clay).then_some(MaterialOverride::Clay)
+    }
 }
 
 #[repr(C)]
@@ -18,23 +163,34 @@ pub struct Camera {
 pub struct CameraUniform {
     pub view_proj: [[f32; 4]; 4],
     pub position: [f32; 4],
+    /// Maps clip space back to world space, e.g. to turn pixels into view rays for the skybox.
+    pub inv_view_proj: [[f32; 4]; 4],
+    /// A [`DebugView`], only set for the main camera.
+    pub debug_view: u32,
+    _padding: [u32; 3],
 }
 
 impl CameraUniform {
-    pub fn from_camera(camera: &Camera) -> Self {
+    pub fn new(view_proj: Mat4, position: Vec3) -> Self {
         Self {
-            view_proj: camera.calculate_matrix().to_cols_array_2d(),
-            position: [camera.eye.x, camera.eye.y, camera.eye.z, 1.0],
+            view_proj: view_proj.to_cols_array_2d(),
+            position: position.extend(1.0).to_array(),
+            inv_view_proj: view_proj.inverse().to_cols_array_2d(),
+            debug_view: DebugView::Off as u32,
+            _padding: [0; 3],
         }
     }
 
+    pub fn from_camera(camera: &Camera) -> Self {
+        Self::new(camera.calculate_matrix(), camera.eye)
+    }
+
     pub fn update(&mut self, camera: &Camera) {
-        self.view_proj = camera.calculate_matrix().to_cols_array_2d();
-        self.position = [camera.eye.x, camera.eye.y, camera.eye.z, 1.0];
+        *self = Self::from_camera(camera);
     }
 
     pub fn get_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
-        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        device.reflect_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[wgpu::BindGroupLayoutEntry {
                 binding: 0,
                 visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
@@ -51,10 +207,121 @@ impl CameraUniform {
 }
 
 impl Camera {
+    pub const MIN_FOVY: f32 = 10.0;
+    pub const MAX_FOVY: f32 = 120.0;
+    /// Closest distance [`Camera::zoom`] brings the eye to the target.
+    pub const MIN_ZOOM_DISTANCE: f32 = 0.5;
+
     pub fn calculate_matrix(&self) -> Mat4 {
-        let view = Mat4::look_at_rh(self.eye, self.target, self.up);
-        let projection = Mat4::perspective_rh(self.fovy.to_radians(), self.aspect, self.znear, self.zfar);
-        projection * view
+        self.projection_matrix() * self.view_matrix()
+    }
+
+    pub fn view_matrix(&self) -> Mat4 {
+        Mat4::look_at_rh(self.eye, self.target, self.up)
+    }
+
+    pub fn projection_matrix(&self) -> Mat4 {
+        match self.projection {
+            Projection::Perspective => {
+                Mat4::perspective_rh(self.fovy.to_radians(), self.aspect, self.znear, self.zfar)
+            }
+            Projection::Orthographic { height } => {
+                let half_height = height * 0.5;
+                let half_width = half_height * self.aspect;
+                Mat4::orthographic_rh(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    self.znear,
+                    self.zfar,
+                )
+            }
+        }
+    }
+
+    /// Switches to an orthographic projection looking along one of the world axes,
+    /// framing the box spanned by `min` and `max`.
+    pub fn snap_to_view(&mut self, view: CanonicalView, min: Vec3, max: Vec3) {
+        let (forward, up) = view.orientation();
+        let right = forward.cross(up);
+        let center = (min + max) * 0.5;
+        let half_extent = (max - min) * 0.5;
+        let radius = half_extent.length();
+
+        // extent of the box on screen, the corners are symmetric around the center
+        let half_width = half_extent.abs().dot(right.abs());
+        let half_height = half_extent.abs().dot(up.abs());
+        let height = half_height.max(half_width / self.aspect) * 2.0 * 1.1;
+
+        let distance = radius + self.znear + 1.0;
+        self.eye = center - forward * distance;
+        self.target = center;
+        self.up = up;
+        self.zfar = self.zfar.max(distance + radius);
+        self.projection = Projection::Orthographic { height };
+    }
+
+    /// Toggles between perspective and an orthographic projection showing the same
+    /// extent at the target distance.
+    pub fn toggle_orthographic(&mut self) {
+        self.projection = match self.projection {
+            Projection::Perspective => {
+                let distance = (self.target - self.eye).length();
+                Projection::Orthographic {
+                    height: 2.0 * distance * (self.fovy.to_radians() * 0.5).tan(),
+                }
+            }
+            Projection::Orthographic { .. } => Projection::Perspective,
+        };
+    }
+
+    /// Sets the vertical field of view in degrees, clamped to a usable range.
+    pub fn set_fovy(&mut self, fovy: f32) {
+        self.fovy = fovy.clamp(Self::MIN_FOVY, Self::MAX_FOVY);
+    }
+
+    /// Ray through a point given in normalized device coordinates, starting on the near plane.
+    /// Returns the origin and the unit direction.
+    pub fn ray_through(&self, ndc: Vec2) -> (Vec3, Vec3) {
+        let inverse = self.calculate_matrix().inverse();
+        let near = inverse.project_point3(ndc.extend(0.0));
+        let far = inverse.project_point3(ndc.extend(1.0));
+        (near, (far - near).normalize())
+    }
+
+    pub fn pose(&self) -> CameraPose {
+        CameraPose {
+            eye: self.eye,
+            target: self.target,
+            up: self.up,
+        }
+    }
+
+    pub fn set_pose(&mut self, pose: CameraPose) {
+        self.eye = pose.eye;
+        self.target = pose.target;
+        self.up = pose.up;
+    }
+
+    /// The camera moved by `-origin`, for the GPU which gets every position relative to the render
+    /// origin, see [`SceneGraph::render_origin`](crate::scenegraph::SceneGraph::render_origin).
+    pub fn relative_to(&self, origin: Vec3) -> Camera {
+        Camera {
+            eye: self.eye - origin,
+            target: self.target - origin,
+            up: self.up,
+            aspect: self.aspect,
+            fovy: self.fovy,
+            znear: self.znear,
+            zfar: self.zfar,
+            projection: self.projection,
+        }
+    }
+
+    /// Unit vector pointing to the right of the view direction.
+    pub fn right(&self) -> Vec3 {
+        (self.target - self.eye).cross(self.up).normalize()
     }
 
     pub fn resize(&mut self, width: f32, height: f32) {
@@ -74,22 +341,18 @@ impl Camera {
         self.up = rotation.transform_vector3(self.up);
     }
 
-    // Move the camera by a specified vector
-    pub fn move_by(&mut self, delta: Vec3) {
-        self.eye += delta;
-        self.target += delta;
-    }
-
     // Yaw the camera by a specified angle
     pub fn yaw(&mut self, angle: f32) {
         let rotation = Mat4::from_rotation_y(angle);
         self.eye = rotation.transform_point3(self.eye);
     }
 
-    // Zoom the camera in or out by a specified factor
+    // Zoom the camera in or out by a specified factor, without passing through the target
     pub fn zoom(&mut self, factor: f32) {
         let direction = (self.target - self.eye).normalize();
-        self.eye += direction * factor;
+        let mut pose = self.pose();
+        pose.zoom_by(direction * factor, Self::MIN_ZOOM_DISTANCE);
+        self.set_pose(pose);
     }
 
     pub fn get_focal_point(&self) -> Vec3 {
@@ -97,10 +360,45 @@ impl Camera {
     }
 }
 
+/// Eased move between two poses, used when the camera jumps to a new view.
+struct CameraTransition {
+    from: CameraPose,
+    to: CameraPose,
+    elapsed: f32,
+}
+
 // Derived from: https://sotrh.github.io/learn-wgpu/beginner/tutorial6-uniforms/#a-controller-for-our-camera
+// Input moves a goal pose which the camera follows with exponential damping.
 pub struct CameraController {
+    /// Time in seconds the camera needs to cover about 63% of the distance to its goal, 0 disables smoothing.
+    pub smoothing_time: f32,
+    /// Duration in seconds of transitions started with [`CameraController::begin_transition`].
+    pub transition_time: f32,
+    /// Distance in world units the camera moves towards the cursor per scroll step.
+    pub zoom_speed: f32,
+    /// Closest distance zooming brings the camera to its target.
+    pub min_zoom_distance: f32,
+    /// Scroll steps per unit of touchpad pinch, a pinch doubling the content's size is a magnification of 1.
+    pub pinch_steps: f32,
+    /// Change of the field of view in degrees per scroll step with Ctrl held or per -/= key press.
+    pub fov_step: f32,
+    /// How quickly the velocity follows the movement keys in 1/s, it covers about 63% of the difference
+    /// in `1 / acceleration` seconds. Also damps the movement after the keys are released.
+    pub acceleration: f32,
+    /// Rotation in degrees per second with the look stick fully deflected.
+    pub stick_look_speed: f32,
+    /// Speed multiplier while [`Action::Sprint`] is active.
+    pub sprint_factor: f32,
+    goal: Option<CameraPose>,
+    transition: Option<CameraTransition>,
+    // world units per second with a movement key held
     speed: f32,
+    // degrees per pixel of mouse movement
     sensitivity: f32,
+    velocity: Vec3,
+    // analog movement along right, up and forward and look rates, see set_analog_input
+    analog_movement: Vec3,
+    analog_look: Vec2,
     is_forward_pressed: bool,
     is_backward_pressed: bool,
     is_left_pressed: bool,
@@ -108,16 +406,37 @@ pub struct CameraController {
     is_mouse_pressed: bool,
     is_up_pressed: bool,
     is_down_pressed: bool,
+    is_sprint_active: bool,
+    is_wheel_fov_active: bool,
+    // mouse movement while looking, accumulated until the next update
     delta_x: f64,
     delta_y: f64,
     last_mouse_position: Option<(f64, f64)>,
+    viewport_size: (f64, f64),
+    // scroll steps and field of view change not yet applied to the camera
+    scroll: f32,
+    fov_change: f32,
 }
 
 impl CameraController {
     pub fn new(speed: f32, sensitivity: f32) -> Self {
         Self {
+            smoothing_time: 0.08,
+            transition_time: 0.5,
+            goal: None,
+            transition: None,
+            zoom_speed: 2.0,
+            min_zoom_distance: Camera::MIN_ZOOM_DISTANCE,
+            pinch_steps: 10.0,
+            fov_step: 5.0,
+            acceleration: 10.0,
+            stick_look_speed: 120.0,
+            sprint_factor: 3.0,
             speed,
             sensitivity,
+            velocity: Vec3::ZERO,
+            analog_movement: Vec3::ZERO,
+            analog_look: Vec2::ZERO,
             is_forward_pressed: false,
             is_backward_pressed: false,
             is_left_pressed: false,
@@ -125,118 +444,269 @@ impl CameraController {
             is_mouse_pressed: false,
             is_up_pressed: false,
             is_down_pressed: false,
+            is_sprint_active: false,
+            is_wheel_fov_active: false,
             delta_x: 0.0,
             delta_y: 0.0,
             last_mouse_position: None,
+            viewport_size: (1.0, 1.0),
+            scroll: 0.0,
+            fov_change: 0.0,
+        }
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Movement speed in world units per second.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.max(0.0);
+    }
+
+    pub fn sensitivity(&self) -> f32 {
+        self.sensitivity
+    }
+
+    /// Rotation in degrees per pixel the mouse moves while dragging.
+    pub fn set_sensitivity(&mut self, sensitivity: f32) {
+        self.sensitivity = sensitivity.max(0.0);
+    }
+
+    /// Analog input, e.g. from a gamepad, which is held until it is set again. `movement` moves along the
+    /// camera's right, up and forward axis at up to the full speed per component, `look` turns right and
+    /// up at up to [`Self::stick_look_speed`].
+    #[cfg_attr(not(feature = "gamepad"), allow(dead_code))]
+    pub fn set_analog_input(&mut self, movement: Vec3, look: Vec2) {
+        self.analog_movement = movement.clamp(Vec3::NEG_ONE, Vec3::ONE);
+        self.analog_look = look.clamp(Vec2::NEG_ONE, Vec2::ONE);
+    }
+
+    /// Size of the viewport in physical pixels, needed to turn the cursor position into a ray.
+    pub fn resize(&mut self, width: f64, height: f64) {
+        self.viewport_size = (width.max(1.0), height.max(1.0));
+    }
+
+    /// Applies an action of the [`crate::input::ActionMap`], returns whether the camera needs an update.
+    pub fn process_action(&mut self, action: Action, active: bool) -> bool {
+        match action {
+            Action::MoveForward => self.is_forward_pressed = active,
+            Action::MoveBackward => self.is_backward_pressed = active,
+            Action::MoveLeft => self.is_left_pressed = active,
+            Action::MoveRight => self.is_right_pressed = active,
+            Action::MoveUp => self.is_up_pressed = active,
+            Action::MoveDown => self.is_down_pressed = active,
+            Action::Sprint => self.is_sprint_active = active,
+            Action::Look => self.is_mouse_pressed = active,
+            Action::WheelFov => {
+                self.is_wheel_fov_active = active;
+                return false;
+            }
+            Action::WidenFov if active => self.fov_change += self.fov_step,
+            Action::NarrowFov if active => self.fov_change -= self.fov_step,
+            Action::WidenFov | Action::NarrowFov => return false,
+            // handled by the stereo pass and the app
+            _ => return false,
         }
+        true
+    }
+
+    /// Turns the view by a mouse movement of `dx`, `dy` pixels while [`Action::Look`] is active.
+    pub fn look(&mut self, dx: f64, dy: f64) {
+        self.delta_x += dx;
+        self.delta_y += dy;
     }
 
+    /// Last position of the cursor over the window in physical pixels, None until it moved there.
+    pub fn cursor_position(&self) -> Option<(f64, f64)> {
+        self.last_mouse_position
+    }
+
+    /// Tracks the cursor, which zooming aims at, and applies the mouse wheel and touchpad pinch.
     pub fn process_events(&mut self, event: &WindowEvent) -> bool {
         match event {
-            WindowEvent::KeyboardInput {
-                event:
-                KeyEvent {
-                    state,
-                    physical_key: PhysicalKey::Code(keycode),
-                    ..
-                },
-                ..
-            } => {
-                let is_pressed = *state == ElementState::Pressed;
-                match keycode {
-                    KeyCode::KeyW | KeyCode::ArrowUp => {
-                        self.is_forward_pressed = is_pressed;
-                        true
-                    }
-                    KeyCode::KeyA | KeyCode::ArrowLeft => {
-                        self.is_left_pressed = is_pressed;
-                        true
-                    }
-                    KeyCode::KeyS | KeyCode::ArrowDown => {
-                        self.is_backward_pressed = is_pressed;
-                        true
-                    }
-                    KeyCode::KeyD | KeyCode::ArrowRight => {
-                        self.is_right_pressed = is_pressed;
-                        true
-                    }
-                    KeyCode::Space => {
-                        self.is_up_pressed = is_pressed;
-                        true
-                    }
-                    KeyCode::ShiftLeft | KeyCode::ShiftRight => {
-                        self.is_down_pressed = is_pressed;
-                        true
-                    }
-                    _ => false,
-                }
-            }
-            WindowEvent::MouseInput { state, button, .. } => {
-                if *button == winit::event::MouseButton::Left {
-                    self.is_mouse_pressed = *state == ElementState::Pressed;
-                    return true;
-                }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.last_mouse_position = Some((position.x, position.y));
                 false
             }
-            WindowEvent::CursorMoved { position, .. } => {
-                let mut output = false;
-                if self.is_mouse_pressed {
-                    if let Some((last_x, last_y)) = self.last_mouse_position {
-                        self.delta_x = position.x - last_x;
-                        self.delta_y = position.y - last_y;
-                    }
-                    output = true
+            WindowEvent::MouseWheel { delta, .. } => {
+                let steps = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    // touchpads report pixels, roughly one step per text line
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+                };
+                if self.is_wheel_fov_active {
+                    self.fov_change -= steps * self.fov_step;
                 } else {
-                    self.delta_x = 0.0;
-                    self.delta_y = 0.0;
+                    self.scroll += steps;
                 }
-                self.last_mouse_position = Some((position.x, position.y));
-                output
+                true
+            }
+            // spreading the fingers zooms in
+            WindowEvent::PinchGesture { delta, .. } => {
+                self.scroll += *delta as f32 * self.pinch_steps;
+                true
             }
             _ => false,
         }
     }
 
-    pub fn update_camera(&self, camera: &mut Camera) {
-        let forward = (camera.target - camera.eye).normalize();
-        let right = forward.cross(camera.up).normalize();
-        let up = camera.up.normalize();
+    /// Eases the camera from `from` to its current pose instead of jumping there.
+    pub fn begin_transition(&mut self, camera: &mut Camera, from: CameraPose) {
+        let to = camera.pose();
+        camera.set_pose(from);
+        self.goal = Some(to);
+        self.velocity = Vec3::ZERO;
+        self.transition = Some(CameraTransition {
+            from,
+            to,
+            elapsed: 0.0,
+        });
+    }
+
+    fn has_input(&self) -> bool {
+        self.is_forward_pressed
+            || self.is_backward_pressed
+            || self.is_left_pressed
+            || self.is_right_pressed
+            || self.is_up_pressed
+            || self.is_down_pressed
+            || self.is_mouse_pressed
+            || self.scroll != 0.0
+            || self.analog_movement != Vec3::ZERO
+            || self.analog_look != Vec2::ZERO
+    }
+
+    /// Applies the input to the goal pose and moves the camera towards it, `dt` is the frame time in seconds.
+    pub fn update_camera(&mut self, camera: &mut Camera, dt: f32) {
+        // any input takes over from a running transition
+        if self.has_input() {
+            self.transition = None;
+        }
+        if let Some(transition) = &mut self.transition {
+            transition.elapsed += dt;
+            let t = (transition.elapsed / self.transition_time.max(f32::EPSILON)).min(1.0);
+            // smoothstep
+            let eased = t * t * (3.0 - 2.0 * t);
+            camera.set_pose(transition.from.lerp(&transition.to, eased));
+            if t >= 1.0 {
+                self.transition = None;
+            }
+            return;
+        }
 
+        let fov_change = std::mem::take(&mut self.fov_change);
+        if fov_change != 0.0 {
+            camera.set_fovy(camera.fovy + fov_change);
+        }
+
+        let mut goal = self.goal.unwrap_or_else(|| camera.pose());
+        self.apply_input(&mut goal, camera.projection, dt);
+        self.apply_zoom(&mut goal, camera);
+        self.goal = Some(goal);
+
+        let alpha = if self.smoothing_time > 0.0 {
+            1.0 - (-dt / self.smoothing_time).exp()
+        } else {
+            1.0
+        };
+        camera.set_pose(camera.pose().lerp(&goal, alpha));
+    }
+
+    /// Zooms towards the point under the cursor: perspective cameras dolly along the cursor ray up to
+    /// [`Self::min_zoom_distance`] in front of the target, orthographic cameras shrink the visible area
+    /// while keeping that point in place.
+    fn apply_zoom(&mut self, pose: &mut CameraPose, camera: &mut Camera) {
+        let steps = std::mem::take(&mut self.scroll);
+        if steps == 0.0 {
+            return;
+        }
+        let (x, y) = self.last_mouse_position.unwrap_or((
+            self.viewport_size.0 * 0.5,
+            self.viewport_size.1 * 0.5,
+        ));
+        let ndc = Vec2::new(
+            (2.0 * x / self.viewport_size.0 - 1.0) as f32,
+            (1.0 - 2.0 * y / self.viewport_size.1) as f32,
+        );
+        let (origin, direction) = camera.ray_through(ndc);
+
+        match &mut camera.projection {
+            Projection::Perspective => {
+                pose.zoom_by(direction * steps * self.zoom_speed, self.min_zoom_distance)
+            }
+            Projection::Orthographic { height } => {
+                let scale = 0.9f32.powf(steps);
+                *height *= scale;
+                // offset of the cursor from the view center, in the view plane
+                let offset = (origin - camera.eye).reject_from(direction);
+                pose.move_by(offset * (1.0 - scale));
+            }
+        }
+    }
+
+    /// Moves the pose with the velocity of the movement keys and rotates it by the mouse movement since
+    /// the last update.
+    fn apply_input(&mut self, pose: &mut CameraPose, projection: Projection, dt: f32) {
+        let forward = (pose.target - pose.eye).normalize();
+        let right = forward.cross(pose.up).normalize();
+        let up = pose.up.normalize();
+
+        let mut direction = Vec3::ZERO;
         if self.is_forward_pressed {
-            camera.move_by(forward * self.speed);
+            direction += forward;
         }
         if self.is_backward_pressed {
-            camera.move_by(-forward * self.speed);
+            direction -= forward;
         }
         if self.is_right_pressed {
-            camera.move_by(right * self.speed);
+            direction += right;
         }
         if self.is_left_pressed {
-            camera.move_by(-right * self.speed);
+            direction -= right;
         }
         if self.is_up_pressed {
-            camera.move_by(up * self.speed);
+            direction += up;
         }
         if self.is_down_pressed {
-            camera.move_by(-up * self.speed);
+            direction -= up;
+        }
+        direction += right * self.analog_movement.x
+            + up * self.analog_movement.y
+            + forward * self.analog_movement.z;
+        let speed = if self.is_sprint_active {
+            self.speed * self.sprint_factor
+        } else {
+            self.speed
+        };
+        // exact solution of dv/dt = acceleration * (target - v), independent of the frame time
+        let target_velocity = direction.clamp_length_max(1.0) * speed;
+        let blend = (-self.acceleration.max(0.0) * dt).exp();
+        self.velocity = target_velocity + (self.velocity - target_velocity) * blend;
+        if direction == Vec3::ZERO && self.velocity.length() < self.speed * 1e-3 {
+            self.velocity = Vec3::ZERO;
         }
+        pose.move_by(self.velocity * dt);
 
         // Verhindere, dass die Kamera unter den Boden geht
-        if camera.eye.y <= 0.0 {
-            camera.eye.y = 0.1;
+        // (orthographic views from below are still allowed)
+        if projection == Projection::Perspective && pose.eye.y <= 0.0 {
+            pose.eye.y = 0.1;
         }
 
-        if self.is_mouse_pressed {
-            let delta_x = self.delta_x as f32 * self.sensitivity;
-            let delta_y = self.delta_y as f32 * self.sensitivity;
-
+        // dragging turns the scene with the mouse, the stick turns the camera
+        let look = self.analog_look * self.stick_look_speed * dt;
+        let delta_x = std::mem::take(&mut self.delta_x) as f32 * self.sensitivity - look.x;
+        let delta_y = std::mem::take(&mut self.delta_y) as f32 * self.sensitivity - look.y;
+        if delta_x != 0.0 || delta_y != 0.0 {
             let rotation_x = Mat3::from_rotation_y(delta_x.to_radians());
             let rotation_y = Mat3::from_axis_angle(right, -delta_y.to_radians());
 
+            // keep the focus distance, the mouse wheel zooms towards the target
+            let distance = pose.eye.distance(pose.target);
             let new_forward = rotation_y * rotation_x * forward;
-            camera.target = camera.eye + new_forward;
-            camera.up = rotation_y * rotation_x * camera.up;
+            pose.target = pose.eye + new_forward * distance;
+            pose.up = rotation_y * rotation_x * pose.up;
         }
     }
 }
-
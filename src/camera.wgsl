@@ -0,0 +1,13 @@
+// Camera uniform of the forward and shadow passes, see CameraUniform.
+#import bind_groups
+
+struct Camera {
+    view_proj: mat4x4<f32>,
+    position: vec4<f32>,
+    inv_view_proj: mat4x4<f32>,
+    // DebugView, 0 for the lit scene
+    debug_view: u32,
+};
+
+@group(CAMERA_GROUP) @binding(0)
+var<uniform> camera: Camera;
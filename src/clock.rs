@@ -0,0 +1,53 @@
+/*
+ * Frame clock.
+ * Animation (the rotating sun) and camera movement advance by the time the clock reports. In real time
+ * that is the wall-clock time between frames; the deterministic mode of `--deterministic` advances every
+ * frame by exactly the target frame time instead, so runs render the same frames regardless of how long
+ * they take.
+ */
+use instant::Instant;
+use std::time::Duration;
+
+pub enum FrameClock {
+    Realtime {
+        start: Instant,
+        last_frame: Instant,
+    },
+    Fixed {
+        /// Frames since the start.
+        frame: u32,
+    },
+}
+
+impl FrameClock {
+    pub fn new(deterministic: bool) -> Self {
+        if deterministic {
+            FrameClock::Fixed { frame: 0 }
+        } else {
+            let now = Instant::now();
+            FrameClock::Realtime {
+                start: now,
+                last_frame: now,
+            }
+        }
+    }
+
+    /// Advances to the next frame and returns the time since the start and the time since the previous
+    /// frame in seconds. `step` is the frame time of the deterministic mode.
+    pub fn tick(&mut self, step: Duration) -> (f32, f32) {
+        match self {
+            FrameClock::Realtime { start, last_frame } => {
+                let now = Instant::now();
+                let frame_time = (now - *last_frame).as_secs_f32();
+                *last_frame = now;
+                ((now - *start).as_secs_f32(), frame_time)
+            }
+            FrameClock::Fixed { frame } => {
+                let step = step.as_secs_f32();
+                let time = *frame as f32 * step;
+                *frame += 1;
+                (time, step)
+            }
+        }
+    }
+}
@@ -0,0 +1,292 @@
+/*
+ * Color-blind filters.
+ * A final pass over the frame that either simulates how it looks with a color vision deficiency, to check
+ * that the scene and the debug colors stay readable, or daltonizes it: the colors a deficiency can't tell
+ * apart are shifted towards ones it can. The simulation uses the matrices of Machado et al. 2009 for
+ * protanopia, deuteranopia and tritanopia, blended with the unchanged colors by the severity, and the
+ * daltonization moves the lost part of each color into the channels that remain visible. It is set with
+ * the `[color_blind]` table of the render settings and can be switched in the debug UI:
+ *
+ *     [color_blind]
+ *     deficiency = "deuteranopia"
+ *     filter = "simulate"
+ *     severity = 1.0
+ */
+use crate::custom_pass::{CustomPass, PassContext, PassStage};
+use crate::reflection::ReflectDevice;
+use crate::renderer::Pipeline;
+use glam::{Mat3, Vec3};
+use serde::Deserialize;
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Deficiency {
+    /// No red cones.
+    Protanopia,
+    /// No green cones, the most common deficiency.
+    #[default]
+    Deuteranopia,
+    /// No blue cones.
+    Tritanopia,
+}
+
+impl Deficiency {
+    pub const ALL: [Deficiency; 3] = [
+        Deficiency::Protanopia,
+        Deficiency::Deuteranopia,
+        Deficiency::Tritanopia,
+    ];
+
+    /// Simulation matrix for linear RGB at full severity, by rows.
+    fn simulation(self) -> [[f32; 3]; 3] {
+        match self {
+            Deficiency::Protanopia => [
+                [0.152286, 1.052583, -0.204868],
+                [0.114503, 0.786281, 0.099216],
+                [-0.003882, -0.048116, 1.051998],
+            ],
+            Deficiency::Deuteranopia => [
+                [0.367322, 0.860646, -0.227968],
+                [0.280085, 0.672501, 0.047413],
+                [-0.011820, 0.042940, 0.968881],
+            ],
+            Deficiency::Tritanopia => [
+                [1.255528, -0.076749, -0.178779],
+                [-0.078411, 0.930809, 0.147602],
+                [0.004733, 0.691367, 0.303900],
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorBlindFilter {
+    /// Shows the frame as it looks with the deficiency.
+    #[default]
+    Simulate,
+    /// Shifts the colors the deficiency confuses so they can be told apart.
+    Daltonize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ColorBlindSettings {
+    pub deficiency: Deficiency,
+    pub filter: ColorBlindFilter,
+    /// 0 leaves the colors unchanged, 1 is the full deficiency.
+    pub severity: f32,
+}
+
+impl Default for ColorBlindSettings {
+    fn default() -> Self {
+        Self {
+            deficiency: Deficiency::default(),
+            filter: ColorBlindFilter::default(),
+            severity: 1.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ColorBlindUniform {
+    /// Columns of the simulation matrix, padded like a `mat3x3` in WGSL.
+    simulation: [[f32; 4]; 3],
+    daltonize: u32,
+    _padding: [u32; 3],
+}
+
+impl ColorBlindUniform {
+    fn from_settings(settings: &ColorBlindSettings) -> Self {
+        let full = Mat3::from_cols_array_2d(&settings.deficiency.simulation()).transpose();
+        let severity = settings.severity.clamp(0.0, 1.0);
+        let simulation = Mat3::IDENTITY * (1.0 - severity) + full * severity;
+        let column = |column: Vec3| column.extend(0.0).to_array();
+        Self {
+            simulation: [
+                column(simulation.x_axis),
+                column(simulation.y_axis),
+                column(simulation.z_axis),
+            ],
+            daltonize: (settings.filter == ColorBlindFilter::Daltonize) as u32,
+            _padding: [0; 3],
+        }
+    }
+}
+
+/// Filters the frame with the color-blind settings of the renderer, nothing while they are None.
+pub struct ColorBlindPass {
+    pipeline: Pipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    uniform_buffer: wgpu::Buffer,
+    /// The settings in the uniform buffer.
+    uploaded: Option<ColorBlindSettings>,
+    /// Copy of the frame the pass reads from and its bind group, created for the size of the frame.
+    frame_copy: Option<(wgpu::Texture, wgpu::BindGroup)>,
+    /// Set once it was reported that the frame texture can't be copied.
+    reported: bool,
+}
+
+impl ColorBlindPass {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let bind_group_layout = device.reflect_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("color_blind_bind_group_layout"),
+        });
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Color Blind Buffer"),
+            size: std::mem::size_of::<ColorBlindUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let shader = device.reflect_shader(wgpu::ShaderModuleDescriptor {
+            label: Some("color_blind"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("color_blind.wgsl"))),
+        });
+        let pipeline = Pipeline::new(
+            device,
+            "color_blind_pipeline",
+            &shader,
+            &[&bind_group_layout],
+            "vs_fullscreen",
+            &[],
+            Some("fs_color_blind"),
+            &[Some(wgpu::ColorTargetState {
+                format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            None,
+            None,
+            None,
+            None,
+        );
+
+        Self {
+            pipeline,
+            bind_group_layout,
+            uniform_buffer,
+            uploaded: None,
+            frame_copy: None,
+            reported: false,
+        }
+    }
+
+    /// Creates the copy of the frame for a frame texture like `frame`, again when its size changed.
+    fn prepare_frame_copy(&mut self, device: &wgpu::Device, frame: &wgpu::Texture) {
+        // read through the sRGB view of the frame, like the passes draw into it
+        let format = frame.format().add_srgb_suffix();
+        let fits = self
+            .frame_copy
+            .as_ref()
+            .is_some_and(|(copy, _)| copy.size() == frame.size() && copy.format() == format);
+        if fits {
+            return;
+        }
+        let copy = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("color_blind_frame_copy"),
+            size: frame.size(),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(
+                        &copy.create_view(&Default::default()),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("color_blind_bind_group"),
+        });
+        self.frame_copy = Some((copy, bind_group));
+    }
+}
+
+impl CustomPass for ColorBlindPass {
+    /// After the HUD, so its colors are filtered too.
+    fn stage(&self) -> PassStage {
+        PassStage::Overlay
+    }
+
+    fn render(&mut self, context: PassContext) {
+        let Some(settings) = context.renderer.settings.color_blind else {
+            return;
+        };
+        let frame = context.color_texture;
+        if !frame.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+            if !std::mem::replace(&mut self.reported, true) {
+                println!("Color-blind filters are off, the frame texture can't be copied");
+            }
+            return;
+        }
+        if self.uploaded != Some(settings) {
+            context.renderer.queue.write_buffer(
+                &self.uniform_buffer,
+                0,
+                bytemuck::bytes_of(&ColorBlindUniform::from_settings(&settings)),
+            );
+            self.uploaded = Some(settings);
+        }
+        self.prepare_frame_copy(&context.renderer.device, frame);
+        let Some((copy, bind_group)) = &self.frame_copy else {
+            return;
+        };
+        context.encoder.copy_texture_to_texture(
+            frame.as_image_copy(),
+            copy.as_image_copy(),
+            frame.size(),
+        );
+
+        let mut rpass = context
+            .encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("color_blind_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: context.color,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+        rpass.set_pipeline(&self.pipeline.pipeline);
+        rpass.set_bind_group(0, bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
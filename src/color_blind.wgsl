@@ -0,0 +1,35 @@
+// Simulates or daltonizes the frame for a color vision deficiency, see color_blind.rs.
+struct ColorBlind {
+    // linear RGB as seen with the deficiency
+    simulation: mat3x3<f32>,
+    daltonize: u32,
+};
+
+@group(0) @binding(0) var t_frame: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> color_blind: ColorBlind;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+};
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // a single triangle covering the whole viewport
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_color_blind(in: VertexOutput) -> @location(0) vec4<f32> {
+    let frame = textureLoad(t_frame, vec2<i32>(in.position.xy), 0);
+    let simulated = color_blind.simulation * frame.rgb;
+    if (color_blind.daltonize == 0u) {
+        return vec4<f32>(simulated, frame.a);
+    }
+    // the part of the color the deficiency loses, moved into green and blue which stay visible
+    let lost = frame.rgb - simulated;
+    let shift = vec3<f32>(0.0, 0.7 * lost.r + lost.g, 0.7 * lost.r + lost.b);
+    return vec4<f32>(max(frame.rgb + shift, vec3<f32>(0.0)), frame.a);
+}
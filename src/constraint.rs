@@ -0,0 +1,110 @@
+/*
+ * Node constraints.
+ * A constraint sets the transform of a node from another node or the camera each frame, after the paths,
+ * light animations and the camera moved and before the transforms are uploaded: look-at turns the -Z axis
+ * of the node to the target, follow keeps the node at an offset from the target's position, attach also
+ * takes over its rotation like a child would, and billboard turns the +Z axis of the node to the camera.
+ * Spot lights aim at the look-at target instead of turning, their shadow faces it. Constraints are
+ * applied in the order they were added, so a node can follow a node that follows another one. In a
+ * scene file the constraint is declared next to the node:
+ *
+ *     "constraint": { "type": "look_at", "target": "camera" }
+ *     "constraint": { "type": "follow", "target": { "node": "drone" }, "offset": [0.0, 3.0, 0.0] }
+ */
+use crate::spline::orientation;
+use glam::{DMat4, DVec3, Vec3};
+use serde::{Deserialize, Serialize};
+
+/// What a constraint follows.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConstraintTarget {
+    Camera,
+    /// The node with this name, its origin for group and light nodes and the mesh center for render
+    /// nodes like [`SceneGraph::world_position`](crate::scenegraph::SceneGraph::world_position).
+    Node(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Constraint {
+    /// Turns the -Z axis of the node to the target, with +Y up.
+    LookAt { target: ConstraintTarget },
+    /// Moves the node to the position of the target plus `offset` in world space, keeps its rotation.
+    Follow {
+        target: ConstraintTarget,
+        #[serde(default)]
+        offset: Vec3,
+    },
+    /// Moves and turns the node with the target, `offset` is in the space of the target.
+    Attach {
+        target: ConstraintTarget,
+        #[serde(default)]
+        offset: Vec3,
+    },
+    /// Turns the +Z axis of the node to the camera, with +Y up, e.g. for sprites and labels.
+    Billboard,
+}
+
+impl Constraint {
+    pub fn target(&self) -> Option<&ConstraintTarget> {
+        match self {
+            Constraint::LookAt { target }
+            | Constraint::Follow { target, .. }
+            | Constraint::Attach { target, .. } => Some(target),
+            Constraint::Billboard => None,
+        }
+    }
+
+    /// World matrix of a node at `world` under the constraint. `target` is the world matrix of the target
+    /// with the position the constraint uses as translation, `eye` the camera position.
+    pub fn world_matrix(&self, world: DMat4, target: DMat4, eye: DVec3) -> DMat4 {
+        let (scale, rotation, translation) = world.to_scale_rotation_translation();
+        let target_position = target.w_axis.truncate();
+        let facing = |direction: DVec3| {
+            let rotation = orientation(direction.normalize_or_zero().as_vec3());
+            DMat4::from_scale_rotation_translation(scale, rotation.as_dquat(), translation)
+        };
+        match self {
+            Constraint::LookAt { .. } => facing(target_position - translation),
+            Constraint::Follow { offset, .. } => DMat4::from_scale_rotation_translation(
+                scale,
+                rotation,
+                target_position + offset.as_dvec3(),
+            ),
+            Constraint::Attach { offset, .. } => {
+                let (_, target_rotation, _) = target.to_scale_rotation_translation();
+                DMat4::from_scale_rotation_translation(
+                    scale,
+                    target_rotation,
+                    target_position + target_rotation * offset.as_dvec3(),
+                )
+            }
+            // -Z away from the camera turns +Z to it
+            Constraint::Billboard => facing(translation - eye),
+        }
+    }
+}
+
+/// A constraint of the node named `node`, applied by
+/// [`SceneGraph::apply_constraints`](crate::scenegraph::SceneGraph::apply_constraints).
+#[derive(Debug, Clone)]
+pub struct NodeConstraint {
+    pub node: String,
+    pub constraint: Constraint,
+}
+
+impl NodeConstraint {
+    pub fn new(node: &str, constraint: Constraint) -> Self {
+        Self {
+            node: node.to_string(),
+            constraint,
+        }
+    }
+}
+
+/// World matrix of the camera at `eye` looking at `target`.
+pub fn camera_matrix(eye: Vec3, target: Vec3) -> DMat4 {
+    let rotation = orientation((target - eye).normalize_or_zero());
+    DMat4::from_rotation_translation(rotation.as_dquat(), eye.as_dvec3())
+}
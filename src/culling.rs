@@ -0,0 +1,92 @@
+/*
+ * Bounding boxes and view frustum tests.
+ * Render nodes keep the bounds of their mesh in local space, the draw list transforms them into
+ * world space and skips nodes outside of the frustum of the pass.
+ */
+use glam::{Mat3, Mat4, Vec3, Vec4};
+
+/// Axis aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// Bounds of the points, a single point at the origin if there are none.
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self {
+        let mut points = points.into_iter();
+        let Some(first) = points.next() else {
+            return Self {
+                min: Vec3::ZERO,
+                max: Vec3::ZERO,
+            };
+        };
+        points.fold(
+            Self {
+                min: first,
+                max: first,
+            },
+            |aabb, point| Self {
+                min: aabb.min.min(point),
+                max: aabb.max.max(point),
+            },
+        )
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn union(&self, other: &Aabb) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    /// Bounds of the transformed box, which are larger than the box if the matrix rotates it.
+    pub fn transform(&self, matrix: Mat4) -> Self {
+        let center = matrix.transform_point3(self.center());
+        let half_extents = (self.max - self.min) * 0.5;
+        let linear = Mat3::from_mat4(matrix);
+        let extents = linear.x_axis.abs() * half_extents.x
+            + linear.y_axis.abs() * half_extents.y
+            + linear.z_axis.abs() * half_extents.z;
+        Self {
+            min: center - extents,
+            max: center + extents,
+        }
+    }
+}
+
+/// Side and far planes of a view projection, pointing inwards.
+/// The near plane is left out: pipelines use unclipped depth where the adapter supports it, so
+/// geometry in front of the near plane still ends up in the image, e.g. as shadow casters.
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    planes: [Vec4; 5],
+}
+
+impl Frustum {
+    pub fn from_view_proj(view_proj: Mat4) -> Self {
+        let x = view_proj.row(0);
+        let y = view_proj.row(1);
+        let z = view_proj.row(2);
+        let w = view_proj.row(3);
+        // depth runs from 0 to 1 in wgpu, so the far plane is z <= w
+        let planes =
+            [w + x, w - x, w + y, w - y, w - z].map(|plane| plane / plane.truncate().length());
+        Self { planes }
+    }
+
+    /// False if the box lies completely outside of one of the planes.
+    pub fn intersects(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            // corner of the box furthest along the plane normal
+            let corner = Vec3::select(normal.cmpge(Vec3::ZERO), aabb.max, aabb.min);
+            normal.dot(corner) + plane.w >= 0.0
+        })
+    }
+}
@@ -0,0 +1,235 @@
+/*
+ * Custom material shaders.
+ * A render node can be drawn with a WGSL fragment shader and a bind group of its own instead of its MTL
+ * material, e.g. an animated lava surface with a time uniform. The source is appended to shader.wgsl and
+ * pbr.wgsl, so the fragment entry receives the `VertexOutput` of `vs_main` and can use the camera, model
+ * and light bindings at their usual groups along with the lighting and shadow functions. It can `#import`
+ * the snippets of shader_compose.rs as well. `MATERIAL_GROUP` has the bind group layout given with the
+ * shader, its variables need names of their own.
+ * Custom materials are drawn by the forward passes; the multiview stereo, glass and pick passes skip them.
+ * The materials of scene files (see scene.rs) share a bind group with the seconds since the start:
+ *
+ *     struct LavaTime { seconds: f32 };
+ *     @group(MATERIAL_GROUP) @binding(0) var<uniform> lava_time: LavaTime;
+ */
+use crate::bind_groups;
+use crate::error::{validate, RendererError};
+use crate::light::ShadowMode;
+use crate::model::{Tangent, Vertex};
+use crate::reflection::ReflectDevice;
+use crate::renderer::{Pipeline, PipelineKey, PipelineVariants};
+use crate::scenegraph::InstanceRaw;
+use crate::shader_compose;
+use crate::texture;
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+
+pub struct CustomMaterial {
+    pub name: String,
+    pipeline: PipelineVariants,
+    /// The pipeline for the MSAA target, if the renderer has one.
+    multisampled_pipeline: Option<PipelineVariants>,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl CustomMaterial {
+    /// The pipelines for a target with `sample_count` samples.
+    pub fn pipeline(&self, sample_count: u32) -> &PipelineVariants {
+        match &self.multisampled_pipeline {
+            Some(pipeline) if sample_count > 1 => pipeline,
+            _ => &self.pipeline,
+        }
+    }
+}
+
+/// Uniform of the materials of scene files, padded to 16 bytes.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct SceneMaterialUniform {
+    seconds: f32,
+    _padding: [f32; 3],
+}
+
+/// The custom materials of the renderer, render nodes refer to them by index.
+pub struct CustomMaterials {
+    /// Camera, model and light layouts, the material layout is replaced by the one of each material.
+    bind_group_layouts: [wgpu::BindGroupLayout; 4],
+    color_target: wgpu::ColorTargetState,
+    shadow_mode: ShadowMode,
+    msaa_sample_count: u32,
+    /// See [`CustomMaterials::set_shadows`], also applies to materials added later.
+    shadows: bool,
+    /// Layout and buffer of [`SceneMaterialUniform`], created with the first material of a scene file.
+    scene_uniform: Option<(wgpu::BindGroupLayout, wgpu::Buffer)>,
+    pub materials: Vec<CustomMaterial>,
+}
+
+impl CustomMaterials {
+    pub fn new(
+        bind_group_layouts: [wgpu::BindGroupLayout; 4],
+        color_target: wgpu::ColorTargetState,
+        shadow_mode: ShadowMode,
+        msaa_sample_count: u32,
+    ) -> Self {
+        Self {
+            bind_group_layouts,
+            color_target,
+            shadow_mode,
+            msaa_sample_count,
+            shadows: true,
+            scene_uniform: None,
+            materials: Vec::new(),
+        }
+    }
+
+    /// Adds a material drawn by `fragment_entry` of the WGSL `source`, with `bind_group` of
+    /// `bind_group_layout` at [`bind_groups::MATERIAL`]. Returns its index for
+    /// [`crate::scenegraph::SceneGraph::set_custom_material`].
+    pub fn add(
+        &mut self,
+        device: &wgpu::Device,
+        name: &str,
+        source: &str,
+        fragment_entry: &str,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        bind_group: wgpu::BindGroup,
+    ) -> Result<usize, RendererError> {
+        let shader = validate(
+            device,
+            || format!("custom material \"{name}\" shader"),
+            || {
+                device.reflect_shader(wgpu::ShaderModuleDescriptor {
+                    label: Some(name),
+                    source: wgpu::ShaderSource::Wgsl(Cow::Owned(shader_compose::compose(
+                        &[
+                            include_str!("shader.wgsl"),
+                            include_str!("pbr.wgsl"),
+                            source,
+                        ],
+                        self.shadow_mode,
+                    ))),
+                })
+            },
+        )?;
+        let mut bind_group_layouts = self.bind_group_layouts.clone();
+        bind_group_layouts[bind_groups::MATERIAL as usize] = bind_group_layout.clone();
+        let color_target = self.color_target.clone();
+        let label = format!("custom material \"{name}\"");
+        let fragment_entry = fragment_entry.to_string();
+        let mut pipeline = validate(
+            device,
+            || format!("custom material \"{name}\" pipeline"),
+            || {
+                PipelineVariants::new(
+                    device,
+                    Default::default(),
+                    move |device, bias, key, multisample| {
+                        Pipeline::new_variant(
+                            device,
+                            &format!("{label} {:?} {}x", key.shading, multisample.count),
+                            &shader,
+                            &bind_group_layouts.each_ref(),
+                            "vs_main",
+                            &[Vertex::desc(), InstanceRaw::desc(), Tangent::desc()],
+                            Some(&fragment_entry),
+                            &[Some(color_target.clone())],
+                            Some(texture::Texture::DEPTH_FORMAT),
+                            Some(bias),
+                            Some(multisample),
+                            None,
+                            Some(key),
+                        )
+                    },
+                )
+            },
+        )?;
+        pipeline.set_shadows(self.shadows);
+        let multisampled_pipeline = (self.msaa_sample_count > 1)
+            .then(|| pipeline.multisampled(device, self.msaa_sample_count));
+        self.materials.push(CustomMaterial {
+            name: name.to_string(),
+            pipeline,
+            multisampled_pipeline,
+            bind_group,
+        });
+        Ok(self.materials.len() - 1)
+    }
+
+    /// Adds a material of a scene file, its bind group holds the seconds since the start.
+    pub fn add_scene_material(
+        &mut self,
+        device: &wgpu::Device,
+        name: &str,
+        source: &str,
+        fragment_entry: &str,
+    ) -> Result<usize, RendererError> {
+        let (layout, buffer) = self.scene_uniform.get_or_insert_with(|| {
+            let layout = device.reflect_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("scene_material_bind_group_layout"),
+            });
+            let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Scene Material Buffer"),
+                contents: bytemuck::cast_slice(&[SceneMaterialUniform {
+                    seconds: 0.0,
+                    _padding: [0.0; 3],
+                }]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+            (layout, buffer)
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            label: Some(name),
+        });
+        let layout = layout.clone();
+        self.add(device, name, source, fragment_entry, &layout, bind_group)
+    }
+
+    /// Updates the time of the materials of scene files.
+    pub fn set_time(&self, queue: &wgpu::Queue, seconds: f32) {
+        if let Some((_, buffer)) = &self.scene_uniform {
+            queue.write_buffer(
+                buffer,
+                0,
+                bytemuck::cast_slice(&[SceneMaterialUniform {
+                    seconds,
+                    _padding: [0.0; 3],
+                }]),
+            );
+        }
+    }
+
+    /// See [`PipelineVariants::set_shadows`].
+    pub fn set_shadows(&mut self, enabled: bool) {
+        self.shadows = enabled;
+        for material in &mut self.materials {
+            material.pipeline.set_shadows(enabled);
+            if let Some(pipeline) = &mut material.multisampled_pipeline {
+                pipeline.set_shadows(enabled);
+            }
+        }
+    }
+
+    pub fn prepare(&mut self, device: &wgpu::Device, keys: &[PipelineKey]) {
+        for material in &mut self.materials {
+            material.pipeline.prepare(device, keys.iter().copied());
+            if let Some(pipeline) = &mut material.multisampled_pipeline {
+                pipeline.prepare(device, keys.iter().copied());
+            }
+        }
+    }
+}
@@ -0,0 +1,43 @@
+/*
+ * Custom render passes.
+ * Effects that need a pass of their own are added with `Renderer::add_pass` instead of changes to
+ * `App::draw`. A pass chooses the stage of the frame it runs at and records into the encoder of the frame,
+ * with the renderer at hand for the device, the scene graph and its draw helpers (`DrawScenegraph` on a
+ * render pass), the camera and light bind groups and the shadow maps. Passes run in the order they were
+ * added. The demo adds the depth of field pass of `depth_of_field.rs`, the auto exposure pass of
+ * `exposure.rs` and the stylization pass of `stylize.rs` when the render settings ask for them.
+ */
+use crate::renderer::Renderer;
+
+/// Where in the frame a [`CustomPass`] runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PassStage {
+    /// After the shadow maps are rendered and blurred, before the forward pass.
+    AfterShadows,
+    /// After the forward pass and the depth visualization, with the depth of the forward pass.
+    AfterForward,
+    /// After the HUD, on top of everything else.
+    Overlay,
+}
+
+#[allow(dead_code)]
+pub struct PassContext<'a> {
+    pub renderer: &'a Renderer,
+    pub encoder: &'a mut wgpu::CommandEncoder,
+    /// The frame texture, in the format of `Renderer::frame_format`.
+    pub color: &'a wgpu::TextureView,
+    /// The texture of `color`. It can be copied from where the surface allows it, see
+    /// [`wgpu::Texture::usage`].
+    pub color_texture: &'a wgpu::Texture,
+    /// Depth of the forward pass in [`crate::texture::Texture::DEPTH_FORMAT`], only at
+    /// [`PassStage::AfterForward`] and only while the frame is rendered without stereo.
+    pub depth: Option<&'a wgpu::TextureView>,
+    /// Samples per pixel of `depth`, more than 1 while MSAA is on.
+    pub depth_sample_count: u32,
+}
+
+pub trait CustomPass {
+    fn stage(&self) -> PassStage;
+
+    fn render(&mut self, context: PassContext);
+}
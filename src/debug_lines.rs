@@ -0,0 +1,222 @@
+/*
+ * Debug lines.
+ * Draws the world-space bounds of the render nodes, the boxes the draw lists cull against the view
+ * frustum, the frusta of the shadow map layers of the lights and the spline paths nodes follow as
+ * wireframes on top of the frame. They show why a node is culled, how much of the scene the shadow maps
+ * cover and where animated nodes go. The lines ignore the depth of the scene, so boxes hidden behind
+ * geometry are drawn as well.
+ */
+use crate::reflection::ReflectDevice;
+use crate::scenegraph::SceneGraph;
+use crate::shader_cache;
+use glam::Vec3;
+use std::borrow::Cow;
+
+const BOUNDS_COLOR: [f32; 3] = [0.2, 1.0, 0.2];
+const SHADOW_FRUSTUM_COLOR: [f32; 3] = [1.0, 0.6, 0.1];
+const PATH_COLOR: [f32; 3] = [0.2, 0.8, 1.0];
+/// Corners of the edges of a box whose corners are numbered with a bit each for the x, y and z side.
+const BOX_EDGES: [(usize, usize); 12] = [
+    (0, 1),
+    (2, 3),
+    (4, 5),
+    (6, 7),
+    (0, 2),
+    (1, 3),
+    (4, 6),
+    (5, 7),
+    (0, 4),
+    (1, 5),
+    (2, 6),
+    (3, 7),
+];
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct LineVertex {
+    position: [f32; 3],
+    color: [f32; 3],
+}
+
+impl LineVertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 2] =
+        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: size_of::<LineVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+pub struct DebugLines {
+    pub enabled: bool,
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    /// Number of vertices that fit into the vertex buffer.
+    capacity: usize,
+}
+
+impl DebugLines {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let shader = device.reflect_shader(wgpu::ShaderModuleDescriptor {
+            label: Some("debug_lines"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("debug_lines.wgsl"))),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("debug_lines_pipeline_layout"),
+            bind_group_layouts: &[camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("debug_lines_pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_line"),
+                compilation_options: Default::default(),
+                buffers: &[LineVertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_line"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: shader_cache::pipeline_cache(device).as_ref(),
+        });
+        let capacity = BOX_EDGES.len() * 2;
+
+        Self {
+            enabled: false,
+            pipeline,
+            vertex_buffer: Self::create_vertex_buffer(device, capacity),
+            capacity,
+        }
+    }
+
+    fn create_vertex_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("debug_lines_vertex_buffer"),
+            size: (capacity * size_of::<LineVertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Draws the lines of `scene_graph` over `view`, seen through the camera of `camera_bind_group`.
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        scene_graph: &SceneGraph,
+        camera_bind_group: &wgpu::BindGroup,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        let vertices = Self::lines(scene_graph);
+        if vertices.is_empty() {
+            return;
+        }
+        if vertices.len() > self.capacity {
+            self.capacity = vertices.len().next_power_of_two();
+            self.vertex_buffer = Self::create_vertex_buffer(device, self.capacity);
+        }
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("debug_lines_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, camera_bind_group, &[]);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.draw(0..vertices.len() as u32, 0..1);
+    }
+
+    /// Line list of the render node bounds, the shadow frusta and the paths, relative to the render
+    /// origin like the camera.
+    fn lines(scene_graph: &SceneGraph) -> Vec<LineVertex> {
+        let origin = scene_graph.render_origin();
+        let mut vertices = Vec::new();
+        for bounds in scene_graph.render_node_bounds() {
+            let corners = box_corners(|corner| {
+                Vec3::select(corner.cmpgt(Vec3::ZERO), bounds.max, bounds.min) - origin
+            });
+            push_box(&mut vertices, corners, BOUNDS_COLOR);
+        }
+        for view_proj in scene_graph.shadow_frusta() {
+            let inverse = view_proj.inverse();
+            // depth runs from 0 to 1 in wgpu
+            let corners = box_corners(|corner| {
+                inverse.project_point3(Vec3::new(
+                    corner.x * 2.0 - 1.0,
+                    corner.y * 2.0 - 1.0,
+                    corner.z,
+                ))
+            });
+            push_box(&mut vertices, corners, SHADOW_FRUSTUM_COLOR);
+        }
+        for curve in scene_graph.path_curves() {
+            for segment in curve.windows(2) {
+                for point in segment {
+                    vertices.push(LineVertex {
+                        position: (*point - origin).to_array(),
+                        color: PATH_COLOR,
+                    });
+                }
+            }
+        }
+        vertices
+    }
+}
+
+/// The corners of a box, `corner` maps the corners of the unit cube to them.
+fn box_corners(corner: impl Fn(Vec3) -> Vec3) -> [Vec3; 8] {
+    std::array::from_fn(|index| {
+        corner(Vec3::new(
+            (index & 1) as f32,
+            (index >> 1 & 1) as f32,
+            (index >> 2 & 1) as f32,
+        ))
+    })
+}
+
+fn push_box(vertices: &mut Vec<LineVertex>, corners: [Vec3; 8], color: [f32; 3]) {
+    for (start, end) in BOX_EDGES {
+        for corner in [corners[start], corners[end]] {
+            vertices.push(LineVertex {
+                position: corner.to_array(),
+                color,
+            });
+        }
+    }
+}
@@ -0,0 +1,519 @@
+/*
+ * Debug UI.
+ * An egui overlay for inspecting and tweaking the renderer while it runs: frame rate, camera position,
+ * light colors and positions, the shadow maps with their statistics, color-blind filters and the scene
+ * tree. It is drawn in a pass of its own after the HUD and sees the window events first while it is
+ * shown, so dragging a slider doesn't also turn the camera. F5 toggles it, it is built with the
+ * `debug-ui` feature.
+ */
+use crate::camera::RenderMode;
+use crate::color_blind::{ColorBlindFilter, ColorBlindSettings, Deficiency};
+use crate::light::{FogMode, LightKind};
+use crate::renderer::Renderer;
+use crate::resources::with_resource_cache;
+use crate::scenegraph::{MaterialOverride, Node, SortPolicy};
+use crate::shadow_readback::read_back_light_shadow;
+use std::path::Path;
+use std::sync::Arc;
+use winit::event::WindowEvent;
+use winit::window::Window;
+
+/// Range of the light position sliders in world units.
+const LIGHT_POSITION_RANGE: f32 = 50.0;
+
+pub struct DebugUi {
+    pub visible: bool,
+    window: Arc<Window>,
+    context: egui::Context,
+    state: egui_winit::State,
+    renderer: egui_wgpu::Renderer,
+}
+
+impl DebugUi {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, window: Arc<Window>) -> Self {
+        let context = egui::Context::default();
+        let state = egui_winit::State::new(
+            context.clone(),
+            egui::ViewportId::ROOT,
+            &*window,
+            Some(window.scale_factor() as f32),
+            None,
+            Some(device.limits().max_texture_dimension_2d as usize),
+        );
+        let renderer = egui_wgpu::Renderer::new(device, format, None, 1, false);
+        Self {
+            visible: false,
+            window,
+            context,
+            state,
+            renderer,
+        }
+    }
+
+    /// Passes `event` to the UI while it is shown. Returns true if the UI used it, e.g. a click on a
+    /// button, and the application should ignore it.
+    pub fn on_window_event(&mut self, event: &WindowEvent) -> bool {
+        self.visible && self.state.on_window_event(&self.window, event).consumed
+    }
+
+    /// Builds the UI from the state of `renderer`, applies the changes made in it and draws it over `view`.
+    pub fn render(
+        &mut self,
+        renderer: &mut Renderer,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        frame_time: f32,
+    ) {
+        if !self.visible {
+            return;
+        }
+        let input = self.state.take_egui_input(&self.window);
+        let output = self.context.run(input, |context| {
+            egui::Window::new("Debug")
+                .default_width(320.0)
+                .show(context, |ui| Self::ui(ui, renderer, frame_time));
+        });
+        self.state
+            .handle_platform_output(&self.window, output.platform_output);
+
+        let paint_jobs = self
+            .context
+            .tessellate(output.shapes, output.pixels_per_point);
+        let screen = egui_wgpu::ScreenDescriptor {
+            size_in_pixels: [
+                renderer.surface_config.width,
+                renderer.surface_config.height,
+            ],
+            pixels_per_point: output.pixels_per_point,
+        };
+        for (id, image_delta) in &output.textures_delta.set {
+            self.renderer
+                .update_texture(&renderer.device, &renderer.queue, *id, image_delta);
+        }
+        let callback_buffers = self.renderer.update_buffers(
+            &renderer.device,
+            &renderer.queue,
+            encoder,
+            &paint_jobs,
+            &screen,
+        );
+        renderer.queue.submit(callback_buffers);
+
+        let mut rpass = encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("debug_ui_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            })
+            .forget_lifetime();
+        self.renderer.render(&mut rpass, &paint_jobs, &screen);
+        drop(rpass);
+
+        for id in &output.textures_delta.free {
+            self.renderer.free_texture(id);
+        }
+    }
+
+    fn ui(ui: &mut egui::Ui, renderer: &mut Renderer, frame_time: f32) {
+        ui.label(format!(
+            "{:.0} FPS, {:.2} ms",
+            1.0 / frame_time.max(f32::EPSILON),
+            frame_time * 1000.0
+        ));
+        let camera = &renderer.camera_state.camera;
+        ui.label(format!(
+            "Camera at {:.2}, looking at {:.2}",
+            camera.eye, camera.target
+        ));
+        let mut render_mode = renderer.render_mode;
+        egui::ComboBox::from_label("render mode")
+            .selected_text(format!("{render_mode:?}"))
+            .show_ui(ui, |ui| {
+                for mode in RenderMode::ALL {
+                    ui.selectable_value(&mut render_mode, mode, format!("{mode:?}"));
+                }
+            });
+        if render_mode != renderer.render_mode {
+            renderer.set_render_mode(render_mode);
+        }
+
+        egui::CollapsingHeader::new("Lights")
+            .default_open(true)
+            .show(ui, |ui| {
+                let mut ambient = renderer.scene_graph.ambient();
+                let mut ambient_changed = false;
+                ui.horizontal(|ui| {
+                    ambient_changed |= ui.color_edit_button_rgb(&mut ambient.sky).changed();
+                    ambient_changed |= ui.color_edit_button_rgb(&mut ambient.ground).changed();
+                    ui.label("ambient sky and ground");
+                });
+                let strength = egui::Slider::new(&mut ambient.strength, 0.0..=1.0).text("ambient");
+                ambient_changed |= ui.add(strength).changed();
+                if ambient_changed {
+                    renderer.scene_graph.set_hemisphere_ambient(
+                        ambient.sky,
+                        ambient.ground,
+                        ambient.strength,
+                    );
+                }
+
+                let mut fog = renderer.scene_graph.fog();
+                let mut fog_changed = false;
+                ui.horizontal(|ui| {
+                    fog_changed |= ui.color_edit_button_rgb(&mut fog.color).changed();
+                    egui::ComboBox::from_label("fog")
+                        .selected_text(format!("{:?}", fog.mode))
+                        .show_ui(ui, |ui| {
+                            for mode in [FogMode::Off, FogMode::Linear, FogMode::Exponential] {
+                                fog_changed |= ui
+                                    .selectable_value(&mut fog.mode, mode, format!("{mode:?}"))
+                                    .changed();
+                            }
+                        });
+                });
+                match fog.mode {
+                    FogMode::Off => {}
+                    FogMode::Linear => {
+                        let start =
+                            egui::Slider::new(&mut fog.start, 0.0..=200.0).text("fog start");
+                        fog_changed |= ui.add(start).changed();
+                        let end = egui::Slider::new(&mut fog.end, 0.0..=200.0).text("fog end");
+                        fog_changed |= ui.add(end).changed();
+                    }
+                    FogMode::Exponential => {
+                        let density =
+                            egui::Slider::new(&mut fog.density, 0.0..=0.2).text("fog density");
+                        fog_changed |= ui.add(density).changed();
+                    }
+                }
+                if fog_changed {
+                    renderer.scene_graph.set_fog(fog);
+                }
+
+                let map_sizes = [LightKind::Spot, LightKind::Point]
+                    .map(|kind| renderer.scene_graph.shadow_map_for(kind).size);
+                let mut inspect = None;
+                // the next sync uploads the lights that changed
+                renderer.scene_graph.root.visit_mut(&mut |node| {
+                    let name = node.name().to_string();
+                    let Node::LightNode(light_node) = node else {
+                        return;
+                    };
+                    let light = &mut light_node.light;
+                    ui.push_id(&name, |ui| {
+                        ui.label(format!("{name} ({:?})", light.kind));
+                        // light colors are linear RGB
+                        let color = light.color();
+                        let mut rgb = [color.r as f32, color.g as f32, color.b as f32];
+                        ui.horizontal(|ui| {
+                            if ui.color_edit_button_rgb(&mut rgb).changed() {
+                                let [r, g, b] = rgb.map(f64::from);
+                                light.set_color(wgpu::Color { r, g, b, ..color });
+                            }
+                            ui.label("color");
+                        });
+                        let intensity = egui::Slider::new(&mut light.intensity, 0.0..=100.0)
+                            .logarithmic(true)
+                            .text("intensity");
+                        ui.add(intensity);
+                        let range = egui::Slider::new(&mut light.range, 0.0..=100.0)
+                            .text("range (0 without falloff)");
+                        ui.add(range);
+                        if light.shadow_layer.is_some() {
+                            ui.horizontal(|ui| {
+                                ui.checkbox(&mut light.cast_shadows, "cast shadows");
+                                if ui.button("inspect shadow map").clicked() {
+                                    inspect = Some(name.clone());
+                                }
+                            });
+                            let map_size = map_sizes[light.kind as usize];
+                            let text = |resolution: Option<u32>| match resolution {
+                                Some(resolution) => format!("{resolution}px"),
+                                None => format!("full ({map_size}px)"),
+                            };
+                            egui::ComboBox::from_label("shadow resolution")
+                                .selected_text(text(light.shadow_resolution))
+                                .show_ui(ui, |ui| {
+                                    let sizes = (8..12).map(|exponent| 1 << exponent);
+                                    let options = std::iter::once(None)
+                                        .chain(sizes.filter(|&size| size < map_size).map(Some));
+                                    for resolution in options {
+                                        ui.selectable_value(
+                                            &mut light.shadow_resolution,
+                                            resolution,
+                                            text(resolution),
+                                        );
+                                    }
+                                });
+                        }
+                        for (axis, value) in ["x", "y", "z"].into_iter().zip(light.pos.as_mut()) {
+                            let slider = egui::Slider::new(
+                                value,
+                                -LIGHT_POSITION_RANGE..=LIGHT_POSITION_RANGE,
+                            )
+                            .text(axis);
+                            ui.add(slider);
+                        }
+                    });
+                });
+                if let Some(name) = inspect {
+                    inspect_shadow_map(renderer, &name);
+                }
+            });
+
+        egui::CollapsingHeader::new("Shadows").show(ui, |ui| {
+            let mut shadows = renderer.scene_graph.shadows_enabled();
+            if ui.checkbox(&mut shadows, "shadows").changed() {
+                renderer.set_shadows_enabled(shadows);
+            }
+            for kind in [LightKind::Spot, LightKind::Point] {
+                let shadow_map = renderer.scene_graph.shadow_map_for(kind);
+                ui.label(format!(
+                    "{kind:?} shadow map: {} layers of {}px, {:?}",
+                    shadow_map.layers, shadow_map.size, shadow_map.mode
+                ));
+            }
+            if let Some(gaussian_pass) = &renderer.gaussian_pass {
+                ui.label(format!(
+                    "Blur radius: {} texels, workgroups of {}x{1}",
+                    renderer.settings.blur_radius, gaussian_pass.workgroup_size
+                ));
+                ui.checkbox(
+                    &mut renderer.settings.blur_moved_lights_only,
+                    "only draw and blur moved lights",
+                );
+            }
+            egui::ComboBox::from_label("Caster order")
+                .selected_text(format!("{:?}", renderer.shadow_sort_policy))
+                .show_ui(ui, |ui| {
+                    for policy in [SortPolicy::State, SortPolicy::Depth] {
+                        ui.selectable_value(
+                            &mut renderer.shadow_sort_policy,
+                            policy,
+                            format!("{policy:?}"),
+                        );
+                    }
+                });
+            for stats in &renderer.shadow_stats {
+                ui.label(stats.hud_line());
+            }
+        });
+
+        if !renderer.scene_graph.paths.is_empty() {
+            egui::CollapsingHeader::new("Paths").show(ui, |ui| {
+                for (index, animation) in renderer.scene_graph.paths.iter_mut().enumerate() {
+                    ui.push_id(index, |ui| {
+                        ui.label(format!(
+                            "{} ({:.1} units)",
+                            animation.node,
+                            animation.path.length()
+                        ));
+                        ui.add(egui::Slider::new(&mut animation.speed, 0.0..=50.0).text("speed"));
+                        ui.checkbox(&mut animation.orient, "face along the path");
+                    });
+                }
+            });
+        }
+
+        if !renderer.scene_graph.light_animations.is_empty() {
+            egui::CollapsingHeader::new("Light animations").show(ui, |ui| {
+                let animations = renderer.scene_graph.light_animations.iter_mut();
+                for (index, animation) in animations.enumerate() {
+                    ui.push_id(index, |ui| {
+                        ui.label(&animation.node);
+                        let tracks = &mut animation.tracks;
+                        if let Some(orbit) = &mut tracks.orbit {
+                            ui.add(
+                                egui::Slider::new(&mut orbit.speed, -3.0..=3.0).text("orbit speed"),
+                            );
+                            ui.add(
+                                egui::Slider::new(&mut orbit.radius, 0.0..=60.0)
+                                    .text("orbit radius"),
+                            );
+                        }
+                        if let Some(cycle) = &mut tracks.color_cycle {
+                            ui.add(
+                                egui::Slider::new(&mut cycle.period, 1.0..=120.0)
+                                    .text("color period"),
+                            );
+                        }
+                        if let Some(flicker) = &mut tracks.flicker {
+                            ui.add(
+                                egui::Slider::new(&mut flicker.amount, 0.0..=1.0).text("flicker"),
+                            );
+                            ui.add(
+                                egui::Slider::new(&mut flicker.speed, 0.0..=30.0)
+                                    .text("flicker speed"),
+                            );
+                        }
+                    });
+                }
+            });
+        }
+
+        egui::CollapsingHeader::new("Color vision").show(ui, |ui| {
+            let color_blind = &mut renderer.settings.color_blind;
+            let mut enabled = color_blind.is_some();
+            if ui.checkbox(&mut enabled, "color-blind filter").changed() {
+                *color_blind = enabled.then(ColorBlindSettings::default);
+            }
+            if let Some(settings) = color_blind {
+                egui::ComboBox::from_label("deficiency")
+                    .selected_text(format!("{:?}", settings.deficiency))
+                    .show_ui(ui, |ui| {
+                        for deficiency in Deficiency::ALL {
+                            ui.selectable_value(
+                                &mut settings.deficiency,
+                                deficiency,
+                                format!("{deficiency:?}"),
+                            );
+                        }
+                    });
+                ui.horizontal(|ui| {
+                    for filter in [ColorBlindFilter::Simulate, ColorBlindFilter::Daltonize] {
+                        ui.radio_value(&mut settings.filter, filter, format!("{filter:?}"));
+                    }
+                });
+                ui.add(egui::Slider::new(&mut settings.severity, 0.0..=1.0).text("severity"));
+            }
+        });
+
+        egui::CollapsingHeader::new("Scene").show(ui, |ui| {
+            let scene_graph = &mut renderer.scene_graph;
+            let mut groups = Vec::new();
+            scene_graph.root.visit(&mut |node| {
+                if let Node::GroupNode(_) = node {
+                    groups.push(node.name().to_string());
+                }
+            });
+            let mut edit = None;
+            egui::ScrollArea::vertical()
+                .max_height(300.0)
+                .show(ui, |ui| {
+                    scene_tree(ui, &mut scene_graph.root, true, &groups, &mut edit)
+                });
+            let removed = matches!(edit, Some(SceneEdit::Remove(_)));
+            let result = match edit {
+                Some(SceneEdit::Remove(name)) => scene_graph.remove_node(&name),
+                Some(SceneEdit::Move { name, parent }) => {
+                    scene_graph.move_node(&name, Some(&parent))
+                }
+                None => Ok(()),
+            };
+            match result {
+                Ok(()) if removed => {
+                    // the models of the removed nodes are not shared with other nodes anymore
+                    let models = scene_graph.model_groups();
+                    let used = models.iter().map(|(_, source, _)| source.as_str());
+                    with_resource_cache(&renderer.device, |cache| cache.evict_unused_models(used));
+                }
+                Ok(()) => {}
+                Err(e) => println!("{e}"),
+            }
+        });
+    }
+}
+
+/// A change of the scene tree picked in the UI, applied after the tree is built.
+enum SceneEdit {
+    Remove(String),
+    Move { name: String, parent: String },
+}
+
+/// The node and its children as a tree of collapsible groups, each group with a toggle to draw it in clay.
+/// Nodes below the root can be removed or moved into one of the `groups`.
+fn scene_tree(
+    ui: &mut egui::Ui,
+    node: &mut Node,
+    is_root: bool,
+    groups: &[String],
+    edit: &mut Option<SceneEdit>,
+) {
+    let name = node.name().to_string();
+    match node {
+        Node::GroupNode(group) => {
+            egui::CollapsingHeader::new(&name).show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    let mut clay = group.material_override == Some(MaterialOverride::Clay);
+                    if ui.checkbox(&mut clay, "clay").changed() {
+                        group.material_override = clay.then_some(MaterialOverride::Clay);
+                    }
+                    if !is_root {
+                        edit_buttons(ui, &name, groups, edit);
+                    }
+                });
+                for (index, child) in group.children.iter_mut().enumerate() {
+                    // names are not unique, e.g. the meshes of two instances of a model
+                    ui.push_id(index, |ui| scene_tree(ui, child, false, groups, edit));
+                }
+            });
+        }
+        Node::RenderNode(render) => {
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "{name} ({} triangles, {} instances)",
+                    render.num_elements / 3,
+                    render.instance_count()
+                ));
+                edit_buttons(ui, &name, groups, edit);
+            });
+        }
+        Node::LightNode(light_node) => {
+            ui.horizontal(|ui| {
+                ui.label(format!("{name} ({:?} light)", light_node.light.kind));
+                edit_buttons(ui, &name, groups, edit);
+            });
+        }
+    }
+}
+
+/// A button removing the node `name` and a menu moving it into another group.
+fn edit_buttons(ui: &mut egui::Ui, name: &str, groups: &[String], edit: &mut Option<SceneEdit>) {
+    if ui.small_button("remove").clicked() {
+        *edit = Some(SceneEdit::Remove(name.to_string()));
+    }
+    egui::ComboBox::from_id_salt("move")
+        .selected_text("move to")
+        .show_ui(ui, |ui| {
+            for group in groups.iter().filter(|group| *group != name) {
+                if ui.selectable_label(false, group).clicked() {
+                    *edit = Some(SceneEdit::Move {
+                        name: name.to_string(),
+                        parent: group.clone(),
+                    });
+                }
+            }
+        });
+}
+
+/// Prints the statistics of the shadow map layers of the light `name` and saves them as
+/// `shadow_<name>_<face>.png`.
+fn inspect_shadow_map(renderer: &Renderer, name: &str) {
+    let stats = match read_back_light_shadow(
+        &renderer.device,
+        &renderer.queue,
+        &renderer.scene_graph,
+        name,
+    ) {
+        Ok(stats) => stats,
+        Err(e) => {
+            println!("Failed to read back the shadow map of {name}: {e}");
+            return;
+        }
+    };
+    for layer in stats {
+        println!("{layer}");
+        let file = format!("shadow_{name}_{}.png", layer.face);
+        match layer.save_png(Path::new(&file)) {
+            Ok(()) => println!("Saved {file}"),
+            Err(e) => println!("Failed to save {file}: {e}"),
+        }
+    }
+}
@@ -0,0 +1,444 @@
+/*
+ * Depth of field.
+ * Blurs what lies in front of or behind the focus distance after the forward pass, like the lens of a
+ * camera. A compute pass meters the distance of the surfaces under the cursor, or in the center of the
+ * frame, from the depth of the forward pass and the focus follows it over a few frames, so it can be
+ * pulled by moving the cursor over the scene. A fullscreen pass then blurs each pixel with a disk whose
+ * radius grows with the distance to the focus, like the circle of confusion of a lens. It is added from
+ * the `[depth_of_field]` table of the render settings, needs compute shaders and is skipped while the
+ * frame is rendered in stereo, which has no depth for the passes.
+ */
+use crate::camera::Projection;
+use crate::custom_pass::{CustomPass, PassContext, PassStage};
+use crate::reflection::ReflectDevice;
+use crate::renderer::Pipeline;
+use crate::shader_cache;
+use serde::Deserialize;
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+
+/// Where the focus distance is metered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FocusMode {
+    /// Under the cursor, the center of the frame until the cursor moved over the window.
+    #[default]
+    Cursor,
+    Center,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DepthOfFieldSettings {
+    /// `"cursor"` or `"center"`, see [`FocusMode`].
+    pub focus: FocusMode,
+    /// Blur radius in pixels of what lies infinitely far behind the focus, larger for a wider aperture.
+    pub aperture: f32,
+    /// Largest blur radius in pixels, also of what lies close in front of the camera.
+    pub max_blur: f32,
+    /// Radius in pixels of the area the focus distance is metered in, weighted towards its center.
+    pub focus_area: f32,
+    /// How fast the focus follows the metered distance, per second.
+    pub focus_speed: f32,
+}
+
+impl Default for DepthOfFieldSettings {
+    fn default() -> Self {
+        Self {
+            focus: FocusMode::default(),
+            aperture: 12.0,
+            max_blur: 16.0,
+            focus_area: 24.0,
+            focus_speed: 4.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct DepthOfFieldUniform {
+    focus_point: [f32; 2],
+    focus_area: f32,
+    adaptation: f32,
+    near: f32,
+    far: f32,
+    orthographic: u32,
+    aperture: f32,
+    max_blur: f32,
+    _padding: [f32; 3],
+}
+
+/// Pipelines for a depth texture with one sample count, the shader declares the depth texture to match.
+struct DepthOfFieldPipelines {
+    sample_count: u32,
+    focus_pipeline: wgpu::ComputePipeline,
+    blur_pipeline: Pipeline,
+    meter_layout: wgpu::BindGroupLayout,
+    blur_layout: wgpu::BindGroupLayout,
+}
+
+impl DepthOfFieldPipelines {
+    fn new(device: &wgpu::Device, format: wgpu::TextureFormat, sample_count: u32) -> Self {
+        let multisampled = sample_count > 1;
+        let depth_entry = |visibility| wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility,
+            ty: wgpu::BindingType::Texture {
+                multisampled,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Depth,
+            },
+            count: None,
+        };
+        let buffer_entry = |binding, visibility, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let meter_layout = device.reflect_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                depth_entry(wgpu::ShaderStages::COMPUTE),
+                buffer_entry(
+                    2,
+                    wgpu::ShaderStages::COMPUTE,
+                    wgpu::BufferBindingType::Uniform,
+                ),
+                buffer_entry(
+                    3,
+                    wgpu::ShaderStages::COMPUTE,
+                    wgpu::BufferBindingType::Storage { read_only: false },
+                ),
+            ],
+            label: Some("depth_of_field_meter_bind_group_layout"),
+        });
+        let blur_layout = device.reflect_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                depth_entry(wgpu::ShaderStages::FRAGMENT),
+                buffer_entry(
+                    2,
+                    wgpu::ShaderStages::FRAGMENT,
+                    wgpu::BufferBindingType::Uniform,
+                ),
+                buffer_entry(
+                    4,
+                    wgpu::ShaderStages::FRAGMENT,
+                    wgpu::BufferBindingType::Storage { read_only: true },
+                ),
+            ],
+            label: Some("depth_of_field_bind_group_layout"),
+        });
+
+        let depth_type = if multisampled {
+            "texture_depth_multisampled_2d"
+        } else {
+            "texture_depth_2d"
+        };
+        let source = format!(
+            "@group(0) @binding(1) var t_depth: {depth_type};\n{}",
+            include_str!("depth_of_field.wgsl")
+        );
+        let shader = device.reflect_shader(wgpu::ShaderModuleDescriptor {
+            label: Some("depth_of_field"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(source)),
+        });
+        let meter_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("depth_of_field_meter_pipeline_layout"),
+                bind_group_layouts: &[&meter_layout],
+                push_constant_ranges: &[],
+            });
+        let focus_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("depth_of_field_focus_pipeline"),
+            layout: Some(&meter_pipeline_layout),
+            module: &shader,
+            entry_point: Some("cs_focus"),
+            compilation_options: Default::default(),
+            cache: shader_cache::pipeline_cache(device).as_ref(),
+        });
+        let blur_pipeline = Pipeline::new(
+            device,
+            "depth_of_field_pipeline",
+            &shader,
+            &[&blur_layout],
+            "vs_fullscreen",
+            &[],
+            Some("fs_blur"),
+            &[Some(wgpu::ColorTargetState {
+                format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            None,
+            None,
+            None,
+            None,
+        );
+
+        Self {
+            sample_count,
+            focus_pipeline,
+            blur_pipeline,
+            meter_layout,
+            blur_layout,
+        }
+    }
+}
+
+/// Copy of the frame the blur reads from and the bind groups, for one size of the frame and depth view.
+struct FrameCopy {
+    copy: wgpu::Texture,
+    depth: wgpu::TextureView,
+    meter_bind_group: wgpu::BindGroup,
+    blur_bind_group: wgpu::BindGroup,
+}
+
+pub struct DepthOfFieldPass {
+    settings: DepthOfFieldSettings,
+    format: wgpu::TextureFormat,
+    frame_time: f32,
+    /// Created for the sample count of the depth texture, again once MSAA is switched.
+    pipelines: Option<DepthOfFieldPipelines>,
+    uniform_buffer: wgpu::Buffer,
+    focus_buffer: wgpu::Buffer,
+    frame_copy: Option<FrameCopy>,
+    /// Set once it was reported that the pass can't run.
+    reported: bool,
+}
+
+impl DepthOfFieldPass {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        settings: &DepthOfFieldSettings,
+        frame_time: f32,
+    ) -> Self {
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Depth Of Field Buffer"),
+            size: size_of::<DepthOfFieldUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        // negative until the first metering, which is taken as it is
+        let focus_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Depth Of Field Focus Buffer"),
+            contents: bytemuck::bytes_of(&-1.0f32),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        Self {
+            settings: *settings,
+            format,
+            frame_time,
+            pipelines: None,
+            uniform_buffer,
+            focus_buffer,
+            frame_copy: None,
+            reported: false,
+        }
+    }
+
+    fn report(&mut self, reason: &str) {
+        if !std::mem::replace(&mut self.reported, true) {
+            println!("Depth of field is off, {reason}");
+        }
+    }
+
+    /// Creates the pipelines for a depth texture with `sample_count` samples and the copy of the frame
+    /// for a frame texture like `frame`, again when either changed.
+    fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        frame: &wgpu::Texture,
+        depth: &wgpu::TextureView,
+        sample_count: u32,
+    ) {
+        if self
+            .pipelines
+            .as_ref()
+            .is_none_or(|pipelines| pipelines.sample_count != sample_count)
+        {
+            self.pipelines = Some(DepthOfFieldPipelines::new(
+                device,
+                self.format,
+                sample_count,
+            ));
+            self.frame_copy = None;
+        }
+        let Some(pipelines) = &self.pipelines else {
+            return;
+        };
+        // read through the sRGB view of the frame, like the passes draw into it
+        let format = frame.format().add_srgb_suffix();
+        let fits = self.frame_copy.as_ref().is_some_and(|frame_copy| {
+            frame_copy.copy.size() == frame.size()
+                && frame_copy.copy.format() == format
+                && frame_copy.depth == *depth
+        });
+        if fits {
+            return;
+        }
+        let copy = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("depth_of_field_frame_copy"),
+            size: frame.size(),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let copy_view = copy.create_view(&Default::default());
+        let meter_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &pipelines.meter_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(depth),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.focus_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("depth_of_field_meter_bind_group"),
+        });
+        let blur_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &pipelines.blur_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&copy_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(depth),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: self.focus_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("depth_of_field_bind_group"),
+        });
+        self.frame_copy = Some(FrameCopy {
+            copy,
+            depth: depth.clone(),
+            meter_bind_group,
+            blur_bind_group,
+        });
+    }
+
+    fn uniform(&self, context: &PassContext) -> DepthOfFieldUniform {
+        let size = context.color_texture.size();
+        let center = (f64::from(size.width) / 2.0, f64::from(size.height) / 2.0);
+        let camera_state = &context.renderer.camera_state;
+        let focus_point = match self.settings.focus {
+            FocusMode::Cursor => camera_state
+                .camera_controller
+                .cursor_position()
+                .unwrap_or(center),
+            FocusMode::Center => center,
+        };
+        let camera = &camera_state.camera;
+        DepthOfFieldUniform {
+            focus_point: [focus_point.0 as f32, focus_point.1 as f32],
+            focus_area: self.settings.focus_area.max(0.0),
+            adaptation: 1.0 - (-self.settings.focus_speed * self.frame_time).exp(),
+            near: camera.znear,
+            far: camera.zfar,
+            orthographic: matches!(camera.projection, Projection::Orthographic { .. }) as u32,
+            aperture: self.settings.aperture.max(0.0),
+            max_blur: self.settings.max_blur.max(0.0),
+            _padding: [0.0; 3],
+        }
+    }
+}
+
+impl CustomPass for DepthOfFieldPass {
+    fn stage(&self) -> PassStage {
+        PassStage::AfterForward
+    }
+
+    fn render(&mut self, context: PassContext) {
+        let frame = context.color_texture;
+        let Some(depth) = context.depth else {
+            self.report("the frame is rendered without depth");
+            return;
+        };
+        if !frame.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+            self.report("the frame texture can't be copied");
+            return;
+        }
+        self.prepare(
+            &context.renderer.device,
+            frame,
+            depth,
+            context.depth_sample_count,
+        );
+        let (Some(pipelines), Some(frame_copy)) = (&self.pipelines, &self.frame_copy) else {
+            return;
+        };
+        context.renderer.queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::bytes_of(&self.uniform(&context)),
+        );
+        context.encoder.copy_texture_to_texture(
+            frame.as_image_copy(),
+            frame_copy.copy.as_image_copy(),
+            frame.size(),
+        );
+
+        {
+            let mut cpass = context
+                .encoder
+                .begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("depth_of_field_focus_pass"),
+                    timestamp_writes: None,
+                });
+            cpass.set_pipeline(&pipelines.focus_pipeline);
+            cpass.set_bind_group(0, &frame_copy.meter_bind_group, &[]);
+            cpass.dispatch_workgroups(1, 1, 1);
+        }
+
+        let mut rpass = context
+            .encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("depth_of_field_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: context.color,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+        rpass.set_pipeline(&pipelines.blur_pipeline.pipeline);
+        rpass.set_bind_group(0, &frame_copy.blur_bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
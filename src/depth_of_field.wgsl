@@ -0,0 +1,103 @@
+// t_depth at binding 1 is declared in front of this file, single or multisampled like the forward pass,
+// see depth_of_field.rs
+
+struct DepthOfField {
+    // pixel the focus is metered around
+    focus_point: vec2<f32>,
+    // radius in pixels of the metered area
+    focus_area: f32,
+    // share of the step to the metered focus distance taken this frame
+    adaptation: f32,
+    near: f32,
+    far: f32,
+    orthographic: u32,
+    // blur radius in pixels of what lies infinitely far behind the focus
+    aperture: f32,
+    max_blur: f32,
+    _padding0: f32,
+    _padding1: f32,
+    _padding2: f32,
+};
+
+@group(0) @binding(0) var t_frame: texture_2d<f32>;
+@group(0) @binding(2) var<uniform> dof: DepthOfField;
+// distance from the camera in focus, adapted each frame, negative until the first metering
+@group(0) @binding(3) var<storage, read_write> focus: f32;
+
+// samples of the blur disk
+const SAMPLE_COUNT: u32 = 24u;
+const GOLDEN_ANGLE: f32 = 2.39996323;
+
+// distance from the camera plane of the surface at `pixel`, the far plane where nothing was drawn
+fn linear_depth(pixel: vec2<i32>) -> f32 {
+    let size = vec2<i32>(textureDimensions(t_depth));
+    let depth = textureLoad(t_depth, clamp(pixel, vec2<i32>(0), size - 1), 0);
+    if dof.orthographic != 0u {
+        return mix(dof.near, dof.far, depth);
+    }
+    return dof.near * dof.far / (dof.far - depth * (dof.far - dof.near));
+}
+
+// 5x5 samples over the focus area, weighted towards its center
+@compute @workgroup_size(1)
+fn cs_focus() {
+    var weight_sum = 0.0;
+    var inverse_sum = 0.0;
+    for (var y = -2; y <= 2; y++) {
+        for (var x = -2; x <= 2; x++) {
+            let offset = vec2<f32>(f32(x), f32(y)) * 0.5;
+            let weight = exp(-2.0 * dot(offset, offset));
+            let pixel = vec2<i32>(dof.focus_point + offset * dof.focus_area);
+            // averaged in inverse distance, the blur grows linearly in it
+            inverse_sum += weight / linear_depth(pixel);
+            weight_sum += weight;
+        }
+    }
+    let metered = weight_sum / inverse_sum;
+    focus = select(mix(focus, metered, dof.adaptation), metered, focus < 0.0);
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+};
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // a single triangle covering the whole viewport
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    return out;
+}
+
+// the focus buffer again, read-only for the fragment stage
+@group(0) @binding(4) var<storage, read> applied_focus: f32;
+
+fn blur_radius(depth: f32) -> f32 {
+    return min(dof.aperture * abs(depth - applied_focus) / depth, dof.max_blur);
+}
+
+@fragment
+fn fs_blur(in: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(in.position.xy);
+    let size = vec2<i32>(textureDimensions(t_frame));
+    var color = textureLoad(t_frame, pixel, 0).rgb;
+    let radius = blur_radius(linear_depth(pixel));
+    if radius < 0.5 {
+        return vec4<f32>(color, 1.0);
+    }
+    var weight_sum = 1.0;
+    for (var i = 0u; i < SAMPLE_COUNT; i++) {
+        // golden angle steps on a spiral fill the disk evenly
+        let distance = sqrt((f32(i) + 0.5) / f32(SAMPLE_COUNT)) * radius;
+        let angle = f32(i) * GOLDEN_ANGLE;
+        let offset = vec2<i32>(round(vec2<f32>(cos(angle), sin(angle)) * distance));
+        let sample_pixel = clamp(pixel + offset, vec2<i32>(0), size - 1);
+        // a sample only counts where its own blur reaches this pixel, so sharp surfaces in front don't
+        // bleed into the blurred background
+        let weight = clamp(blur_radius(linear_depth(sample_pixel)) - distance + 1.0, 0.0, 1.0);
+        color += textureLoad(t_frame, sample_pixel, 0).rgb * weight;
+        weight_sum += weight;
+    }
+    return vec4<f32>(color / weight_sum, 1.0);
+}
@@ -0,0 +1,218 @@
+/*
+ * Depth buffer visualization.
+ * Converts the non-linear depth of the forward pass back into view distance and shows it as a
+ * grayscale image with contour lines at equal distance steps, to help tuning znear/zfar and to
+ * find z-fighting.
+ * The pipeline is built on the first toggle: the GL backend can't translate a textureLoad from a depth
+ * texture, so it is never built there.
+ */
+use crate::camera::{Camera, Projection};
+use crate::reflection::ReflectDevice;
+use crate::renderer::Pipeline;
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+
+/// Number of contour lines between the near and the far plane.
+const CONTOURS: f32 = 10.0;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct DepthRangeUniform {
+    near: f32,
+    far: f32,
+    orthographic: u32,
+    contours: f32,
+}
+
+impl DepthRangeUniform {
+    fn from_camera(camera: &Camera) -> Self {
+        Self {
+            near: camera.znear,
+            far: camera.zfar,
+            orthographic: matches!(camera.projection, Projection::Orthographic { .. }) as u32,
+            contours: CONTOURS,
+        }
+    }
+}
+
+pub struct DepthView {
+    pub enabled: bool,
+    /// Built by [`DepthView::toggle`], None until the view is first shown.
+    pipeline: Option<Pipeline>,
+    /// Whether the adapter's shaders can read the depth texture.
+    supported: bool,
+    format: wgpu::TextureFormat,
+    bind_group_layout: wgpu::BindGroupLayout,
+    range_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl DepthView {
+    pub fn new(
+        device: &wgpu::Device,
+        adapter: &wgpu::Adapter,
+        format: wgpu::TextureFormat,
+        depth_view: &wgpu::TextureView,
+        camera: &Camera,
+    ) -> Self {
+        let bind_group_layout = device.reflect_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("depth_view_bind_group_layout"),
+        });
+        let range_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Depth Range Buffer"),
+            contents: bytemuck::cast_slice(&[DepthRangeUniform::from_camera(camera)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group =
+            Self::create_bind_group(device, &bind_group_layout, depth_view, &range_buffer);
+
+        Self {
+            enabled: false,
+            pipeline: None,
+            supported: adapter.get_info().backend != wgpu::Backend::Gl,
+            format,
+            bind_group_layout,
+            range_buffer,
+            bind_group,
+        }
+    }
+
+    /// Shows or hides the depth view, the pipeline is built the first time it is shown.
+    pub fn toggle(&mut self, device: &wgpu::Device) {
+        if !self.supported {
+            println!("The depth view is off, the GL backend can't read depth textures in shaders");
+            return;
+        }
+        if self.pipeline.is_none() {
+            self.pipeline = Some(self.create_pipeline(device));
+        }
+        self.enabled = !self.enabled;
+    }
+
+    fn create_pipeline(&self, device: &wgpu::Device) -> Pipeline {
+        let shader = device.reflect_shader(wgpu::ShaderModuleDescriptor {
+            label: Some("depth_view"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("depth_view.wgsl"))),
+        });
+        Pipeline::new(
+            device,
+            "depth_view_pipeline",
+            &shader,
+            &[&self.bind_group_layout],
+            "vs_fullscreen",
+            &[],
+            Some("fs_depth"),
+            &[Some(wgpu::ColorTargetState {
+                format: self.format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            None,
+            None,
+            None,
+            None,
+        )
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        depth_view: &wgpu::TextureView,
+        range_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(depth_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: range_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("depth_view_bind_group"),
+        })
+    }
+
+    /// Must be called whenever the depth texture is recreated.
+    pub fn set_depth_texture(&mut self, device: &wgpu::Device, depth_view: &wgpu::TextureView) {
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            depth_view,
+            &self.range_buffer,
+        );
+    }
+
+    /// HUD lines describing the displayed range.
+    pub fn annotations(camera: &Camera) -> Vec<String> {
+        vec![
+            format!(
+                "Depth: near {} (white), far {} (black)",
+                camera.znear, camera.zfar
+            ),
+            format!(
+                "Contours every {:.2} units",
+                (camera.zfar - camera.znear) / CONTOURS
+            ),
+        ]
+    }
+
+    /// Replaces the contents of `view` with the linearized depth of the last forward pass.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        view: &wgpu::TextureView,
+        camera: &Camera,
+    ) {
+        let Some(pipeline) = &self.pipeline else {
+            return;
+        };
+        queue.write_buffer(
+            &self.range_buffer,
+            0,
+            bytemuck::cast_slice(&[DepthRangeUniform::from_camera(camera)]),
+        );
+
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("depth_view_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+        rpass.set_pipeline(&pipeline.pipeline);
+        rpass.set_bind_group(0, &self.bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
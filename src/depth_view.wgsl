@@ -0,0 +1,50 @@
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+};
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // a single triangle covering the whole viewport
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    return out;
+}
+
+struct DepthRange {
+    near: f32,
+    far: f32,
+    orthographic: u32,
+    contours: f32,
+};
+
+@group(0) @binding(0) var t_depth: texture_depth_2d;
+@group(0) @binding(1) var<uniform> range: DepthRange;
+
+// Inverse of the depth mapping of perspective_rh / orthographic_rh
+fn linearize(depth: f32) -> f32 {
+    if (range.orthographic != 0u) {
+        return range.near + depth * (range.far - range.near);
+    }
+    return range.near * range.far / (range.far - depth * (range.far - range.near));
+}
+
+@fragment
+fn fs_depth(in: VertexOutput) -> @location(0) vec4<f32> {
+    let depth = textureLoad(t_depth, vec2<i32>(in.position.xy), 0);
+    // cleared depth, nothing was drawn here
+    if (depth >= 1.0) {
+        return vec4<f32>(0.05, 0.05, 0.15, 1.0);
+    }
+
+    // near is white, far is black
+    let t = clamp((linearize(depth) - range.near) / (range.far - range.near), 0.0, 1.0);
+    let gray = vec3<f32>(1.0 - t);
+
+    // contour lines split the range into equal steps, unevenly spaced or broken lines
+    // show where the depth precision runs out
+    let steps = t * range.contours;
+    let band = fract(steps);
+    let line = 1.0 - smoothstep(0.0, fwidth(steps) * 1.5, min(band, 1.0 - band));
+    return vec4<f32>(mix(gray, vec3<f32>(1.0, 0.5, 0.0), line * 0.8), 1.0);
+}
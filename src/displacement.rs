@@ -0,0 +1,267 @@
+/*
+ * Displacement mapping without tessellation shaders.
+ * Meshes whose material has a displacement map (MTL `disp`) are subdivided when the model is loaded:
+ * a compute pass splits every triangle into a grid of smaller ones and moves the new vertices along the
+ * normal by the height in the map. The vertices are read back, so the denser mesh goes through the same
+ * path as any other mesh (tangents, bounds, culling).
+ */
+use crate::model::{Mesh, Vertex};
+use crate::shader_cache;
+use crate::texture;
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct DisplacementUniform {
+    segments: u32,
+    triangle_count: u32,
+    base: f32,
+    gain: f32,
+}
+
+/// Height map of a material, the displacement is `base + gain * height`.
+pub struct DisplacementMap {
+    pub texture: texture::Texture,
+    pub base: f32,
+    pub gain: f32,
+}
+
+impl DisplacementMap {
+    /// Splits the value of an MTL `disp` statement, e.g. `-mm 0 0.2 height.png`, into the file name and
+    /// the base and gain of the `-mm` option.
+    pub fn parse(value: &str) -> (Option<&str>, f32, f32) {
+        let mut tokens = value.split_whitespace();
+        let (mut base, mut gain) = (0.0, 1.0);
+        let mut file = None;
+        while let Some(token) = tokens.next() {
+            if token == "-mm" {
+                base = tokens.next().and_then(|t| t.parse().ok()).unwrap_or(base);
+                gain = tokens.next().and_then(|t| t.parse().ok()).unwrap_or(gain);
+            } else {
+                file = Some(token);
+            }
+        }
+        (file, base, gain)
+    }
+}
+
+/// Subdivides every triangle of `mesh` into `segments` x `segments` triangles and displaces them by
+/// `map`. The segments are lowered if the result would not fit into a storage buffer.
+pub fn displace_mesh(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    mesh: &Mesh,
+    map: &DisplacementMap,
+    segments: u32,
+) -> Mesh {
+    let triangle_count = mesh.indices.len() as u32 / 3;
+    let vertices_per_triangle = |segments: u32| (segments + 1) * (segments + 2) / 2;
+    let output_size = |segments: u32| {
+        (triangle_count * vertices_per_triangle(segments)) as u64 * size_of::<Vertex>() as u64
+    };
+    let max_size = device.limits().max_storage_buffer_binding_size as u64;
+    let segments = (1..=segments.max(1))
+        .rev()
+        .find(|segments| output_size(*segments) <= max_size)
+        .unwrap_or(1);
+    let vertex_count = triangle_count * vertices_per_triangle(segments);
+    println!(
+        "Displacing {} with {segments}x{segments} subdivisions, {} -> {} triangles",
+        mesh.name,
+        triangle_count,
+        triangle_count * segments * segments
+    );
+
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("displacement_bind_group_layout"),
+        entries: &[
+            storage_entry(0, true),
+            storage_entry(1, true),
+            storage_entry(2, false),
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 4,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 5,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    });
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("displacement"),
+        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("displacement.wgsl"))),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("displacement_pipeline_layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("displacement_compute_pipeline"),
+        layout: Some(&pipeline_layout),
+        module: &shader,
+        entry_point: Some("main"),
+        compilation_options: Default::default(),
+        cache: shader_cache::pipeline_cache(device).as_ref(),
+    });
+
+    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("displacement_input_vertices"),
+        contents: bytemuck::cast_slice(&mesh.vertices),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+    let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("displacement_input_indices"),
+        contents: bytemuck::cast_slice(&mesh.indices),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+    let output_size = vertex_count as u64 * size_of::<Vertex>() as u64;
+    let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("displacement_output_vertices"),
+        size: output_size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("displacement_readback"),
+        size: output_size,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("displacement_uniform"),
+        contents: bytemuck::bytes_of(&DisplacementUniform {
+            segments,
+            triangle_count,
+            base: map.base,
+            gain: map.gain,
+        }),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("displacement_bind_group"),
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: vertex_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: index_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: output_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(&map.texture.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::Sampler(&map.texture.sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: uniform_buffer.as_entire_binding(),
+            },
+        ],
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("displacement_encoder"),
+    });
+    {
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("displacement_pass"),
+            timestamp_writes: None,
+        });
+        cpass.set_pipeline(&pipeline);
+        cpass.set_bind_group(0, &bind_group, &[]);
+        // one invocation per output vertex, spread over rows when there are too many workgroups for one
+        let workgroups = vertex_count.div_ceil(64);
+        let max_workgroups = device.limits().max_compute_workgroups_per_dimension;
+        let rows = workgroups.div_ceil(max_workgroups);
+        cpass.dispatch_workgroups(workgroups.div_ceil(rows), rows, 1);
+    }
+    encoder.copy_buffer_to_buffer(&output_buffer, 0, &readback_buffer, 0, output_size);
+    queue.submit(Some(encoder.finish()));
+
+    let slice = readback_buffer.slice(..);
+    slice.map_async(wgpu::MapMode::Read, |_| ());
+    device.poll(wgpu::Maintain::Wait);
+    let vertices: Vec<Vertex> = bytemuck::pod_collect_to_vec(&slice.get_mapped_range());
+    readback_buffer.unmap();
+
+    let indices = subdivided_indices(triangle_count, segments);
+    Mesh {
+        name: mesh.name.clone(),
+        vertices,
+        num_elements: indices.len() as u32,
+        indices,
+        material: mesh.material,
+    }
+}
+
+fn storage_entry(binding: u32, read_only: bool) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+/// Indices of the triangle grids written by the compute pass, with the winding of the original triangles.
+/// Each triangle's vertices are stored row by row, row `i` has `segments + 1 - i` vertices.
+fn subdivided_indices(triangle_count: u32, segments: u32) -> Vec<u32> {
+    let vertices_per_triangle = (segments + 1) * (segments + 2) / 2;
+    let row_start = |row: u32| row * (segments + 1) - row * row.saturating_sub(1) / 2;
+    let mut indices = Vec::with_capacity((triangle_count * segments * segments * 3) as usize);
+    for triangle in 0..triangle_count {
+        let offset = triangle * vertices_per_triangle;
+        let vertex = |row: u32, column: u32| offset + row_start(row) + column;
+        for row in 0..segments {
+            for column in 0..segments - row {
+                indices.extend([
+                    vertex(row, column),
+                    vertex(row, column + 1),
+                    vertex(row + 1, column),
+                ]);
+                if column + 1 < segments - row {
+                    indices.extend([
+                        vertex(row, column + 1),
+                        vertex(row + 1, column + 1),
+                        vertex(row + 1, column),
+                    ]);
+                }
+            }
+        }
+    }
+    indices
+}
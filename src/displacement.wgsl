@@ -0,0 +1,97 @@
+// Vertices as in model.rs: position, texture coordinates, normal
+const VERTEX_FLOATS: u32 = 8u;
+
+struct Params {
+    segments: u32,
+    triangle_count: u32,
+    base: f32,
+    gain: f32,
+};
+
+@group(0) @binding(0) var<storage, read> in_vertices: array<f32>;
+@group(0) @binding(1) var<storage, read> in_indices: array<u32>;
+@group(0) @binding(2) var<storage, read_write> out_vertices: array<f32>;
+@group(0) @binding(3) var t_displacement: texture_2d<f32>;
+@group(0) @binding(4) var s_displacement: sampler;
+@group(0) @binding(5) var<uniform> params: Params;
+
+struct Corner {
+    pos: vec3<f32>,
+    tex_coords: vec2<f32>,
+    normal: vec3<f32>,
+};
+
+fn load_corner(triangle: u32, corner: u32) -> Corner {
+    let base = in_indices[triangle * 3u + corner] * VERTEX_FLOATS;
+    var out: Corner;
+    out.pos = vec3<f32>(in_vertices[base], in_vertices[base + 1u], in_vertices[base + 2u]);
+    out.tex_coords = vec2<f32>(in_vertices[base + 3u], in_vertices[base + 4u]);
+    out.normal = vec3<f32>(in_vertices[base + 5u], in_vertices[base + 6u], in_vertices[base + 7u]);
+    return out;
+}
+
+// Point of the triangle at (u, w) towards the second and third corner, moved along the interpolated normal
+fn displaced(c0: Corner, c1: Corner, c2: Corner, u: f32, w: f32) -> Corner {
+    let v = 1.0 - u - w;
+    var out: Corner;
+    out.tex_coords = c0.tex_coords * v + c1.tex_coords * u + c2.tex_coords * w;
+    out.normal = normalize(c0.normal * v + c1.normal * u + c2.normal * w);
+    let height = params.base + params.gain * textureSampleLevel(t_displacement, s_displacement, out.tex_coords, 0.0).r;
+    out.pos = c0.pos * v + c1.pos * u + c2.pos * w + out.normal * height;
+    return out;
+}
+
+@compute @workgroup_size(64, 1, 1)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    // large meshes are dispatched over two dimensions
+    let index = global_id.x + global_id.y * num_workgroups.x * 64u;
+    let segments = params.segments;
+    let vertices_per_triangle = (segments + 1u) * (segments + 2u) / 2u;
+    let triangle = index / vertices_per_triangle;
+    if (triangle >= params.triangle_count) {
+        return;
+    }
+
+    // row i has segments + 1 - i vertices
+    var k = index % vertices_per_triangle;
+    var row = 0u;
+    loop {
+        let row_length = segments + 1u - row;
+        if (k < row_length) {
+            break;
+        }
+        k -= row_length;
+        row += 1u;
+    }
+    let u = f32(k) / f32(segments);
+    let w = f32(row) / f32(segments);
+
+    let c0 = load_corner(triangle, 0u);
+    let c1 = load_corner(triangle, 1u);
+    let c2 = load_corner(triangle, 2u);
+    let center = displaced(c0, c1, c2, u, w);
+
+    // normal of the displaced surface from finite differences, which agree across shared edges
+    let epsilon = 0.25 / f32(segments);
+    let du = displaced(c0, c1, c2, u + epsilon, w).pos - center.pos;
+    let dw = displaced(c0, c1, c2, u, w + epsilon).pos - center.pos;
+    var normal = cross(du, dw);
+    if (length(normal) > 0.0) {
+        normal = normalize(normal) * select(-1.0, 1.0, dot(normal, center.normal) >= 0.0);
+    } else {
+        normal = center.normal;
+    }
+
+    let base = index * VERTEX_FLOATS;
+    out_vertices[base] = center.pos.x;
+    out_vertices[base + 1u] = center.pos.y;
+    out_vertices[base + 2u] = center.pos.z;
+    out_vertices[base + 3u] = center.tex_coords.x;
+    out_vertices[base + 4u] = center.tex_coords.y;
+    out_vertices[base + 5u] = normal.x;
+    out_vertices[base + 6u] = normal.y;
+    out_vertices[base + 7u] = normal.z;
+}
@@ -0,0 +1,88 @@
+/*
+ * Errors of the renderer.
+ * Creating the renderer, loading models and creating textures return a RendererError instead of
+ * panicking. wgpu reports invalid resources through the device's error handler, which panics by default.
+ * Resource creation that depends on content (models, materials, pipeline variants) runs inside an error
+ * scope instead, so a failure is returned with the name of what was being created and the rest of the
+ * scene keeps rendering.
+ */
+use std::fmt::Display;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RendererError {
+    /// wgpu rejected a resource, `context` names the resource, e.g. the node or material it belongs to.
+    #[error("Creating {context} failed: {message}")]
+    Validation { context: String, message: String },
+    /// A file could not be read or decoded.
+    #[error("Loading {path} failed: {source}")]
+    Asset {
+        path: String,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    #[error("Creating the window failed: {0}")]
+    Window(#[from] winit::error::OsError),
+    #[error("Creating the surface failed: {0}")]
+    CreateSurface(#[from] wgpu::CreateSurfaceError),
+    #[error("No graphics adapter can render to the window")]
+    NoAdapter,
+    #[error("Requesting the device failed: {0}")]
+    RequestDevice(#[from] wgpu::RequestDeviceError),
+    #[error("The surface cannot be configured for the adapter")]
+    UnsupportedSurface,
+    /// The next frame could not be acquired.
+    #[error("Acquiring the frame failed: {0}")]
+    Surface(#[from] wgpu::SurfaceError),
+    /// The device stopped working, e.g. after a driver reset. Nothing can be rendered with it anymore.
+    #[error("The device was lost: {0}")]
+    DeviceLost(String),
+}
+
+impl RendererError {
+    pub fn asset(
+        path: impl Display,
+        source: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+    ) -> Self {
+        RendererError::Asset {
+            path: path.to_string(),
+            source: source.into(),
+        }
+    }
+}
+
+/// Runs `create` in a validation error scope and returns its result, or the validation error with the
+/// `context` of what was created.
+///
+/// On the web the scope can only be awaited, so errors are printed once they arrive and `create`'s
+/// result is returned right away.
+pub fn validate<T>(
+    device: &wgpu::Device,
+    context: impl FnOnce() -> String,
+    create: impl FnOnce() -> T,
+) -> Result<T, RendererError> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let value = create();
+    let error = device.pop_error_scope();
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        let context = context();
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Some(error) = error.await {
+                let error = RendererError::Validation {
+                    context,
+                    message: error.to_string(),
+                };
+                println!("{error}");
+            }
+        });
+        Ok(value)
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    match pollster::block_on(error) {
+        Some(error) => Err(RendererError::Validation {
+            context: context(),
+            message: error.to_string(),
+        }),
+        None => Ok(value),
+    }
+}
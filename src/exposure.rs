@@ -0,0 +1,334 @@
+/*
+ * Auto exposure.
+ * Eye adaptation after the forward pass: a compute pass sorts the pixels of the frame into a histogram of
+ * their log luminance, weighted towards the center of the frame, and a second one turns the weighted
+ * average into the exposure that brings it to the key value. The exposure follows the metered one over a
+ * few frames, like eyes adapting to a dark room, and a fullscreen pass scales the frame by it. It is added
+ * from the `[auto_exposure]` table of the render settings and needs compute shaders. The frame has no HDR
+ * range yet, so bright frames can only be darkened down to where they clip.
+ */
+use crate::custom_pass::{CustomPass, PassContext, PassStage};
+use crate::reflection::ReflectDevice;
+use crate::renderer::Pipeline;
+use crate::shader_cache;
+use serde::Deserialize;
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+
+/// Bins of the luminance histogram, must match `BIN_COUNT` in exposure.wgsl.
+const BIN_COUNT: usize = 64;
+/// Must match the workgroup size of `cs_histogram`.
+const WORKGROUP_SIZE: u32 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AutoExposureSettings {
+    /// Average linear luminance the frame is exposed to.
+    pub key: f32,
+    pub min_exposure: f32,
+    pub max_exposure: f32,
+    /// How fast the exposure follows the metered one, per second.
+    pub adaptation_speed: f32,
+    /// 0 meters the whole frame evenly, 1 weights the center most.
+    pub center_weight: f32,
+}
+
+impl Default for AutoExposureSettings {
+    fn default() -> Self {
+        Self {
+            key: 0.18,
+            min_exposure: 0.25,
+            max_exposure: 4.0,
+            adaptation_speed: 1.5,
+            center_weight: 0.5,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ExposureUniform {
+    key: f32,
+    min_exposure: f32,
+    max_exposure: f32,
+    adaptation: f32,
+    center_weight: f32,
+    _padding: [f32; 3],
+}
+
+impl ExposureUniform {
+    fn from_settings(settings: &AutoExposureSettings, frame_time: f32) -> Self {
+        Self {
+            key: settings.key,
+            min_exposure: settings.min_exposure,
+            max_exposure: settings.max_exposure.max(settings.min_exposure),
+            adaptation: 1.0 - (-settings.adaptation_speed * frame_time).exp(),
+            center_weight: settings.center_weight.clamp(0.0, 1.0),
+            _padding: [0.0; 3],
+        }
+    }
+}
+
+pub struct AutoExposurePass {
+    histogram_pipeline: wgpu::ComputePipeline,
+    adapt_pipeline: wgpu::ComputePipeline,
+    expose_pipeline: Pipeline,
+    meter_layout: wgpu::BindGroupLayout,
+    expose_layout: wgpu::BindGroupLayout,
+    uniform_buffer: wgpu::Buffer,
+    histogram_buffer: wgpu::Buffer,
+    exposure_buffer: wgpu::Buffer,
+    /// Copy of the frame the passes read from and their bind groups, created for the size of the frame.
+    frame_copy: Option<(wgpu::Texture, wgpu::BindGroup, wgpu::BindGroup)>,
+    /// Set once it was reported that the frame texture can't be copied.
+    reported: bool,
+}
+
+impl AutoExposurePass {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        settings: &AutoExposureSettings,
+        frame_time: f32,
+    ) -> Self {
+        let frame_entry = |visibility| wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            },
+            count: None,
+        };
+        let buffer_entry = |binding, visibility, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let read_write = wgpu::BufferBindingType::Storage { read_only: false };
+        let meter_layout = device.reflect_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                frame_entry(wgpu::ShaderStages::COMPUTE),
+                buffer_entry(
+                    1,
+                    wgpu::ShaderStages::COMPUTE,
+                    wgpu::BufferBindingType::Uniform,
+                ),
+                buffer_entry(2, wgpu::ShaderStages::COMPUTE, read_write),
+                buffer_entry(3, wgpu::ShaderStages::COMPUTE, read_write),
+            ],
+            label: Some("exposure_meter_bind_group_layout"),
+        });
+        let expose_layout = device.reflect_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                frame_entry(wgpu::ShaderStages::FRAGMENT),
+                buffer_entry(
+                    4,
+                    wgpu::ShaderStages::FRAGMENT,
+                    wgpu::BufferBindingType::Storage { read_only: true },
+                ),
+            ],
+            label: Some("exposure_bind_group_layout"),
+        });
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Exposure Buffer"),
+            contents: bytemuck::cast_slice(&[ExposureUniform::from_settings(settings, frame_time)]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let histogram_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Exposure Histogram Buffer"),
+            contents: bytemuck::cast_slice(&[0u32; BIN_COUNT]),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        // starts out unchanged
+        let exposure_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Exposure Value Buffer"),
+            contents: bytemuck::bytes_of(&1.0f32),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+
+        let shader = device.reflect_shader(wgpu::ShaderModuleDescriptor {
+            label: Some("exposure"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("exposure.wgsl"))),
+        });
+        let meter_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("exposure_meter_pipeline_layout"),
+                bind_group_layouts: &[&meter_layout],
+                push_constant_ranges: &[],
+            });
+        let compute_pipeline = |label, entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&meter_pipeline_layout),
+                module: &shader,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: shader_cache::pipeline_cache(device).as_ref(),
+            })
+        };
+        let histogram_pipeline = compute_pipeline("exposure_histogram_pipeline", "cs_histogram");
+        let adapt_pipeline = compute_pipeline("exposure_adapt_pipeline", "cs_adapt");
+        let expose_pipeline = Pipeline::new(
+            device,
+            "exposure_pipeline",
+            &shader,
+            &[&expose_layout],
+            "vs_fullscreen",
+            &[],
+            Some("fs_expose"),
+            &[Some(wgpu::ColorTargetState {
+                format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            None,
+            None,
+            None,
+            None,
+        );
+
+        Self {
+            histogram_pipeline,
+            adapt_pipeline,
+            expose_pipeline,
+            meter_layout,
+            expose_layout,
+            uniform_buffer,
+            histogram_buffer,
+            exposure_buffer,
+            frame_copy: None,
+            reported: false,
+        }
+    }
+
+    /// Creates the copy of the frame for a frame texture like `frame`, again when its size changed.
+    fn prepare_frame_copy(&mut self, device: &wgpu::Device, frame: &wgpu::Texture) {
+        // read through the sRGB view of the frame, like the passes draw into it
+        let format = frame.format().add_srgb_suffix();
+        let fits = self
+            .frame_copy
+            .as_ref()
+            .is_some_and(|(copy, _, _)| copy.size() == frame.size() && copy.format() == format);
+        if fits {
+            return;
+        }
+        let copy = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("exposure_frame_copy"),
+            size: frame.size(),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let copy_view = copy.create_view(&Default::default());
+        let meter_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.meter_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&copy_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.histogram_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.exposure_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("exposure_meter_bind_group"),
+        });
+        let expose_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.expose_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&copy_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: self.exposure_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("exposure_bind_group"),
+        });
+        self.frame_copy = Some((copy, meter_bind_group, expose_bind_group));
+    }
+}
+
+impl CustomPass for AutoExposurePass {
+    fn stage(&self) -> PassStage {
+        PassStage::AfterForward
+    }
+
+    fn render(&mut self, context: PassContext) {
+        let frame = context.color_texture;
+        if !frame.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+            if !std::mem::replace(&mut self.reported, true) {
+                println!("Auto exposure is off, the frame texture can't be copied");
+            }
+            return;
+        }
+        self.prepare_frame_copy(&context.renderer.device, frame);
+        let Some((copy, meter_bind_group, expose_bind_group)) = &self.frame_copy else {
+            return;
+        };
+        context.encoder.copy_texture_to_texture(
+            frame.as_image_copy(),
+            copy.as_image_copy(),
+            frame.size(),
+        );
+
+        {
+            let mut cpass = context
+                .encoder
+                .begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("exposure_meter_pass"),
+                    timestamp_writes: None,
+                });
+            cpass.set_bind_group(0, meter_bind_group, &[]);
+            cpass.set_pipeline(&self.histogram_pipeline);
+            let size = frame.size();
+            cpass.dispatch_workgroups(
+                size.width.div_ceil(WORKGROUP_SIZE),
+                size.height.div_ceil(WORKGROUP_SIZE),
+                1,
+            );
+            cpass.set_pipeline(&self.adapt_pipeline);
+            cpass.dispatch_workgroups(1, 1, 1);
+        }
+
+        let mut rpass = context
+            .encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("exposure_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: context.color,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+        rpass.set_pipeline(&self.expose_pipeline.pipeline);
+        rpass.set_bind_group(0, expose_bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
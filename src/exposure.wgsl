@@ -0,0 +1,86 @@
+struct Exposure {
+    // average luminance the frame is exposed to
+    key: f32,
+    min_exposure: f32,
+    max_exposure: f32,
+    // share of the step to the metered exposure taken this frame
+    adaptation: f32,
+    // 0 meters the whole frame evenly, 1 only the center
+    center_weight: f32,
+    _padding: vec3<f32>,
+};
+
+@group(0) @binding(0) var t_frame: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> settings: Exposure;
+// weighted pixel count per log luminance bin
+@group(0) @binding(2) var<storage, read_write> histogram: array<atomic<u32>, 64>;
+// the exposure of the last frame, adapted each frame
+@group(0) @binding(3) var<storage, read_write> exposure: f32;
+
+const BIN_COUNT: u32 = 64u;
+// log2 luminance range of the bins, the frame is LDR so nothing is brighter than 1
+const MIN_LOG_LUMINANCE: f32 = -10.0;
+const MAX_LOG_LUMINANCE: f32 = 0.0;
+// weights are counted in fixed point
+const WEIGHT_SCALE: f32 = 16.0;
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+@compute @workgroup_size(16, 16)
+fn cs_histogram(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(t_frame);
+    if id.x >= size.x || id.y >= size.y {
+        return;
+    }
+    let color = textureLoad(t_frame, vec2<i32>(id.xy), 0).rgb;
+    // the undone exposure of the last frame meters the scene itself
+    let log_luminance = log2(max(luminance(color) / max(exposure, 1e-4), 1e-5));
+    let t = clamp((log_luminance - MIN_LOG_LUMINANCE) / (MAX_LOG_LUMINANCE - MIN_LOG_LUMINANCE), 0.0, 1.0);
+    let bin = min(u32(t * f32(BIN_COUNT)), BIN_COUNT - 1u);
+    // falls off towards the corners
+    let offset = (vec2<f32>(id.xy) + 0.5) / vec2<f32>(size) * 2.0 - 1.0;
+    let weight = mix(1.0, max(1.0 - dot(offset, offset) * 0.5, 0.0), settings.center_weight);
+    atomicAdd(&histogram[bin], u32(weight * WEIGHT_SCALE + 0.5));
+}
+
+@compute @workgroup_size(1)
+fn cs_adapt() {
+    var total = 0.0;
+    var log_sum = 0.0;
+    for (var bin = 0u; bin < BIN_COUNT; bin++) {
+        let count = f32(atomicExchange(&histogram[bin], 0u));
+        let log_luminance = mix(MIN_LOG_LUMINANCE, MAX_LOG_LUMINANCE, (f32(bin) + 0.5) / f32(BIN_COUNT));
+        total += count;
+        log_sum += count * log_luminance;
+    }
+    if total == 0.0 {
+        return;
+    }
+    let average = exp2(log_sum / total);
+    let target_exposure = clamp(settings.key / average, settings.min_exposure, settings.max_exposure);
+    exposure = mix(exposure, target_exposure, settings.adaptation);
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+};
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // a single triangle covering the whole viewport
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    return out;
+}
+
+// the exposure buffer again, read-only for the fragment stage
+@group(0) @binding(4) var<storage, read> applied_exposure: f32;
+
+@fragment
+fn fs_expose(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureLoad(t_frame, vec2<i32>(in.position.xy), 0).rgb;
+    return vec4<f32>(color * applied_exposure, 1.0);
+}
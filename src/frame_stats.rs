@@ -0,0 +1,250 @@
+/*
+ * Frame statistics.
+ * The frame times of the last frames give the average frame rate and the 1% low, the frame rate of the
+ * slowest hundredth of the frames, which shows stutter an average hides. Where the adapter supports
+ * timestamp queries inside encoders, the GPU time of the shadow, blur and forward passes is measured as
+ * well, each on its own so work recorded between them doesn't count, and is available to embedders with
+ * `Renderer::last_pass_timings`. The HUD shows the statistics, `--frame-stats` also prints them every
+ * second.
+ */
+use instant::Instant;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Features the GPU timer needs, requested if the adapter has them.
+pub const GPU_TIMER_FEATURES: wgpu::Features =
+    wgpu::Features::TIMESTAMP_QUERY.union(wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS);
+
+/// Number of frames the statistics are taken over.
+const FRAME_WINDOW: usize = 300;
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct FrameStats {
+    /// Frame times in seconds, the latest last.
+    frame_times: VecDeque<f32>,
+    /// GPU time of the passes of the latest frame that was read back.
+    gpu_times: Vec<(&'static str, Duration)>,
+    /// Prints the statistics to the console every [`REPORT_INTERVAL`].
+    print: bool,
+    last_report: Instant,
+}
+
+impl FrameStats {
+    pub fn new(print: bool) -> Self {
+        Self {
+            frame_times: VecDeque::with_capacity(FRAME_WINDOW),
+            gpu_times: Vec::new(),
+            print,
+            last_report: Instant::now(),
+        }
+    }
+
+    /// Adds a frame that took `frame_time` seconds, with the latest pass times of the [`GpuTimer`].
+    pub fn record(&mut self, frame_time: f32, gpu_times: &[(&'static str, Duration)]) {
+        if self.frame_times.len() == FRAME_WINDOW {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_time);
+        self.gpu_times.clear();
+        self.gpu_times.extend_from_slice(gpu_times);
+
+        if self.print && self.last_report.elapsed() >= REPORT_INTERVAL {
+            self.last_report = Instant::now();
+            println!("{}", self.summary_lines().join("\n  "));
+        }
+    }
+
+    /// Average frames per second.
+    pub fn average_fps(&self) -> f32 {
+        let total: f32 = self.frame_times.iter().sum();
+        self.frame_times.len() as f32 / total.max(f32::EPSILON)
+    }
+
+    /// Frames per second over the slowest 1% of the frames, at least one frame.
+    pub fn one_percent_low_fps(&self) -> f32 {
+        let mut frame_times = self.frame_times.iter().copied().collect::<Vec<_>>();
+        frame_times.sort_by(|a, b| b.total_cmp(a));
+        let slowest = &frame_times[..frame_times.len().div_ceil(100)];
+        let total: f32 = slowest.iter().sum();
+        slowest.len() as f32 / total.max(f32::EPSILON)
+    }
+
+    pub fn hud_lines(&self) -> Vec<String> {
+        if self.frame_times.is_empty() {
+            return Vec::new();
+        }
+        self.summary_lines()
+    }
+
+    fn summary_lines(&self) -> Vec<String> {
+        let average = self.average_fps();
+        let mut lines = vec![format!(
+            "{average:.0} FPS ({:.2} ms), 1% low {:.0} FPS",
+            1000.0 / average,
+            self.one_percent_low_fps()
+        )];
+        if !self.gpu_times.is_empty() {
+            let passes = self
+                .gpu_times
+                .iter()
+                .map(|(pass, time)| format!("{pass} {:.2} ms", time.as_secs_f64() * 1000.0))
+                .collect::<Vec<_>>();
+            lines.push(format!("GPU: {}", passes.join(", ")));
+        }
+        lines
+    }
+}
+
+/// Most timestamps a frame writes, the start and end of each pass.
+const MAX_TIMESTAMPS: u32 = 16;
+const TIMESTAMP_SIZE: wgpu::BufferAddress = size_of::<u64>() as wgpu::BufferAddress;
+
+/// Set by the callback of `map_async`, Some(true) once the buffer is mapped.
+type MapResult = Arc<Mutex<Option<bool>>>;
+
+/// Measures the GPU time of passes with timestamp queries. Results are read back asynchronously, frames
+/// recorded while the previous results are still being mapped are not measured.
+pub struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    /// Passes of the frame being recorded, None outside of measured frames.
+    passes: Option<Vec<&'static str>>,
+    /// Set between [`GpuTimer::begin_pass`] and [`GpuTimer::end_pass`] of a measured pass.
+    pass_open: bool,
+    /// Passes of the frame in the readback buffer, and whether mapping it succeeded once it finished.
+    readback: Option<(Vec<&'static str>, MapResult)>,
+    /// Set when a frame was copied into the readback buffer and it needs to be mapped after the submit.
+    map_after_submit: bool,
+    /// GPU time of the passes of the latest frame that was read back.
+    pass_times: Vec<(&'static str, Duration)>,
+}
+
+impl GpuTimer {
+    /// None if the device lacks the [`GPU_TIMER_FEATURES`].
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(GPU_TIMER_FEATURES) {
+            return None;
+        }
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("gpu_timer_queries"),
+            ty: wgpu::QueryType::Timestamp,
+            count: MAX_TIMESTAMPS,
+        });
+        let size = MAX_TIMESTAMPS as wgpu::BufferAddress * TIMESTAMP_SIZE;
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("gpu_timer_resolve_buffer"),
+            size,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("gpu_timer_readback_buffer"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Some(Self {
+            query_set,
+            resolve_buffer,
+            readback_buffer,
+            period: queue.get_timestamp_period(),
+            passes: None,
+            pass_open: false,
+            readback: None,
+            map_after_submit: false,
+            pass_times: Vec::new(),
+        })
+    }
+
+    /// Collects the results of an earlier frame if they arrived and starts measuring the next frame, unless
+    /// the readback buffer is still in use.
+    pub fn begin_frame(&mut self, device: &wgpu::Device) {
+        if let Some((passes, mapped)) = &self.readback {
+            let _ = device.poll(wgpu::Maintain::Poll);
+            let Some(success) = *mapped.lock().unwrap() else {
+                return;
+            };
+            if success {
+                let data = self.readback_buffer.slice(..).get_mapped_range();
+                let timestamps: &[u64] = bytemuck::cast_slice(&data);
+                self.pass_times = passes
+                    .iter()
+                    .zip(timestamps.chunks_exact(2))
+                    .map(|(pass, ticks)| {
+                        let nanos = ticks[1].saturating_sub(ticks[0]) as f32 * self.period;
+                        (*pass, Duration::from_nanos(nanos as u64))
+                    })
+                    .collect();
+                drop(data);
+                self.readback_buffer.unmap();
+            }
+            self.readback = None;
+        }
+        self.passes = Some(Vec::new());
+    }
+
+    /// Starts measuring `pass`, until [`GpuTimer::end_pass`]. Does nothing outside of a measured frame or
+    /// once the frame has measured as many passes as there are queries.
+    pub fn begin_pass(&mut self, encoder: &mut wgpu::CommandEncoder, pass: &'static str) {
+        let Some(passes) = &mut self.passes else {
+            return;
+        };
+        let index = passes.len() as u32 * 2;
+        if index + 1 < MAX_TIMESTAMPS {
+            encoder.write_timestamp(&self.query_set, index);
+            passes.push(pass);
+            self.pass_open = true;
+        }
+    }
+
+    pub fn end_pass(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if !std::mem::take(&mut self.pass_open) {
+            return;
+        }
+        if let Some(passes) = &self.passes {
+            encoder.write_timestamp(&self.query_set, passes.len() as u32 * 2 - 1);
+        }
+    }
+
+    /// GPU time of the passes of the latest frame that was read back, in the order they were recorded.
+    pub fn pass_times(&self) -> &[(&'static str, Duration)] {
+        &self.pass_times
+    }
+
+    /// Copies the timestamps of the frame into the readback buffer.
+    pub fn end_frame(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let Some(passes) = self.passes.take() else {
+            return;
+        };
+        let count = passes.len() as u32 * 2;
+        encoder.resolve_query_set(&self.query_set, 0..count, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &self.readback_buffer,
+            0,
+            count as wgpu::BufferAddress * TIMESTAMP_SIZE,
+        );
+        self.readback = Some((passes, Arc::new(Mutex::new(None))));
+        self.map_after_submit = true;
+    }
+
+    /// Maps the readback buffer once the encoder of the measured frame was submitted.
+    pub fn submitted(&mut self) {
+        if !std::mem::take(&mut self.map_after_submit) {
+            return;
+        }
+        if let Some((_, mapped)) = &self.readback {
+            let mapped = mapped.clone();
+            self.readback_buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    *mapped.lock().unwrap() = Some(result.is_ok());
+                });
+        }
+    }
+}
@@ -0,0 +1,98 @@
+/*
+ * Gamepad input.
+ * gilrs reads the native controller APIs and, on the web, the browser's Gamepad API. The gamepad that
+ * was used last is polled once per frame: its sticks and triggers are read with a radial dead zone, and
+ * its button presses are collected so the application can map them to its actions.
+ */
+use gilrs::{Axis, Button, EventType, GamepadId, Gilrs};
+use glam::Vec2;
+
+/// Analog input and button presses since the last poll, all zero without a gamepad.
+#[derive(Debug, Clone, Default)]
+pub struct GamepadState {
+    pub left_stick: Vec2,
+    pub right_stick: Vec2,
+    /// Analog triggers from 0 to 1.
+    pub left_trigger: f32,
+    pub right_trigger: f32,
+    pub pressed: Vec<Button>,
+}
+
+pub struct GamepadInput {
+    /// Stick deflection from 0 to 1 that is ignored, the rest of the range is scaled to start at 0.
+    pub dead_zone: f32,
+    gilrs: Option<Gilrs>,
+    active: Option<GamepadId>,
+}
+
+impl GamepadInput {
+    pub fn new() -> Self {
+        let gilrs = match Gilrs::new() {
+            Ok(gilrs) => Some(gilrs),
+            Err(e) => {
+                println!("Gamepads are not available: {e}");
+                None
+            }
+        };
+        Self {
+            dead_zone: 0.15,
+            gilrs,
+            active: None,
+        }
+    }
+
+    /// Processes the pending gamepad events and reads the state of the active gamepad.
+    pub fn poll(&mut self) -> GamepadState {
+        let Some(gilrs) = &mut self.gilrs else {
+            return GamepadState::default();
+        };
+        let mut pressed = Vec::new();
+        while let Some(event) = gilrs.next_event() {
+            match event.event {
+                EventType::Connected => {
+                    println!("Gamepad connected: {}", gilrs.gamepad(event.id).name())
+                }
+                EventType::Disconnected if self.active == Some(event.id) => self.active = None,
+                EventType::ButtonPressed(button, _) => {
+                    // the pressed buttons of the previously active gamepad are dropped
+                    if self.active != Some(event.id) {
+                        pressed.clear();
+                    }
+                    self.active = Some(event.id);
+                    pressed.push(button);
+                }
+                EventType::AxisChanged(..) => self.active = Some(event.id),
+                _ => {}
+            }
+        }
+
+        let Some(gamepad) = self.active.and_then(|id| gilrs.connected_gamepad(id)) else {
+            return GamepadState::default();
+        };
+        let stick = |x, y| {
+            apply_dead_zone(
+                Vec2::new(gamepad.value(x), gamepad.value(y)),
+                self.dead_zone,
+            )
+        };
+        let trigger = |button| gamepad.button_data(button).map_or(0.0, |data| data.value());
+        GamepadState {
+            left_stick: stick(Axis::LeftStickX, Axis::LeftStickY),
+            right_stick: stick(Axis::RightStickX, Axis::RightStickY),
+            left_trigger: trigger(Button::LeftTrigger2),
+            right_trigger: trigger(Button::RightTrigger2),
+            pressed,
+        }
+    }
+}
+
+/// Ignores deflections up to `dead_zone` in any direction and scales the rest to the range from 0 to 1,
+/// so the stick starts moving smoothly at the edge of the dead zone.
+fn apply_dead_zone(stick: Vec2, dead_zone: f32) -> Vec2 {
+    let length = stick.length();
+    if length <= dead_zone {
+        return Vec2::ZERO;
+    }
+    let scaled = ((length - dead_zone) / (1.0 - dead_zone)).min(1.0);
+    stick / length * scaled
+}
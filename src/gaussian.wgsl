@@ -7,16 +7,20 @@ var output_texture: texture_storage_2d_array<rgba32float, write>;
 @group(0) @binding(2)
 var<uniform> is_vertical: u32;
 
-const KERNEL_RADIUS: i32 = 8;
-const KERNEL_SIZE: i32 = (KERNEL_RADIUS * 2 + 1);
-const INV_KERNEL_SIZE: f32 = 1.0 / f32(KERNEL_SIZE);
+// layer of the first workgroup along z, set through a dynamic offset
+@group(0) @binding(3)
+var<uniform> first_layer: u32;
 
-@compute @workgroup_size(16, 16, 1)
+// set from the render settings when the pipeline is created
+override KERNEL_RADIUS: i32 = 8;
+override WORKGROUP_SIZE: u32 = 16u;
+
+@compute @workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE, 1)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let dims = textureDimensions(input_texture);
     let width = i32(dims.x);
     let height = i32(dims.y);
-    let layer = i32(global_id.z);
+    let layer = i32(first_layer + global_id.z);
     let x = i32(global_id.x);
     let y = i32(global_id.y);
 
@@ -41,6 +45,6 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
         }
     }
 
-    let blurred = sum * INV_KERNEL_SIZE;
+    let blurred = sum / f32(KERNEL_RADIUS * 2 + 1);
     textureStore(output_texture, vec2<i32>(x, y), layer, blurred);
 }
@@ -0,0 +1,60 @@
+// Appended to shader.wgsl for the glass pass, see refraction.rs.
+// Glass is not lit, it shows the refracted opaque scene and a fresnel reflection of the sky.
+struct Refraction {
+    screen_size: vec2<f32>,
+    // distance the refracted ray travels before the background is looked up, in world units
+    thickness: f32,
+    // blur radius in pixels at roughness 1
+    blur_radius: f32,
+};
+
+@group(LIGHTS_GROUP) @binding(0) var t_opaque: texture_2d<f32>;
+@group(LIGHTS_GROUP) @binding(1) var s_opaque: sampler;
+@group(LIGHTS_GROUP) @binding(2) var<uniform> refraction: Refraction;
+
+// clear color of the forward pass
+const SKY_COLOR: vec3<f32> = vec3<f32>(0.1, 0.2, 0.3);
+
+@fragment
+fn fs_glass(in: VertexOutput) -> @location(0) vec4<f32> {
+    let view_dir = normalize(in.world_position.xyz - camera.position.xyz);
+    var normal = normalize(in.world_normal);
+    // panes are seen from both sides
+    if (dot(normal, view_dir) > 0.0) {
+        normal = -normal;
+    }
+
+    // entering a denser medium, so there is no total internal reflection
+    let ior = max(material.ior, 1.0);
+    let refracted = refract(view_dir, normal, 1.0 / ior);
+    let exit_clip = camera.view_proj * vec4<f32>(in.world_position.xyz + refracted * refraction.thickness, 1.0);
+    var uv = in.out_position.xy / refraction.screen_size;
+    if (exit_clip.w > 0.0) {
+        uv = exit_clip.xy / exit_clip.w * vec2<f32>(0.5, -0.5) + vec2<f32>(0.5);
+    }
+
+    // rough glass averages a disc around the refracted position
+    var taps = array<vec2<f32>, 8>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(0.527, 0.085),
+        vec2<f32>(-0.040, 0.536),
+        vec2<f32>(-0.670, -0.180),
+        vec2<f32>(0.120, -0.800),
+        vec2<f32>(0.870, 0.430),
+        vec2<f32>(-0.460, 0.850),
+        vec2<f32>(-0.580, -0.790),
+    );
+    let radius = material.roughness * refraction.blur_radius / refraction.screen_size;
+    var background = vec3<f32>(0.0);
+    for (var i = 0u; i < 8u; i += 1u) {
+        let tap_uv = clamp(uv + taps[i] * radius, vec2<f32>(0.0), vec2<f32>(1.0));
+        background += textureSampleLevel(t_opaque, s_opaque, tap_uv, 0.0).rgb;
+    }
+    background /= 8.0;
+
+    // thin panes barely tint the background, opaque ones take on the diffuse color
+    let tint = mix(vec3<f32>(1.0), material.diffuse.rgb, material.dissolve);
+    let f0 = pow((ior - 1.0) / (ior + 1.0), 2.0);
+    let fresnel = f0 + (1.0 - f0) * pow(1.0 - max(dot(-view_dir, normal), 0.0), 5.0);
+    return vec4<f32>(mix(background * tint, SKY_COLOR, fresnel), 1.0);
+}
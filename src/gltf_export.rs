@@ -0,0 +1,413 @@
+/*
+ * glTF export.
+ * Writes the scene graph into a binary glTF file (.glb), to take a scene assembled in the viewer into
+ * other tools: the groups and render nodes with their transforms, the meshes with their positions,
+ * normals and texture coordinates, the materials as metallic-roughness materials with the diffuse texture
+ * embedded, the lights with KHR_lights_punctual and the camera. Each instance of an instanced node becomes
+ * a node of its own. Shadows, custom materials, paths and constraints have no glTF counterpart and are
+ * left out, Ctrl+E exports the current scene into `scene_<timestamp>.glb`.
+ */
+use crate::camera::{Camera, Projection};
+use crate::constraint::camera_matrix;
+use crate::light::{Light, LightKind};
+use crate::model::MaterialInfo;
+use crate::resources::load_binary;
+use crate::scenegraph::{Node, RenderNode, SceneGraph};
+use glam::{DMat4, Mat4, Vec3};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::Path;
+
+const GLB_MAGIC: u32 = 0x4654_6C67;
+const GLB_VERSION: u32 = 2;
+const CHUNK_JSON: u32 = 0x4E4F_534A;
+const CHUNK_BIN: u32 = 0x004E_4942;
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+
+/// Half of the opening angle of the spot light shadow frustum, see [`crate::light::Light::calculate_matrix`].
+const SPOT_OUTER_CONE: f32 = std::f32::consts::FRAC_PI_6;
+
+/// Writes the scene graph and `camera` into the .glb file at `path`.
+pub fn export_glb(scene_graph: &SceneGraph, camera: &Camera, path: &Path) -> anyhow::Result<()> {
+    let mut gltf = GltfBuilder::default();
+    let mut scene_nodes = vec![gltf.node(&scene_graph.root, DMat4::IDENTITY)];
+    scene_nodes.push(gltf.camera(camera));
+    std::fs::write(path, gltf.into_glb(scene_nodes)?)?;
+    Ok(())
+}
+
+/// The arrays of the glTF document and the binary chunk their data is in.
+#[derive(Default)]
+struct GltfBuilder {
+    binary: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>,
+    nodes: Vec<Value>,
+    meshes: Vec<Value>,
+    materials: Vec<Value>,
+    /// Material index of each material exported so far, materials are shared by many meshes.
+    material_indices: Vec<(MaterialInfo, usize)>,
+    images: Vec<Value>,
+    textures: Vec<Value>,
+    /// Texture index of each image file, None if it couldn't be embedded.
+    texture_indices: HashMap<String, Option<usize>>,
+    lights: Vec<Value>,
+    cameras: Vec<Value>,
+}
+
+impl GltfBuilder {
+    /// Adds `node` and its children below a parent at `parent_world`, returns its index.
+    fn node(&mut self, node: &Node, parent_world: DMat4) -> usize {
+        let mut value = json!({ "name": node.name() });
+        let local = match node {
+            Node::GroupNode(group) => {
+                let world = parent_world * node.matrix_f64();
+                let children: Vec<usize> = group
+                    .children
+                    .iter()
+                    .map(|child| self.node(child, world))
+                    .collect();
+                if !children.is_empty() {
+                    value["children"] = json!(children);
+                }
+                node.matrix()
+            }
+            Node::RenderNode(render_node) => {
+                if let Some(mesh) = self.mesh(render_node) {
+                    match render_node.instance_transforms() {
+                        [transform] if *transform == Mat4::IDENTITY => value["mesh"] = json!(mesh),
+                        // the instance transforms are relative to the node
+                        transforms => {
+                            let instances: Vec<usize> = transforms
+                                .iter()
+                                .enumerate()
+                                .map(|(i, transform)| {
+                                    self.nodes.push(json!({
+                                        "name": format!("{} {i}", node.name()),
+                                        "matrix": transform.to_cols_array(),
+                                        "mesh": mesh,
+                                    }));
+                                    self.nodes.len() - 1
+                                })
+                                .collect();
+                            value["children"] = json!(instances);
+                        }
+                    }
+                }
+                node.matrix()
+            }
+            Node::LightNode(light_node) => {
+                let light = &light_node.light;
+                let color = light.color();
+                let mut khr_light = json!({
+                    "name": node.name(),
+                    "color": [color.r, color.g, color.b],
+                    "intensity": light.intensity,
+                });
+                if light.range > 0.0 {
+                    khr_light["range"] = json!(light.range);
+                }
+                let position =
+                    (parent_world * node.matrix_f64()).transform_point3(light.pos.as_dvec3());
+                // glTF lights shine along -Z of their node
+                let world = match light.kind {
+                    LightKind::Spot => {
+                        khr_light["type"] = json!("spot");
+                        khr_light["spot"] = json!({ "outerConeAngle": SPOT_OUTER_CONE });
+                        let aim = light.aim.unwrap_or(Light::DEFAULT_AIM);
+                        camera_matrix(position.as_vec3(), aim)
+                    }
+                    LightKind::Point => {
+                        khr_light["type"] = json!("point");
+                        DMat4::from_translation(position)
+                    }
+                };
+                value["extensions"] =
+                    json!({ "KHR_lights_punctual": { "light": self.lights.len() } });
+                self.lights.push(khr_light);
+                (parent_world.inverse() * world).as_mat4()
+            }
+        };
+        if local != Mat4::IDENTITY {
+            value["matrix"] = json!(local.to_cols_array());
+        }
+        self.nodes.push(value);
+        self.nodes.len() - 1
+    }
+
+    /// Adds the mesh of `render_node`, None if it has no triangles.
+    fn mesh(&mut self, render_node: &RenderNode) -> Option<usize> {
+        let vertices = render_node.vertices();
+        let indices = render_node.indices();
+        if vertices.is_empty() || indices.is_empty() {
+            return None;
+        }
+        let positions: Vec<[f32; 3]> = vertices.iter().map(|vertex| vertex.pos).collect();
+        let min = positions
+            .iter()
+            .fold(Vec3::INFINITY, |min, &pos| min.min(pos.into()));
+        let max = positions
+            .iter()
+            .fold(Vec3::NEG_INFINITY, |max, &pos| max.max(pos.into()));
+        let mut attributes = json!({
+            "POSITION": self.accessor(
+                bytemuck::cast_slice(&positions),
+                ARRAY_BUFFER,
+                FLOAT,
+                positions.len(),
+                "VEC3",
+                Some((min.to_array(), max.to_array())),
+            ),
+        });
+        // glTF normals are unit vectors, meshes without normals have zero ones
+        let normals: Option<Vec<[f32; 3]>> = vertices
+            .iter()
+            .map(|vertex| Some(Vec3::from(vertex.normal).try_normalize()?.to_array()))
+            .collect();
+        if let Some(normals) = normals {
+            attributes["NORMAL"] = json!(self.accessor(
+                bytemuck::cast_slice(&normals),
+                ARRAY_BUFFER,
+                FLOAT,
+                normals.len(),
+                "VEC3",
+                None,
+            ));
+        }
+        let tex_coords: Vec<[f32; 2]> = vertices.iter().map(|vertex| vertex.tex_coords).collect();
+        attributes["TEXCOORD_0"] = json!(self.accessor(
+            bytemuck::cast_slice(&tex_coords),
+            ARRAY_BUFFER,
+            FLOAT,
+            tex_coords.len(),
+            "VEC2",
+            None,
+        ));
+        let mut primitive = json!({
+            "attributes": attributes,
+            "indices": self.accessor(
+                bytemuck::cast_slice(indices),
+                ELEMENT_ARRAY_BUFFER,
+                UNSIGNED_INT,
+                indices.len(),
+                "SCALAR",
+                None,
+            ),
+        });
+        // nodes without a material, e.g. added with add_render_node, get the glTF default material
+        if !render_node.material_info.name.is_empty() {
+            primitive["material"] = json!(self.material(&render_node.material_info));
+        }
+        self.meshes.push(json!({ "primitives": [primitive] }));
+        Some(self.meshes.len() - 1)
+    }
+
+    fn material(&mut self, info: &MaterialInfo) -> usize {
+        if let Some((_, index)) = self
+            .material_indices
+            .iter()
+            .find(|(other, _)| other == info)
+        {
+            return *index;
+        }
+        let texture = info
+            .diffuse_file
+            .as_ref()
+            .and_then(|file| self.texture(file));
+        // the diffuse texture replaces the diffuse color in the forward shaders instead of tinting it
+        let [r, g, b] = if texture.is_some() {
+            [1.0; 3]
+        } else {
+            info.diffuse
+        };
+        let mut pbr = json!({
+            "baseColorFactor": [r, g, b, info.dissolve],
+            "metallicFactor": info.metallic,
+            "roughnessFactor": info.roughness,
+        });
+        if let Some(texture) = texture {
+            pbr["baseColorTexture"] = json!({ "index": texture });
+        }
+        let mut material = json!({
+            "name": info.name,
+            "pbrMetallicRoughness": pbr,
+            "doubleSided": info.double_sided,
+        });
+        if info.emissive != [0.0; 3] {
+            // glTF needs an extension for emission brighter than white
+            material["emissiveFactor"] =
+                json!(info.emissive.map(|channel| channel.clamp(0.0, 1.0)));
+        }
+        if info.dissolve < 1.0 {
+            material["alphaMode"] = json!("BLEND");
+        }
+        let index = self.materials.len();
+        self.materials.push(material);
+        self.material_indices.push((info.clone(), index));
+        index
+    }
+
+    /// Embeds the image `file` as a texture, PNG and JPEG files as they are and other formats converted
+    /// to PNG. None if the file can't be read.
+    fn texture(&mut self, file: &str) -> Option<usize> {
+        if let Some(index) = self.texture_indices.get(file) {
+            return *index;
+        }
+        let index = match embedded_image(file) {
+            Ok((data, mime_type)) => {
+                let view = self.buffer_view(&data, None);
+                self.images
+                    .push(json!({ "bufferView": view, "mimeType": mime_type }));
+                self.textures
+                    .push(json!({ "source": self.images.len() - 1 }));
+                Some(self.textures.len() - 1)
+            }
+            Err(e) => {
+                println!("glTF export leaves out the texture {file}: {e}");
+                None
+            }
+        };
+        self.texture_indices.insert(file.to_string(), index);
+        index
+    }
+
+    /// Adds a node with the camera, returns its index.
+    fn camera(&mut self, camera: &Camera) -> usize {
+        let projection = match camera.projection {
+            Projection::Perspective => json!({
+                "type": "perspective",
+                "perspective": {
+                    "yfov": camera.fovy.to_radians(),
+                    "aspectRatio": camera.aspect,
+                    "znear": camera.znear,
+                    "zfar": camera.zfar,
+                },
+            }),
+            Projection::Orthographic { height } => json!({
+                "type": "orthographic",
+                "orthographic": {
+                    "xmag": height * 0.5 * camera.aspect,
+                    "ymag": height * 0.5,
+                    "znear": camera.znear,
+                    "zfar": camera.zfar,
+                },
+            }),
+        };
+        self.cameras.push(projection);
+        self.nodes.push(json!({
+            "name": "camera",
+            "camera": self.cameras.len() - 1,
+            "matrix": camera_matrix(camera.eye, camera.target).as_mat4().to_cols_array(),
+        }));
+        self.nodes.len() - 1
+    }
+
+    /// Appends `data` to the binary chunk, 4 byte aligned as accessors need it.
+    fn buffer_view(&mut self, data: &[u8], target: Option<u32>) -> usize {
+        self.binary.resize(self.binary.len().next_multiple_of(4), 0);
+        let mut view = json!({
+            "buffer": 0,
+            "byteOffset": self.binary.len(),
+            "byteLength": data.len(),
+        });
+        if let Some(target) = target {
+            view["target"] = json!(target);
+        }
+        self.binary.extend_from_slice(data);
+        self.buffer_views.push(view);
+        self.buffer_views.len() - 1
+    }
+
+    fn accessor(
+        &mut self,
+        data: &[u8],
+        target: u32,
+        component_type: u32,
+        count: usize,
+        kind: &str,
+        bounds: Option<([f32; 3], [f32; 3])>,
+    ) -> usize {
+        let view = self.buffer_view(data, Some(target));
+        let mut accessor = json!({
+            "bufferView": view,
+            "componentType": component_type,
+            "count": count,
+            "type": kind,
+        });
+        if let Some((min, max)) = bounds {
+            accessor["min"] = json!(min);
+            accessor["max"] = json!(max);
+        }
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    /// The .glb file with `scene_nodes` as the nodes of its scene.
+    fn into_glb(mut self, scene_nodes: Vec<usize>) -> anyhow::Result<Vec<u8>> {
+        self.binary.resize(self.binary.len().next_multiple_of(4), 0);
+        let mut document = json!({
+            "asset": { "version": "2.0", "generator": env!("CARGO_PKG_NAME") },
+            "scene": 0,
+            "scenes": [{ "nodes": scene_nodes }],
+            "nodes": self.nodes,
+            "cameras": self.cameras,
+        });
+        // a scene without render nodes has no binary data, and glTF doesn't allow empty buffers
+        if !self.binary.is_empty() {
+            document["buffers"] = json!([{ "byteLength": self.binary.len() }]);
+        }
+        // glTF doesn't allow empty arrays
+        for (name, values) in [
+            ("bufferViews", self.buffer_views),
+            ("accessors", self.accessors),
+            ("meshes", self.meshes),
+            ("materials", self.materials),
+            ("images", self.images),
+            ("textures", self.textures),
+        ] {
+            if !values.is_empty() {
+                document[name] = Value::Array(values);
+            }
+        }
+        if !self.lights.is_empty() {
+            document["extensionsUsed"] = json!(["KHR_lights_punctual"]);
+            document["extensions"] = json!({ "KHR_lights_punctual": { "lights": self.lights } });
+        }
+
+        let mut json = serde_json::to_vec(&document)?;
+        json.resize(json.len().next_multiple_of(4), b' ');
+        let mut chunks = vec![(CHUNK_JSON, &json)];
+        if !self.binary.is_empty() {
+            chunks.push((CHUNK_BIN, &self.binary));
+        }
+        let length = 12 + chunks.iter().map(|(_, chunk)| 8 + chunk.len()).sum::<usize>();
+        let mut glb = Vec::with_capacity(length);
+        for word in [GLB_MAGIC, GLB_VERSION, u32::try_from(length)?] {
+            glb.extend_from_slice(&word.to_le_bytes());
+        }
+        for (chunk_type, chunk) in chunks {
+            glb.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+            glb.extend_from_slice(&chunk_type.to_le_bytes());
+            glb.extend_from_slice(chunk);
+        }
+        Ok(glb)
+    }
+}
+
+/// The data and MIME type of the image `file` as glTF can embed it.
+fn embedded_image(file: &str) -> anyhow::Result<(Vec<u8>, &'static str)> {
+    let data = pollster::block_on(load_binary(file))?;
+    match image::guess_format(&data)? {
+        image::ImageFormat::Png => Ok((data, "image/png")),
+        image::ImageFormat::Jpeg => Ok((data, "image/jpeg")),
+        _ => {
+            let mut png = std::io::Cursor::new(Vec::new());
+            image::load_from_memory(&data)?.write_to(&mut png, image::ImageFormat::Png)?;
+            Ok((png.into_inner(), "image/png"))
+        }
+    }
+}
@@ -0,0 +1,143 @@
+/*
+ * Golden-image comparison, started with `--compare-golden <dir>`.
+ * Renders the demo scene from a fixed set of camera views into an offscreen target and compares each
+ * image with the reference `<dir>/<view>.png`. Pixels are compared by their perceived color difference
+ * (in YIQ space, as in pixelmatch), so small rounding differences between GPUs pass. A missing
+ * reference fails the view, with `--update-golden` the current renderings are saved as the references
+ * instead. A failing view writes `<view>.actual.png` and `<view>.diff.png` next to the reference.
+ */
+use glam::Vec3;
+use image::{Rgba, RgbaImage};
+use std::path::{Path, PathBuf};
+
+/// Size of the rendered views, independent of the window.
+pub const GOLDEN_WIDTH: u32 = 640;
+pub const GOLDEN_HEIGHT: u32 = 480;
+/// Color difference from 0 to 1 above which a pixel counts as different.
+const PIXEL_THRESHOLD: f32 = 0.1;
+/// Share of different pixels a view may have and still pass.
+const MAX_DIFF_RATIO: f32 = 0.001;
+/// Largest possible YIQ difference, between black and white.
+const MAX_YIQ_DELTA: f32 = 35215.0;
+
+/// The references of `--compare-golden <dir>`, saved instead of compared with `--update-golden`.
+#[derive(Debug, Clone)]
+pub struct GoldenRun {
+    pub dir: PathBuf,
+    pub update: bool,
+}
+
+pub struct GoldenView {
+    pub name: &'static str,
+    pub eye: Vec3,
+    pub target: Vec3,
+}
+
+pub const GOLDEN_VIEWS: [GoldenView; 4] = [
+    // the start view
+    GoldenView {
+        name: "front",
+        eye: Vec3::new(0.0, 1.0, 30.0),
+        target: Vec3::ZERO,
+    },
+    GoldenView {
+        name: "side",
+        eye: Vec3::new(30.0, 5.0, 0.0),
+        target: Vec3::ZERO,
+    },
+    GoldenView {
+        name: "top",
+        eye: Vec3::new(0.0, 40.0, 10.0),
+        target: Vec3::ZERO,
+    },
+    GoldenView {
+        name: "close",
+        eye: Vec3::new(6.0, 3.0, 8.0),
+        target: Vec3::new(0.0, 1.0, 0.0),
+    },
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GoldenStatus {
+    /// The rendering was saved as the reference, see [`compare`].
+    Updated,
+    Passed {
+        diff_pixels: u32,
+    },
+    Failed {
+        diff_pixels: u32,
+    },
+}
+
+/// Compares `actual` with the reference of the view `name` in `dir`, or with `update` saves it as the
+/// reference. Fails if there is no reference to compare with.
+pub fn compare(
+    dir: &Path,
+    name: &str,
+    actual: &RgbaImage,
+    update: bool,
+) -> anyhow::Result<GoldenStatus> {
+    let reference_path = dir.join(format!("{name}.png"));
+    if update {
+        std::fs::create_dir_all(dir)?;
+        actual.save(&reference_path)?;
+        return Ok(GoldenStatus::Updated);
+    }
+    if !reference_path.exists() {
+        anyhow::bail!(
+            "There is no reference {}, run with --update-golden to create it",
+            reference_path.display()
+        );
+    }
+    let reference = image::open(&reference_path)?.to_rgba8();
+    if reference.dimensions() != actual.dimensions() {
+        anyhow::bail!(
+            "{} is {:?}, the rendering is {:?}",
+            reference_path.display(),
+            reference.dimensions(),
+            actual.dimensions()
+        );
+    }
+
+    let max_delta = MAX_YIQ_DELTA * PIXEL_THRESHOLD * PIXEL_THRESHOLD;
+    let mut diff = RgbaImage::new(actual.width(), actual.height());
+    let mut diff_pixels = 0;
+    for ((expected, got), out) in reference
+        .pixels()
+        .zip(actual.pixels())
+        .zip(diff.pixels_mut())
+    {
+        if color_delta(expected, got) > max_delta {
+            diff_pixels += 1;
+            *out = Rgba([255, 0, 0, 255]);
+        } else {
+            // faded reference for orientation
+            let gray = (luma(expected) * 0.25 + 191.0) as u8;
+            *out = Rgba([gray, gray, gray, 255]);
+        }
+    }
+
+    let allowed = (MAX_DIFF_RATIO * (actual.width() * actual.height()) as f32) as u32;
+    if diff_pixels <= allowed {
+        return Ok(GoldenStatus::Passed { diff_pixels });
+    }
+    actual.save(dir.join(format!("{name}.actual.png")))?;
+    diff.save(dir.join(format!("{name}.diff.png")))?;
+    Ok(GoldenStatus::Failed { diff_pixels })
+}
+
+fn luma(pixel: &Rgba<u8>) -> f32 {
+    let [r, g, b, _] = pixel.0.map(f32::from);
+    0.298_895 * r + 0.586_622 * g + 0.114_482 * b
+}
+
+/// Squared perceived difference of two colors in YIQ space, from 0 to [`MAX_YIQ_DELTA`].
+fn color_delta(a: &Rgba<u8>, b: &Rgba<u8>) -> f32 {
+    let [r1, g1, b1, _] = a.0.map(f32::from);
+    let [r2, g2, b2, _] = b.0.map(f32::from);
+    let (dr, dg, db) = (r1 - r2, g1 - g2, b1 - b2);
+    let y = 0.298_895 * dr + 0.586_622 * dg + 0.114_482 * db;
+    let i = 0.595_978 * dr - 0.274_176 * dg - 0.321_802 * db;
+    let q = 0.211_470 * dr - 0.522_617 * dg + 0.311_147 * db;
+    0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q
+}
@@ -0,0 +1,90 @@
+/*
+ * Adapter capability report, printed with `--gpu-info` and returned by `gpu_info()` on the web.
+ * Lists the adapter with its limits and features, followed by the optional paths of the renderer it
+ * enables, which explains fallbacks like the uniform light array or rendering without MSAA.
+ */
+use crate::frame_stats::GPU_TIMER_FEATURES;
+use crate::light::ShadowMap;
+use crate::msaa::Msaa;
+use crate::renderer::{
+    required_limits, supports_storage_resources, MSAA_SAMPLE_COUNT, STORAGE_LIGHT_FEATURES,
+};
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// Color format the MSAA support is reported for, the usual surface format.
+const REPORT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8UnormSrgb;
+
+/// Requests the adapter the renderer would use and describes it.
+pub async fn request_report() -> String {
+    let instance = wgpu::Instance::default();
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            compatible_surface: None,
+            power_preference: wgpu::PowerPreference::None,
+            force_fallback_adapter: false,
+        })
+        .await;
+    match adapter {
+        Some(adapter) => report(&adapter),
+        None => "No graphics adapter found".to_string(),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub async fn gpu_info() -> String {
+    request_report().await
+}
+
+pub fn report(adapter: &wgpu::Adapter) -> String {
+    let info = adapter.get_info();
+    let features = adapter.features();
+    let mut lines = vec![
+        format!("Adapter: {} ({:?})", info.name, info.device_type),
+        format!(
+            "Backend: {:?}, driver {} {}",
+            info.backend, info.driver, info.driver_info
+        ),
+        "Features:".to_string(),
+    ];
+    lines.extend(features.iter_names().map(|(name, _)| format!("  {name}")));
+    lines.push(format!("Limits: {:#?}", adapter.limits()));
+
+    let yes_no = |supported: bool| if supported { "yes" } else { "no" };
+    let storage = supports_storage_resources(adapter, &required_limits());
+    let light_buffer = if storage {
+        "a storage buffer"
+    } else {
+        "a uniform array"
+    };
+    let compute_blur = adapter
+        .get_downlevel_capabilities()
+        .flags
+        .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+        && adapter
+            .get_texture_format_features(ShadowMap::DEPTH_FORMAT)
+            .allowed_usages
+            .contains(wgpu::TextureUsages::STORAGE_BINDING);
+    let sample_count = Msaa::supported_sample_count(adapter, REPORT_FORMAT, MSAA_SAMPLE_COUNT);
+    lines.extend([
+        "Renderer:".to_string(),
+        format!(
+            "  storage buffers with {STORAGE_LIGHT_FEATURES:?}: {}, lights are read from {light_buffer}",
+            yes_no(storage)
+        ),
+        format!("  compute shadow blur: {}", yes_no(compute_blur)),
+        format!(
+            "  MSAA: {sample_count}x of the requested {MSAA_SAMPLE_COUNT}x for {REPORT_FORMAT:?}"
+        ),
+        format!(
+            "  multiview stereo and layered shadows: {}",
+            yes_no(features.contains(wgpu::Features::MULTIVIEW))
+        ),
+        format!(
+            "  GPU pass timing: {}",
+            yes_no(features.contains(GPU_TIMER_FEATURES))
+        ),
+    ]);
+    lines.join("\n")
+}
@@ -0,0 +1,237 @@
+/*
+ * HDR output.
+ * On displays and platforms where the surface offers `Rgba16Float`, the swapchain can be configured in
+ * scRGB: linear colors with the sRGB primaries where 1.0 is SDR white at 80 nits and brighter highlights
+ * go above it. The frame is rendered in the same linear colors as for an sRGB surface, and a fullscreen
+ * pass at the end of the frame scales it so that the white of the scene is as bright as SDR white of the
+ * display ("paper white") and rolls off highlights above it towards the peak luminance of the display
+ * instead of clipping them. It is enabled with the `[hdr]` table of the render settings; without an HDR
+ * surface format the frame is rendered in SDR as before. wgpu doesn't expose the color space of a
+ * surface, so HDR10 (Rec.2020 primaries with the PQ curve) can't be selected, only scRGB. The debug UI is
+ * drawn after the pass and keeps SDR brightness.
+ */
+use crate::custom_pass::{CustomPass, PassContext, PassStage};
+use crate::reflection::ReflectDevice;
+use crate::renderer::Pipeline;
+use serde::Deserialize;
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+
+/// Surface format of scRGB output.
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+/// Luminance of 1.0 in scRGB.
+const SCRGB_WHITE_NITS: f32 = 80.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HdrSettings {
+    /// Luminance in nits white surfaces of the scene are shown with.
+    pub paper_white: f32,
+    /// Luminance in nits the display reaches at most, highlights roll off towards it.
+    pub peak_luminance: f32,
+}
+
+impl Default for HdrSettings {
+    fn default() -> Self {
+        Self {
+            paper_white: 200.0,
+            peak_luminance: 1000.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct HdrUniform {
+    paper_white: f32,
+    peak: f32,
+    _padding: [f32; 2],
+}
+
+impl HdrUniform {
+    fn from_settings(settings: &HdrSettings) -> Self {
+        let paper_white = settings.paper_white.max(1.0) / SCRGB_WHITE_NITS;
+        Self {
+            paper_white,
+            peak: (settings.peak_luminance / SCRGB_WHITE_NITS).max(paper_white),
+            _padding: [0.0; 2],
+        }
+    }
+}
+
+/// Picks the scRGB format for the surface if it offers it, returns whether it did.
+pub fn select_hdr_format(
+    config: &mut wgpu::SurfaceConfiguration,
+    formats: &[wgpu::TextureFormat],
+) -> bool {
+    if !formats.contains(&HDR_FORMAT) {
+        println!("No HDR surface format, rendering SDR");
+        return false;
+    }
+    println!("HDR output in scRGB");
+    config.format = HDR_FORMAT;
+    config.view_formats.clear();
+    true
+}
+
+pub struct HdrOutputPass {
+    pipeline: Pipeline,
+    layout: wgpu::BindGroupLayout,
+    uniform_buffer: wgpu::Buffer,
+    /// Copy of the frame the pass reads from and its bind group, created for the size of the frame.
+    frame_copy: Option<(wgpu::Texture, wgpu::BindGroup)>,
+    /// Set once it was reported that the frame texture can't be copied.
+    reported: bool,
+}
+
+impl HdrOutputPass {
+    pub fn new(device: &wgpu::Device, settings: &HdrSettings) -> Self {
+        let layout = device.reflect_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("hdr_output_bind_group_layout"),
+        });
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("HDR Output Buffer"),
+            contents: bytemuck::bytes_of(&HdrUniform::from_settings(settings)),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let shader = device.reflect_shader(wgpu::ShaderModuleDescriptor {
+            label: Some("hdr"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("hdr.wgsl"))),
+        });
+        let pipeline = Pipeline::new(
+            device,
+            "hdr_output_pipeline",
+            &shader,
+            &[&layout],
+            "vs_fullscreen",
+            &[],
+            Some("fs_hdr"),
+            &[Some(wgpu::ColorTargetState {
+                format: HDR_FORMAT,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            None,
+            None,
+            None,
+            None,
+        );
+
+        Self {
+            pipeline,
+            layout,
+            uniform_buffer,
+            frame_copy: None,
+            reported: false,
+        }
+    }
+
+    /// Creates the copy of the frame for a frame texture like `frame`, again when its size changed.
+    fn prepare_frame_copy(&mut self, device: &wgpu::Device, frame: &wgpu::Texture) {
+        let fits = self
+            .frame_copy
+            .as_ref()
+            .is_some_and(|(copy, _)| copy.size() == frame.size());
+        if fits {
+            return;
+        }
+        let copy = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("hdr_frame_copy"),
+            size: frame.size(),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HDR_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(
+                        &copy.create_view(&Default::default()),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("hdr_output_bind_group"),
+        });
+        self.frame_copy = Some((copy, bind_group));
+    }
+}
+
+impl CustomPass for HdrOutputPass {
+    /// After the HUD, so it is as bright as the scene.
+    fn stage(&self) -> PassStage {
+        PassStage::Overlay
+    }
+
+    fn render(&mut self, context: PassContext) {
+        let frame = context.color_texture;
+        if frame.format() != HDR_FORMAT {
+            return;
+        }
+        if !frame.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+            if !std::mem::replace(&mut self.reported, true) {
+                println!(
+                    "HDR output shows the frame at 80 nits, the frame texture can't be copied"
+                );
+            }
+            return;
+        }
+        self.prepare_frame_copy(&context.renderer.device, frame);
+        let Some((copy, bind_group)) = &self.frame_copy else {
+            return;
+        };
+        context.encoder.copy_texture_to_texture(
+            frame.as_image_copy(),
+            copy.as_image_copy(),
+            frame.size(),
+        );
+
+        let mut rpass = context
+            .encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("hdr_output_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: context.color,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+        rpass.set_pipeline(&self.pipeline.pipeline);
+        rpass.set_bind_group(0, bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
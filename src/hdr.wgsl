@@ -0,0 +1,39 @@
+// Maps the linear frame to scRGB, see hdr.rs. 1.0 in scRGB is 80 nits.
+struct HdrOutput {
+    // scRGB value of white in the frame
+    paper_white: f32,
+    // scRGB value of the peak luminance of the display, at least paper_white
+    peak: f32,
+    _padding: vec2<f32>,
+};
+
+@group(0) @binding(0) var t_frame: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> output: HdrOutput;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+};
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // a single triangle covering the whole viewport
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_hdr(in: VertexOutput) -> @location(0) vec4<f32> {
+    let frame = textureLoad(t_frame, vec2<i32>(in.position.xy), 0);
+    let color = max(frame.rgb, vec3<f32>(0.0)) * output.paper_white;
+    // above paper white the brightest channel approaches the peak instead of clipping at it, the hue is
+    // kept by scaling all channels alike
+    let brightest = max(color.r, max(color.g, color.b));
+    let headroom = output.peak - output.paper_white;
+    if (brightest <= output.paper_white || headroom <= 0.0) {
+        return vec4<f32>(min(color, vec3<f32>(output.peak)), frame.a);
+    }
+    let rolled_off = output.paper_white + headroom * (1.0 - exp(-(brightest - output.paper_white) / headroom));
+    return vec4<f32>(color * (rolled_off / brightest), frame.a);
+}
@@ -0,0 +1,281 @@
+/*
+ * Debug HUD.
+ * Text lines are rasterized on the CPU with ab_glyph into a texture, which is drawn over the
+ * top left corner of the frame. Sizes are given in logical pixels and multiplied by the window's
+ * scale factor and a user adjustable UI scale, so the text stays readable on high DPI displays.
+ */
+use crate::reflection::ReflectDevice;
+use crate::renderer::Pipeline;
+use ab_glyph::{Font, FontArc, PxScale, ScaleFont};
+use std::borrow::Cow;
+
+const FONT_SIZE: f32 = 14.0;
+const PADDING: f32 = 4.0;
+const MARGIN: f32 = 8.0;
+const BACKGROUND_ALPHA: u8 = 160;
+
+pub struct Hud {
+    pub visible: bool,
+    font: FontArc,
+    scale_factor: f32,
+    ui_scale: f32,
+    lines: Vec<String>,
+    dirty: bool,
+    pipeline: Pipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    // rasterized text and its bind group, None while there is nothing to show
+    texture: Option<(wgpu::Texture, wgpu::BindGroup)>,
+}
+
+impl Hud {
+    pub const MIN_UI_SCALE: f32 = 0.5;
+    pub const MAX_UI_SCALE: f32 = 4.0;
+
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, scale_factor: f64) -> Self {
+        let font = FontArc::try_from_slice(include_bytes!("../assets/fonts/DejaVuSansMono.ttf"))
+            .expect("Failed to load the HUD font");
+
+        let bind_group_layout = device.reflect_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("hud_bind_group_layout"),
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("hud_sampler"),
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let shader = device.reflect_shader(wgpu::ShaderModuleDescriptor {
+            label: Some("hud"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("hud.wgsl"))),
+        });
+        let pipeline = Pipeline::new(
+            device,
+            "hud_pipeline",
+            &shader,
+            &[&bind_group_layout],
+            "vs_fullscreen",
+            &[],
+            Some("fs_hud"),
+            &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            None,
+            None,
+            None,
+            None,
+        );
+
+        Self {
+            visible: true,
+            font,
+            scale_factor: scale_factor as f32,
+            ui_scale: 1.0,
+            lines: Vec::new(),
+            dirty: false,
+            pipeline,
+            bind_group_layout,
+            sampler,
+            texture: None,
+        }
+    }
+
+    /// Called when the window moves to a display with a different DPI.
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor as f32;
+        self.dirty = true;
+    }
+
+    pub fn ui_scale(&self) -> f32 {
+        self.ui_scale
+    }
+
+    /// User scale on top of the window scale factor, clamped to a sensible range.
+    pub fn set_ui_scale(&mut self, ui_scale: f32) {
+        self.ui_scale = ui_scale.clamp(Self::MIN_UI_SCALE, Self::MAX_UI_SCALE);
+        self.dirty = true;
+    }
+
+    fn scale(&self) -> f32 {
+        self.scale_factor * self.ui_scale
+    }
+
+    /// Replaces the displayed text, the texture is only rebuilt if it changed.
+    pub fn set_lines(&mut self, lines: Vec<String>) {
+        if lines != self.lines {
+            self.lines = lines;
+            self.dirty = true;
+        }
+    }
+
+    /// Rasterizes the text into RGBA pixels, white on a translucent black background.
+    fn rasterize(&self) -> (u32, u32, Vec<u8>) {
+        let font = self.font.as_scaled(PxScale::from(FONT_SIZE * self.scale()));
+        let padding = (PADDING * self.scale()).ceil();
+        let line_height = font.height() + font.line_gap();
+
+        let text_width = self
+            .lines
+            .iter()
+            .map(|line| {
+                line.chars()
+                    .map(|c| font.h_advance(font.glyph_id(c)))
+                    .sum::<f32>()
+            })
+            .fold(0.0f32, f32::max);
+        let width = (text_width + 2.0 * padding).ceil() as u32;
+        let height = (line_height * self.lines.len() as f32 + 2.0 * padding).ceil() as u32;
+
+        let mut pixels = [0, 0, 0, BACKGROUND_ALPHA].repeat((width * height) as usize);
+        for (row, line) in self.lines.iter().enumerate() {
+            let mut caret = ab_glyph::point(
+                padding,
+                padding + font.ascent() + line_height * row as f32,
+            );
+            for c in line.chars() {
+                let mut glyph = font.scaled_glyph(c);
+                glyph.position = caret;
+                caret.x += font.h_advance(glyph.id);
+
+                let Some(outlined) = font.outline_glyph(glyph) else {
+                    continue;
+                };
+                let bounds = outlined.px_bounds();
+                outlined.draw(|x, y, coverage| {
+                    let px = bounds.min.x as i32 + x as i32;
+                    let py = bounds.min.y as i32 + y as i32;
+                    if px < 0 || py < 0 || px >= width as i32 || py >= height as i32 {
+                        return;
+                    }
+                    let index = (py as usize * width as usize + px as usize) * 4;
+                    let value = (coverage.clamp(0.0, 1.0) * 255.0) as u8;
+                    let pixel = &mut pixels[index..index + 4];
+                    let value = value.max(pixel[0]);
+                    pixel[0] = value;
+                    pixel[1] = value;
+                    pixel[2] = value;
+                    pixel[3] = pixel[3].max(value);
+                });
+            }
+        }
+        (width, height, pixels)
+    }
+
+    /// Uploads the text if it or the scale changed since the last frame.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+        if self.lines.is_empty() {
+            self.texture = None;
+            return;
+        }
+
+        let (width, height, pixels) = self.rasterize();
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("hud_texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &pixels,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(width * 4),
+                rows_per_image: Some(height),
+            },
+            size,
+        );
+        let view = texture.create_view(&Default::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+            label: Some("hud_bind_group"),
+        });
+        self.texture = Some((texture, bind_group));
+    }
+
+    /// Draws the HUD over `view`, which has a size of `target_width` by `target_height` pixels.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        target_width: u32,
+        target_height: u32,
+    ) {
+        let Some((texture, bind_group)) = &self.texture else {
+            return;
+        };
+        let margin = (MARGIN * self.scale()).round();
+        if !self.visible || margin >= target_width as f32 || margin >= target_height as f32 {
+            return;
+        }
+        // the viewport has to stay inside the target, a HUD larger than the window gets squeezed
+        let width = (texture.width() as f32).min(target_width as f32 - margin);
+        let height = (texture.height() as f32).min(target_height as f32 - margin);
+
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("hud_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+        rpass.set_viewport(margin, margin, width, height, 0.0, 1.0);
+        rpass.set_pipeline(&self.pipeline.pipeline);
+        rpass.set_bind_group(0, bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,22 @@
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// The viewport is set to the HUD rectangle, so a single triangle covering it is enough
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@group(0) @binding(0) var t_hud: texture_2d<f32>;
+@group(0) @binding(1) var s_hud: sampler;
+
+@fragment
+fn fs_hud(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(t_hud, s_hud, in.uv);
+}
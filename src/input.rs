@@ -0,0 +1,347 @@
+/*
+ * Input actions.
+ * Raw window and device events are translated into named actions through an ActionMap, so keys and
+ * mouse buttons can be rebound at runtime. The camera controller, the stereo pass and the app's hotkeys
+ * react to actions instead of key codes.
+ */
+use std::collections::{HashMap, HashSet};
+use winit::event::{DeviceEvent, ElementState, KeyEvent, MouseButton, WindowEvent};
+use winit::keyboard::{KeyCode, ModifiersState, PhysicalKey};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    /// Held to move faster.
+    Sprint,
+    /// Held to turn the view by dragging the mouse.
+    Look,
+    /// Held to change the field of view with the mouse wheel instead of zooming.
+    WheelFov,
+    WidenFov,
+    NarrowFov,
+    /// Steps through the stereo modes, see [`crate::stereo::StereoMode`].
+    CycleStereoMode,
+    NarrowEyeDistance,
+    WidenEyeDistance,
+    ConvergeNearer,
+    ConvergeFarther,
+    CapturePanorama,
+    /// Saves the scene graph as a scene file.
+    SaveScene,
+    /// Exports the scene graph as a binary glTF file.
+    ExportGltf,
+    ToggleHud,
+    ToggleDepthView,
+    ToggleMsaa,
+    /// Only with the `debug-ui` feature.
+    ToggleDebugUi,
+    CycleDebugView,
+    ToggleDebugLines,
+    ToggleTrails,
+    /// Snaps the camera to a view of the scene bounds from the front, back, right, left, top or bottom.
+    SnapFront,
+    SnapBack,
+    SnapRight,
+    SnapLeft,
+    SnapTop,
+    SnapBottom,
+    ToggleOrthographic,
+    FasterCamera,
+    SlowerCamera,
+    /// Changes how far the view turns per pixel of mouse movement.
+    MoreSensitive,
+    LessSensitive,
+    LargerHud,
+    SmallerHud,
+    ResetHudScale,
+    /// Selects a render mode, see [`crate::camera::RenderMode`].
+    RenderLit,
+    RenderUnlit,
+    RenderClay,
+    RenderNormals,
+    RenderUvChecker,
+    /// Blends to the next time of day preset.
+    NextTimeOfDay,
+    /// Saves the targets of the passes of the next frame.
+    CapturePasses,
+}
+
+impl Action {
+    pub const ALL: [Action; 47] = [
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::Sprint,
+        Action::Look,
+        Action::WheelFov,
+        Action::WidenFov,
+        Action::NarrowFov,
+        Action::CycleStereoMode,
+        Action::NarrowEyeDistance,
+        Action::WidenEyeDistance,
+        Action::ConvergeNearer,
+        Action::ConvergeFarther,
+        Action::CapturePanorama,
+        Action::SaveScene,
+        Action::ExportGltf,
+        Action::ToggleHud,
+        Action::ToggleDepthView,
+        Action::ToggleMsaa,
+        Action::ToggleDebugUi,
+        Action::CycleDebugView,
+        Action::ToggleDebugLines,
+        Action::ToggleTrails,
+        Action::SnapFront,
+        Action::SnapBack,
+        Action::SnapRight,
+        Action::SnapLeft,
+        Action::SnapTop,
+        Action::SnapBottom,
+        Action::ToggleOrthographic,
+        Action::FasterCamera,
+        Action::SlowerCamera,
+        Action::MoreSensitive,
+        Action::LessSensitive,
+        Action::LargerHud,
+        Action::SmallerHud,
+        Action::ResetHudScale,
+        Action::RenderLit,
+        Action::RenderUnlit,
+        Action::RenderClay,
+        Action::RenderNormals,
+        Action::RenderUvChecker,
+        Action::NextTimeOfDay,
+        Action::CapturePasses,
+    ];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(KeyCode),
+    /// The key pressed while Ctrl is held, takes precedence over the key alone.
+    CtrlKey(KeyCode),
+    Mouse(MouseButton),
+}
+
+/// Bindings of the actions. An action can have several bindings, a binding triggers one action.
+pub struct ActionMap {
+    bindings: HashMap<Binding, Action>,
+}
+
+impl Default for ActionMap {
+    fn default() -> Self {
+        let mut map = Self {
+            bindings: HashMap::new(),
+        };
+        let keys = [
+            (KeyCode::KeyW, Action::MoveForward),
+            (KeyCode::ArrowUp, Action::MoveForward),
+            (KeyCode::KeyS, Action::MoveBackward),
+            (KeyCode::ArrowDown, Action::MoveBackward),
+            (KeyCode::KeyA, Action::MoveLeft),
+            (KeyCode::ArrowLeft, Action::MoveLeft),
+            (KeyCode::KeyD, Action::MoveRight),
+            (KeyCode::ArrowRight, Action::MoveRight),
+            (KeyCode::Space, Action::MoveUp),
+            (KeyCode::ShiftLeft, Action::MoveDown),
+            (KeyCode::ShiftRight, Action::MoveDown),
+            (KeyCode::KeyE, Action::Sprint),
+            (KeyCode::ControlLeft, Action::WheelFov),
+            (KeyCode::ControlRight, Action::WheelFov),
+            (KeyCode::Equal, Action::WidenFov),
+            (KeyCode::Minus, Action::NarrowFov),
+            (KeyCode::KeyV, Action::CycleStereoMode),
+            (KeyCode::BracketLeft, Action::NarrowEyeDistance),
+            (KeyCode::BracketRight, Action::WidenEyeDistance),
+            (KeyCode::Comma, Action::ConvergeNearer),
+            (KeyCode::Period, Action::ConvergeFarther),
+            (KeyCode::KeyP, Action::CapturePanorama),
+            (KeyCode::F1, Action::ToggleHud),
+            (KeyCode::F2, Action::ToggleDepthView),
+            (KeyCode::F3, Action::ToggleMsaa),
+            (KeyCode::F5, Action::ToggleDebugUi),
+            (KeyCode::F6, Action::CycleDebugView),
+            (KeyCode::F7, Action::ToggleDebugLines),
+            (KeyCode::F8, Action::ToggleTrails),
+            (KeyCode::Numpad1, Action::SnapFront),
+            (KeyCode::Numpad3, Action::SnapRight),
+            (KeyCode::Numpad7, Action::SnapTop),
+            (KeyCode::Numpad5, Action::ToggleOrthographic),
+            (KeyCode::PageUp, Action::FasterCamera),
+            (KeyCode::PageDown, Action::SlowerCamera),
+            (KeyCode::Digit1, Action::RenderLit),
+            (KeyCode::Digit2, Action::RenderUnlit),
+            (KeyCode::Digit3, Action::RenderClay),
+            (KeyCode::Digit4, Action::RenderNormals),
+            (KeyCode::Digit5, Action::RenderUvChecker),
+            (KeyCode::KeyT, Action::NextTimeOfDay),
+            (KeyCode::F9, Action::CapturePasses),
+        ];
+        for (key, action) in keys {
+            map.bind(Binding::Key(key), action);
+        }
+        let ctrl_keys = [
+            (KeyCode::KeyS, Action::SaveScene),
+            (KeyCode::KeyE, Action::ExportGltf),
+            (KeyCode::Numpad1, Action::SnapBack),
+            (KeyCode::Numpad3, Action::SnapLeft),
+            (KeyCode::Numpad7, Action::SnapBottom),
+            (KeyCode::Numpad5, Action::ToggleOrthographic),
+            (KeyCode::PageUp, Action::MoreSensitive),
+            (KeyCode::PageDown, Action::LessSensitive),
+            (KeyCode::Equal, Action::LargerHud),
+            (KeyCode::Minus, Action::SmallerHud),
+            (KeyCode::Digit0, Action::ResetHudScale),
+        ];
+        for (key, action) in ctrl_keys {
+            map.bind(Binding::CtrlKey(key), action);
+        }
+        map.bind(Binding::Mouse(MouseButton::Left), Action::Look);
+        map
+    }
+}
+
+impl ActionMap {
+    /// Adds `binding` to `action`, taking it from the action it was bound to before.
+    pub fn bind(&mut self, binding: Binding, action: Action) {
+        self.bindings.insert(binding, action);
+    }
+
+    /// Replaces all bindings of `action` with `binding`.
+    pub fn rebind(&mut self, action: Action, binding: Binding) {
+        self.bindings.retain(|_, bound| *bound != action);
+        self.bind(binding, action);
+    }
+
+    pub fn action(&self, binding: Binding) -> Option<Action> {
+        self.bindings.get(&binding).copied()
+    }
+
+    pub fn bindings(&self, action: Action) -> Vec<Binding> {
+        self.bindings
+            .iter()
+            .filter(|(_, bound)| **bound == action)
+            .map(|(binding, _)| *binding)
+            .collect()
+    }
+}
+
+/// Which actions are active, derived from the held keys and mouse buttons.
+#[derive(Default)]
+pub struct InputState {
+    pub action_map: ActionMap,
+    held: HashSet<Binding>,
+    modifiers: ModifiersState,
+}
+
+impl InputState {
+    /// Must be called with the modifiers of every [`WindowEvent::ModifiersChanged`].
+    pub fn set_modifiers(&mut self, modifiers: ModifiersState) {
+        self.modifiers = modifiers;
+    }
+
+    /// The binding of `keycode` pressed now, with Ctrl if it is held. Ctrl itself is a key of its own.
+    pub fn key_binding(&self, keycode: KeyCode) -> Binding {
+        let is_ctrl = matches!(keycode, KeyCode::ControlLeft | KeyCode::ControlRight);
+        if self.modifiers.control_key() && !is_ctrl {
+            Binding::CtrlKey(keycode)
+        } else {
+            Binding::Key(keycode)
+        }
+    }
+
+    /// Translates a key or mouse button event into its action and whether the action is active now, an
+    /// action stays active while any of its bindings is held. Key repeats report the action again.
+    pub fn process_window_event(&mut self, event: &WindowEvent) -> Option<(Action, bool)> {
+        let (binding, state) = match event {
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(keycode),
+                        state,
+                        ..
+                    },
+                ..
+            } => {
+                // keys without a Ctrl binding keep their own action while Ctrl is held
+                let binding = match self.key_binding(*keycode) {
+                    binding @ Binding::CtrlKey(_) if self.action_map.action(binding).is_some() => {
+                        binding
+                    }
+                    _ => Binding::Key(*keycode),
+                };
+                if *state == ElementState::Released {
+                    // Ctrl may have been let go before the key
+                    self.held.remove(&Binding::Key(*keycode));
+                    self.held.remove(&Binding::CtrlKey(*keycode));
+                }
+                (binding, *state)
+            }
+            WindowEvent::MouseInput { button, state, .. } => (Binding::Mouse(*button), *state),
+            _ => return None,
+        };
+        match state {
+            ElementState::Pressed => self.held.insert(binding),
+            ElementState::Released => self.held.remove(&binding),
+        };
+        let action = self.action_map.action(binding)?;
+        Some((action, self.is_active(action)))
+    }
+
+    /// The mouse movement in pixels while [`Action::Look`] is active. Raw device motion keeps turning the
+    /// view when the cursor reaches the edge of the window.
+    pub fn process_device_event(&self, event: &DeviceEvent) -> Option<(f64, f64)> {
+        match event {
+            DeviceEvent::MouseMotion { delta } if self.is_active(Action::Look) => Some(*delta),
+            _ => None,
+        }
+    }
+
+    /// Whether `event` presses a key or mouse button, as opposed to releasing it or repeating a held key.
+    /// Actions that step a setting only react to presses.
+    pub fn is_press(event: &WindowEvent) -> bool {
+        matches!(
+            event,
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                },
+                ..
+            } | WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                ..
+            }
+        )
+    }
+
+    /// Whether `event` is a repeat of a held key, for actions that keep stepping while their key is held.
+    pub fn is_repeat(event: &WindowEvent) -> bool {
+        matches!(
+            event,
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    state: ElementState::Pressed,
+                    repeat: true,
+                    ..
+                },
+                ..
+            }
+        )
+    }
+
+    pub fn is_active(&self, action: Action) -> bool {
+        self.held
+            .iter()
+            .any(|binding| self.action_map.action(*binding) == Some(action))
+    }
+}
@@ -0,0 +1,32 @@
+/*
+ * Debug labels of GPU resources, passes and debug groups.
+ * Graphics debuggers like RenderDoc and the GPU profilers of browsers show these names. Resources owned
+ * by a scene node, a material or a light are named after it, so a resource keeps its name between runs
+ * and can be searched for by the name in the scene.
+ */
+use crate::model::Shading;
+
+/// Label of a resource of the scene node `node`, e.g. `node "sponza-floor" vertices`.
+pub fn node(node: &str, resource: &str) -> String {
+    format!("node \"{node}\" {resource}")
+}
+
+/// Label of a resource of the material `material`.
+pub fn material(material: &str, resource: &str) -> String {
+    format!("material \"{material}\" {resource}")
+}
+
+/// Label of a resource of the light at `index` in scene graph order.
+pub fn light(index: usize, resource: &str) -> String {
+    format!("light {index} {resource}")
+}
+
+/// Label of a layer of a shadow map, e.g. `Point Shadow Map layer 3`.
+pub fn shadow_layer(shadow_map: &str, layer: u32) -> String {
+    format!("{shadow_map} layer {layer}")
+}
+
+/// Label of a variant of a pipeline, which differ in shading and sample count.
+pub fn pipeline_variant(pipeline: &str, shading: Shading, sample_count: u32) -> String {
+    format!("{pipeline} {shading:?} {sample_count}x")
+}
@@ -0,0 +1,168 @@
+/*
+ * Layered shadow rendering.
+ * Where the adapter supports multiview, the shadow pass renders up to six layers of a shadow map in one
+ * render pass, each view with the camera of the light (or cube face) owning the layer. A point light takes
+ * one pass instead of six, and spot lights share passes, which saves the per-pass overhead of scenes with
+ * many shadow casting lights. The views share their draws, so these passes draw the scene unculled.
+ */
+use crate::camera::CameraUniform;
+use crate::error::RendererError;
+use crate::labels;
+use crate::light::{LightKind, ShadowMap};
+use crate::renderer::{PipelineKey, PipelineVariants};
+use crate::scenegraph::SceneGraph;
+use crate::texture;
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::ops::Range;
+
+/// Layers rendered by one pass, the length of the camera array in shadow_multiview.wgsl.
+pub const MAX_VIEWS: u32 = 6;
+
+/// Consecutive layers of a shadow map rendered by one pass.
+pub struct LayerChunk {
+    pub kind: LightKind,
+    pub layers: Range<u32>,
+    /// [`MAX_VIEWS`] cameras, one per layer of the chunk followed by unused ones.
+    pub camera_buffer: wgpu::Buffer,
+    pub camera_bind_group: wgpu::BindGroup,
+    /// The layers of a moment shadow map, None for depth shadow maps, which are the depth attachment.
+    pub color_view: Option<wgpu::TextureView>,
+    pub depth_view: wgpu::TextureView,
+}
+
+impl LayerChunk {
+    pub fn views(&self) -> u32 {
+        self.layers.len() as u32
+    }
+}
+
+pub struct LayeredShadowPass {
+    /// Shadow pipelines by the number of views they render.
+    pipelines: HashMap<u32, PipelineVariants>,
+    pub chunks: Vec<LayerChunk>,
+}
+
+impl LayeredShadowPass {
+    /// `create_pipeline` builds the shadow pipeline rendering the given number of views.
+    pub fn new(
+        device: &wgpu::Device,
+        scene_graph: &SceneGraph,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        create_pipeline: impl Fn(NonZeroU32) -> PipelineVariants,
+    ) -> Self {
+        let mut chunks = Vec::new();
+        for kind in [LightKind::Spot, LightKind::Point] {
+            let shadow_map = scene_graph.shadow_map_for(kind);
+            chunks.extend(Self::create_chunks(
+                device,
+                camera_bind_group_layout,
+                kind,
+                shadow_map,
+            ));
+        }
+        let mut pipelines = HashMap::new();
+        for chunk in &chunks {
+            let views = chunk.views();
+            pipelines
+                .entry(views)
+                .or_insert_with(|| create_pipeline(NonZeroU32::new(views).unwrap()));
+        }
+        Self { pipelines, chunks }
+    }
+
+    fn create_chunks(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        kind: LightKind,
+        shadow_map: &ShadowMap,
+    ) -> Vec<LayerChunk> {
+        if shadow_map.layers == 0 {
+            return Vec::new();
+        }
+        let name = format!("{kind:?} Shadow Map");
+        // moment shadow maps are color targets and need a depth attachment with as many layers,
+        // the chunks share one
+        let moments_depth_texture = (!shadow_map.mode.is_depth()).then(|| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(&format!("{name} layered depth")),
+                size: wgpu::Extent3d {
+                    width: shadow_map.size,
+                    height: shadow_map.size,
+                    depth_or_array_layers: shadow_map.layers.min(MAX_VIEWS),
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: texture::Texture::DEPTH_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            })
+        });
+        let layer_views = |texture: &wgpu::Texture, label: &str, layers: &Range<u32>| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some(label),
+                dimension: Some(wgpu::TextureViewDimension::D2Array),
+                base_array_layer: layers.start,
+                array_layer_count: Some(layers.len() as u32),
+                ..Default::default()
+            })
+        };
+
+        (0..shadow_map.layers)
+            .step_by(MAX_VIEWS as usize)
+            .map(|start| {
+                let layers = start..(start + MAX_VIEWS).min(shadow_map.layers);
+                let label = labels::shadow_layer(&name, start);
+                let camera_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(&format!("{label} Layered Camera Buffer")),
+                    size: MAX_VIEWS as u64 * size_of::<CameraUniform>() as wgpu::BufferAddress,
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                });
+                let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: camera_bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: camera_buffer.as_entire_binding(),
+                    }],
+                    label: Some(&format!("{label} layered_camera_bind_group")),
+                });
+                let (color_view, depth_view) = match &moments_depth_texture {
+                    Some(depth_texture) => (
+                        Some(layer_views(&shadow_map.texture, &label, &layers)),
+                        layer_views(depth_texture, &label, &(0..layers.len() as u32)),
+                    ),
+                    None => (None, layer_views(&shadow_map.texture, &label, &layers)),
+                };
+                LayerChunk {
+                    kind,
+                    layers,
+                    camera_buffer,
+                    camera_bind_group,
+                    color_view,
+                    depth_view,
+                }
+            })
+            .collect()
+    }
+
+    /// Builds the pipelines again, see [`PipelineVariants::rebuild`].
+    pub fn rebuild(&mut self, device: &wgpu::Device) -> Result<(), RendererError> {
+        for pipeline in self.pipelines.values_mut() {
+            pipeline.rebuild(device)?;
+        }
+        Ok(())
+    }
+
+    /// The pipelines of the chunks rendering `views` layers.
+    pub fn pipeline(&self, views: u32) -> &PipelineVariants {
+        &self.pipelines[&views]
+    }
+
+    pub fn prepare(&mut self, device: &wgpu::Device, keys: &[PipelineKey]) {
+        for pipeline in self.pipelines.values_mut() {
+            pipeline.prepare(device, keys.iter().copied());
+        }
+    }
+}
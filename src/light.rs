@@ -1,7 +1,11 @@
 use crate::camera::CameraUniform;
+use crate::labels;
+use crate::reflection::ReflectDevice;
+use crate::scenegraph::DrawStats;
 use bytemuck::{Pod, Zeroable};
 use glam::{Mat4, Vec3};
-use std::num::NonZeroU32;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
 use wgpu::{Texture, TextureUsages, TextureView};
 
 #[repr(C)]
@@ -11,10 +15,28 @@ pub struct LightUniform {
     color: [f32; 4],
     model_mat: [[f32; 4]; 4],
     view_proj: [[f32; 4]; 4],
+    // shadow map layer, -1 if the light casts no shadow
+    shadow_layer: i32,
+    kind: u32,
+    intensity: f32,
+    // 0 without distance falloff
+    range: f32,
+    // share of the width and height of the shadow map layers the light renders into
+    shadow_scale: f32,
+    _padding: [f32; 3],
 }
 
 impl LightUniform {
-    pub fn from_light(light: &Light, model: Mat4) -> Self {
+    /// The light with the world matrix `model`, relative to the render `origin`. Without `shadows` the
+    /// light casts no shadow, as if it had no shadow map layer. `shadow_scale` is the share of its shadow
+    /// map layers the light renders into, see [`Light::shadow_resolution`].
+    pub fn from_light(
+        light: &Light,
+        model: Mat4,
+        origin: Vec3,
+        shadows: bool,
+        shadow_scale: f32,
+    ) -> Self {
         Self {
             pos: [light.pos.x, light.pos.y, light.pos.z, 1.0],
             color: [
@@ -23,17 +45,38 @@ impl LightUniform {
                 light.color.b as f32,
                 light.color.a as f32,
             ],
-            model_mat: model.to_cols_array_2d(),
-            view_proj: light.calculate_matrix(model).to_cols_array_2d(),
+            model_mat: (Mat4::from_translation(-origin) * model).to_cols_array_2d(),
+            view_proj: light.calculate_matrix(model, origin).to_cols_array_2d(),
+            shadow_layer: light
+                .shadow_layer
+                .filter(|_| shadows && light.cast_shadows)
+                .map_or(-1, |layer| layer as i32),
+            kind: light.kind as u32,
+            intensity: light.intensity,
+            range: light.range,
+            shadow_scale,
+            _padding: [0.0; 3],
         }
     }
 
+    /// Length of the light array in the uniform fallback path, must match `u_lights` in shader.wgsl.
+    pub const UNIFORM_ARRAY_SIZE: usize = 10;
+
     pub fn get_bind_group_layout(
         device: &wgpu::Device,
-        light_count: u32,
         supports_storage_resources: bool,
+        shadow_mode: ShadowMode,
     ) -> wgpu::BindGroupLayout {
-        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        let shadow_texture = wgpu::BindingType::Texture {
+            multisampled: false,
+            sample_type: if shadow_mode.is_depth() {
+                wgpu::TextureSampleType::Depth
+            } else {
+                wgpu::TextureSampleType::Float { filterable: false }
+            },
+            view_dimension: wgpu::TextureViewDimension::D2Array,
+        };
+        device.reflect_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[
                 wgpu::BindGroupLayoutEntry {
                     binding: 0,
@@ -47,24 +90,58 @@ impl LightUniform {
                         has_dynamic_offset: false,
                         min_binding_size: None,
                     },
-                    count: NonZeroU32::new(light_count),
+                    count: None,
                 },
                 wgpu::BindGroupLayoutEntry {
                     binding: 1,
                     visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        multisampled: false,
-                        sample_type: wgpu::TextureSampleType::Float {
-                            filterable: false,
-                        },
-                        view_dimension: wgpu::TextureViewDimension::D2Array,
-                    },
+                    ty: shadow_texture,
                     count: None,
                 },
                 wgpu::BindGroupLayoutEntry {
                     binding: 2,
                     visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
+                    ty: wgpu::BindingType::Sampler(if shadow_mode.is_depth() {
+                        wgpu::SamplerBindingType::Comparison
+                    } else {
+                        wgpu::SamplerBindingType::NonFiltering
+                    }),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: shadow_texture,
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
                     count: None,
                 },
             ],
@@ -73,75 +150,446 @@ impl LightUniform {
     }
 }
 
+/// Light that reaches every surface, also where no light of the scene shines. Surfaces facing up get
+/// the sky color and surfaces facing down the ground color, blended by the normal in between.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Ambient {
+    /// Linear RGB.
+    pub sky: [f32; 3],
+    /// Linear RGB.
+    pub ground: [f32; 3],
+    pub strength: f32,
+}
+
+impl Default for Ambient {
+    /// The flat gray the scenes were lit with before the ambient light could be set.
+    fn default() -> Self {
+        Self {
+            sky: [1.0; 3],
+            ground: [1.0; 3],
+            strength: 0.3,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct AmbientUniform {
+    // colors scaled by the strength, w unused
+    sky: [f32; 4],
+    ground: [f32; 4],
+}
+
+impl AmbientUniform {
+    pub fn from_ambient(ambient: &Ambient) -> Self {
+        let scaled =
+            |[r, g, b]: [f32; 3]| [r, g, b, 0.0].map(|channel| channel * ambient.strength.max(0.0));
+        Self {
+            sky: scaled(ambient.sky),
+            ground: scaled(ambient.ground),
+        }
+    }
+}
+
+/// How fog thickens with the distance from the camera, the discriminants are used as `mode` in
+/// shader.wgsl.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FogMode {
+    Off = 0,
+    /// Clear up to `start` and opaque from `end` on.
+    #[default]
+    Linear = 1,
+    /// Thickens by `density` per unit of distance and never gets fully opaque.
+    Exponential = 2,
+}
+
+/// Distance fog, lit surfaces fade to its color with their distance from the camera so that the scene
+/// doesn't end at a hard horizon.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Fog {
+    pub mode: FogMode,
+    /// Linear RGB, best the background behind the scene.
+    pub color: [f32; 3],
+    /// Share of light lost per unit of distance, only for [`FogMode::Exponential`].
+    pub density: f32,
+    /// Distances from the camera the linear fog starts and ends at.
+    pub start: f32,
+    pub end: f32,
+}
+
+impl Default for Fog {
+    /// Linear fog in the default clear color up to the edge of the ground of the demo scene.
+    fn default() -> Self {
+        Self {
+            mode: FogMode::default(),
+            color: [0.1, 0.2, 0.3],
+            density: 0.02,
+            start: 40.0,
+            end: 100.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct FogUniform {
+    // w unused
+    color: [f32; 4],
+    mode: u32,
+    density: f32,
+    start: f32,
+    end: f32,
+}
+
+impl FogUniform {
+    pub fn from_fog(fog: &Fog) -> Self {
+        let [r, g, b] = fog.color;
+        Self {
+            color: [r, g, b, 0.0],
+            mode: fog.mode as u32,
+            density: fog.density.max(0.0),
+            start: fog.start,
+            end: fog.end.max(fog.start),
+        }
+    }
+}
+
+/// How a light casts its shadow, the discriminants are used as `kind` in shader.wgsl.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LightKind {
+    /// Single perspective shadow map looking at the scene center.
+    Spot = 0,
+    /// Omnidirectional shadow from the six faces of a cube around the light.
+    Point = 1,
+}
+
+impl LightKind {
+    /// Number of shadow map layers one light of this kind renders into.
+    pub fn shadow_layers(self) -> u32 {
+        match self {
+            LightKind::Spot => 1,
+            LightKind::Point => 6,
+        }
+    }
+}
+
+/// Forward direction and up vector of the cube faces of a point light, in the order +X, -X, +Y, -Y, +Z, -Z.
+/// fetch_point_shadow in shader.wgsl selects the faces the same way.
+const CUBE_FACE_ORIENTATIONS: [(Vec3, Vec3); 6] = [
+    (Vec3::X, Vec3::Y),
+    (Vec3::NEG_X, Vec3::Y),
+    (Vec3::Y, Vec3::Z),
+    (Vec3::NEG_Y, Vec3::NEG_Z),
+    (Vec3::Z, Vec3::Y),
+    (Vec3::NEG_Z, Vec3::Y),
+];
+
 #[derive(Debug)]
 pub struct Light {
     pub pos: Vec3,
     color: wgpu::Color,
-    pub target_view: TextureView,
+    pub kind: LightKind,
+    /// Scales the color. With a range it is the brightness at a distance of one unit, like a luminous
+    /// intensity in candela, and falls off with the inverse square of the distance.
+    pub intensity: f32,
+    /// Distance at which the light fades out completely, 0 for a light without distance falloff like the
+    /// sun.
+    pub range: f32,
+    /// First shadow map layer the light renders into, None if it casts no shadow.
+    pub shadow_layer: Option<u32>,
+    /// Whether the light renders its shadow map and shadows the scene. Its shadow map layers stay
+    /// reserved while it doesn't, so the shadow comes back when it is turned on again.
+    pub cast_shadows: bool,
+    /// Width and height the light renders its shadow in, e.g. less for a fill light than for the sun.
+    /// Limited to the size of the shadow map, None for the whole size. The light renders into a corner
+    /// of its shadow map layers of this size.
+    pub shadow_resolution: Option<u32>,
+    /// One view per shadow map layer of the light.
+    pub target_views: Vec<TextureView>,
+    /// World position a spot light and its shadow face, e.g. set by a look-at constraint. None faces
+    /// [`Light::DEFAULT_AIM`].
+    pub aim: Option<Vec3>,
 }
 
 impl Light {
-    pub fn new(pos: Vec3, color: wgpu::Color, shadow_texture: &Texture, light_number: u32) -> Self {
+    /// Near and far plane of the point light cube faces, must match shader.wgsl.
+    pub const POINT_SHADOW_NEAR: f32 = 0.5;
+    pub const POINT_SHADOW_FAR: f32 = 50.0;
+    /// Where spot lights face without an aim.
+    pub const DEFAULT_AIM: Vec3 = Vec3::new(0.0, 0.0, -15.0);
+
+    /// Creates a spot light without a shadow, the scene graph assigns shadow map layers when it is added.
+    pub fn new(pos: Vec3, color: wgpu::Color) -> Self {
         Self {
             pos,
             color,
-            target_view: shadow_texture.create_view(&wgpu::TextureViewDescriptor {
-                label: Some("shadow"),
-                format: None,
-                dimension: Some(wgpu::TextureViewDimension::D2),
-                usage: None,
-                aspect: wgpu::TextureAspect::All,
-                base_mip_level: 0,
-                mip_level_count: None,
-                base_array_layer: light_number,
-                array_layer_count: Some(1),
-            }),
+            kind: LightKind::Spot,
+            intensity: 1.0,
+            range: 0.0,
+            shadow_layer: None,
+            cast_shadows: true,
+            shadow_resolution: None,
+            target_views: Vec::new(),
+            aim: None,
         }
     }
 
-    pub fn calculate_matrix(&self, model: Mat4) -> Mat4 {
+    /// Creates a point light, which casts shadows in all directions.
+    pub fn point(pos: Vec3, color: wgpu::Color) -> Self {
+        Self {
+            kind: LightKind::Point,
+            ..Self::new(pos, color)
+        }
+    }
+
+    pub fn color(&self) -> wgpu::Color {
+        self.color
+    }
+
+    pub fn set_color(&mut self, color: wgpu::Color) {
+        self.color = color;
+    }
+
+    /// Width and height of the shadow in shadow map layers of `map_size`, see
+    /// [`Light::shadow_resolution`].
+    pub fn shadow_size(&self, map_size: u32) -> u32 {
+        self.shadow_resolution
+            .map_or(map_size, |resolution| resolution.clamp(1, map_size))
+    }
+
+    pub fn set_shadow_layer(&mut self, shadow_texture: &Texture, layer: u32) {
+        self.shadow_layer = Some(layer);
+        let shadow_map = format!("{:?} Shadow Map", self.kind);
+        self.target_views = (layer..layer + self.kind.shadow_layers())
+            .map(|layer| {
+                shadow_texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some(&labels::shadow_layer(&shadow_map, layer)),
+                    format: None,
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    usage: None,
+                    aspect: wgpu::TextureAspect::All,
+                    base_mip_level: 0,
+                    mip_level_count: None,
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                })
+            })
+            .collect();
+    }
+
+    /// View projection of the spot light with the world matrix `model`, for positions relative to
+    /// `origin`.
+    pub fn calculate_matrix(&self, model: Mat4, origin: Vec3) -> Mat4 {
         let pos4 = glam::Vec4::new(self.pos.x, self.pos.y, self.pos.z, 1.0);
         let position = model * pos4;
-        let center = Vec3::new(0.0, 0.0, -15.0);
-        let view = Mat4::look_at_rh(position.truncate(), center, Vec3::Y);
+        let center = self.aim.unwrap_or(Self::DEFAULT_AIM);
+        let direction = center - position.truncate();
+        // looking straight up or down, +Y can't be the up vector
+        let up = if direction.cross(Vec3::Y).length_squared() < 1e-6 {
+            Vec3::Z
+        } else {
+            Vec3::Y
+        };
+        let view = Mat4::look_at_rh(position.truncate() - origin, center - origin, up);
         let projection = Mat4::perspective_rh(60.0f32.to_radians(), 1.0, 5.0, 50.0);
         projection * view
     }
 
-    pub fn to_camera_uniform(&self, model: Mat4) -> CameraUniform {
-        CameraUniform {
-            view_proj: self.calculate_matrix(model).to_cols_array_2d(),
-            position: [self.pos.x, self.pos.y, self.pos.z, 1.0],
+    /// View projection matrix of every shadow map layer of the light, for positions relative to `origin`.
+    pub fn shadow_matrices(&self, model: Mat4, origin: Vec3) -> Vec<Mat4> {
+        match self.kind {
+            LightKind::Spot => vec![self.calculate_matrix(model, origin)],
+            LightKind::Point => {
+                let position = model.transform_point3(self.pos) - origin;
+                let projection = Mat4::perspective_rh(
+                    90.0f32.to_radians(),
+                    1.0,
+                    Self::POINT_SHADOW_NEAR,
+                    Self::POINT_SHADOW_FAR,
+                );
+                CUBE_FACE_ORIENTATIONS
+                    .iter()
+                    .map(|(forward, up)| {
+                        projection * Mat4::look_at_rh(position, position + *forward, *up)
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    pub fn to_camera_uniforms(&self, model: Mat4, origin: Vec3) -> Vec<CameraUniform> {
+        self.shadow_matrices(model, origin)
+            .iter()
+            .map(|matrix| CameraUniform::new(*matrix, self.pos))
+            .collect()
+    }
+}
+
+/// How shadow maps store and filter the occluder depth, from the cheapest hard edges to the soft
+/// shadows of the blurred moments. The filter is baked into the shaders when they are composed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShadowMode {
+    /// Moment shadow maps, four moments of the depth in a color target that is blurred for soft shadows.
+    #[default]
+    #[serde(alias = "msm")]
+    Moments,
+    /// Depth attachments compared with a single hardware comparison sample.
+    Hard,
+    /// Depth attachments with percentage-closer filtering, a square of 3x3 bilinear comparison samples
+    /// around the fragment.
+    #[serde(alias = "pcf")]
+    Pcf3x3,
+    /// Like [`ShadowMode::Pcf3x3`] with 5x5 samples, softer edges for almost three times the samples.
+    Pcf5x5,
+}
+
+impl ShadowMode {
+    /// Whether the shadow maps are depth attachments sampled with a comparison sampler.
+    pub fn is_depth(self) -> bool {
+        self != ShadowMode::Moments
+    }
+
+    pub fn format(self) -> wgpu::TextureFormat {
+        if self.is_depth() {
+            wgpu::TextureFormat::Depth32Float
+        } else {
+            ShadowMap::DEPTH_FORMAT
         }
     }
+
+    /// Comparison samples from the center to the edge of the filtered square, 0 for a single sample.
+    pub fn pcf_radius(self) -> i32 {
+        match self {
+            ShadowMode::Moments | ShadowMode::Hard => 0,
+            ShadowMode::Pcf3x3 => 1,
+            ShadowMode::Pcf5x5 => 2,
+        }
+    }
+
+    /// WGSL declaring the shadow map bindings of the light bind group and the functions sampling them,
+    /// the `shadow_mode` snippet of shader_compose.rs.
+    pub fn wgsl(self) -> String {
+        match self {
+            ShadowMode::Moments => include_str!("shadow_moments.wgsl").to_string(),
+            ShadowMode::Hard | ShadowMode::Pcf3x3 | ShadowMode::Pcf5x5 => format!(
+                "const PCF_RADIUS: i32 = {};\n{}",
+                self.pcf_radius(),
+                include_str!("shadow_depth.wgsl")
+            ),
+        }
+    }
+}
+
+/// What the shadow pass did for a light, shown in the HUD.
+#[derive(Debug, Clone, Copy)]
+pub struct ShadowStats {
+    /// Index of the light in scene graph order, as in the debug labels.
+    pub light: usize,
+    pub kind: LightKind,
+    /// Width and height of the shadow map layers of the light.
+    pub resolution: u32,
+    /// Casters drawn into the layers of the light. Layered passes are shared by several lights and
+    /// count their draws for each of them.
+    pub draws: DrawStats,
+    /// CPU time of recording the blur of the shadow map layers drawn this frame, None if it isn't
+    /// blurred.
+    pub blur_time: Option<Duration>,
+    /// Frame the layers of the light were last rendered in.
+    pub last_update: u64,
+}
+
+impl ShadowStats {
+    pub fn hud_line(&self) -> String {
+        let blur = match self.blur_time {
+            Some(time) => format!("blur {:.2} ms", time.as_secs_f64() * 1000.0),
+            None => "no blur".to_string(),
+        };
+        format!(
+            "light {} {:?} {}px: {} casters, {} culled, {blur}, frame {}",
+            self.light,
+            self.kind,
+            self.resolution,
+            self.draws.draws,
+            self.draws.culled,
+            self.last_update
+        )
+    }
 }
 
 #[derive(Clone)]
 pub struct ShadowMap {
+    /// Number of array layers, and so the number of shadow map layers lights can render into.
+    pub layers: u32,
+    /// Width and height of each layer.
+    pub size: u32,
+    pub mode: ShadowMode,
     pub texture: Texture,
     pub view: TextureView,
     pub sampler: wgpu::Sampler,
 }
 
 impl ShadowMap {
-    pub const MAX_LIGHTS: u32 = 3;
+    /// Format of moment shadow maps.
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
-    pub const SHADOW_MAP_SIZE: u32 = 2048;
 
-    pub fn create_shadow_map(device: &wgpu::Device, usages: Option<TextureUsages>) -> Self {
+    pub fn create_shadow_map(
+        device: &wgpu::Device,
+        usages: Option<TextureUsages>,
+        layers: u32,
+        size: u32,
+        mode: ShadowMode,
+    ) -> Self {
+        Self::create(device, usages, layers, size, mode, "Shadow Map")
+    }
+
+    /// Shadow map holding the cube faces of up to `point_lights` point lights.
+    pub fn create_point_shadow_map(
+        device: &wgpu::Device,
+        point_lights: u32,
+        size: u32,
+        mode: ShadowMode,
+    ) -> Self {
+        Self::create(
+            device,
+            None,
+            point_lights * LightKind::Point.shadow_layers(),
+            size,
+            mode,
+            "Point Shadow Map",
+        )
+    }
+
+    fn create(
+        device: &wgpu::Device,
+        usages: Option<TextureUsages>,
+        layers: u32,
+        size: u32,
+        mode: ShadowMode,
+        label: &str,
+    ) -> Self {
         let desc = wgpu::TextureDescriptor {
-            label: Some("Shadow Map"),
+            label: Some(label),
             size: wgpu::Extent3d {
-                width: Self::SHADOW_MAP_SIZE,
-                height: Self::SHADOW_MAP_SIZE,
-                depth_or_array_layers: Self::MAX_LIGHTS,
+                width: size,
+                height: size,
+                depth_or_array_layers: layers,
             },
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: Self::DEPTH_FORMAT,
+            format: mode.format(),
             usage: if let Some(usages) = usages {
                 usages
+            } else if mode.is_depth() {
+                // depth formats can't be storage textures, depth shadow maps are not blurred
+                TextureUsages::RENDER_ATTACHMENT
+                    | TextureUsages::TEXTURE_BINDING
+                    | TextureUsages::COPY_SRC
             } else {
                 TextureUsages::RENDER_ATTACHMENT
                     | TextureUsages::TEXTURE_BINDING
@@ -151,22 +599,34 @@ impl ShadowMap {
             view_formats: &[],
         };
         let texture = device.create_texture(&desc);
+        // PCF interpolates the results of the comparisons with the four nearest texels
+        let filter = if mode.pcf_radius() > 0 {
+            wgpu::FilterMode::Linear
+        } else {
+            wgpu::FilterMode::Nearest
+        };
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(label),
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
             address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
+            mag_filter: filter,
+            min_filter: filter,
             mipmap_filter: wgpu::FilterMode::Nearest,
-            compare: None,
+            compare: mode.is_depth().then_some(wgpu::CompareFunction::LessEqual),
             ..Default::default()
         });
         let view = texture.create_view(&wgpu::TextureViewDescriptor {
-            label: Some("Shadow Map View"),
+            label: Some(label),
+            // also an array with a single layer, like the bind group layout expects
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
             ..Default::default()
         });
 
         Self {
+            layers,
+            size,
+            mode,
             texture,
             view,
             sampler,
@@ -0,0 +1,155 @@
+/*
+ * Light animation.
+ * Tracks animate the properties of a light node each frame, declared in the scene file next to the light
+ * like the spline of a node: an orbit circles the node around a center, a color cycle blends the color of
+ * the light through a list of colors and a flicker varies its intensity with smooth noise, like a fire.
+ * Each track is optional, a light can have any combination of them:
+ *
+ *     "animation": {
+ *         "orbit": { "center": [0.0, 25.0, -15.0], "radius": 30.0, "speed": 0.785 },
+ *         "color_cycle": { "colors": [[1.0, 0.6, 0.4], [1.0, 1.0, 0.95]], "period": 20.0 },
+ *         "flicker": { "amount": 0.3, "speed": 8.0 }
+ *     }
+ */
+use crate::accessibility::AccessibilitySettings;
+use glam::{Mat4, Vec3};
+use serde::{Deserialize, Serialize};
+
+/// The tracks of a light, see the module description.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LightTracks {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub orbit: Option<Orbit>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color_cycle: Option<ColorCycle>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flicker: Option<Flicker>,
+}
+
+/// Circles the node around `center` in the horizontal plane, replacing the translation of its transform.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Orbit {
+    /// In the space of the parent of the node.
+    pub center: Vec3,
+    pub radius: f32,
+    /// Radians per second, negative circles the other way.
+    pub speed: f32,
+    /// Angle at time 0 in radians, 0 is on the +X side of the center.
+    #[serde(default)]
+    pub phase: f32,
+}
+
+impl Orbit {
+    pub fn position(&self, time: f32) -> Vec3 {
+        let angle = self.phase + time * self.speed;
+        self.center + self.radius * Vec3::new(angle.cos(), 0.0, angle.sin())
+    }
+}
+
+/// Blends the color of the light from each color to the next and from the last back to the first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColorCycle {
+    /// Linear RGB.
+    pub colors: Vec<[f64; 3]>,
+    /// Seconds for the whole cycle.
+    pub period: f32,
+}
+
+impl ColorCycle {
+    /// None without colors.
+    pub fn color(&self, time: f32) -> Option<[f64; 3]> {
+        let count = self.colors.len();
+        if count == 0 {
+            return None;
+        }
+        let position = (time / self.period.max(f32::EPSILON)).rem_euclid(1.0) * count as f32;
+        let index = (position as usize).min(count - 1);
+        let t = f64::from(position.fract());
+        let t = t * t * (3.0 - 2.0 * t);
+        let (from, to) = (self.colors[index], self.colors[(index + 1) % count]);
+        Some([0, 1, 2].map(|channel| from[channel] + (to[channel] - from[channel]) * t))
+    }
+}
+
+/// Varies the intensity of the light with smooth noise.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Flicker {
+    /// Share of the intensity that flickers, 0 keeps it steady and 1 lets it drop to nothing.
+    pub amount: f32,
+    /// Changes of brightness per second.
+    pub speed: f32,
+}
+
+impl Flicker {
+    /// Factor of the intensity at `time`, `seed` keeps lights from flickering in step.
+    fn factor(&self, time: f32, seed: u32) -> f32 {
+        let x = time * self.speed;
+        let step = x.floor();
+        let t = x - step;
+        let (a, b) = (noise(step as i32, seed), noise(step as i32 + 1, seed));
+        let value = a + (b - a) * t * t * (3.0 - 2.0 * t);
+        1.0 - self.amount.clamp(0.0, 1.0) * value
+    }
+}
+
+/// Value between 0 and 1 for each integer step.
+fn noise(step: i32, seed: u32) -> f32 {
+    let mut hash = (step as u32).wrapping_mul(0x9E37_79B9) ^ seed;
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85EB_CA6B);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xC2B2_AE35);
+    hash ^= hash >> 16;
+    hash as f32 / u32::MAX as f32
+}
+
+/// Animates the light node `node` with `tracks`.
+#[derive(Debug, Clone)]
+pub struct LightAnimation {
+    pub node: String,
+    pub tracks: LightTracks,
+    /// Intensity the flicker varies, the intensity of the light when the animation was added.
+    pub base_intensity: f32,
+    seed: u32,
+}
+
+impl LightAnimation {
+    pub fn new(node: &str, tracks: LightTracks, base_intensity: f32) -> Self {
+        let seed = node.bytes().fold(0x811C_9DC5u32, |hash, byte| {
+            (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+        });
+        Self {
+            node: node.to_string(),
+            tracks,
+            base_intensity,
+            seed,
+        }
+    }
+
+    /// Node matrix at `time` in seconds for the orbit, keeping the rotation and scale of `matrix`.
+    pub fn matrix(&self, matrix: Mat4, time: f32) -> Option<Mat4> {
+        let orbit = self.tracks.orbit?;
+        let (scale, rotation, _) = matrix.to_scale_rotation_translation();
+        Some(Mat4::from_scale_rotation_translation(
+            scale,
+            rotation,
+            orbit.position(time),
+        ))
+    }
+
+    /// Color at `time`, cycling no faster than `limits` allow.
+    pub fn color(&self, time: f32, limits: &AccessibilitySettings) -> Option<[f64; 3]> {
+        let cycle = self.tracks.color_cycle.as_ref()?;
+        cycle.color(time * cycle.period / limits.color_period(cycle))
+    }
+
+    /// Intensity at `time`, flickering no faster and deeper than `limits` allow.
+    pub fn intensity(&self, time: f32, limits: &AccessibilitySettings) -> Option<f32> {
+        let flicker = limits.flicker(self.tracks.flicker?);
+        Some(self.base_intensity * flicker.factor(time, self.seed))
+    }
+}
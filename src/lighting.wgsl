@@ -0,0 +1,81 @@
+// Lights of the scene, the ambient light and the fog, at LIGHTS_GROUP. The shadows of the lights are
+// in shadows.wgsl.
+#import bind_groups
+#import camera
+
+struct Light {
+    position: vec4<f32>,
+    color: vec4<f32>,
+    model: mat4x4<f32>,
+    view_proj: mat4x4<f32>,
+    // -1 if the light casts no shadow
+    shadow_layer: i32,
+    // LightKind: 0 spot, 1 point
+    kind: u32,
+    intensity: f32,
+    // 0 without distance falloff
+    range: f32,
+    // share of the shadow map layers the light renders into, from their top left corner
+    shadow_scale: f32,
+}
+@group(LIGHTS_GROUP) @binding(0)
+var<storage, read> s_lights: array<Light>;
+@group(LIGHTS_GROUP) @binding(0)
+var<uniform> u_lights: array<Light, 10>;
+// bindings 1 (t_shadow), 2 (sampler_shadow) and 4 (t_point_shadow) depend on the ShadowMode, they are
+// declared with sample_shadow and sample_point_shadow in shadow_moments.wgsl or shadow_depth.wgsl
+@group(LIGHTS_GROUP) @binding(3) var<uniform> light_count: u32;
+
+// sky and ground colors scaled by the strength, see Ambient
+struct Ambient {
+    sky: vec4<f32>,
+    ground: vec4<f32>,
+};
+@group(LIGHTS_GROUP) @binding(5) var<uniform> ambient: Ambient;
+
+const FOG_LINEAR: u32 = 1u;
+const FOG_EXPONENTIAL: u32 = 2u;
+
+// distance fog, see Fog
+struct Fog {
+    color: vec4<f32>,
+    // FogMode, 0 without fog
+    mode: u32,
+    density: f32,
+    start: f32,
+    end: f32,
+};
+@group(LIGHTS_GROUP) @binding(6) var<uniform> fog: Fog;
+
+// Fades the lit color of a surface at the world position into the fog by its distance from the camera.
+fn apply_fog(color: vec3<f32>, world_position: vec4<f32>) -> vec3<f32> {
+    let distance = length(camera.position.xyz - world_position.xyz);
+    var visibility = 1.0;
+    if (fog.mode == FOG_LINEAR) {
+        visibility = clamp((fog.end - distance) / max(fog.end - fog.start, 1e-4), 0.0, 1.0);
+    } else if (fog.mode == FOG_EXPONENTIAL) {
+        visibility = exp(-fog.density * distance);
+    }
+    return mix(fog.color.rgb, color, visibility);
+}
+
+// Hemisphere ambient light of a surface with the world space normal, the sky color facing up and the
+// ground color facing down.
+fn ambient_light(normal: vec3<f32>) -> vec3<f32> {
+    return mix(ambient.ground.rgb, ambient.sky.rgb, normal.y * 0.5 + 0.5);
+}
+
+const LIGHT_KIND_POINT: u32 = 1u;
+
+// Intensity of the light at the fragment. Within the range of the light it falls off with the inverse
+// square of the distance, windowed to reach 0 at the range.
+fn light_falloff(light: Light, world_position: vec3<f32>) -> f32 {
+    if (light.range <= 0.0) {
+        return light.intensity;
+    }
+    let to_light = (light.model * light.position).xyz - world_position;
+    let distance_squared = max(dot(to_light, to_light), 0.0001);
+    let ratio = distance_squared / (light.range * light.range);
+    let window = clamp(1.0 - ratio * ratio, 0.0, 1.0);
+    return light.intensity * window * window / distance_squared;
+}
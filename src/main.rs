@@ -1,26 +1,150 @@
 mod application;
 mod renderer;
 mod scenegraph;
+mod bind_groups;
 mod camera;
 mod model;
 mod resources;
 mod texture;
 mod light;
+mod panorama;
+mod stereo;
+mod hud;
+mod depth_view;
+mod culling;
+mod reflection;
+mod refraction;
+mod msaa;
+mod skybox;
+mod displacement;
+mod pick;
+mod golden;
+mod labels;
+mod error;
+mod input;
+mod watchdog;
+mod settings;
+mod clock;
+mod scene;
+mod gpu_info;
+mod offscreen;
+mod layered_shadow;
+mod frame_stats;
+mod custom_material;
+mod custom_pass;
+mod shader_cache;
+mod debug_lines;
+mod startup;
+mod stylize;
+mod trails;
+mod spline;
+mod time_of_day;
+mod exposure;
+mod depth_of_field;
+mod shader_compose;
+mod light_animation;
+mod hdr;
+mod color_blind;
+mod accessibility;
+mod pass_capture;
+mod shadow_readback;
+mod constraint;
+mod shader_reload;
+mod asset_reload;
+mod gltf_export;
+#[cfg(feature = "debug-ui")]
+mod debug_ui;
+#[cfg(feature = "gamepad")]
+mod gamepad;
+#[cfg(target_arch = "wasm32")]
+mod anchor;
 
 use crate::application::App;
+use crate::golden::GoldenRun;
+use crate::pass_capture::PassCapture;
+use crate::renderer::Renderer;
+use crate::resources::ResourceConfig;
+use std::path::PathBuf;
 use winit::event_loop::{ControlFlow, EventLoop};
 
+/// Frame size of `--headless` without `--size`.
+const HEADLESS_SIZE: (u32, u32) = (1280, 720);
+
 fn main() {
+    ResourceConfig::from_args(arg_value("--asset-root")).init();
+    if std::env::args().any(|arg| arg == "--gpu-info") {
+        println!("{}", pollster::block_on(gpu_info::request_report()));
+        return;
+    }
+    if let Some(file) = arg_value("--headless") {
+        if let Err(e) = render_headless(&file) {
+            println!("Headless rendering failed: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
     let event_loop = EventLoop::with_user_event().build().unwrap();
-    let mut app = App::new(&event_loop);
+    let mut app = App::new(
+        &event_loop,
+        arg_value("--compare-golden").map(|dir| GoldenRun {
+            dir: PathBuf::from(dir),
+            update: std::env::args().any(|arg| arg == "--update-golden"),
+        }),
+        arg_value("--settings"),
+        arg_value("--scene"),
+        std::env::args().any(|arg| arg == "--deterministic"),
+        std::env::args().any(|arg| arg == "--frame-stats"),
+        std::env::args().any(|arg| arg == "--watch-assets"),
+    );
 
     event_loop.set_control_flow(ControlFlow::Poll);
     event_loop.run_app(&mut app).expect("Failed to run app");
+    if app.golden_passed == Some(false) {
+        std::process::exit(1);
+    }
+}
+
+/// The value of the command line option `name`, e.g. the reference directory of `--compare-golden <dir>`.
+fn arg_value(name: &str) -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == name {
+            return args.next();
+        }
+    }
+    None
+}
+
+/// Renders one frame of the scene without a window and saves it as `file`, for thumbnails and CI. The
+/// size is given with `--size <width>x<height>`, `--capture-passes <dir>` also saves the targets of the
+/// passes into `dir`.
+fn render_headless(file: &str) -> anyhow::Result<()> {
+    let (width, height) = match arg_value("--size") {
+        Some(size) => size
+            .split_once('x')
+            .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+            .ok_or_else(|| anyhow::anyhow!("--size {size} is not <width>x<height>"))?,
+        None => HEADLESS_SIZE,
+    };
+    let mut renderer = pollster::block_on(Renderer::new_headless(
+        width,
+        height,
+        arg_value("--settings"),
+        arg_value("--scene"),
+    ))?;
+    renderer.pass_capture = arg_value("--capture-passes").map(PassCapture::new);
+    let pixels = renderer.read_back_frame()?;
+    image::RgbaImage::from_raw(width, height, pixels)
+        .ok_or_else(|| anyhow::anyhow!("The frame has an unexpected size"))?
+        .save(file)?;
+    println!("Saved {file}");
+    Ok(())
 }
 
 #[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
-pub fn run_web() {
+pub fn run_web(asset_root: Option<String>) {
+    ResourceConfig { base: asset_root }.init();
     let window = web_sys::window().unwrap_throw();
     let document = window.document().unwrap_throw();
 
@@ -1,12 +1,18 @@
 /*
    Taken (mostly) from https://sotrh.github.io/learn-wgpu/beginner/tutorial9-models/#loading-models-with-tobj
 */
+use crate::displacement::{displace_mesh, DisplacementMap};
+use crate::error::RendererError;
+use crate::labels;
 use crate::resources::{load_string, load_texture};
 use crate::texture;
-use crate::texture::get_default_texture;
+use crate::texture::{
+    get_default_texture, get_toon_ramp_texture, get_white_texture, TextureContent,
+};
 use bytemuck::{Pod, Zeroable};
+use glam::{Vec2, Vec3};
+use std::collections::HashMap;
 use std::io::{BufReader, Cursor};
-use wasm_bindgen::throw_str;
 use wgpu::util::DeviceExt;
 use wgpu::Device;
 
@@ -44,6 +50,73 @@ impl Vertex {
     }
 }
 
+/// Tangent in xyz and the sign of the bitangent in w, along which anisotropic highlights stretch.
+/// Derived from the texture coordinates when a mesh is uploaded, so it lives in its own vertex buffer.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct Tangent {
+    pub tangent: [f32; 4],
+}
+
+impl Tangent {
+    /// Uses shader location 10, after the ones of [`Vertex`] and the instance transforms.
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: size_of::<Tangent>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[wgpu::VertexAttribute {
+                offset: 0,
+                shader_location: 10,
+                format: wgpu::VertexFormat::Float32x4,
+            }],
+        }
+    }
+
+    /// Accumulates the texture space directions of the triangles around each vertex.
+    /// Vertices without usable texture coordinates get an arbitrary tangent perpendicular to the normal.
+    pub fn compute(vertices: &[Vertex], indices: &[u32]) -> Vec<Tangent> {
+        let mut tangents = vec![Vec3::ZERO; vertices.len()];
+        let mut bitangents = vec![Vec3::ZERO; vertices.len()];
+        for triangle in indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| &vertices[triangle[i] as usize]);
+            let edge1 = Vec3::from(b.pos) - Vec3::from(a.pos);
+            let edge2 = Vec3::from(c.pos) - Vec3::from(a.pos);
+            let duv1 = Vec2::from(b.tex_coords) - Vec2::from(a.tex_coords);
+            let duv2 = Vec2::from(c.tex_coords) - Vec2::from(a.tex_coords);
+            let determinant = duv1.perp_dot(duv2);
+            if determinant.abs() < f32::EPSILON {
+                continue;
+            }
+            let tangent = (edge1 * duv2.y - edge2 * duv1.y) / determinant;
+            let bitangent = (edge2 * duv1.x - edge1 * duv2.x) / determinant;
+            for &index in triangle {
+                tangents[index as usize] += tangent;
+                bitangents[index as usize] += bitangent;
+            }
+        }
+
+        vertices
+            .iter()
+            .zip(tangents.iter().zip(&bitangents))
+            .map(|(vertex, (tangent, bitangent))| {
+                let normal = Vec3::from(vertex.normal).normalize_or(Vec3::Y);
+                // Gram-Schmidt against the normal
+                let tangent = (*tangent - normal * normal.dot(*tangent))
+                    .try_normalize()
+                    .unwrap_or_else(|| normal.any_orthonormal_vector());
+                let handedness = if normal.cross(tangent).dot(*bitangent) < 0.0 {
+                    -1.0
+                } else {
+                    1.0
+                };
+                Tangent {
+                    tangent: tangent.extend(handedness).to_array(),
+                }
+            })
+            .collect()
+    }
+}
+
 pub const CUBE_VERTICES: &[Vertex] = &[
     Vertex { pos: [-1.0, -1.0,  1.0], tex_coords: [0.0, 0.0], normal: [0.0, 0.0,  1.0] },
     Vertex { pos: [ 1.0, -1.0,  1.0], tex_coords: [1.0, 0.0], normal: [0.0, 0.0,  1.0] },
@@ -93,7 +166,23 @@ pub struct MaterialUniform {
     pub specular: [f32; 4],
     pub shininess: f32,
     pub dissolve: f32,
+    /// Index of refraction, from the MTL optical density.
+    pub ior: f32,
+    /// Blur of the refracted background, from the MTL roughness or shininess.
+    pub roughness: f32,
+    /// Scattering color in rgb and the wrap factor in a, all zero without subsurface scattering.
+    pub subsurface: [f32; 4],
+    /// Anisotropy strength and the rotation of the tangent in radians, zero for isotropic highlights.
+    pub anisotropy: [f32; 2],
+    /// Clearcoat strength and roughness, zero without a clearcoat layer.
+    pub clearcoat: [f32; 2],
+    /// Metallic factor of the PBR path, from the MTL `Pm`.
+    pub metallic: f32,
+    /// Strength of the rim light of the toon path, from the MTL `Tn`.
+    pub toon_rim: f32,
     _padding: [f32; 2],
+    /// Emitted light in rgb, a unused. Above 1 for surfaces brighter than white, see [`Material::emissive`].
+    pub emissive: [f32; 4],
 }
 
 impl MaterialUniform {
@@ -110,34 +199,309 @@ impl MaterialUniform {
             specular: [specular[0], specular[1], specular[2], 0.0].into(),
             shininess: material.shininess.unwrap_or(1.0),
             dissolve: material.dissolve.unwrap_or(1.0),
+            ior: material.optical_density.unwrap_or(1.5),
+            roughness: material_roughness(material),
+            subsurface: [0.0; 4],
+            anisotropy: [0.0; 2],
+            clearcoat: [0.0; 2],
+            metallic: material_param(material, "Pm")
+                .unwrap_or(0.0)
+                .clamp(0.0, 1.0),
+            toon_rim: material_param(material, "Tn").unwrap_or(0.0).max(0.0),
             _padding: [0.0; 2],
+            emissive: [0.0; 4],
+        }
+    }
+
+    pub fn from_material(material: &Material) -> Self {
+        let mut uniform = Self::from_tobj_material(&material.material);
+        let [r, g, b] = material.emissive.map(|channel| channel.max(0.0));
+        uniform.emissive = [r, g, b, 0.0];
+        if let Some(subsurface) = &material.subsurface {
+            let [r, g, b] = subsurface.color;
+            uniform.subsurface = [r, g, b, subsurface.wrap.clamp(0.0, 1.0)];
+        }
+        if let Some(anisotropy) = &material.anisotropy {
+            uniform.anisotropy = [anisotropy.strength.clamp(-1.0, 1.0), anisotropy.rotation];
+        }
+        if let Some(clearcoat) = &material.clearcoat {
+            uniform.clearcoat = [
+                clearcoat.strength.clamp(0.0, 1.0),
+                clearcoat.roughness.clamp(0.0, 1.0),
+            ];
+        }
+        uniform
+    }
+}
+
+/// The PBR roughness `Pr` if the MTL file has one, otherwise derived from the Phong exponent.
+/// Materials without either are treated as perfectly smooth.
+fn material_roughness(material: &tobj::Material) -> f32 {
+    material_param(material, "Pr")
+        .or_else(|| {
+            material
+                .shininess
+                .map(|shininess| (2.0 / (shininess.max(0.0) + 2.0)).sqrt())
+        })
+        .unwrap_or(0.0)
+        .clamp(0.0, 1.0)
+}
+
+/// The clearcoat of the PBR extension to MTL, `Pc` for the thickness and `Pcr` for the roughness,
+/// so assets converted from glTF keep the coat of the clearcoat extension.
+fn material_clearcoat(material: &tobj::Material) -> Option<Clearcoat> {
+    let strength = material_param(material, "Pc").filter(|strength| *strength > 0.0)?;
+    Some(Clearcoat {
+        strength,
+        roughness: material_param(material, "Pcr").unwrap_or(0.0),
+    })
+}
+
+/// Materials with a toon rim `Tn` or ramp `map_Tn` are shaded with [`Shading::Toon`], materials using
+/// the parameters of the PBR extension to MTL with [`Shading::Pbr`].
+fn material_shading(material: &tobj::Material) -> Shading {
+    let is_toon = ["Tn", "map_Tn"]
+        .iter()
+        .any(|param| material.unknown_param.contains_key(*param));
+    if is_toon {
+        return Shading::Toon;
+    }
+    let is_pbr = ["Pm", "Pr", "map_Pm", "map_Pr"]
+        .iter()
+        .any(|param| material.unknown_param.contains_key(*param));
+    if is_pbr {
+        Shading::Pbr
+    } else {
+        Shading::Phong
+    }
+}
+
+/// The emissive color `Ke`, white if the material only has an emissive map `map_Ke` and black if neither.
+fn material_emissive(material: &tobj::Material) -> [f32; 3] {
+    let color = material.unknown_param.get("Ke").and_then(|value| {
+        let channels = value
+            .split_whitespace()
+            .map(|channel| channel.parse::<f32>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        match channels[..] {
+            [r, g, b] => Some([r, g, b]),
+            // a single value is a gray
+            [value] => Some([value; 3]),
+            _ => None,
         }
+    });
+    match color {
+        Some(color) => color,
+        None if material.unknown_param.contains_key("map_Ke") => [1.0; 3],
+        None => [0.0; 3],
     }
 }
 
+/// A numeric MTL statement tobj doesn't know about.
+fn material_param(material: &tobj::Material, name: &str) -> Option<f32> {
+    material
+        .unknown_param
+        .get(name)
+        .and_then(|value| value.trim().parse::<f32>().ok())
+}
+
 #[derive(Debug)]
 pub struct Model {
     pub meshes: Vec<Mesh>,
     pub materials: Vec<Material>,
+    /// The OBJ, MTL and texture files the model was loaded from, empty for models built in code.
+    pub files: Vec<String>,
 }
 
 #[derive(Debug, Default)]
 pub struct Material {
     pub name: String,
     pub diffuse_texture: Option<texture::Texture>,
+    /// The file the diffuse texture was loaded from, None for the default texture.
+    pub diffuse_file: Option<String>,
+    /// `map_Ks`, multiplied with the specular color. White if the material has none.
+    pub specular_texture: Option<texture::Texture>,
+    /// `map_Ns`, the red channel scales the specular exponent and makes the surface smoother.
+    /// White if the material has none.
+    pub shininess_texture: Option<texture::Texture>,
+    /// `map_Pm`, the blue channel scales the metallic factor of the PBR path. White if the material has none.
+    pub metallic_texture: Option<texture::Texture>,
+    /// `map_Pr`, the green channel scales the roughness of the PBR path. White if the material has none.
+    /// Together with the metallic map this matches the packed metallic-roughness textures of glTF.
+    pub roughness_texture: Option<texture::Texture>,
+    /// `map_Ka`, the red channel is the ambient occlusion of the PBR path. White if the material has none.
+    pub occlusion_texture: Option<texture::Texture>,
+    /// `map_Tn`, the light ramp of the toon path, looked up along its first row by the lighting of the
+    /// fragment. Three bands if the material has none.
+    pub ramp_texture: Option<texture::Texture>,
+    /// `map_Ke`, multiplied with the emissive color. White if the material has none.
+    pub emissive_texture: Option<texture::Texture>,
     pub material: tobj::Material,
+    /// Light the surface gives off by itself, in linear RGB, from the MTL `Ke`. Black for surfaces that
+    /// don't glow. It is added after the lighting, and values above 1 are kept for a bloom pass to pick
+    /// out the glowing parts.
+    pub emissive: [f32; 3],
+    pub shading: Shading,
+    /// Added to the depth bias of the passes drawing meshes with this material, e.g. to keep
+    /// coplanar surfaces or decals from z-fighting.
+    pub depth_bias: wgpu::DepthBiasState,
+    pub subsurface: Option<Subsurface>,
+    pub anisotropy: Option<Anisotropy>,
+    pub clearcoat: Option<Clearcoat>,
+}
+
+/// Lighting model of a material, each one is drawn with its own pipeline variant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Shading {
+    /// Blinn-Phong style lighting from the MTL colors, with the subsurface and anisotropy extensions.
+    #[default]
+    Phong,
+    /// Cook-Torrance BRDF with metallic, roughness and ambient occlusion maps.
+    Pbr,
+    /// Cel shading, the light falls into the bands of a ramp texture and a rim light traces the silhouette.
+    Toon,
+}
+
+/// Shader features of a material that are baked into the pipeline variant it is drawn with, see
+/// [`crate::renderer::PipelineKey`]. Features a material doesn't use are compiled out of its fragment
+/// shader instead of being skipped by branches on the material uniform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MaterialFeatures {
+    /// Fragments whose diffuse alpha is below one half are discarded, for cutouts like leaves and
+    /// fences. From the MTL `alpha_test 1`.
+    pub alpha_test: bool,
+    /// Back faces are drawn as well. The MTL `double_sided 0` culls them.
+    pub double_sided: bool,
+    /// See [`Material::anisotropy`].
+    pub anisotropy: bool,
+    /// See [`Material::clearcoat`].
+    pub clearcoat: bool,
+}
+
+impl Default for MaterialFeatures {
+    fn default() -> Self {
+        Self {
+            alpha_test: false,
+            double_sided: true,
+            anisotropy: false,
+            clearcoat: false,
+        }
+    }
+}
+
+impl MaterialFeatures {
+    /// Values of the pipeline-overridable constants of shader.wgsl.
+    pub fn constants(&self) -> HashMap<String, f64> {
+        let flag = |enabled: bool| if enabled { 1.0 } else { 0.0 };
+        HashMap::from([
+            ("ALPHA_TEST".to_string(), flag(self.alpha_test)),
+            ("ANISOTROPY".to_string(), flag(self.anisotropy)),
+            ("CLEARCOAT".to_string(), flag(self.clearcoat)),
+        ])
+    }
+}
+
+/// Wrap lighting approximation of subsurface scattering, for skin, wax and similar materials.
+/// Light reaches past the terminator into the unlit side, tinted by the scattering color.
+#[derive(Debug, Clone, Copy)]
+pub struct Subsurface {
+    pub color: [f32; 3],
+    /// How far the light wraps around, 0 is plain Lambert and 1 lights the whole back side.
+    pub wrap: f32,
+}
+
+/// Stretches the specular highlight along the mesh tangent, for brushed metal, hair and similar
+/// materials. The width of the highlight follows the material roughness.
+#[derive(Debug, Clone, Copy)]
+pub struct Anisotropy {
+    /// From -1 to 1, positive values stretch the highlight along the tangent, negative ones along the bitangent.
+    pub strength: f32,
+    /// Rotation of the tangent around the normal in radians.
+    pub rotation: f32,
+}
+
+/// Thin glossy layer on top of the base material, like the lacquer of car paint. The coat reflects
+/// part of the light before it reaches the base.
+#[derive(Debug, Clone, Copy)]
+pub struct Clearcoat {
+    /// From 0 to 1, how much the coat reflects.
+    pub strength: f32,
+    /// Roughness of the coat, independent of the roughness of the base.
+    pub roughness: f32,
+}
+
+/// Factors of a material and the file of its diffuse texture, what an exporter can take over.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MaterialInfo {
+    pub name: String,
+    pub diffuse: [f32; 3],
+    pub dissolve: f32,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub emissive: [f32; 3],
+    pub double_sided: bool,
+    pub diffuse_file: Option<String>,
 }
 
 impl Material {
+    /// Textures after the diffuse texture, sampler and uniform in the material bind group: specular,
+    /// shininess, metallic, roughness, ambient occlusion, toon ramp and emissive maps, from binding 3 on.
+    pub const MAP_COUNT: u32 = 7;
+
+    /// Materials at most this opaque are drawn as refracting glass.
+    pub const GLASS_DISSOLVE: f32 = 0.5;
+
+    pub fn is_glass(&self) -> bool {
+        self.material.dissolve.unwrap_or(1.0) <= Self::GLASS_DISSOLVE
+    }
+
+    /// Partly opaque materials that aren't glass are blended over the scene behind them.
+    pub fn is_transparent(&self) -> bool {
+        self.material.dissolve.unwrap_or(1.0) < 1.0 && !self.is_glass()
+    }
+
+    pub fn info(&self) -> MaterialInfo {
+        let uniform = MaterialUniform::from_material(self);
+        MaterialInfo {
+            name: self.name.clone(),
+            diffuse: self.material.diffuse.unwrap_or([0.0; 3]),
+            dissolve: uniform.dissolve,
+            metallic: uniform.metallic,
+            roughness: uniform.roughness,
+            emissive: self.emissive,
+            double_sided: self.features().double_sided,
+            diffuse_file: self.diffuse_file.clone(),
+        }
+    }
+
+    /// The features of the material, from its MTL statements and extensions.
+    pub fn features(&self) -> MaterialFeatures {
+        let flag = |name| material_param(&self.material, name).map(|value| value != 0.0);
+        MaterialFeatures {
+            alpha_test: flag("alpha_test").unwrap_or(false),
+            double_sided: flag("double_sided").unwrap_or(true),
+            anisotropy: self.anisotropy.is_some(),
+            clearcoat: self.clearcoat.is_some(),
+        }
+    }
+
     pub fn new(
         name: &str,
         diffuse_color: Option<[f32; 3]>,
         device: &Device,
         queue: &wgpu::Queue,
-    ) -> Self {
-        let default_texture =
-            texture::Texture::from_image(device, queue, &get_default_texture(), Some("ground"))
-                .unwrap_or_else(|e| throw_str(&format!("{e:#?}")));
+    ) -> Result<Self, RendererError> {
+        let default_texture = texture::Texture::from_image(
+            device,
+            queue,
+            &get_default_texture(),
+            Some(&labels::material(name, "diffuse")),
+            true,
+        )?;
+        let white_texture = |map| {
+            let label = labels::material(name, map);
+            texture::Texture::from_image(device, queue, &get_white_texture(), Some(&label), false)
+        };
 
         let material = tobj::Material {
                 name: name.to_string(),
@@ -145,48 +509,88 @@ impl Material {
                 dissolve: Some(1.0),
                 ..Default::default()
         };
-        Self {
+        Ok(Self {
             name: name.to_string(),
             diffuse_texture: Some(default_texture),
+            diffuse_file: None,
+            specular_texture: Some(white_texture("specular")?),
+            shininess_texture: Some(white_texture("shininess")?),
+            metallic_texture: Some(white_texture("metallic")?),
+            roughness_texture: Some(white_texture("roughness")?),
+            occlusion_texture: Some(white_texture("occlusion")?),
+            ramp_texture: Some(texture::Texture::from_image(
+                device,
+                queue,
+                &get_toon_ramp_texture(),
+                Some(&labels::material(name, "ramp")),
+                true,
+            )?),
+            emissive_texture: Some(white_texture("emissive")?),
             material,
-        }
+            emissive: [0.0; 3],
+            shading: Shading::Phong,
+            depth_bias: Default::default(),
+            subsurface: None,
+            anisotropy: None,
+            clearcoat: None,
+        })
     }
     pub fn create_bind_group(
         &self,
         device: &Device,
         layout: &wgpu::BindGroupLayout,
     ) -> Option<wgpu::BindGroup> {
-        let material_uniform = MaterialUniform::from_tobj_material(&self.material);
+        let material_uniform = MaterialUniform::from_material(self);
         let material_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Material Buffer"),
+            label: Some(&labels::material(&self.name, "uniform")),
             contents: bytemuck::cast_slice(&[material_uniform]),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
-        if let Some(diffuse_texture) = &self.diffuse_texture {
-            return Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
-                layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 2,
-                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                            buffer: &material_buffer,
-                            offset: 0,
-                            size: None,
-                        }),
-                    },
-                ],
-                label: Some(&self.name),
-            }))
-        }
-        None
+        let diffuse_texture = self.diffuse_texture.as_ref()?;
+        // see MAP_COUNT
+        let maps = [
+            &self.specular_texture,
+            &self.shininess_texture,
+            &self.metallic_texture,
+            &self.roughness_texture,
+            &self.occlusion_texture,
+            &self.ramp_texture,
+            &self.emissive_texture,
+        ]
+        .into_iter()
+        .map(Option::as_ref)
+        .collect::<Option<Vec<_>>>()?;
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &material_buffer,
+                    offset: 0,
+                    size: None,
+                }),
+            },
+        ];
+        entries.extend(
+            maps.iter()
+                .zip(3..)
+                .map(|(map, binding)| wgpu::BindGroupEntry {
+                    binding,
+                    resource: wgpu::BindingResource::TextureView(&map.view),
+                }),
+        );
+        Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &entries,
+            label: Some(&labels::material(&self.name, "bind_group")),
+        }))
     }
 }
 
@@ -199,14 +603,73 @@ pub struct Mesh {
     pub material: usize,
 }
 
+/// Loads an optional map of the material relative to the model, white if the material has none.
+/// The texture maps `m` refers to, relative to the directory of its MTL file.
+fn material_maps(m: &tobj::Material) -> impl Iterator<Item = &str> {
+    let param_maps = ["map_Pm", "map_Pr", "map_Tn", "map_Ke"]
+        .into_iter()
+        .filter_map(|name| m.unknown_param.get(name).map(String::as_str));
+    let displacement_map = m
+        .unknown_param
+        .get("disp")
+        .and_then(|value| DisplacementMap::parse(value).0);
+    [
+        &m.diffuse_texture,
+        &m.specular_texture,
+        &m.shininess_texture,
+        &m.ambient_texture,
+    ]
+    .into_iter()
+    .filter_map(|map| map.as_deref())
+    .chain(param_maps)
+    .chain(displacement_map)
+}
+
+async fn load_material_map(
+    file_path: &str,
+    map: Option<&str>,
+    device: &Device,
+    queue: &wgpu::Queue,
+    srgb: bool,
+) -> Result<texture::Texture, RendererError> {
+    match map {
+        Some(map) => {
+            let texture_path = std::path::Path::new(&file_path).join(map);
+            load_texture(&texture_path.to_string_lossy(), device, queue, srgb).await
+        }
+        None => {
+            texture::Texture::from_image(device, queue, &get_white_texture(), Some("white"), srgb)
+        }
+    }
+}
+
+/// Meshes with a displacement map are subdivided into `displacement_segments` x `displacement_segments`
+/// triangles per triangle, 0 leaves them as they are.
 pub async fn load_model(
     file_path: &str,
     file_name: &str,
     device: &Device,
     queue: &wgpu::Queue,
-) -> anyhow::Result<Model> {
+    displacement_segments: u32,
+) -> Result<Model, RendererError> {
     let full_path = std::path::Path::new(&file_path).join(file_name);
-    let obj_text = load_string(full_path.to_str().unwrap()).await?;
+    let full_path = full_path.to_string_lossy();
+    let obj_text = load_string(&full_path)
+        .await
+        .map_err(|e| RendererError::asset(&full_path, e))?;
+    let in_directory = |path: &str| {
+        std::path::Path::new(&file_path)
+            .join(path)
+            .to_string_lossy()
+            .into_owned()
+    };
+    let mut files = vec![full_path.to_string()];
+    files.extend(
+        obj_text
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("mtllib "))
+            .map(|material_file| in_directory(material_file.trim())),
+    );
     let obj_cursor = Cursor::new(obj_text);
     let mut obj_reader = BufReader::new(obj_cursor);
 
@@ -220,36 +683,128 @@ pub async fn load_model(
         |p| async move {
             // Replace the file path with the path to the material file
             let material_path = std::path::Path::new(&file_path).join(&p);
-            let mat_text = load_string(material_path.to_str().unwrap()).await.unwrap();
-            tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text)))
+            match load_string(&material_path.to_string_lossy()).await {
+                Ok(mat_text) => tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text))),
+                Err(e) => {
+                    println!("Failed to load {}: {e}", material_path.display());
+                    Err(tobj::LoadError::OpenFileFailed)
+                }
+            }
         },
     )
-    .await?;
+    .await
+    .map_err(|e| RendererError::asset(&full_path, e))?;
 
     let mut materials = Vec::new();
-    for m in obj_materials? {
-        if m.diffuse_texture.is_none() {
-            materials.push(Material {
-                name: m.name.clone(),
-                diffuse_texture: Some(texture::Texture::from_image(device, queue, &get_default_texture(), Some(m.name.as_str()))?),
-                material: m.clone(),
-            });
-            continue;
+    let mut displacement_maps = Vec::new();
+    let obj_materials = obj_materials.map_err(|e| RendererError::asset(&full_path, e))?;
+    for m in obj_materials {
+        files.extend(material_maps(&m).map(in_directory));
+        let mut diffuse_texture = match &m.diffuse_texture {
+            Some(path) => {
+                let texture_path = std::path::Path::new(&file_path).join(path);
+                load_texture(&texture_path.to_string_lossy(), device, queue, true).await?
+            }
+            None => texture::Texture::from_image(
+                device,
+                queue,
+                &get_default_texture(),
+                Some(m.name.as_str()),
+                true,
+            )?,
+        };
+        // `texture_content pixelart|ui|photo` overrides the filtering guessed from the diffuse map
+        if let Some(content) = m.unknown_param.get("texture_content") {
+            match content.trim().parse::<TextureContent>() {
+                Ok(content) => {
+                    let label = labels::material(&m.name, "diffuse");
+                    diffuse_texture.set_content(device, content, Some(&label));
+                }
+                Err(e) => println!("Material {}: {e}", m.name),
+            }
         }
-        let material = m.clone();
-        let texture_path = std::path::Path::new(&file_path).join(&m.diffuse_texture.unwrap());
-        let diffuse_texture = Some(load_texture(texture_path.to_str(), device, queue).await?);
+        let specular_texture = load_material_map(
+            file_path,
+            m.specular_texture.as_deref(),
+            device,
+            queue,
+            true,
+        )
+        .await?;
+        let shininess_texture = load_material_map(
+            file_path,
+            m.shininess_texture.as_deref(),
+            device,
+            queue,
+            false,
+        )
+        .await?;
+        let param_map = |name: &str| m.unknown_param.get(name).map(String::as_str);
+        let metallic_texture =
+            load_material_map(file_path, param_map("map_Pm"), device, queue, false).await?;
+        let roughness_texture =
+            load_material_map(file_path, param_map("map_Pr"), device, queue, false).await?;
+        let occlusion_texture = load_material_map(
+            file_path,
+            m.ambient_texture.as_deref(),
+            device,
+            queue,
+            false,
+        )
+        .await?;
+        let ramp_texture = match param_map("map_Tn") {
+            Some(map) => load_material_map(file_path, Some(map), device, queue, true).await?,
+            None => texture::Texture::from_image(
+                device,
+                queue,
+                &get_toon_ramp_texture(),
+                Some(&labels::material(&m.name, "ramp")),
+                true,
+            )?,
+        };
+        let emissive_texture =
+            load_material_map(file_path, param_map("map_Ke"), device, queue, true).await?;
+        let clearcoat = material_clearcoat(&m);
+        let shading = material_shading(&m);
+        let displacement_map = match param_map("disp").map(DisplacementMap::parse) {
+            Some((Some(map), base, gain)) if displacement_segments > 0 => Some(DisplacementMap {
+                texture: load_material_map(file_path, Some(map), device, queue, false).await?,
+                base,
+                gain,
+            }),
+            _ => None,
+        };
+        displacement_maps.push(displacement_map);
 
         materials.push(Material {
-            name: m.name,
-            diffuse_texture,
-            material,
+            name: m.name.clone(),
+            diffuse_texture: Some(diffuse_texture),
+            diffuse_file: m.diffuse_texture.as_deref().map(in_directory),
+            specular_texture: Some(specular_texture),
+            shininess_texture: Some(shininess_texture),
+            metallic_texture: Some(metallic_texture),
+            roughness_texture: Some(roughness_texture),
+            occlusion_texture: Some(occlusion_texture),
+            ramp_texture: Some(ramp_texture),
+            emissive_texture: Some(emissive_texture),
+            emissive: material_emissive(&m),
+            material: m,
+            shading,
+            depth_bias: Default::default(),
+            subsurface: None,
+            anisotropy: None,
+            clearcoat,
         });
     }
 
     let meshes = models
         .into_iter()
         .map(|m| {
+            // meshes without texture coordinates sample the corner of their textures
+            let tex_coord = |i: usize| match m.mesh.texcoords.get(i * 2..i * 2 + 2) {
+                Some(&[u, v]) => [u, 1.0 - v],
+                _ => [0.0, 0.0],
+            };
             let vertices = (0..m.mesh.positions.len() / 3)
                 .map(|i| {
                     if m.mesh.normals.is_empty() {
@@ -259,10 +814,7 @@ pub async fn load_model(
                                 m.mesh.positions[i * 3 + 1],
                                 m.mesh.positions[i * 3 + 2],
                             ],
-                            tex_coords: [
-                                m.mesh.texcoords[i * 2],
-                                1.0 - m.mesh.texcoords[i * 2 + 1],
-                            ],
+                            tex_coords: tex_coord(i),
                             normal: [0.0, 0.0, 0.0],
                         }
                     } else {
@@ -272,10 +824,7 @@ pub async fn load_model(
                                 m.mesh.positions[i * 3 + 1],
                                 m.mesh.positions[i * 3 + 2],
                             ],
-                            tex_coords: [
-                                m.mesh.texcoords[i * 2],
-                                1.0 - m.mesh.texcoords[i * 2 + 1],
-                            ],
+                            tex_coords: tex_coord(i),
                             normal: [
                                 m.mesh.normals[i * 3],
                                 m.mesh.normals[i * 3 + 1],
@@ -289,14 +838,28 @@ pub async fn load_model(
             let len = m.mesh.indices.len() as u32;
 
             Mesh {
-                name: full_path.to_str().unwrap().to_string(),
+                name: full_path.to_string(),
                 vertices,
                 indices: m.mesh.indices,
                 num_elements: len,
                 material: m.mesh.material_id.unwrap_or(0),
             }
         })
+        .map(|mesh| {
+            // displacement moves along the normals
+            let has_normals = mesh.vertices.iter().all(|vertex| vertex.normal != [0.0; 3]);
+            match displacement_maps.get(mesh.material) {
+                Some(Some(map)) if has_normals => {
+                    displace_mesh(device, queue, &mesh, map, displacement_segments)
+                }
+                _ => mesh,
+            }
+        })
         .collect::<Vec<_>>();
 
-    Ok(Model { meshes, materials })
+    Ok(Model {
+        meshes,
+        materials,
+        files,
+    })
 }
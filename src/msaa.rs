@@ -0,0 +1,146 @@
+/*
+ * Multisample anti-aliasing for the forward pass.
+ * The forward and glass passes render into a multisampled color and depth target, the color is
+ * resolved into the frame (or the offscreen texture of the refraction pass). Stereo and panorama
+ * captures stay single sampled.
+ */
+use crate::error::RendererError;
+use crate::renderer::{PipelineKey, PipelineVariants};
+use crate::texture;
+
+/// Sample counts tried in order, the largest one supported by the adapter up to the requested count is used.
+const SAMPLE_COUNTS: [u32; 3] = [8, 4, 2];
+
+/// Multisampled target and the pipelines drawing into it.
+pub struct MsaaPass {
+    pub color_view: wgpu::TextureView,
+    pub depth_texture: texture::Texture,
+    pub forward_pipeline: PipelineVariants,
+    pub glass_pipeline: PipelineVariants,
+}
+
+pub struct Msaa {
+    pub sample_count: u32,
+    /// Toggled at runtime, has no effect without multisampling support.
+    pub enabled: bool,
+    format: wgpu::TextureFormat,
+    pass: Option<MsaaPass>,
+}
+
+impl Msaa {
+    /// The largest sample count up to `requested` that the adapter supports for the color and depth format,
+    /// 1 disables multisampling.
+    pub fn supported_sample_count(
+        adapter: &wgpu::Adapter,
+        format: wgpu::TextureFormat,
+        requested: u32,
+    ) -> u32 {
+        let color_flags = adapter.get_texture_format_features(format).flags;
+        let depth_flags = adapter
+            .get_texture_format_features(texture::Texture::DEPTH_FORMAT)
+            .flags;
+        SAMPLE_COUNTS
+            .into_iter()
+            .filter(|count| *count <= requested)
+            .find(|count| {
+                color_flags.sample_count_supported(*count)
+                    && depth_flags.sample_count_supported(*count)
+            })
+            .unwrap_or(1)
+    }
+
+    /// Derives the multisampled variants of the forward and glass pipelines.
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+        forward_pipeline: &PipelineVariants,
+        glass_pipeline: &PipelineVariants,
+    ) -> Self {
+        let pass = (sample_count > 1).then(|| {
+            let (color_view, depth_texture) =
+                Self::create_target(device, format, width, height, sample_count);
+            MsaaPass {
+                color_view,
+                depth_texture,
+                forward_pipeline: forward_pipeline.multisampled(device, sample_count),
+                glass_pipeline: glass_pipeline.multisampled(device, sample_count),
+            }
+        });
+        Self {
+            sample_count,
+            enabled: pass.is_some(),
+            format,
+            pass,
+        }
+    }
+
+    /// Builds the multisampled forward variants again, see [`PipelineVariants::rebuild`].
+    pub fn rebuild_forward_pipeline(&mut self, device: &wgpu::Device) -> Result<(), RendererError> {
+        match &mut self.pass {
+            Some(pass) => pass.forward_pipeline.rebuild(device),
+            None => Ok(()),
+        }
+    }
+
+    fn create_target(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+    ) -> (wgpu::TextureView, texture::Texture) {
+        let color_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("msaa_color_texture"),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let color_view = color_texture.create_view(&Default::default());
+        let depth_texture = texture::Texture::create_multisampled_depth_texture(
+            device,
+            width.max(1),
+            height.max(1),
+            sample_count,
+            "msaa_depth_texture",
+        );
+        (color_view, depth_texture)
+    }
+
+    /// Must be called when the window is resized, the target matches the frame.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        if let Some(pass) = &mut self.pass {
+            (pass.color_view, pass.depth_texture) =
+                Self::create_target(device, self.format, width, height, self.sample_count);
+        }
+    }
+
+    /// The pass to render the frame with, None renders single sampled.
+    pub fn active_pass(&self) -> Option<&MsaaPass> {
+        self.pass.as_ref().filter(|_| self.enabled)
+    }
+
+    /// See [`PipelineVariants::set_shadows`], the glass pipeline samples no shadows.
+    pub fn set_shadows(&mut self, enabled: bool) {
+        if let Some(pass) = &mut self.pass {
+            pass.forward_pipeline.set_shadows(enabled);
+        }
+    }
+
+    pub fn prepare(&mut self, device: &wgpu::Device, keys: &[PipelineKey]) {
+        if let Some(pass) = &mut self.pass {
+            pass.forward_pipeline.prepare(device, keys.iter().copied());
+            pass.glass_pipeline.prepare(device, keys.iter().copied());
+        }
+    }
+}
@@ -0,0 +1,86 @@
+// Moment shadow mapping: the light visibility from the four optimized moments stored in a shadow map
+// texel, see ShadowMode::Moments.
+
+fn msm_shadow(moments: vec4<f32>, depth: f32) -> f32 {
+    let reversed_moments = convert_optimized_moments(moments);
+
+    return reduce_light_bleeding(
+        compute_msm_shadow_intensity(reversed_moments, depth),
+        0.0
+    );
+}
+
+// Reverts the projection of the moments done in the shadow pass
+fn convert_optimized_moments(optimized: vec4<f32>) -> vec4<f32> {
+    var adjusted = optimized;
+    adjusted.x -= 0.035955884801;
+
+    let M_inv = mat4x4<f32>(
+        vec4<f32>(0.2227744146,  0.1549679261,  0.1451988946,  0.163127443),
+        vec4<f32>(0.0771972861,  0.1394629426,  0.2120202157,  0.2591432266),
+        vec4<f32>(0.7926986636,  0.7963415838,  0.7258694464,  0.6539092497),
+        vec4<f32>(0.0319417555, -0.1722823173, -0.2758014811, -0.3376131734)
+    );
+
+    return M_inv * adjusted;
+}
+
+fn compute_msm_shadow_intensity(moments: vec4<f32>, fragment_depth: f32) -> f32 {
+    let b = mix(moments, vec4<f32>(0.5), 0.03);
+    var z: vec3<f32>;
+    z.x = fragment_depth;
+
+    // cholesky
+    let l32_d22 = -b.x * b.y + b.z;
+    let d22 = -b.x * b.x + b.y;
+    let squared_depth_var = -b.y * b.y + b.w;
+
+    let d33_d22 = dot(
+        vec2<f32>(squared_depth_var, -l32_d22),
+        vec2<f32>(d22,          l32_d22)
+    );
+
+    let inv_d22 = 1.0 / d22;
+    let l32 = l32_d22 * inv_d22;
+
+    // build quadratic equation to find z1 and z2
+    var c = vec3<f32>(1.0, z.x, z.x * z.x);
+
+    c.y -= b.x;
+    c.z -= b.y + l32 * c.y;
+    c.y *= inv_d22;
+    c.z *= d22 / d33_d22;
+    c.y -= l32 * c.z;
+    c.x -= dot(c.yz, b.xy);
+
+    let p = c.y / c.z;
+    let q = c.x / c.z;
+    let radicand = (p * p * 0.25) - q;
+    let r = sqrt(max(radicand, 0.0));
+    z.y = -0.5 * p - r;
+    z.z = -0.5 * p + r;
+
+    var switch_vals = vec4<f32>(0.0);
+    // z2 < z0  → [ z1, z0, 1, 1 ]
+    if (z.z < z.x) {
+        switch_vals = vec4<f32>(z.y, z.x, 1.0, 1.0);
+    } else if (z.y < z.x) {
+        // z1 < z0  → [ z0, z1, 0, 1 ]
+        switch_vals = vec4<f32>(z.x, z.y, 0.0, 1.0);
+    } else {
+        switch_vals = vec4<f32>(0.0, 0.0, 0.0, 0.0);
+    }
+
+    let numerator = switch_vals.x * z.z - b.x * (switch_vals.x + z.z + b.y);
+    let denominator = (z.z - switch_vals.y) * (z.x - z.y);
+    let quotient = numerator / denominator;
+
+    let raw_light = switch_vals.z + switch_vals.w * quotient;
+    let intensity = clamp(raw_light, 0.0, 1.0);
+
+    return 1.0 - intensity;
+}
+
+fn reduce_light_bleeding(p_max: f32, amount: f32) -> f32 {
+    return clamp((p_max - amount) / (1.0 - amount), 0.0, 1.0);
+}
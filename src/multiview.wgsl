@@ -0,0 +1,21 @@
+// Appended to shader.wgsl when the adapter supports multiview,
+// so the forward pass can render both eyes of the stereo target in one pass.
+@group(CAMERA_GROUP) @binding(0)
+var<uniform> eye_cameras: array<Camera, 2>;
+
+@vertex
+fn vs_main_multiview(
+    in: VertexInput,
+    instance: InstanceInput,
+    @builtin(view_index) view_index: i32,
+) -> VertexOutput {
+    let world = model.model * instance_model(instance);
+    let world_position = world * vec4<f32>(in.position, 1.0);
+    var out = VertexOutput();
+    out.out_position = eye_cameras[view_index].view_proj * world_position;
+    out.tex_coords = in.tex_coords;
+    out.world_position = world_position;
+    out.world_normal = normalize(model.normal * instance_normal(instance) * in.normal);
+    out.world_tangent = vec4<f32>(normalize((world * vec4<f32>(in.tangent.xyz, 0.0)).xyz), in.tangent.w);
+    return out;
+}
@@ -0,0 +1,97 @@
+/*
+ * Offscreen color target.
+ * Frames rendered without presenting them, for the golden-image comparison and headless renderers, are
+ * drawn into this texture and copied into a buffer that is mapped to read the image back.
+ */
+use image::RgbaImage;
+
+/// Offscreen color target of the frame's format, with a buffer to read it back.
+pub struct OffscreenTarget {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    readback_buffer: wgpu::Buffer,
+    padded_bytes_per_row: u32,
+}
+
+impl OffscreenTarget {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("offscreen_texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+        let padded_bytes_per_row = (width * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("offscreen_readback"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self {
+            texture,
+            view,
+            readback_buffer,
+            padded_bytes_per_row,
+        }
+    }
+
+    pub fn encode_readback(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &self.readback_buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.padded_bytes_per_row),
+                    rows_per_image: Some(self.texture.height()),
+                },
+            },
+            self.texture.size(),
+        );
+    }
+
+    /// Waits for the submitted readback and returns the image in RGBA order.
+    pub fn read(&self, device: &wgpu::Device) -> anyhow::Result<RgbaImage> {
+        let slice = self.readback_buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |_| ());
+        device.poll(wgpu::Maintain::Wait);
+
+        let (width, height) = (self.texture.width(), self.texture.height());
+        let row_bytes = (width * 4) as usize;
+        let mut pixels: Vec<u8> = slice
+            .get_mapped_range()
+            .chunks(self.padded_bytes_per_row as usize)
+            .flat_map(|row| row[..row_bytes].to_vec())
+            .collect();
+        self.readback_buffer.unmap();
+
+        if matches!(
+            self.texture.format(),
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        ) {
+            pixels.chunks_mut(4).for_each(|pixel| pixel.swap(0, 2));
+        }
+        RgbaImage::from_raw(width, height, pixels)
+            .ok_or_else(|| anyhow::anyhow!("Offscreen readback has an unexpected size"))
+    }
+}
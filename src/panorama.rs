@@ -0,0 +1,304 @@
+/*
+ * 360° panorama capture.
+ * The scene is rendered from the camera position into the six faces of a cube, which are then
+ * reprojected into an equirectangular image and written to disk as a PNG.
+ * The faces are kept in a plain texture array and the conversion projects each direction with the
+ * face's own matrix, so no cube map orientation conventions are involved.
+ */
+use crate::camera::{Camera, CameraUniform};
+use crate::hdr::HDR_FORMAT;
+use crate::reflection::ReflectDevice;
+use crate::renderer::Pipeline;
+use crate::texture;
+use glam::{Mat4, Vec3};
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+
+pub const FACE_COUNT: u32 = 6;
+
+/// Forward direction and up vector of each face, in the order +X, -X, +Y, -Y, +Z, -Z.
+const FACE_ORIENTATIONS: [(Vec3, Vec3); FACE_COUNT as usize] = [
+    (Vec3::X, Vec3::Y),
+    (Vec3::NEG_X, Vec3::Y),
+    (Vec3::Y, Vec3::Z),
+    (Vec3::NEG_Y, Vec3::NEG_Z),
+    (Vec3::Z, Vec3::Y),
+    (Vec3::NEG_Z, Vec3::Y),
+];
+
+pub struct PanoramaCapture {
+    face_size: u32,
+    output_texture: wgpu::Texture,
+    output_view: wgpu::TextureView,
+    pub face_views: Vec<wgpu::TextureView>,
+    pub face_depth_views: Vec<wgpu::TextureView>,
+    pub face_camera_bind_groups: Vec<wgpu::BindGroup>,
+    conversion_pipeline: Pipeline,
+    conversion_bind_group: wgpu::BindGroup,
+    readback_buffer: wgpu::Buffer,
+}
+
+impl PanoramaCapture {
+    /// Prepares a capture with square faces of `face_size` pixels, producing an image of
+    /// `4 * face_size` by `2 * face_size` pixels. `format` must match the forward pipeline's color target.
+    pub fn new(
+        device: &wgpu::Device,
+        camera: &Camera,
+        format: wgpu::TextureFormat,
+        face_size: u32,
+    ) -> Self {
+        let projection =
+            Mat4::perspective_rh(90.0f32.to_radians(), 1.0, camera.znear, camera.zfar);
+        let face_matrices = FACE_ORIENTATIONS.map(|(forward, up)| {
+            projection * Mat4::look_at_rh(Vec3::ZERO, forward, up)
+        });
+
+        let face_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("panorama_faces"),
+            size: wgpu::Extent3d {
+                width: face_size,
+                height: face_size,
+                depth_or_array_layers: FACE_COUNT,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let face_views = (0..FACE_COUNT)
+            .map(|face| {
+                face_texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some(&format!("panorama_face_{face}")),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: face,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        let face_array_view = face_texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("panorama_faces"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let face_depth_views = (0..FACE_COUNT)
+            .map(|_| {
+                texture::Texture::create_depth_texture_with_dimensions(
+                    device,
+                    face_size,
+                    face_size,
+                    "panorama_depth",
+                )
+                .view
+            })
+            .collect();
+
+        let camera_bind_group_layout = CameraUniform::get_bind_group_layout(device);
+        let face_camera_bind_groups = face_matrices
+            .iter()
+            .map(|face_matrix| {
+                // the face matrices look from the origin, move the world so the camera sits there
+                let view_proj = *face_matrix * Mat4::from_translation(-camera.eye);
+                let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Panorama Camera Buffer"),
+                    contents: bytemuck::cast_slice(&[CameraUniform::new(view_proj, camera.eye)]),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                });
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &camera_bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }],
+                    label: Some("panorama_camera_bind_group"),
+                })
+            })
+            .collect();
+
+        // HDR frames are linear like the colors of sRGB frames
+        let output_format = if format.is_srgb() || format == HDR_FORMAT {
+            wgpu::TextureFormat::Rgba8UnormSrgb
+        } else {
+            wgpu::TextureFormat::Rgba8Unorm
+        };
+        let (width, height) = (face_size * 4, face_size * 2);
+        let output_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("panorama_equirectangular"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: output_format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let output_view = output_texture.create_view(&Default::default());
+
+        let conversion_bind_group_layout =
+            device.reflect_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+                label: Some("panorama_conversion_bind_group_layout"),
+            });
+        let face_matrix_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Panorama Face Matrix Buffer"),
+            contents: bytemuck::cast_slice(&face_matrices.map(|matrix| matrix.to_cols_array_2d())),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("panorama_sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let conversion_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &conversion_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&face_array_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: face_matrix_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("panorama_conversion_bind_group"),
+        });
+
+        let shader = device.reflect_shader(wgpu::ShaderModuleDescriptor {
+            label: Some("panorama"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("panorama.wgsl"))),
+        });
+        let conversion_pipeline = Pipeline::new(
+            device,
+            "panorama_conversion_pipeline",
+            &shader,
+            &[&conversion_bind_group_layout],
+            "vs_fullscreen",
+            &[],
+            Some("fs_equirectangular"),
+            &[Some(wgpu::ColorTargetState {
+                format: output_format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            None,
+            None,
+            None,
+            None,
+        );
+
+        // a width of 4 * face_size keeps rows 256 byte aligned for any face size that is a multiple of 16
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Panorama Readback Buffer"),
+            size: (width * height * 4) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            face_size,
+            output_texture,
+            output_view,
+            face_views,
+            face_depth_views,
+            face_camera_bind_groups,
+            conversion_pipeline,
+            conversion_bind_group,
+            readback_buffer,
+        }
+    }
+
+    /// Records the equirectangular conversion of the rendered faces and the copy into the readback buffer.
+    pub fn encode_conversion(&self, encoder: &mut wgpu::CommandEncoder) {
+        {
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("panorama_conversion_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.output_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+            rpass.set_pipeline(&self.conversion_pipeline.pipeline);
+            rpass.set_bind_group(0, &self.conversion_bind_group, &[]);
+            rpass.draw(0..3, 0..1);
+        }
+
+        let size = self.output_texture.size();
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.output_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &self.readback_buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(size.width * 4),
+                    rows_per_image: Some(size.height),
+                },
+            },
+            size,
+        );
+    }
+
+    /// Waits for the submitted capture and writes it to `path` as a PNG.
+    pub fn save(&self, device: &wgpu::Device, path: &str) -> anyhow::Result<()> {
+        let slice = self.readback_buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |_| ());
+        device.poll(wgpu::Maintain::Wait);
+
+        let (width, height) = (self.face_size * 4, self.face_size * 2);
+        let pixels = slice.get_mapped_range().to_vec();
+        self.readback_buffer.unmap();
+
+        let image = image::RgbaImage::from_raw(width, height, pixels)
+            .ok_or_else(|| anyhow::anyhow!("Panorama readback has an unexpected size"))?;
+        image.save(path)?;
+        Ok(())
+    }
+}
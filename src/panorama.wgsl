@@ -0,0 +1,53 @@
+const PI: f32 = 3.14159265358979;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // a single triangle covering the whole viewport
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// Rotation-only view projections of the six faces, in the order +X, -X, +Y, -Y, +Z, -Z
+struct Faces {
+    view_proj: array<mat4x4<f32>, 6>,
+};
+
+@group(0) @binding(0) var t_faces: texture_2d_array<f32>;
+@group(0) @binding(1) var s_faces: sampler;
+@group(0) @binding(2) var<uniform> faces: Faces;
+
+@fragment
+fn fs_equirectangular(in: VertexOutput) -> @location(0) vec4<f32> {
+    // the image center looks down -Z, the top row is straight up
+    let longitude = (in.uv.x - 0.5) * 2.0 * PI;
+    let latitude = (0.5 - in.uv.y) * PI;
+    let dir = vec3<f32>(
+        sin(longitude) * cos(latitude),
+        sin(latitude),
+        -cos(longitude) * cos(latitude)
+    );
+
+    // the face is picked by the major axis of the direction
+    let a = abs(dir);
+    var face: i32;
+    if (a.x >= a.y && a.x >= a.z) {
+        face = select(1, 0, dir.x > 0.0);
+    } else if (a.y >= a.z) {
+        face = select(3, 2, dir.y > 0.0);
+    } else {
+        face = select(5, 4, dir.z > 0.0);
+    }
+
+    // project the direction with the same matrix the face was rendered with
+    let clip = faces.view_proj[face] * vec4<f32>(dir, 1.0);
+    let uv = clip.xy / clip.w * vec2<f32>(0.5, -0.5) + vec2<f32>(0.5, 0.5);
+    return textureSampleLevel(t_faces, s_faces, uv, face, 0.0);
+}
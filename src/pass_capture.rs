@@ -0,0 +1,180 @@
+/*
+ * Pass capture.
+ * Saves the intermediate targets of one frame into a folder, to compare what the passes produce before
+ * and after a change, e.g. of the shadow algorithm: the shadow maps as the shadow pass drew them and after
+ * the blur, the depth and color of the forward pass and the frame after each custom pass. F9 captures the
+ * next frame into `passes_<timestamp>/`, `--capture-passes <dir>` the frame of `--headless`. The targets
+ * are copied into buffers while the frame is encoded and written once it is done: 8 bit targets as PNG,
+ * float and depth targets as OpenEXR so nothing is lost to quantization, each layer of an array into a
+ * file of its own. Targets the surface doesn't allow to copy are skipped.
+ */
+use anyhow::bail;
+use image::{Rgba32FImage, RgbaImage};
+use std::path::{Path, PathBuf};
+
+/// A target copied into a buffer, saved by [`PassCapture::save`].
+struct CapturedTarget {
+    name: String,
+    format: wgpu::TextureFormat,
+    size: wgpu::Extent3d,
+    bytes_per_pixel: u32,
+    padded_bytes_per_row: u32,
+    buffer: wgpu::Buffer,
+}
+
+/// The targets of the frame being captured.
+pub struct PassCapture {
+    dir: PathBuf,
+    targets: Vec<CapturedTarget>,
+}
+
+impl PassCapture {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            targets: Vec::new(),
+        }
+    }
+
+    /// Copies all layers of `texture` as it is at this point of `encoder`, to be saved as `name`.
+    pub fn capture(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        name: &str,
+        texture: &wgpu::Texture,
+    ) {
+        let format = texture.format();
+        if !texture.usage().contains(wgpu::TextureUsages::COPY_SRC) || texture.sample_count() > 1 {
+            println!("Pass capture skips {name}, the texture can't be copied");
+            return;
+        }
+        let aspect = if format.is_depth_stencil_format() {
+            wgpu::TextureAspect::DepthOnly
+        } else {
+            wgpu::TextureAspect::All
+        };
+        let Some(bytes_per_pixel) = format.block_copy_size(Some(aspect)) else {
+            println!("Pass capture skips {name}, {format:?} can't be copied");
+            return;
+        };
+        let size = texture.size();
+        let padded_bytes_per_row =
+            (size.width * bytes_per_pixel).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("pass_capture_buffer"),
+            size: (padded_bytes_per_row * size.height * size.depth_or_array_layers)
+                as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(size.height),
+                },
+            },
+            size,
+        );
+        self.targets.push(CapturedTarget {
+            name: name.to_string(),
+            format,
+            size,
+            bytes_per_pixel,
+            padded_bytes_per_row,
+            buffer,
+        });
+    }
+
+    /// Writes the captured targets once the commands that copied them were submitted, returns the
+    /// folder they were written to.
+    pub fn save(self, device: &wgpu::Device) -> anyhow::Result<PathBuf> {
+        std::fs::create_dir_all(&self.dir)?;
+        for target in &self.targets {
+            target
+                .buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, |_| ());
+        }
+        device.poll(wgpu::Maintain::Wait);
+
+        for (index, target) in self.targets.iter().enumerate() {
+            let data = target.buffer.slice(..).get_mapped_range();
+            let layers = target.size.depth_or_array_layers;
+            let layer_bytes = (target.padded_bytes_per_row * target.size.height) as usize;
+            for (layer, layer_data) in data.chunks(layer_bytes).enumerate() {
+                let mut name = format!("{index:02}_{}", target.name);
+                if layers > 1 {
+                    name.push_str(&format!("_layer{layer}"));
+                }
+                if let Err(e) = save_layer(&self.dir.join(name), target, layer_data) {
+                    println!("Pass capture skips {}: {e}", target.name);
+                    break;
+                }
+            }
+            drop(data);
+            target.buffer.unmap();
+        }
+        Ok(self.dir)
+    }
+}
+
+/// Writes a layer of `target` to `path` with the extension of its image format.
+fn save_layer(path: &Path, target: &CapturedTarget, data: &[u8]) -> anyhow::Result<()> {
+    let (width, height) = (target.size.width, target.size.height);
+    // the rows without their padding
+    let row_bytes = (width * target.bytes_per_pixel) as usize;
+    let pixels: Vec<u8> = data
+        .chunks(target.padded_bytes_per_row as usize)
+        .flat_map(|row| &row[..row_bytes])
+        .copied()
+        .collect();
+    let floats = |values: Vec<f32>| {
+        Rgba32FImage::from_raw(width, height, values)
+            .ok_or_else(|| anyhow::anyhow!("unexpected size"))
+    };
+    match target.format {
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => {
+            RgbaImage::from_raw(width, height, pixels)
+                .ok_or_else(|| anyhow::anyhow!("unexpected size"))?
+                .save(path.with_extension("png"))?;
+        }
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => {
+            let mut pixels = pixels;
+            pixels.chunks_mut(4).for_each(|pixel| pixel.swap(0, 2));
+            RgbaImage::from_raw(width, height, pixels)
+                .ok_or_else(|| anyhow::anyhow!("unexpected size"))?
+                .save(path.with_extension("png"))?;
+        }
+        wgpu::TextureFormat::Rgba16Float => {
+            let values = bytemuck::pod_collect_to_vec::<u8, u16>(&pixels)
+                .into_iter()
+                .map(|bits| half::f16::from_bits(bits).to_f32())
+                .collect();
+            floats(values)?.save(path.with_extension("exr"))?;
+        }
+        wgpu::TextureFormat::Rgba32Float => {
+            let values = bytemuck::pod_collect_to_vec::<u8, f32>(&pixels);
+            floats(values)?.save(path.with_extension("exr"))?;
+        }
+        // gray, opaque
+        wgpu::TextureFormat::Depth32Float => {
+            let values = bytemuck::pod_collect_to_vec::<u8, f32>(&pixels)
+                .into_iter()
+                .flat_map(|depth| [depth, depth, depth, 1.0])
+                .collect();
+            floats(values)?.save(path.with_extension("exr"))?;
+        }
+        format => bail!("no image format for {format:?}"),
+    }
+    Ok(())
+}
@@ -0,0 +1,98 @@
+// Appended to shader.wgsl for materials with Shading::Pbr, see model.rs.
+// Cook-Torrance BRDF with the GGX distribution, Smith-Schlick visibility and Schlick fresnel, using
+// the metallic-roughness model of glTF.
+#import math
+
+@group(MATERIAL_GROUP) @binding(5)
+var t_metallic: texture_2d<f32>;
+@group(MATERIAL_GROUP) @binding(6)
+var t_roughness: texture_2d<f32>;
+@group(MATERIAL_GROUP) @binding(7)
+var t_occlusion: texture_2d<f32>;
+
+struct PbrSurface {
+    albedo: vec4<f32>,
+    metallic: f32,
+    roughness: f32,
+    occlusion: f32,
+};
+
+fn sample_pbr_surface(tex_coords: vec2<f32>) -> PbrSurface {
+    var surface: PbrSurface;
+    surface.albedo = textureSample(t_diffuse, s_diffuse, tex_coords);
+    // same fallback to the material color as fs_main
+    if (all(surface.albedo == vec4<f32>(0.0))) {
+        surface.albedo = vec4<f32>(material.diffuse.rgb, material.dissolve);
+    }
+    // roughness in green and metallic in blue like the packed glTF texture, grayscale maps work as well
+    surface.metallic = material.metallic * textureSample(t_metallic, s_diffuse, tex_coords).b;
+    surface.roughness = material.roughness * textureSample(t_roughness, s_diffuse, tex_coords).g;
+    surface.occlusion = textureSample(t_occlusion, s_diffuse, tex_coords).r;
+    return surface;
+}
+
+fn cook_torrance(light: Light, normal: vec3<f32>, surface: PbrSurface, in: VertexOutput) -> vec3<f32> {
+    let light_world_position = light.model * light.position;
+    let light_dir = normalize(light_world_position.xyz - in.world_position.xyz);
+    let view_dir = normalize(camera.position.xyz - in.world_position.xyz);
+    let h = normalize(light_dir + view_dir);
+    let n_dot_l = max(dot(normal, light_dir), 0.0);
+    let n_dot_v = max(dot(normal, view_dir), 0.0001);
+    let n_dot_h = max(dot(normal, h), 0.0);
+    let v_dot_h = max(dot(view_dir, h), 0.0);
+
+    let alpha = max(surface.roughness * surface.roughness, 0.002);
+    let alpha2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    let distribution = alpha2 / (PI * d * d);
+    let k = alpha * 0.5;
+    let visibility = 0.25 / ((n_dot_l * (1.0 - k) + k) * (n_dot_v * (1.0 - k) + k));
+    let f0 = mix(vec3<f32>(0.04), surface.albedo.rgb, surface.metallic);
+    let fresnel = f0 + (1.0 - f0) * pow(1.0 - v_dot_h, 5.0);
+
+    let specular = distribution * visibility * fresnel;
+    let diffuse = (1.0 - fresnel) * (1.0 - surface.metallic) * surface.albedo.rgb / PI;
+    // scaled by pi so a white lambertian surface reflects the full light color, as in the phong path
+    let radiance = (diffuse + specular) * PI * light.color.xyz * n_dot_l;
+    let falloff = light_falloff(light, in.world_position.xyz);
+    if (CLEARCOAT && material.clearcoat.x > 0.0) {
+        return apply_clearcoat(radiance, light, normal, light_dir, view_dir) * falloff;
+    }
+    return radiance * falloff;
+}
+
+@fragment
+fn fs_pbr(in: VertexOutput) -> @location(0) vec4<f32> {
+    if (camera.debug_view != DEBUG_VIEW_OFF) {
+        return debug_view_color(in);
+    }
+    let surface = sample_pbr_surface(in.tex_coords);
+    alpha_test(surface.albedo.a);
+    let normal = normalize(in.world_normal);
+
+    var color = ambient_light(normal) * surface.albedo.rgb * surface.occlusion;
+    for (var i = 0u; i < min(light_count, arrayLength(&s_lights)); i += 1u) {
+        let light = s_lights[i];
+        color += cook_torrance(light, normal, surface, in) * light_shadow(light, in.world_position);
+    }
+
+    return vec4<f32>(apply_fog(color + emission(in.tex_coords), in.world_position), surface.albedo.a);
+}
+
+@fragment
+fn fs_pbr_without_storage(in: VertexOutput) -> @location(0) vec4<f32> {
+    if (camera.debug_view != DEBUG_VIEW_OFF) {
+        return debug_view_color(in);
+    }
+    let surface = sample_pbr_surface(in.tex_coords);
+    alpha_test(surface.albedo.a);
+    let normal = normalize(in.world_normal);
+
+    var color = ambient_light(normal) * surface.albedo.rgb * surface.occlusion;
+    for (var i = 0u; i < min(light_count, 10u); i += 1u) {
+        let light = u_lights[i];
+        color += cook_torrance(light, normal, surface, in) * light_shadow(light, in.world_position);
+    }
+
+    return vec4<f32>(apply_fog(color + emission(in.tex_coords), in.world_position), surface.albedo.a);
+}
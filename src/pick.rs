@@ -0,0 +1,332 @@
+/*
+ * Per-pixel debug readout.
+ * While Alt is held, the pixel under the cursor is rendered again into small float targets holding its
+ * normal, albedo and the shadow moments of the first shadowed light, with a projection that stretches
+ * the pixel over the whole 1x1 target. The targets and the depth are read back after the frame and
+ * shown in the HUD.
+ */
+use crate::bind_groups;
+use crate::camera::{Camera, CameraUniform};
+use crate::labels;
+use crate::light::ShadowMode;
+use crate::model::{Tangent, Vertex};
+use crate::reflection::ReflectDevice;
+use crate::renderer::{Pipeline, PipelineVariants};
+use crate::scenegraph::{DrawView, InstanceRaw};
+use crate::shader_compose;
+use crate::texture;
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+
+const NORMAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+const ALBEDO_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+const MOMENTS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
+/// Buffer copies of the targets start at multiples of this offset.
+const READBACK_STRIDE: u64 = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as u64;
+
+/// Values of the pixel under the cursor.
+#[derive(Debug, Clone, Copy)]
+pub struct PickReadout {
+    pub pixel: (u32, u32),
+    /// Depth buffer value, 1 where nothing was drawn.
+    pub depth: f32,
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub albedo: Vec4,
+    pub moments: Vec4,
+    /// Light space depth the moments are compared to.
+    pub light_depth: f32,
+}
+
+impl PickReadout {
+    pub fn hud_lines(&self) -> Vec<String> {
+        let (x, y) = self.pixel;
+        if self.depth >= 1.0 {
+            return vec![format!("Pixel {x} {y}: background")];
+        }
+        let [px, py, pz] = self.position.to_array();
+        let [nx, ny, nz] = self.normal.to_array();
+        let [r, g, b, a] = self.albedo.to_array();
+        let [m1, m2, m3, m4] = self.moments.to_array();
+        vec![
+            format!("Pixel {x} {y}: depth {:.6}", self.depth),
+            format!("Position {px:.3} {py:.3} {pz:.3}"),
+            format!("Normal {nx:.3} {ny:.3} {nz:.3}"),
+            format!("Albedo {r:.3} {g:.3} {b:.3} {a:.3}"),
+            format!("Moments {m1:.4} {m2:.4} {m3:.4} {m4:.4}"),
+            format!("Light depth {:.4}", self.light_depth),
+        ]
+    }
+}
+
+pub struct PickPass {
+    /// Draws nodes with the camera, model matrix, material and light bind groups, like the forward pass.
+    pub pipeline: PipelineVariants,
+    camera_buffer: wgpu::Buffer,
+    pub camera_bind_group: wgpu::BindGroup,
+    normal_texture: wgpu::Texture,
+    albedo_texture: wgpu::Texture,
+    moments_texture: wgpu::Texture,
+    depth_texture: wgpu::Texture,
+    readback_buffer: wgpu::Buffer,
+}
+
+impl PickPass {
+    /// `bind_group_layouts` are the ones of the forward pass, camera, model matrix, material and lights.
+    pub fn new(
+        device: &wgpu::Device,
+        bind_group_layouts: &[wgpu::BindGroupLayout; 4],
+        supports_storage_resources: bool,
+        shadow_mode: ShadowMode,
+    ) -> Self {
+        let camera_bind_group_layout = &bind_group_layouts[bind_groups::CAMERA as usize];
+        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Pick Camera Buffer"),
+            contents: bytemuck::cast_slice(&[CameraUniform::new(Mat4::IDENTITY, Vec3::ZERO)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+            label: Some("pick_camera_bind_group"),
+        });
+
+        let create_target = |label: &str, format: wgpu::TextureFormat| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            })
+        };
+        let normal_texture = create_target("pick_normal_texture", NORMAL_FORMAT);
+        let albedo_texture = create_target("pick_albedo_texture", ALBEDO_FORMAT);
+        let moments_texture = create_target("pick_moments_texture", MOMENTS_FORMAT);
+        let depth_texture = create_target("pick_depth_texture", texture::Texture::DEPTH_FORMAT);
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("pick_readback"),
+            size: READBACK_STRIDE * 4,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let shader = device.reflect_shader(wgpu::ShaderModuleDescriptor {
+            label: Some("pick"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(shader_compose::compose(
+                &[include_str!("shader.wgsl"), include_str!("pick.wgsl")],
+                shadow_mode,
+            ))),
+        });
+        let bind_group_layouts = bind_group_layouts.clone();
+        let fragment_entry = if supports_storage_resources {
+            "fs_pick"
+        } else {
+            "fs_pick_without_storage"
+        };
+        let pipeline = PipelineVariants::new(
+            device,
+            Default::default(),
+            move |device, bias, key, multisample| {
+                let target = |format| {
+                    Some(wgpu::ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })
+                };
+                Pipeline::new(
+                    device,
+                    &labels::pipeline_variant("pick_pipeline", key.shading, multisample.count),
+                    &shader,
+                    &bind_group_layouts.each_ref(),
+                    "vs_main",
+                    &[Vertex::desc(), InstanceRaw::desc(), Tangent::desc()],
+                    Some(fragment_entry),
+                    &[
+                        target(NORMAL_FORMAT),
+                        target(ALBEDO_FORMAT),
+                        target(MOMENTS_FORMAT),
+                    ],
+                    Some(texture::Texture::DEPTH_FORMAT),
+                    Some(bias),
+                    None,
+                    None,
+                )
+            },
+        );
+
+        Self {
+            pipeline,
+            camera_buffer,
+            camera_bind_group,
+            normal_texture,
+            albedo_texture,
+            moments_texture,
+            depth_texture,
+            readback_buffer,
+        }
+    }
+
+    /// Points the pick camera at the pixel `cursor` of a `width` x `height` frame, relative to the render
+    /// `origin`. The returned view culls everything outside of the pixel.
+    pub fn update_camera(
+        &self,
+        queue: &wgpu::Queue,
+        camera: &Camera,
+        origin: Vec3,
+        cursor: (u32, u32),
+        width: u32,
+        height: u32,
+    ) -> DrawView {
+        let center = pixel_ndc(cursor, width, height);
+        // stretches the pixel over the whole clip space, like gluPickMatrix
+        let pick_matrix = Mat4::from_scale(Vec3::new(width as f32, height as f32, 1.0))
+            * Mat4::from_translation(-center.extend(0.0));
+        let relative = camera.relative_to(origin);
+        queue.write_buffer(
+            &self.camera_buffer,
+            0,
+            bytemuck::cast_slice(&[CameraUniform::new(
+                pick_matrix * relative.calculate_matrix(),
+                relative.eye,
+            )]),
+        );
+        // culled in world space
+        DrawView::new(camera.eye, pick_matrix * camera.calculate_matrix())
+    }
+
+    /// Starts the pass drawing the pixel, with the pick camera bound at group 0.
+    pub fn begin_pass<'e>(&self, encoder: &'e mut wgpu::CommandEncoder) -> wgpu::RenderPass<'e> {
+        let view = |texture: &wgpu::Texture| texture.create_view(&Default::default());
+        let attachment = |view| {
+            Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })
+        };
+        let (normal_view, albedo_view, moments_view, depth_view) = (
+            view(&self.normal_texture),
+            view(&self.albedo_texture),
+            view(&self.moments_texture),
+            view(&self.depth_texture),
+        );
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("pick_pass"),
+            color_attachments: &[
+                attachment(&normal_view),
+                attachment(&albedo_view),
+                attachment(&moments_view),
+            ],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            ..Default::default()
+        });
+        rpass.set_bind_group(bind_groups::CAMERA, &self.camera_bind_group, &[]);
+        rpass
+    }
+
+    /// Copies the targets into the readback buffer, after the pass.
+    pub fn encode_readback(&self, encoder: &mut wgpu::CommandEncoder) {
+        let targets = [
+            &self.normal_texture,
+            &self.albedo_texture,
+            &self.moments_texture,
+            &self.depth_texture,
+        ];
+        for (i, texture) in targets.into_iter().enumerate() {
+            encoder.copy_texture_to_buffer(
+                wgpu::TexelCopyTextureInfo {
+                    texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::TexelCopyBufferInfo {
+                    buffer: &self.readback_buffer,
+                    layout: wgpu::TexelCopyBufferLayout {
+                        offset: i as u64 * READBACK_STRIDE,
+                        bytes_per_row: None,
+                        rows_per_image: None,
+                    },
+                },
+                texture.size(),
+            );
+        }
+    }
+
+    /// Waits for the submitted readback, `camera`, `cursor` and the frame size must match [`Self::update_camera`].
+    pub fn read(
+        &self,
+        device: &wgpu::Device,
+        camera: &Camera,
+        cursor: (u32, u32),
+        width: u32,
+        height: u32,
+    ) -> PickReadout {
+        let slice = self.readback_buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |_| ());
+        device.poll(wgpu::Maintain::Wait);
+        let data = slice.get_mapped_range().to_vec();
+        self.readback_buffer.unmap();
+
+        let target = |i: usize| &data[i * READBACK_STRIDE as usize..];
+        let half_vec4 = |bytes: &[u8]| {
+            Vec4::from_array(std::array::from_fn(|c| {
+                half::f16::from_le_bytes([bytes[c * 2], bytes[c * 2 + 1]]).to_f32()
+            }))
+        };
+        let float = |bytes: &[u8], c: usize| {
+            f32::from_le_bytes([
+                bytes[c * 4],
+                bytes[c * 4 + 1],
+                bytes[c * 4 + 2],
+                bytes[c * 4 + 3],
+            ])
+        };
+        let normal = half_vec4(target(0));
+        let moments = Vec4::from_array(std::array::from_fn(|c| float(target(2), c)));
+        let depth = float(target(3), 0);
+
+        let ndc = pixel_ndc(cursor, width, height).extend(depth);
+        let position = camera.calculate_matrix().inverse().project_point3(ndc);
+        PickReadout {
+            pixel: cursor,
+            depth,
+            position,
+            normal: normal.truncate(),
+            albedo: half_vec4(target(1)),
+            moments,
+            light_depth: normal.w,
+        }
+    }
+}
+
+/// Normalized device coordinates of the center of a pixel.
+fn pixel_ndc((x, y): (u32, u32), width: u32, height: u32) -> Vec2 {
+    Vec2::new(
+        2.0 * (x as f32 + 0.5) / width as f32 - 1.0,
+        1.0 - 2.0 * (y as f32 + 0.5) / height as f32,
+    )
+}
@@ -0,0 +1,64 @@
+// Debug pick readout, appended to shader.wgsl. Writes what the forward pass works with for the pixel
+// under the cursor into float targets that are read back.
+
+struct PickOutput {
+    // world normal, light space depth of the first shadowed light in w
+    @location(0) normal: vec4<f32>,
+    @location(1) albedo: vec4<f32>,
+    // raw moments of the first shadowed light, or the stored depth in x with depth shadow maps
+    @location(2) moments: vec4<f32>,
+};
+
+fn pick_albedo(in: VertexOutput) -> vec4<f32> {
+    let texture_result = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    // same fallback to the material color as fs_main
+    if (all(texture_result == vec4<f32>(0.0))) {
+        return vec4<f32>(material.diffuse.rgb, material.dissolve);
+    }
+    return texture_result;
+}
+
+// Writes the moments in the shadow map of `light` at the fragment and the depth they are compared to into `out`
+fn pick_light(light: Light, in: VertexOutput, out: ptr<function, PickOutput>) {
+    if (light.kind == LIGHT_KIND_POINT) {
+        let light_world_position = light.model * light.position;
+        let point_face = point_shadow_face(in.world_position.xyz - light_world_position.xyz);
+        let coords = shadow_coords(point_face.ls_pos, light.shadow_scale);
+        (*out).moments = point_shadow_texel(coords.xy, light.shadow_layer + point_face.face);
+        (*out).normal.w = coords.z;
+    } else {
+        let coords = shadow_coords(light.view_proj * in.world_position, light.shadow_scale);
+        (*out).moments = shadow_texel(coords.xy, light.shadow_layer);
+        (*out).normal.w = coords.z;
+    }
+}
+
+@fragment
+fn fs_pick(in: VertexOutput) -> PickOutput {
+    var out: PickOutput;
+    out.normal = vec4<f32>(normalize(in.world_normal), 0.0);
+    out.albedo = pick_albedo(in);
+    out.moments = vec4<f32>(0.0);
+    for (var i = 0u; i < min(light_count, arrayLength(&s_lights)); i += 1u) {
+        if (s_lights[i].shadow_layer >= 0) {
+            pick_light(s_lights[i], in, &out);
+            break;
+        }
+    }
+    return out;
+}
+
+@fragment
+fn fs_pick_without_storage(in: VertexOutput) -> PickOutput {
+    var out: PickOutput;
+    out.normal = vec4<f32>(normalize(in.world_normal), 0.0);
+    out.albedo = pick_albedo(in);
+    out.moments = vec4<f32>(0.0);
+    for (var i = 0u; i < min(light_count, 10u); i += 1u) {
+        if (u_lights[i].shadow_layer >= 0) {
+            pick_light(u_lights[i], in, &out);
+            break;
+        }
+    }
+    return out;
+}
@@ -0,0 +1,390 @@
+/*
+ * Shader reflection.
+ * Shader modules and bind group layouts created with `ReflectDevice` are remembered along with the
+ * bindings each entry point of the WGSL uses and the layout entries. Creating a `Pipeline` compares the bindings its entry
+ * points use with its bind group layouts and prints what doesn't match, e.g. a texture the shader reads
+ * from a group whose layout has a uniform buffer at that binding, or a binding that isn't visible to the
+ * stage using it. The backends only report that the pipeline layout is incompatible with the shader.
+ * Modules and layouts created directly with the device are not checked.
+ */
+use crate::shader_cache;
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+use naga::{AddressSpace, ImageClass, ImageDimension, ScalarKind, ShaderStage, TypeInner};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use wgpu::{BindGroupLayout, BindingType, ShaderStages, TextureSampleType, TextureViewDimension};
+
+thread_local! {
+    static SHADERS: RefCell<HashMap<wgpu::ShaderModule, ShaderReflection>> =
+        RefCell::new(HashMap::new());
+    static LAYOUTS: RefCell<HashMap<BindGroupLayout, Vec<wgpu::BindGroupLayoutEntry>>> =
+        RefCell::new(HashMap::new());
+}
+
+/// The bindings each entry point of a WGSL module uses, all that's needed of the naga module for
+/// [`check_bindings`]. Kept in the shader cache.
+#[derive(Serialize, Deserialize)]
+pub struct ShaderReflection {
+    entry_points: Vec<EntryPointBindings>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EntryPointBindings {
+    name: String,
+    stage: ShaderStages,
+    bindings: Vec<BindingUse>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BindingUse {
+    name: String,
+    group: u32,
+    binding: u32,
+    /// None for globals that don't need a particular layout entry.
+    expected: Option<Expected>,
+}
+
+impl ShaderReflection {
+    /// Parses and validates the WGSL `source`, None if it has errors.
+    fn new(source: &str) -> Option<Self> {
+        let module = naga::front::wgsl::parse_str(source).ok()?;
+        // the device checks what the adapter supports
+        let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+            .validate(&module)
+            .ok()?;
+        let entry_points = module
+            .entry_points
+            .iter()
+            .enumerate()
+            .map(|(index, entry_point)| {
+                let function_info = info.get_entry_point(index);
+                let bindings = module
+                    .global_variables
+                    .iter()
+                    .filter(|(handle, _)| !function_info[*handle].is_empty())
+                    .filter_map(|(_, global)| {
+                        let binding = global.binding.as_ref()?;
+                        Some(BindingUse {
+                            name: global.name.clone().unwrap_or_else(|| "?".to_string()),
+                            group: binding.group,
+                            binding: binding.binding,
+                            expected: expected_binding(&module, global),
+                        })
+                    })
+                    .collect();
+                EntryPointBindings {
+                    name: entry_point.name.clone(),
+                    stage: match entry_point.stage {
+                        ShaderStage::Vertex => ShaderStages::VERTEX,
+                        ShaderStage::Fragment => ShaderStages::FRAGMENT,
+                        ShaderStage::Compute => ShaderStages::COMPUTE,
+                    },
+                    bindings,
+                }
+            })
+            .collect();
+        Some(Self { entry_points })
+    }
+}
+
+/// Creation of shader modules and bind group layouts that are remembered for [`check_bindings`].
+pub trait ReflectDevice {
+    /// Creates the shader module and reflects its WGSL, or takes the reflection from the shader cache.
+    /// Errors in the WGSL are left to wgpu to report.
+    fn reflect_shader(&self, descriptor: wgpu::ShaderModuleDescriptor) -> wgpu::ShaderModule;
+
+    /// Creates and reflects the shader modules, on a thread each where there are threads.
+    fn reflect_shaders<const N: usize>(
+        &self,
+        descriptors: [wgpu::ShaderModuleDescriptor; N],
+    ) -> [wgpu::ShaderModule; N];
+
+    fn reflect_layout(&self, descriptor: &wgpu::BindGroupLayoutDescriptor) -> BindGroupLayout;
+}
+
+impl ReflectDevice for wgpu::Device {
+    fn reflect_shader(&self, descriptor: wgpu::ShaderModuleDescriptor) -> wgpu::ShaderModule {
+        let reflection = reflect_source(&descriptor.source);
+        let shader = self.create_shader_module(descriptor);
+        remember(&shader, reflection);
+        shader
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn reflect_shaders<const N: usize>(
+        &self,
+        descriptors: [wgpu::ShaderModuleDescriptor; N],
+    ) -> [wgpu::ShaderModule; N] {
+        // the modules are remembered on this thread, the registry is per thread
+        let created = std::thread::scope(|scope| {
+            descriptors
+                .map(|descriptor| {
+                    scope.spawn(move || {
+                        let reflection = reflect_source(&descriptor.source);
+                        (self.create_shader_module(descriptor), reflection)
+                    })
+                })
+                .map(|thread| thread.join().unwrap())
+        });
+        created.map(|(shader, reflection)| {
+            remember(&shader, reflection);
+            shader
+        })
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn reflect_shaders<const N: usize>(
+        &self,
+        descriptors: [wgpu::ShaderModuleDescriptor; N],
+    ) -> [wgpu::ShaderModule; N] {
+        descriptors.map(|descriptor| self.reflect_shader(descriptor))
+    }
+
+    fn reflect_layout(&self, descriptor: &wgpu::BindGroupLayoutDescriptor) -> BindGroupLayout {
+        let layout = self.create_bind_group_layout(descriptor);
+        LAYOUTS
+            .with_borrow_mut(|layouts| layouts.insert(layout.clone(), descriptor.entries.to_vec()));
+        layout
+    }
+}
+
+/// The reflection of a WGSL source from the shader cache, or reflected and added to the cache.
+fn reflect_source(source: &wgpu::ShaderSource) -> Option<ShaderReflection> {
+    let wgpu::ShaderSource::Wgsl(source) = source else {
+        return None;
+    };
+    shader_cache::load_reflection(source).or_else(|| {
+        let reflection = ShaderReflection::new(source)?;
+        shader_cache::store_reflection(source, &reflection);
+        Some(reflection)
+    })
+}
+
+fn remember(shader: &wgpu::ShaderModule, reflection: Option<ShaderReflection>) {
+    if let Some(reflection) = reflection {
+        SHADERS.with_borrow_mut(|shaders| shaders.insert(shader.clone(), reflection));
+    }
+}
+
+/// Compares the bindings the entry points of `shader` use with `bind_group_layouts`. Returns a line per
+/// mismatch, empty if they match or the shader or a layout wasn't reflected.
+pub fn check_bindings(
+    shader: &wgpu::ShaderModule,
+    entry_points: &[&str],
+    bind_group_layouts: &[&BindGroupLayout],
+) -> Vec<String> {
+    let layouts = bind_group_layouts
+        .iter()
+        .map(|layout| LAYOUTS.with_borrow(|layouts| layouts.get(*layout).cloned()))
+        .collect::<Vec<_>>();
+    SHADERS.with_borrow(|shaders| {
+        let Some(reflection) = shaders.get(shader) else {
+            return Vec::new();
+        };
+        let mut mismatches = Vec::new();
+        for entry_point in &reflection.entry_points {
+            if !entry_points.contains(&entry_point.name.as_str()) {
+                continue;
+            }
+            for binding in &entry_point.bindings {
+                let describe = |problem: String| {
+                    format!(
+                        "{} uses `{}` at group {} binding {}, {problem}",
+                        entry_point.name, binding.name, binding.group, binding.binding
+                    )
+                };
+                let Some(layout) = layouts.get(binding.group as usize) else {
+                    mismatches.push(describe(format!(
+                        "the pipeline has {} bind groups",
+                        layouts.len()
+                    )));
+                    continue;
+                };
+                let Some(entries) = layout else {
+                    continue;
+                };
+                let Some(entry) = entries
+                    .iter()
+                    .find(|entry| entry.binding == binding.binding)
+                else {
+                    mismatches.push(describe("the layout has no such binding".to_string()));
+                    continue;
+                };
+                if !entry.visibility.contains(entry_point.stage) {
+                    mismatches.push(describe(format!(
+                        "the layout makes it visible to {:?} only",
+                        entry.visibility
+                    )));
+                }
+                let expected = binding.expected.as_ref();
+                if let Some(problem) = expected.and_then(|expected| mismatch(expected, &entry.ty)) {
+                    mismatches.push(describe(problem));
+                }
+            }
+        }
+        mismatches
+    })
+}
+
+/// What the shader declares a global as, in terms of the layout entry it needs.
+#[derive(Serialize, Deserialize)]
+enum Expected {
+    Uniform,
+    Storage {
+        writes: bool,
+    },
+    Texture {
+        kind: Option<SampleKind>,
+        dimension: TextureViewDimension,
+        multisampled: bool,
+    },
+    StorageTexture {
+        dimension: TextureViewDimension,
+    },
+    Sampler {
+        comparison: bool,
+    },
+}
+
+/// The scalar kind of a sampled texture, None in [`Expected::Texture`] for depth textures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum SampleKind {
+    Float,
+    Sint,
+    Uint,
+}
+
+fn expected_binding(module: &naga::Module, global: &naga::GlobalVariable) -> Option<Expected> {
+    let mut inner = &module.types[global.ty].inner;
+    if let TypeInner::BindingArray { base, .. } = inner {
+        inner = &module.types[*base].inner;
+    }
+    match global.space {
+        AddressSpace::Uniform => Some(Expected::Uniform),
+        AddressSpace::Storage { access } => Some(Expected::Storage {
+            writes: access.contains(naga::StorageAccess::STORE),
+        }),
+        AddressSpace::Handle => match *inner {
+            TypeInner::Image {
+                dim,
+                arrayed,
+                class,
+            } => {
+                let dimension = match (dim, arrayed) {
+                    (ImageDimension::D1, _) => TextureViewDimension::D1,
+                    (ImageDimension::D2, false) => TextureViewDimension::D2,
+                    (ImageDimension::D2, true) => TextureViewDimension::D2Array,
+                    (ImageDimension::D3, _) => TextureViewDimension::D3,
+                    (ImageDimension::Cube, false) => TextureViewDimension::Cube,
+                    (ImageDimension::Cube, true) => TextureViewDimension::CubeArray,
+                };
+                Some(match class {
+                    ImageClass::Sampled { kind, multi } => Expected::Texture {
+                        kind: Some(match kind {
+                            ScalarKind::Sint => SampleKind::Sint,
+                            ScalarKind::Uint => SampleKind::Uint,
+                            _ => SampleKind::Float,
+                        }),
+                        dimension,
+                        multisampled: multi,
+                    },
+                    ImageClass::Depth { multi } => Expected::Texture {
+                        kind: None,
+                        dimension,
+                        multisampled: multi,
+                    },
+                    ImageClass::Storage { .. } => Expected::StorageTexture { dimension },
+                })
+            }
+            TypeInner::Sampler { comparison } => Some(Expected::Sampler { comparison }),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Why the layout entry `ty` doesn't fit `expected`, None if it does.
+fn mismatch(expected: &Expected, ty: &BindingType) -> Option<String> {
+    match (expected, ty) {
+        (
+            Expected::Uniform,
+            BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                ..
+            },
+        ) => None,
+        (
+            Expected::Storage { writes },
+            BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                ..
+            },
+        ) => (*writes && *read_only)
+            .then(|| "the shader writes a buffer the layout has read-only".to_string()),
+        (
+            Expected::Texture {
+                kind,
+                dimension,
+                multisampled,
+            },
+            BindingType::Texture {
+                sample_type,
+                view_dimension,
+                multisampled: layout_multisampled,
+            },
+        ) => {
+            let sample_type_fits = match (kind, sample_type) {
+                (None, TextureSampleType::Depth) => true,
+                // depth textures can also be sampled as floats
+                (Some(SampleKind::Float), TextureSampleType::Float { .. })
+                | (Some(SampleKind::Float), TextureSampleType::Depth) => true,
+                (Some(SampleKind::Sint), TextureSampleType::Sint) => true,
+                (Some(SampleKind::Uint), TextureSampleType::Uint) => true,
+                _ => false,
+            };
+            if !sample_type_fits {
+                Some(format!(
+                    "the shader samples {} but the layout has {sample_type:?}",
+                    kind.map_or("depth".to_string(), |kind| format!("{kind:?}"))
+                ))
+            } else if dimension != view_dimension {
+                Some(format!(
+                    "the shader has a {dimension:?} texture but the layout {view_dimension:?}"
+                ))
+            } else if multisampled != layout_multisampled {
+                Some(format!(
+                    "multisampled is {multisampled} in the shader but {layout_multisampled} in the layout"
+                ))
+            } else {
+                None
+            }
+        }
+        (
+            Expected::StorageTexture { dimension },
+            BindingType::StorageTexture { view_dimension, .. },
+        ) => (dimension != view_dimension).then(|| {
+            format!("the shader has a {dimension:?} texture but the layout {view_dimension:?}")
+        }),
+        (Expected::Sampler { comparison }, BindingType::Sampler(binding)) => {
+            let layout_comparison = *binding == wgpu::SamplerBindingType::Comparison;
+            match (comparison, layout_comparison) {
+                (true, false) => Some(format!(
+                    "the shader compares with the sampler but the layout has {binding:?}"
+                )),
+                (false, true) => Some("the layout has a comparison sampler".to_string()),
+                _ => None,
+            }
+        }
+        (expected, ty) => Some(format!(
+            "the shader declares a {} but the layout has {ty:?}",
+            match expected {
+                Expected::Uniform => "uniform buffer",
+                Expected::Storage { .. } => "storage buffer",
+                Expected::Texture { .. } => "texture",
+                Expected::StorageTexture { .. } => "storage texture",
+                Expected::Sampler { .. } => "sampler",
+            }
+        )),
+    }
+}
@@ -0,0 +1,271 @@
+/*
+ * Refraction for glass materials.
+ * With glass in the scene, the opaque render nodes are drawn into an offscreen color texture, which is
+ * then copied into the frame. The glass nodes are drawn on top of the copy and look up the offscreen
+ * texture along the refracted view ray, blurred according to their roughness.
+ */
+use crate::bind_groups;
+use crate::labels;
+use crate::light::ShadowMode;
+use crate::model::{Tangent, Vertex};
+use crate::reflection::ReflectDevice;
+use crate::renderer::{Pipeline, PipelineVariants};
+use crate::scenegraph::InstanceRaw;
+use crate::shader_compose;
+use crate::texture;
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+
+/// Distance the refracted ray travels through the scene before the background is looked up.
+const THICKNESS: f32 = 0.5;
+/// Blur radius in pixels of a material with roughness 1.
+const BLUR_RADIUS: f32 = 12.0;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct RefractionUniform {
+    screen_size: [f32; 2],
+    thickness: f32,
+    blur_radius: f32,
+}
+
+impl RefractionUniform {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            screen_size: [width as f32, height as f32],
+            thickness: THICKNESS,
+            blur_radius: BLUR_RADIUS,
+        }
+    }
+}
+
+pub struct RefractionPass {
+    /// Draws glass nodes, with the camera, model matrix, material and refraction bind groups.
+    pub glass_pipeline: PipelineVariants,
+    copy_pipeline: Pipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    format: wgpu::TextureFormat,
+    /// Target of the opaque nodes.
+    pub opaque_view: wgpu::TextureView,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl RefractionPass {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        model_bind_group_layout: &wgpu::BindGroupLayout,
+        material_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let bind_group_layout = device.reflect_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("refraction_bind_group_layout"),
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("refraction_sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Refraction Buffer"),
+            contents: bytemuck::cast_slice(&[RefractionUniform::new(width, height)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let (opaque_view, bind_group) = Self::create_target(
+            device,
+            &bind_group_layout,
+            &sampler,
+            &uniform_buffer,
+            format,
+            width,
+            height,
+        );
+
+        let copy_shader = device.reflect_shader(wgpu::ShaderModuleDescriptor {
+            label: Some("refraction"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("refraction.wgsl"))),
+        });
+        let copy_pipeline = Pipeline::new(
+            device,
+            "refraction_copy_pipeline",
+            &copy_shader,
+            &[&bind_group_layout],
+            "vs_fullscreen",
+            &[],
+            Some("fs_copy"),
+            &[Some(wgpu::ColorTargetState {
+                format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            None,
+            None,
+            None,
+            None,
+        );
+
+        let glass_shader = device.reflect_shader(wgpu::ShaderModuleDescriptor {
+            label: Some("glass"),
+            // glass is not lit, the shadow functions shader.wgsl calls only need to be declared
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(shader_compose::compose(
+                &[include_str!("shader.wgsl"), include_str!("glass.wgsl")],
+                ShadowMode::Moments,
+            ))),
+        });
+        let glass_bind_group_layouts = bind_groups::layouts(
+            camera_bind_group_layout,
+            model_bind_group_layout,
+            material_bind_group_layout,
+            &bind_group_layout,
+        );
+        let glass_pipeline =
+            PipelineVariants::new(device, Default::default(), move |device, bias, key, multisample| {
+                Pipeline::new(
+                    device,
+                    &labels::pipeline_variant("glass_pipeline", key.shading, multisample.count),
+                    &glass_shader,
+                    &glass_bind_group_layouts.each_ref(),
+                    "vs_main",
+                    &[Vertex::desc(), InstanceRaw::desc(), Tangent::desc()],
+                    Some("fs_glass"),
+                    &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    Some(texture::Texture::DEPTH_FORMAT),
+                    Some(bias),
+                    Some(multisample),
+                    None,
+                )
+            });
+
+        Self {
+            glass_pipeline,
+            copy_pipeline,
+            bind_group_layout,
+            sampler,
+            uniform_buffer,
+            format,
+            opaque_view,
+            bind_group,
+        }
+    }
+
+    fn create_target(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        uniform_buffer: &wgpu::Buffer,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> (wgpu::TextureView, wgpu::BindGroup) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("refraction_opaque_texture"),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("refraction_bind_group"),
+        });
+        (view, bind_group)
+    }
+
+    /// Must be called when the window is resized, the offscreen texture matches the frame.
+    pub fn resize(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, width: u32, height: u32) {
+        (self.opaque_view, self.bind_group) = Self::create_target(
+            device,
+            &self.bind_group_layout,
+            &self.sampler,
+            &self.uniform_buffer,
+            self.format,
+            width,
+            height,
+        );
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[RefractionUniform::new(width, height)]),
+        );
+    }
+
+    /// Copies the opaque scene into `view`, before the glass is drawn over it.
+    pub fn copy_opaque(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("refraction_copy_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+        rpass.set_pipeline(&self.copy_pipeline.pipeline);
+        rpass.set_bind_group(0, &self.bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
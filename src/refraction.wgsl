@@ -0,0 +1,23 @@
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // a single triangle covering the whole viewport
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@group(0) @binding(0) var t_opaque: texture_2d<f32>;
+@group(0) @binding(1) var s_opaque: sampler;
+
+// Copies the opaque scene into the frame, the glass is drawn over it afterwards
+@fragment
+fn fs_copy(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSampleLevel(t_opaque, s_opaque, in.uv, 0.0);
+}
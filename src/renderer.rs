@@ -1,11 +1,60 @@
-use crate::camera::{Camera, CameraController, CameraUniform};
-use crate::light::{Light, ShadowMap};
-use crate::model::{load_model, Material, Mesh, Model, Vertex, CUBE_INDICES, CUBE_VERTICES};
-use crate::scenegraph::{ModelUniform, Node, SceneGraph};
+use crate::application::render_scene;
+use crate::asset_reload::AssetWatcher;
+use crate::bind_groups;
+use crate::camera::{Camera, CameraController, CameraUniform, DebugView, Projection, RenderMode};
+use crate::color_blind::ColorBlindPass;
+use crate::custom_material::CustomMaterials;
+use crate::custom_pass::CustomPass;
+use crate::debug_lines::DebugLines;
+use crate::depth_of_field::DepthOfFieldPass;
+use crate::depth_view::DepthView;
+use crate::error::{validate, RendererError};
+use crate::exposure::AutoExposurePass;
+use crate::frame_stats::{GpuTimer, GPU_TIMER_FEATURES};
+use crate::hdr::{select_hdr_format, HdrOutputPass, HDR_FORMAT};
+use crate::hud::Hud;
+use crate::labels;
+use crate::layered_shadow::LayeredShadowPass;
+use crate::light::{Light, LightKind, ShadowMap, ShadowMode, ShadowStats};
+use crate::model::{
+    load_model, Anisotropy, Material, MaterialFeatures, Mesh, Model, Shading, Subsurface, Tangent,
+    Vertex, CUBE_INDICES, CUBE_VERTICES,
+};
+use crate::msaa::Msaa;
+use crate::offscreen::OffscreenTarget;
+use crate::pass_capture::PassCapture;
+use crate::pick::PickPass;
+use crate::reflection::{self, ReflectDevice};
+use crate::refraction::RefractionPass;
+use crate::resources::{self, load_cached_model, with_resource_cache, CubeMapImages};
+use crate::scene::SceneDescription;
+use crate::scenegraph::{
+    GroupNode, InstanceRaw, NodeStats, SceneGraph, SceneGraphLightNodeIterator, SceneSync,
+    SortPolicy,
+};
+use crate::settings::RenderSettings;
+use crate::shader_cache;
+use crate::shader_compose;
+use crate::shader_reload::{ShaderSlot, ShaderWatcher, WatchedShader};
+use crate::skybox::Skybox;
+use crate::spline::{PathAnimation, SplinePath};
+use crate::startup::StartupTimer;
+use crate::stereo::{StereoPass, EYE_COUNT};
+use crate::stylize::StylizePass;
 use crate::texture;
+use crate::time_of_day::{DayCycle, SunNodes};
+use crate::trails::{Trail, Trails};
+use crate::watchdog::FrameWatchdog;
 use glam::{Mat4, Vec3};
 use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::future::Future;
+use std::num::NonZeroU32;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+#[cfg(target_arch = "wasm32")]
 use wasm_bindgen::{throw_str, UnwrapThrowExt};
 use wgpu::util::DeviceExt;
 use wgpu::{
@@ -24,6 +73,15 @@ type Rc<T> = std::sync::Arc<T>;
 #[cfg(target_arch = "wasm32")]
 const CANVAS_ID: &str = "wgpu-canvas";
 
+/// Samples per pixel of the forward pass, 2, 4 or 8. Lowered to what the adapter supports, 1 disables MSAA.
+pub const MSAA_SAMPLE_COUNT: u32 = 4;
+/// Subdivisions per triangle edge of meshes with a displacement map, 0 disables displacement.
+const DISPLACEMENT_SEGMENTS: u32 = 8;
+/// Color format of the frames of a headless renderer, read back as RGBA.
+const HEADLESS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+/// Environment maps tried at startup, an equirectangular HDR image or a directory of six faces.
+const SKYBOX_PATHS: [&str; 2] = ["assets/skybox.hdr", "assets/skybox"];
+
 pub struct Pipeline {
     pub layout: wgpu::PipelineLayout,
     pub pipeline: wgpu::RenderPipeline,
@@ -32,6 +90,7 @@ pub struct Pipeline {
 impl Pipeline {
     pub fn new(
         device: &Device,
+        label: &str,
         shader: &wgpu::ShaderModule,
         bind_group_layouts: &[&BindGroupLayout],
         vertex_entry: &str,
@@ -41,27 +100,83 @@ impl Pipeline {
         depth_format: Option<wgpu::TextureFormat>,
         depth_bias: Option<wgpu::DepthBiasState>,
         multisample_state: Option<wgpu::MultisampleState>,
+        multiview: Option<NonZeroU32>,
     ) -> Self {
+        Self::new_variant(
+            device,
+            label,
+            shader,
+            bind_group_layouts,
+            vertex_entry,
+            vertex_buffer_layout,
+            fragment_entry,
+            color_target,
+            depth_format,
+            depth_bias,
+            multisample_state,
+            multiview,
+            None,
+        )
+    }
+
+    /// Like [`Pipeline::new`], for the material of `key`: transparent materials test the depth but don't
+    /// write it, single sided ones cull their back faces and the [`MaterialFeatures`] set the
+    /// overridable constants of [`PipelineKey::constants`], which the shader has to declare. The depth bias is the one
+    /// passed in, not the one of the key. Without a key the pipeline is built like [`Pipeline::new`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_variant(
+        device: &Device,
+        label: &str,
+        shader: &wgpu::ShaderModule,
+        bind_group_layouts: &[&BindGroupLayout],
+        vertex_entry: &str,
+        vertex_buffer_layout: &[wgpu::VertexBufferLayout],
+        fragment_entry: Option<&str>,
+        color_target: &[Option<wgpu::ColorTargetState>],
+        depth_format: Option<wgpu::TextureFormat>,
+        depth_bias: Option<wgpu::DepthBiasState>,
+        multisample_state: Option<wgpu::MultisampleState>,
+        multiview: Option<NonZeroU32>,
+        key: Option<PipelineKey>,
+    ) -> Self {
+        let entry_points = [Some(vertex_entry), fragment_entry];
+        let mismatches = reflection::check_bindings(
+            shader,
+            &entry_points.into_iter().flatten().collect::<Vec<_>>(),
+            bind_group_layouts,
+        );
+        if !mismatches.is_empty() {
+            println!(
+                "The bind group layouts of {label} don't match its shader:\n  {}",
+                mismatches.join("\n  ")
+            );
+        }
         let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: None,
+            label: Some(label),
             bind_group_layouts,
             push_constant_ranges: &[],
         });
 
+        let constants = key.map(|key| key.constants()).unwrap_or_default();
+        let features = key.map(|key| key.features).unwrap_or_default();
+        let compilation_options = wgpu::PipelineCompilationOptions {
+            constants: &constants,
+            ..Default::default()
+        };
         let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
+            label: Some(label),
             layout: Some(&layout),
             vertex: wgpu::VertexState {
                 module: shader,
                 entry_point: Some(vertex_entry),
-                compilation_options: Default::default(),
+                compilation_options: compilation_options.clone(),
                 buffers: vertex_buffer_layout,
             },
             fragment: if let Some(entry) = fragment_entry {
                 Some(wgpu::FragmentState {
                     module: shader,
                     entry_point: Some(entry),
-                    compilation_options: Default::default(),
+                    compilation_options,
                     targets: color_target,
                 })
             } else {
@@ -71,7 +186,7 @@ impl Pipeline {
                 topology: wgpu::PrimitiveTopology::TriangleList,
                 strip_index_format: None,
                 front_face: wgpu::FrontFace::Ccw,
-                cull_mode: None,
+                cull_mode: (!features.double_sided).then_some(wgpu::Face::Back),
                 polygon_mode: wgpu::PolygonMode::Fill,
                 unclipped_depth: device
                     .features()
@@ -82,7 +197,7 @@ impl Pipeline {
             depth_stencil: if let Some(format) = depth_format {
                 Some(wgpu::DepthStencilState {
                     format,
-                    depth_write_enabled: true,
+                    depth_write_enabled: !key.is_some_and(|key| key.transparent),
                     depth_compare: wgpu::CompareFunction::Less,
                     stencil: wgpu::StencilState::default(),
                     bias: depth_bias.unwrap_or_default(),
@@ -91,30 +206,215 @@ impl Pipeline {
                 None
             },
             multisample: multisample_state.unwrap_or(MultisampleState::default()),
-            multiview: None,
-            cache: None,
+            multiview,
+            cache: shader_cache::pipeline_cache(device).as_ref(),
         });
 
         Self { layout, pipeline }
     }
 }
 
+/// What a render node needs from the pipeline it is drawn with, see [`PipelineVariants`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct PipelineKey {
+    /// The [`Material::depth_bias`] of the node.
+    pub depth_bias: wgpu::DepthBiasState,
+    /// The [`Material::shading`] of the node, passes without lighting ignore it.
+    pub shading: Shading,
+    /// See [`Material::is_transparent`], the forward passes draw the node without depth writes. Passes
+    /// that don't blend ignore it.
+    pub transparent: bool,
+    /// See [`Material::features`], baked into the forward passes. Passes without shading ignore them.
+    pub features: MaterialFeatures,
+    /// Drawn without shadows, set by [`PipelineVariants::set_shadows`] rather than by the node.
+    pub shadowless: bool,
+}
+
+impl PipelineKey {
+    /// Values of the pipeline-overridable constants of shader.wgsl and shadows.wgsl.
+    pub fn constants(&self) -> HashMap<String, f64> {
+        let mut constants = self.features.constants();
+        let shadows = if self.shadowless { 0.0 } else { 1.0 };
+        constants.insert("SHADOWS".to_string(), shadows);
+        constants
+    }
+}
+
+/// A pipeline and its variants that only differ in their depth bias, shading, depth writes and material
+/// features. The variant of a draw is the base bias of the pass plus the [`Material::depth_bias`] of the
+/// drawn node, so coplanar surfaces and decals can be pushed behind or in front of each other per
+/// material, lit with the [`Shading`] of the material, for transparent materials drawn without writing
+/// depth and compiled with only the [`MaterialFeatures`] the material uses.
+pub struct PipelineVariants {
+    base_bias: wgpu::DepthBiasState,
+    multisample: MultisampleState,
+    /// Builds and draws every key without shadows, see [`PipelineVariants::set_shadows`].
+    shadowless: bool,
+    variants: HashMap<PipelineKey, Pipeline>,
+    // keys whose variant failed to build, they are not tried again
+    failed: HashSet<PipelineKey>,
+    build: Rc<BuildPipeline>,
+}
+
+type BuildPipeline =
+    dyn Fn(&Device, wgpu::DepthBiasState, PipelineKey, MultisampleState) -> Pipeline;
+
+impl PipelineVariants {
+    /// `build` creates the pipeline of a key with the given depth bias, which includes the bias of the
+    /// key, and multisample state. The variant of the default key is built right away.
+    pub fn new(
+        device: &Device,
+        base_bias: wgpu::DepthBiasState,
+        build: impl Fn(&Device, wgpu::DepthBiasState, PipelineKey, MultisampleState) -> Pipeline
+            + 'static,
+    ) -> Self {
+        Self::with_multisample(
+            device,
+            base_bias,
+            MultisampleState::default(),
+            Rc::new(build),
+        )
+    }
+
+    fn with_multisample(
+        device: &Device,
+        base_bias: wgpu::DepthBiasState,
+        multisample: MultisampleState,
+        build: Rc<BuildPipeline>,
+    ) -> Self {
+        let mut variants = HashMap::new();
+        variants.insert(
+            PipelineKey::default(),
+            build(device, base_bias, PipelineKey::default(), multisample),
+        );
+        Self {
+            base_bias,
+            multisample,
+            shadowless: false,
+            variants,
+            failed: HashSet::new(),
+            build,
+        }
+    }
+
+    /// The same pipelines for targets with `sample_count` samples per pixel.
+    pub fn multisampled(&self, device: &Device, sample_count: u32) -> Self {
+        let multisample = MultisampleState {
+            count: sample_count,
+            ..self.multisample
+        };
+        let mut multisampled =
+            Self::with_multisample(device, self.base_bias, multisample, self.build.clone());
+        multisampled.shadowless = self.shadowless;
+        multisampled
+    }
+
+    /// Switches to the variants with or without shadows, from the next [`PipelineVariants::prepare`]
+    /// on. The variants of the other setting are kept, so switching back doesn't build them again.
+    pub fn set_shadows(&mut self, enabled: bool) {
+        self.shadowless = !enabled;
+    }
+
+    /// Builds the variants for keys that were not seen before. A variant that fails to build is reported
+    /// and the key is drawn with the default variant.
+    pub fn prepare(&mut self, device: &Device, keys: impl IntoIterator<Item = PipelineKey>) {
+        for key in keys {
+            let key = PipelineKey {
+                shadowless: self.shadowless,
+                ..key
+            };
+            if self.variants.contains_key(&key) || self.failed.contains(&key) {
+                continue;
+            }
+            let material_bias = key.depth_bias;
+            let bias = wgpu::DepthBiasState {
+                constant: self.base_bias.constant + material_bias.constant,
+                slope_scale: self.base_bias.slope_scale + material_bias.slope_scale,
+                clamp: self.base_bias.clamp.max(material_bias.clamp),
+            };
+            let context = format!(
+                "{:?} pipeline variant with depth bias {bias:?}{}{} and {:?}",
+                key.shading,
+                if key.transparent {
+                    " without depth writes"
+                } else {
+                    ""
+                },
+                if key.shadowless {
+                    " without shadows"
+                } else {
+                    ""
+                },
+                key.features
+            );
+            match validate(
+                device,
+                || context,
+                || (self.build)(device, bias, key, self.multisample),
+            ) {
+                Ok(pipeline) => {
+                    self.variants.insert(key, pipeline);
+                }
+                Err(e) => {
+                    println!("{e}");
+                    self.failed.insert(key);
+                }
+            }
+        }
+    }
+
+    /// Drops the variants to build them again, e.g. after the shader the build function reads was
+    /// reloaded. The default variant is built right away and the others by the next
+    /// [`PipelineVariants::prepare`]; if the default one fails, the variants are kept.
+    pub fn rebuild(&mut self, device: &Device) -> Result<(), RendererError> {
+        let key = PipelineKey::default();
+        let pipeline = validate(
+            device,
+            || "default pipeline variant".to_string(),
+            || (self.build)(device, self.base_bias, key, self.multisample),
+        )?;
+        self.variants = HashMap::from([(key, pipeline)]);
+        self.failed.clear();
+        Ok(())
+    }
+
+    /// The variant for `key`, or the default one if it wasn't prepared.
+    pub fn get(&self, key: &PipelineKey) -> &Pipeline {
+        let key = PipelineKey {
+            shadowless: self.shadowless,
+            ..*key
+        };
+        self.variants
+            .get(&key)
+            .unwrap_or_else(|| &self.variants[&PipelineKey::default()])
+    }
+}
+
 pub struct GaussianPass {
     pub blur_pipeline: wgpu::ComputePipeline,
     pub bind_group_layout: BindGroupLayout,
     pub horizontal_blur_bind_group: wgpu::BindGroup,
     pub vertical_blur_bind_group: wgpu::BindGroup,
+    /// Width and height of the workgroups of `blur_pipeline`.
+    pub workgroup_size: u32,
+    /// Distance between the layer indices in the layer buffer, the dynamic offset of layer `n` is `n`
+    /// times the stride.
+    pub layer_stride: u32,
     pub horizontal_direction_buffer: wgpu::Buffer,
     pub vertical_direction_buffer: wgpu::Buffer,
 }
 
 impl GaussianPass {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         device: &wgpu::Device,
         shader_module: &wgpu::ShaderModule,
         shadow_map_view: &wgpu::TextureView,
         ping_pong_view: &wgpu::TextureView,
         storage_format: wgpu::TextureFormat,
+        blur_radius: u32,
+        workgroup_size: u32,
+        layers: u32,
     ) -> Self {
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("gaussian::bind_group_layout"),
@@ -149,22 +449,26 @@ impl GaussianPass {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(size_of::<u32>() as u64),
+                    },
+                    count: None,
+                },
             ],
         });
 
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("gaussian_pipeline_layout"),
-            bind_group_layouts: &[&bind_group_layout],
-            push_constant_ranges: &[],
-        });
-        let blur_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            label: Some("gaussian_compute_pipeline"),
-            layout: Some(&pipeline_layout),
-            module: shader_module,
-            entry_point: Some("main"),
-            compilation_options: Default::default(),
-            cache: None,
-        });
+        let blur_pipeline = Self::create_pipeline(
+            device,
+            &bind_group_layout,
+            shader_module,
+            blur_radius,
+            workgroup_size,
+        );
 
         let horizontal_direction_buffer =
             device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -178,6 +482,26 @@ impl GaussianPass {
                 contents: bytemuck::bytes_of(&1u32),
                 usage: wgpu::BufferUsages::UNIFORM,
             });
+        // the index of each layer at its own dynamic offset, so single layers can be blurred
+        let layer_stride = device.limits().min_uniform_buffer_offset_alignment;
+        let mut layer_indices = vec![0u8; (layers.max(1) * layer_stride) as usize];
+        for layer in 0..layers {
+            let offset = (layer * layer_stride) as usize;
+            layer_indices[offset..offset + 4].copy_from_slice(bytemuck::bytes_of(&layer));
+        }
+        let layer_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("gaussian_layers"),
+            contents: &layer_indices,
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let layer_binding = wgpu::BindGroupEntry {
+            binding: 3,
+            resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                buffer: &layer_buffer,
+                offset: 0,
+                size: wgpu::BufferSize::new(size_of::<u32>() as u64),
+            }),
+        };
 
         let horizontal_blur_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("gaussian_horizontal_bind_group"),
@@ -195,6 +519,7 @@ impl GaussianPass {
                     binding: 2,
                     resource: horizontal_direction_buffer.as_entire_binding(),
                 },
+                layer_binding.clone(),
             ],
         });
 
@@ -214,6 +539,7 @@ impl GaussianPass {
                     binding: 2,
                     resource: vertical_direction_buffer.as_entire_binding(),
                 },
+                layer_binding,
             ],
         });
 
@@ -224,29 +550,117 @@ impl GaussianPass {
             vertical_blur_bind_group,
             horizontal_direction_buffer,
             vertical_direction_buffer,
+            workgroup_size,
+            layer_stride,
         }
     }
+
+    /// The blur pipeline with the bind group layout of the pass, e.g. to replace it with one of a
+    /// reloaded shader.
+    pub fn create_pipeline(
+        device: &wgpu::Device,
+        bind_group_layout: &BindGroupLayout,
+        shader_module: &wgpu::ShaderModule,
+        blur_radius: u32,
+        workgroup_size: u32,
+    ) -> wgpu::ComputePipeline {
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("gaussian_pipeline_layout"),
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("gaussian_compute_pipeline"),
+            layout: Some(&pipeline_layout),
+            module: shader_module,
+            entry_point: Some("main"),
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants: &HashMap::from([
+                    ("KERNEL_RADIUS".to_string(), blur_radius as f64),
+                    ("WORKGROUP_SIZE".to_string(), workgroup_size as f64),
+                ]),
+                ..Default::default()
+            },
+            cache: shader_cache::pipeline_cache(device).as_ref(),
+        })
+    }
 }
 
 pub struct Renderer {
-    pub window: Rc<Window>,
+    /// None for a headless renderer, see [`Renderer::new_headless`].
+    pub window: Option<Rc<Window>>,
     instance: Instance,
-    pub surface: Surface<'static>,
+    pub surface: Option<Surface<'static>>,
     pub surface_config: SurfaceConfiguration,
     adapter: Adapter,
     pub device: Device,
     pub queue: Queue,
-    pub render_pipeline: Pipeline,
-    pub shadow_pipeline: Pipeline, // TODO extract struct
+    pub render_pipeline: PipelineVariants,
+    pub shadow_pipeline: PipelineVariants, // TODO extract struct
+    /// Renders several shadow map layers per pass, if the adapter supports multiview.
+    pub layered_shadows: Option<LayeredShadowPass>,
     pub scene_graph: SceneGraph,
     pub depth_texture: texture::Texture,
-    pub shadow_depth_texture: texture::Texture,
-    pub model_matrix_buffer: wgpu::Buffer,
-    pub model_matrix_bind_group: wgpu::BindGroup,
+    /// Depth attachments of the moment shadow passes, None for depth shadow maps and layered shadows.
+    pub shadow_depth_texture: Option<texture::Texture>,
+    pub point_shadow_depth_texture: Option<texture::Texture>,
     pub camera_state: CameraState,
-    pub sp_camera_buffer: wgpu::Buffer,
-    pub sp_camera_bind_group: wgpu::BindGroup,
-    pub gaussian_pass: GaussianPass,
+    pub sp_camera_buffers: Vec<wgpu::Buffer>,
+    pub sp_camera_bind_groups: Vec<wgpu::BindGroup>,
+    /// Blurs moment shadow maps, None with depth shadow maps.
+    pub gaussian_pass: Option<GaussianPass>,
+    /// What the last [`Renderer::sync_scene`] uploaded.
+    pub scene_sync: SceneSync,
+    /// Light matrix and shadow size each spot light shadow map layer was last drawn and blurred with,
+    /// see [`RenderSettings::blur_moved_lights_only`].
+    shadow_layer_views: HashMap<u32, (Mat4, u32)>,
+    pub forward_sort_policy: SortPolicy,
+    pub shadow_sort_policy: SortPolicy,
+    pub stereo: StereoPass,
+    pub hud: Hud,
+    pub depth_view: DepthView,
+    /// Wireframes of the render node bounds and the shadow frusta.
+    pub debug_lines: DebugLines,
+    /// Ribbons behind moving nodes, the sun of the demo scene has one.
+    pub trails: Trails,
+    /// Lighting presets the scene blends to, see time_of_day.
+    pub time_of_day: DayCycle,
+    pub debug_view: DebugView,
+    /// Set with [`Renderer::set_render_mode`].
+    pub render_mode: RenderMode,
+    pub refraction: RefractionPass,
+    pub msaa: Msaa,
+    /// Drawn behind the scene instead of the clear color.
+    pub skybox: Option<Skybox>,
+    pub pick: PickPass,
+    pub watchdog: FrameWatchdog,
+    /// GPU time of the passes, if the adapter supports timestamp queries.
+    pub gpu_timer: Option<GpuTimer>,
+    /// Materials with shaders of their own, see [`SceneGraph::set_custom_material`].
+    pub custom_materials: CustomMaterials,
+    /// Passes added with [`Renderer::add_pass`].
+    pub custom_passes: Vec<Box<dyn CustomPass>>,
+    /// Targets of the frame being captured, set before a frame is rendered and saved once it was
+    /// submitted.
+    pub pass_capture: Option<PassCapture>,
+    /// Frames rendered so far.
+    pub frame_count: u64,
+    /// Per light statistics of the last shadow pass.
+    pub shadow_stats: Vec<ShadowStats>,
+    /// Per render node statistics of the last forward pass, the most expensive first.
+    pub node_stats: Vec<NodeStats>,
+    pub settings: RenderSettings,
+    /// Set with the reason when the device is lost, e.g. after a driver reset.
+    pub device_lost: Arc<Mutex<Option<String>>>,
+    /// How long the phases of creating the renderer took.
+    pub startup: StartupTimer,
+    /// Shaders the forward and shadow pipelines are built from, replaced when their source is reloaded.
+    forward_shader: ShaderSlot,
+    shadow_shader: ShaderSlot,
+    /// Polls the shader sources for changes, None without a window or without the sources.
+    shader_watcher: Option<ShaderWatcher>,
+    /// Layout of the material bind groups, for models loaded again by [`Renderer::reload_changed_assets`].
+    material_bind_group_layout: BindGroupLayout,
 }
 
 pub struct CameraState {
@@ -257,7 +671,72 @@ pub struct CameraState {
     pub camera_bind_group: wgpu::BindGroup,
 }
 
-pub fn create_graphics(event_loop: &ActiveEventLoop) -> impl Future<Output = Renderer> + 'static {
+/// Features of the storage buffer light path, requested when the adapter has them.
+pub const STORAGE_LIGHT_FEATURES: wgpu::Features =
+    wgpu::Features::BUFFER_BINDING_ARRAY.union(wgpu::Features::STORAGE_RESOURCE_BINDING_ARRAY);
+
+/// Limits the device is requested with, WebGL2 has no storage buffers.
+pub fn required_limits() -> wgpu::Limits {
+    if cfg!(target_arch = "wasm32") {
+        wgpu::Limits::downlevel_webgl2_defaults()
+    } else {
+        wgpu::Limits::default()
+    }
+}
+
+/// Whether lights are read from storage buffers with the device `limits`, otherwise the shaders use the
+/// fixed-size uniform array.
+pub fn supports_storage_resources(adapter: &Adapter, limits: &wgpu::Limits) -> bool {
+    adapter.features().contains(STORAGE_LIGHT_FEATURES)
+        && adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(wgpu::DownlevelFlags::VERTEX_STORAGE)
+        && limits.max_storage_buffers_per_shader_stage > 0
+}
+
+/// Renders into an sRGB target: the shaders light in linear space and the target encodes their output for
+/// the display. Surfaces without an sRGB format, e.g. a WebGPU canvas, are drawn through an sRGB view of
+/// their texture where the adapter allows it.
+fn select_srgb_format(
+    adapter: &Adapter,
+    config: &mut SurfaceConfiguration,
+    formats: &[wgpu::TextureFormat],
+) {
+    let srgb_view = config.format.add_srgb_suffix();
+    if let Some(format) = formats.iter().find(|format| format.is_srgb()) {
+        config.format = *format;
+    } else if srgb_view != config.format
+        && adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(wgpu::DownlevelFlags::SURFACE_VIEW_FORMATS)
+    {
+        config.view_formats = vec![srgb_view];
+    } else {
+        println!(
+            "No sRGB target for the surface format {:?}, colors are too dark",
+            config.format
+        );
+    }
+}
+
+/// Format the frames are rendered in, the sRGB view of the surface texture if it has one.
+pub fn frame_format(config: &SurfaceConfiguration) -> wgpu::TextureFormat {
+    config
+        .view_formats
+        .first()
+        .copied()
+        .unwrap_or(config.format)
+}
+
+/// Creates the window and the renderer, with the render settings of `settings_file` and the scene of
+/// `scene_file` if given.
+pub fn create_graphics(
+    event_loop: &ActiveEventLoop,
+    settings_file: Option<String>,
+    scene_file: Option<String>,
+) -> impl Future<Output = Result<Renderer, RendererError>> + 'static {
     #[allow(unused_mut)]
     let mut window_attrs = Window::default_attributes();
     window_attrs.maximized = true;
@@ -274,68 +753,165 @@ pub fn create_graphics(event_loop: &ActiveEventLoop) -> impl Future<Output = Ren
         window_attrs = window_attrs.with_canvas(Some(html_canvas_element));
     }
 
-    let window = Rc::new(event_loop.create_window(window_attrs).unwrap_throw());
-    let instance = wgpu::Instance::default();
-    let surface = instance
-        .create_surface(window.clone())
-        .unwrap_or_else(|e| throw_str(&format!("{e:#?}")));
-    let required_features =
-        wgpu::Features::BUFFER_BINDING_ARRAY | wgpu::Features::STORAGE_RESOURCE_BINDING_ARRAY;
+    let window = event_loop.create_window(window_attrs).map(Rc::new);
+    async move {
+        let window = window?;
+        let size = window.inner_size();
+        create_renderer(
+            Some(window),
+            size.width,
+            size.height,
+            settings_file,
+            scene_file,
+        )
+        .await
+    }
+}
+
+/// Reads and decodes the skybox at the first of [`SKYBOX_PATHS`] that has one, after the errors of the
+/// paths tried before it.
+async fn load_skybox_images() -> Vec<(&'static str, anyhow::Result<CubeMapImages>)> {
+    let mut results = Vec::new();
+    for path in SKYBOX_PATHS {
+        let images = resources::load_cube_map_images(path).await;
+        let found = images.is_ok();
+        results.push((path, images));
+        if found {
+            break;
+        }
+    }
+    results
+}
 
+/// Creates the renderer for `window`, or for offscreen frames of `width` x `height` without a window.
+fn create_renderer(
+    window: Option<Rc<Window>>,
+    width: u32,
+    height: u32,
+    settings_file: Option<String>,
+    scene_file: Option<String>,
+) -> impl Future<Output = Result<Renderer, RendererError>> + 'static {
+    let instance = wgpu::Instance::default();
     async move {
+        let mut startup = StartupTimer::new();
+        // the skybox is decoded while the device, the scene and the pipelines are created
+        #[cfg(not(target_arch = "wasm32"))]
+        let skybox_images = std::thread::spawn(|| pollster::block_on(load_skybox_images()));
+        let surface = window
+            .clone()
+            .map(|window| instance.create_surface(window))
+            .transpose()?;
+        let settings = match settings_file {
+            Some(file) => match resources::load_settings(&file).await {
+                Ok(settings) => settings,
+                Err(e) => {
+                    println!("Failed to load render settings from {file}: {e}");
+                    RenderSettings::default()
+                }
+            },
+            None => RenderSettings::default(),
+        };
+        let scene = match scene_file {
+            Some(file) => match resources::load_scene(&file).await {
+                Ok(scene) => Some(scene),
+                Err(e) => {
+                    println!("Failed to load scene {file}, showing the demo scene: {e}");
+                    None
+                }
+            },
+            None => None,
+        };
+        startup.lap("settings");
+
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
-                compatible_surface: Some(&surface),
+                compatible_surface: surface.as_ref(),
                 power_preference: wgpu::PowerPreference::None,
                 force_fallback_adapter: false,
             })
             .await
-            .unwrap_throw();
+            .ok_or(RendererError::NoAdapter)?;
+        // optional features are only requested if available, the renderer falls back without them
+        let required_features = adapter.features()
+            & (STORAGE_LIGHT_FEATURES
+                | wgpu::Features::MULTIVIEW
+                | GPU_TIMER_FEATURES
+                | wgpu::Features::PIPELINE_CACHE);
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
-                    label: None,
+                    label: Some("device"),
                     required_features,
-                    required_limits: if cfg!(target_arch = "wasm32") {
-                        wgpu::Limits::downlevel_webgl2_defaults()
-                    } else {
-                        wgpu::Limits::default()
-                    },
+                    required_limits: required_limits(),
                     memory_hints: wgpu::MemoryHints::MemoryUsage,
                 },
                 None,
             )
-            .await
-            .unwrap_throw();
+            .await?;
+        shader_cache::init(&device, &adapter.get_info());
+        startup.lap("device");
+        let device_lost = Arc::new(Mutex::new(None));
+        {
+            let device_lost = device_lost.clone();
+            device.set_device_lost_callback(move |reason, message| {
+                *device_lost.lock().unwrap() = Some(format!("{reason:?}: {message}"));
+            });
+        }
 
-        let size = window.inner_size();
-        let surface_config = SurfaceConfiguration {
-            ..surface
-                .get_default_config(&adapter, size.width, size.height)
-                .unwrap_throw()
+        let surface_config = match &surface {
+            Some(surface) => {
+                let mut config = surface
+                    .get_default_config(&adapter, width, height)
+                    .ok_or(RendererError::UnsupportedSurface)?;
+                let capabilities = surface.get_capabilities(&adapter);
+                let hdr = settings.hdr.is_some()
+                    && select_hdr_format(&mut config, &capabilities.formats);
+                if !hdr {
+                    select_srgb_format(&adapter, &mut config, &capabilities.formats);
+                }
+                // custom passes that read the frame copy it
+                if capabilities.usages.contains(wgpu::TextureUsages::COPY_SRC) {
+                    config.usage |= wgpu::TextureUsages::COPY_SRC;
+                }
+                config
+            }
+            // offscreen frames are rendered like frames of a surface with this configuration
+            None => SurfaceConfiguration {
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                format: HEADLESS_FORMAT,
+                width,
+                height,
+                present_mode: wgpu::PresentMode::Fifo,
+                desired_maximum_frame_latency: 2,
+                alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+                view_formats: Vec::new(),
+            },
         };
 
-        let supports_storage_resources = adapter
-            .get_downlevel_capabilities()
-            .flags
-            .contains(wgpu::DownlevelFlags::VERTEX_STORAGE)
-            && device.limits().max_storage_buffers_per_shader_stage > 0;
+        let format = frame_format(&surface_config);
+        let supports_storage_resources = supports_storage_resources(&adapter, &device.limits());
 
         #[cfg(not(target_arch = "wasm32"))]
-        {
+        if let Some(surface) = &surface {
             surface.configure(&device, &surface_config);
         }
 
+        let start_view = scene.as_ref().and_then(|scene| scene.camera);
         let camera = Camera {
-            eye: Vec3::new(0.0, 1.0, 30.0),
-            target: Vec3::ZERO,
+            eye: start_view.map_or(Vec3::new(0.0, 1.0, 30.0), |view| view.eye),
+            target: start_view.map_or(Vec3::ZERO, |view| view.target),
             up: Vec3::Y,
-            aspect: size.width as f32 / size.height as f32,
-            fovy: 45.0,
+            aspect: width as f32 / height as f32,
+            fovy: settings.fovy,
             znear: 0.1,
             zfar: 100.,
+            projection: Projection::Perspective,
         };
-        let camera_controller = CameraController::new(0.5, 0.1);
+        let mut camera_controller = CameraController::new(30.0, 0.1);
+        camera_controller.resize(width as f64, height as f64);
+        camera_controller.transition_time = settings
+            .accessibility
+            .transition_time(camera_controller.transition_time);
         let camera_uniform = CameraUniform::from_camera(&camera);
         let camera_bind_group_layout = CameraUniform::get_bind_group_layout(&device);
         let camera_buffer = device.create_buffer(&wgpu::BufferDescriptor {
@@ -354,20 +930,34 @@ pub fn create_graphics(event_loop: &ActiveEventLoop) -> impl Future<Output = Ren
         });
 
         let sp_camera_bind_group_layout = CameraUniform::get_bind_group_layout(&device);
-        let sp_camera_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Camera Buffer"),
-            size: size_of::<CameraUniform>() as wgpu::BufferAddress,
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
-        let sp_camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &sp_camera_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: sp_camera_buffer.as_entire_binding(),
-            }],
-            label: Some("camera_bind_group"),
-        });
+        // one camera per shadow map layer, the shadow passes of all lights are recorded before submitting.
+        // The layers of the point shadow map follow the ones of the regular shadow map.
+        let shadow_layers =
+            settings.max_lights + settings.max_point_lights * LightKind::Point.shadow_layers();
+        let sp_camera_buffers = (0..shadow_layers)
+            .map(|layer| {
+                device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(&labels::shadow_layer("Shadow Camera Buffer", layer)),
+                    size: size_of::<CameraUniform>() as wgpu::BufferAddress,
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                })
+            })
+            .collect::<Vec<_>>();
+        let sp_camera_bind_groups = sp_camera_buffers
+            .iter()
+            .zip(0..)
+            .map(|(sp_camera_buffer, layer)| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &sp_camera_bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: sp_camera_buffer.as_entire_binding(),
+                    }],
+                    label: Some(&labels::shadow_layer("shadow_camera_bind_group", layer)),
+                })
+            })
+            .collect();
 
         let camera_state = CameraState {
             camera,
@@ -380,182 +970,344 @@ pub fn create_graphics(event_loop: &ActiveEventLoop) -> impl Future<Output = Ren
         // let swapchain_capabilities = surface.get_capabilities(&adapter);
         // let swapchain_format = swapchain_capabilities.formats[0];
 
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: None,
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader.wgsl"))),
-        });
-        let shadow_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: None,
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shadow.wgsl"))),
-        });
-        let gaussian_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: None,
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("gaussian.wgsl"))),
-        });
-
-        let material_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
+        // view_index needs multiview, shadow_multiview.wgsl is only added where it is supported
+        let shadow_source = if device.features().contains(wgpu::Features::MULTIVIEW) {
+            shader_compose::compose(
+                &[
+                    include_str!("shadow.wgsl"),
+                    include_str!("shadow_multiview.wgsl"),
                 ],
-                label: Some("material_bind_group_layout"),
-            });
+                settings.shadow_mode,
+            )
+        } else {
+            shader_compose::compose(&[include_str!("shadow.wgsl")], settings.shadow_mode)
+        };
+        let [shader, shadow_shader, gaussian_shader] = device.reflect_shaders([
+            wgpu::ShaderModuleDescriptor {
+                label: Some("forward"),
+                source: wgpu::ShaderSource::Wgsl(Cow::Owned(shader_compose::compose(
+                    &[
+                        include_str!("shader.wgsl"),
+                        include_str!("pbr.wgsl"),
+                        include_str!("toon.wgsl"),
+                    ],
+                    settings.shadow_mode,
+                ))),
+            },
+            wgpu::ShaderModuleDescriptor {
+                label: Some("shadow"),
+                source: wgpu::ShaderSource::Wgsl(Cow::Owned(shadow_source)),
+            },
+            wgpu::ShaderModuleDescriptor {
+                label: Some("gaussian"),
+                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("gaussian.wgsl"))),
+            },
+        ]);
+        startup.lap("shaders");
 
-        let model_matrix_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-                label: Some("model_matrix_bind_group_layout"),
-            });
-        let model_matrix_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Model Matrix Buffer"),
-            size: size_of::<ModelUniform>() as wgpu::BufferAddress,
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
-        let model_matrix_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &model_matrix_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
+        let mut material_bind_group_entries = vec![
+            wgpu::BindGroupLayoutEntry {
                 binding: 0,
-                resource: model_matrix_buffer.as_entire_binding(),
-            }],
-            label: Some("model_matrix_bind_group"),
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+        // specular, shininess, PBR, toon ramp and emissive maps, sampled with the diffuse sampler
+        material_bind_group_entries.extend((3..3 + Material::MAP_COUNT).map(|binding| {
+            wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            }
+        }));
+        let material_bind_group_layout = device.reflect_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &material_bind_group_entries,
+            label: Some("material_bind_group_layout"),
         });
 
-        let shadow_map = ShadowMap::create_shadow_map(&device, None);
+        let shadow_mode = settings.shadow_mode;
         let gaussian_output = ShadowMap::create_shadow_map(
             &device,
             None,
+            settings.max_lights,
+            settings.shadow_map_size,
+            shadow_mode,
         );
-
-        let gaussian_pass = GaussianPass::new(
+        let point_shadow_map = ShadowMap::create_point_shadow_map(
             &device,
-            &gaussian_shader,
-            &shadow_map.view,
-            &gaussian_output.view,
-            ShadowMap::DEPTH_FORMAT,
+            settings.max_point_lights,
+            settings.point_shadow_map_size,
+            shadow_mode,
         );
 
-        let scene_graph = create_scenegraph(
+        // moment shadow maps are blurred through a second map, depth shadow maps are filtered when sampled
+        let gaussian_pass = (!shadow_mode.is_depth()).then(|| {
+            let shadow_map = ShadowMap::create_shadow_map(
+                &device,
+                None,
+                settings.max_lights,
+                settings.shadow_map_size,
+                shadow_mode,
+            );
+            let workgroup_size = settings.blur_workgroup_size.clamp(1, 16);
+            if workgroup_size != settings.blur_workgroup_size {
+                println!("Blurring with workgroups of {workgroup_size}x{workgroup_size}");
+            }
+            GaussianPass::new(
+                &device,
+                &gaussian_shader,
+                &shadow_map.view,
+                &gaussian_output.view,
+                ShadowMap::DEPTH_FORMAT,
+                settings.blur_radius,
+                workgroup_size,
+                settings.max_lights,
+            )
+        });
+
+        let mut scene_graph = create_scenegraph(
             &device,
             &queue,
             &material_bind_group_layout,
             supports_storage_resources,
             gaussian_output,
+            point_shadow_map,
+            scene.as_ref(),
         )
-        .await;
+        .await?;
+        scene_graph.set_fog(settings.fog);
+        startup.lap("scene");
 
         let light_bind_group_layout = &scene_graph.light_bind_group_layout;
 
         let depth_texture =
             texture::Texture::create_depth_texture(&device, &surface_config, "depth_texture");
-        let vertex_buffer_layout = Vertex::desc();
-
-        let shadow_pipeline = Pipeline::new(
+        let depth_view = DepthView::new(
             &device,
-            &shadow_shader,
-            &[
-                &sp_camera_bind_group_layout,
-                &model_matrix_bind_group_layout,
-            ],
-            "vs_shadow",
-            &[vertex_buffer_layout.clone()],
-            Some("fs_shadow"),
-            &[Some(wgpu::ColorTargetState {
-                format: ShadowMap::DEPTH_FORMAT,
-                blend: None,
-                write_mask: wgpu::ColorWrites::ALL,
-            })],
-            Some(texture::Texture::DEPTH_FORMAT),
-            Some(wgpu::DepthBiasState {
-                constant: 2,
-                slope_scale: 2.0,
-                clamp: 0.0005,
-            }),
-            Some(MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
+            &adapter,
+            format,
+            &depth_texture.view,
+            &camera_state.camera,
+        );
+        let debug_lines = DebugLines::new(&device, format, &camera_bind_group_layout);
+        let mut trails = Trails::new(&device, format, &camera_bind_group_layout);
+        // the orbit of the sun, scenes from a file have no node of that name
+        trails.add(Trail::new("light", [1.0, 0.8, 0.3], 0.5, 120));
+        let shadow_bind_group_layouts = bind_groups::shadow_layouts(
+            &sp_camera_bind_group_layout,
+            &scene_graph.model_matrices.bind_group_layout,
+        );
+        let shadow_shader = ShaderSlot::new(RefCell::new(shadow_shader));
+        let shadow_pipeline = create_shadow_pipeline(
+            &device,
+            shadow_shader.clone(),
+            shadow_bind_group_layouts.clone(),
+            shadow_mode,
+            None,
+        );
+        let layered_shadows = device
+            .features()
+            .contains(wgpu::Features::MULTIVIEW)
+            .then(|| {
+                LayeredShadowPass::new(
+                    &device,
+                    &scene_graph,
+                    &sp_camera_bind_group_layout,
+                    |views| {
+                        create_shadow_pipeline(
+                            &device,
+                            shadow_shader.clone(),
+                            shadow_bind_group_layouts.clone(),
+                            shadow_mode,
+                            Some(views),
+                        )
+                    },
+                )
+            });
+        // moments are rendered into a color target with a separate depth attachment, depth shadow maps
+        // are the depth attachment themselves. The layered passes bring their own.
+        let moments_depth_texture = |size, label| {
+            (!shadow_mode.is_depth() && layered_shadows.is_none()).then(|| {
+                texture::Texture::create_depth_texture_with_dimensions(&device, size, size, label)
+            })
+        };
+        let shadow_depth_texture =
+            moments_depth_texture(settings.shadow_map_size, "shadow_depth_texture");
+        let point_shadow_depth_texture =
+            moments_depth_texture(settings.point_shadow_map_size, "point_shadow_depth_texture");
+
+        let fragment_entry = move |shading| match (shading, supports_storage_resources) {
+            (Shading::Phong, true) => "fs_main",
+            (Shading::Phong, false) => "fs_main_without_storage",
+            (Shading::Pbr, true) => "fs_pbr",
+            (Shading::Pbr, false) => "fs_pbr_without_storage",
+            (Shading::Toon, true) => "fs_toon",
+            (Shading::Toon, false) => "fs_toon_without_storage",
+        };
+        let forward_color_target = wgpu::ColorTargetState {
+            format,
+            blend: Some(wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Max,
+                },
             }),
+            write_mask: wgpu::ColorWrites::ALL,
+        };
+        let forward_bind_group_layouts = bind_groups::layouts(
+            &camera_bind_group_layout,
+            &scene_graph.model_matrices.bind_group_layout,
+            &material_bind_group_layout,
+            light_bind_group_layout.as_ref().unwrap(),
         );
-        let shadow_depth_texture = texture::Texture::create_depth_texture_with_dimensions(
+        let forward_shader = ShaderSlot::new(RefCell::new(shader));
+        let render_pipeline = {
+            let bind_group_layouts = forward_bind_group_layouts.clone();
+            let color_target = forward_color_target.clone();
+            let shader = forward_shader.clone();
+            PipelineVariants::new(
+                &device,
+                Default::default(),
+                move |device, bias, key, multisample| {
+                    Pipeline::new_variant(
+                        device,
+                        &labels::pipeline_variant(
+                            "forward_pipeline",
+                            key.shading,
+                            multisample.count,
+                        ),
+                        &shader.borrow(),
+                        &bind_group_layouts.each_ref(),
+                        "vs_main",
+                        &[Vertex::desc(), InstanceRaw::desc(), Tangent::desc()],
+                        Some(fragment_entry(key.shading)),
+                        &[Some(color_target.clone())],
+                        Some(texture::Texture::DEPTH_FORMAT),
+                        Some(bias),
+                        Some(multisample),
+                        None,
+                        Some(key),
+                    )
+                },
+            )
+        };
+        let msaa_sample_count = Msaa::supported_sample_count(&adapter, format, MSAA_SAMPLE_COUNT);
+        let mut custom_materials = CustomMaterials::new(
+            forward_bind_group_layouts.clone(),
+            forward_color_target.clone(),
+            shadow_mode,
+            msaa_sample_count,
+        );
+        if let Some(scene) = &scene {
+            scene
+                .build_materials(&mut scene_graph, &mut custom_materials, &device)
+                .await;
+        }
+        let pick = PickPass::new(
             &device,
-            ShadowMap::SHADOW_MAP_SIZE,
-            ShadowMap::SHADOW_MAP_SIZE,
-            "shadow_depth_texture",
+            &forward_bind_group_layouts,
+            supports_storage_resources,
+            shadow_mode,
         );
 
-        let render_pipeline = Pipeline::new(
+        let multiview_pipeline = if device.features().contains(wgpu::Features::MULTIVIEW) {
+            let multiview_shader = device.reflect_shader(wgpu::ShaderModuleDescriptor {
+                label: Some("multiview"),
+                source: wgpu::ShaderSource::Wgsl(Cow::Owned(shader_compose::compose(
+                    &[
+                        include_str!("shader.wgsl"),
+                        include_str!("pbr.wgsl"),
+                        include_str!("toon.wgsl"),
+                        include_str!("multiview.wgsl"),
+                    ],
+                    shadow_mode,
+                ))),
+            });
+            Some(PipelineVariants::new(
+                &device,
+                Default::default(),
+                move |device, bias, key, multisample| {
+                    Pipeline::new_variant(
+                        device,
+                        &labels::pipeline_variant(
+                            "multiview_pipeline",
+                            key.shading,
+                            multisample.count,
+                        ),
+                        &multiview_shader,
+                        &forward_bind_group_layouts.each_ref(),
+                        "vs_main_multiview",
+                        &[Vertex::desc(), InstanceRaw::desc(), Tangent::desc()],
+                        Some(fragment_entry(key.shading)),
+                        &[Some(forward_color_target.clone())],
+                        Some(texture::Texture::DEPTH_FORMAT),
+                        Some(bias),
+                        Some(multisample),
+                        NonZeroU32::new(EYE_COUNT),
+                        Some(key),
+                    )
+                },
+            ))
+        } else {
+            None
+        };
+        let stereo = StereoPass::new(&device, format, multiview_pipeline);
+        let scale_factor = window.as_ref().map_or(1.0, |window| window.scale_factor());
+        let hud = Hud::new(&device, format, scale_factor);
+        let refraction = RefractionPass::new(
             &device,
-            &shader,
-            &[
-                &camera_bind_group_layout,
-                &model_matrix_bind_group_layout,
-                &material_bind_group_layout,
-                light_bind_group_layout.as_ref().unwrap(),
-            ],
-            "vs_main",
-            &[vertex_buffer_layout],
-            Some(if supports_storage_resources {
-                "fs_main"
-            } else {
-                "fs_main_without_storage"
-            }),
-            &[Some(wgpu::ColorTargetState {
-                format: surface_config.format,
-                blend: Some(wgpu::BlendState {
-                    color: wgpu::BlendComponent {
-                        src_factor: wgpu::BlendFactor::SrcAlpha,
-                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                        operation: wgpu::BlendOperation::Add,
-                    },
-                    alpha: wgpu::BlendComponent {
-                        src_factor: wgpu::BlendFactor::One,
-                        dst_factor: wgpu::BlendFactor::One,
-                        operation: wgpu::BlendOperation::Max,
-                    },
-                }),
-                write_mask: wgpu::ColorWrites::ALL,
-            })],
-            Some(texture::Texture::DEPTH_FORMAT),
-            None,
-            None,
+            format,
+            surface_config.width,
+            surface_config.height,
+            &camera_bind_group_layout,
+            &scene_graph.model_matrices.bind_group_layout,
+            &material_bind_group_layout,
+        );
+        let msaa = Msaa::new(
+            &device,
+            format,
+            surface_config.width,
+            surface_config.height,
+            msaa_sample_count,
+            &render_pipeline,
+            &refraction.glass_pipeline,
         );
+        let gpu_timer = GpuTimer::new(&device, &queue);
+        let shader_watcher = window.as_ref().and_then(|_| ShaderWatcher::new());
 
-        Renderer {
+        let mut renderer = Renderer {
             window,
             instance,
             surface,
@@ -565,37 +1317,616 @@ pub fn create_graphics(event_loop: &ActiveEventLoop) -> impl Future<Output = Ren
             queue,
             render_pipeline,
             shadow_pipeline,
+            layered_shadows,
             scene_graph,
             depth_texture,
             shadow_depth_texture,
-            model_matrix_buffer,
-            model_matrix_bind_group,
+            point_shadow_depth_texture,
             camera_state,
-            sp_camera_buffer,
-            sp_camera_bind_group,
+            sp_camera_buffers,
+            sp_camera_bind_groups,
             gaussian_pass,
+            scene_sync: SceneSync::default(),
+            shadow_layer_views: HashMap::new(),
+            forward_sort_policy: SortPolicy::State,
+            shadow_sort_policy: SortPolicy::Depth,
+            stereo,
+            hud,
+            depth_view,
+            debug_lines,
+            trails,
+            time_of_day: DayCycle::default(),
+            debug_view: DebugView::Off,
+            render_mode: RenderMode::Lit,
+            refraction,
+            msaa,
+            skybox: None,
+            pick,
+            watchdog: FrameWatchdog::new(Duration::from_millis(100)),
+            gpu_timer,
+            custom_materials,
+            custom_passes: Vec::new(),
+            pass_capture: None,
+            frame_count: 0,
+            shadow_stats: Vec::new(),
+            node_stats: Vec::new(),
+            settings,
+            device_lost,
+            startup,
+            forward_shader,
+            shadow_shader,
+            shader_watcher,
+            material_bind_group_layout,
+        };
+        let compute = renderer
+            .adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS);
+        // blurs before the exposure is metered, like the lens in front of the eye
+        if let Some(depth_of_field) = &renderer.settings.depth_of_field {
+            // the fragment stage reads the focus from a storage buffer
+            if compute && supports_storage_resources {
+                let frame_time = renderer.settings.target_frame_time().as_secs_f32();
+                let depth_of_field = renderer
+                    .settings
+                    .accessibility
+                    .depth_of_field(depth_of_field);
+                let pass = DepthOfFieldPass::new(
+                    &renderer.device,
+                    renderer.frame_format(),
+                    &depth_of_field,
+                    frame_time,
+                );
+                renderer.add_pass(Box::new(pass));
+            } else {
+                println!("Depth of field is off, the adapter has no compute shaders");
+            }
+        }
+        if let Some(exposure) = &renderer.settings.auto_exposure {
+            // the fragment stage reads the exposure from a storage buffer
+            if compute && supports_storage_resources {
+                let frame_time = renderer.settings.target_frame_time().as_secs_f32();
+                let exposure = renderer.settings.accessibility.exposure(exposure);
+                let pass = AutoExposurePass::new(
+                    &renderer.device,
+                    renderer.frame_format(),
+                    &exposure,
+                    frame_time,
+                );
+                renderer.add_pass(Box::new(pass));
+            } else {
+                println!("Auto exposure is off, the adapter has no compute shaders");
+            }
+        }
+        if !renderer.settings.shadows_enabled {
+            renderer.set_shadows_enabled(false);
+        }
+        // the layered passes draw the layers of several lights with one viewport
+        let full_size_shadows = renderer.layered_shadows.is_some();
+        renderer
+            .scene_graph
+            .set_full_size_shadows(full_size_shadows);
+        if let Some(stylize) = &renderer.settings.stylize {
+            let stylize = renderer.settings.accessibility.stylize(stylize);
+            let pass = StylizePass::new(&renderer.device, renderer.frame_format(), &stylize);
+            renderer.add_pass(Box::new(pass));
+        }
+        // filters the frame once the debug UI turns it on
+        let pass = ColorBlindPass::new(&renderer.device, renderer.frame_format());
+        renderer.add_pass(Box::new(pass));
+        // last, once the frame is done
+        if let Some(hdr) = &renderer.settings.hdr {
+            if renderer.frame_format() == HDR_FORMAT {
+                let pass = HdrOutputPass::new(&renderer.device, hdr);
+                renderer.add_pass(Box::new(pass));
+            }
+        }
+        renderer.startup.lap("pipelines");
+        #[cfg(not(target_arch = "wasm32"))]
+        let skybox_images = skybox_images.join().unwrap_or_default();
+        #[cfg(target_arch = "wasm32")]
+        let skybox_images = load_skybox_images().await;
+        for (path, images) in skybox_images {
+            match images.and_then(|images| images.upload(&renderer.device, &renderer.queue, path)) {
+                Ok(cube_map) => {
+                    println!("Loaded skybox {path}");
+                    renderer.set_skybox(cube_map);
+                }
+                Err(e) => println!("No skybox at {path}: {e}"),
+            }
         }
+        renderer.startup.lap("skybox");
+        println!("{}", renderer.startup.summary());
+        Ok(renderer)
     }
 }
 
+impl Renderer {
+    /// Format the frames are rendered in, see [`frame_format`].
+    pub fn frame_format(&self) -> wgpu::TextureFormat {
+        frame_format(&self.surface_config)
+    }
+
+    /// Creates a renderer without a window, for frames of `width` x `height` that are read back with
+    /// [`Renderer::read_back_frame`] instead of being presented.
+    pub fn new_headless(
+        width: u32,
+        height: u32,
+        settings_file: Option<String>,
+        scene_file: Option<String>,
+    ) -> impl Future<Output = Result<Self, RendererError>> + 'static {
+        create_renderer(None, width, height, settings_file, scene_file)
+    }
+
+    /// Renders a frame with the camera as it is and returns its pixels, RGBA rows from top to bottom.
+    pub fn read_back_frame(&mut self) -> anyhow::Result<Vec<u8>> {
+        self.sync_scene();
+        let target = OffscreenTarget::new(
+            &self.device,
+            self.frame_format(),
+            self.surface_config.width,
+            self.surface_config.height,
+        );
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("read_back_encoder"),
+            });
+        render_scene(self, &mut encoder, &target.texture, &target.view);
+        if let Some(capture) = &mut self.pass_capture {
+            capture.capture(&self.device, &mut encoder, "frame", &target.texture);
+        }
+        target.encode_readback(&mut encoder);
+        self.queue.submit(Some(encoder.finish()));
+        self.save_pass_capture();
+        Ok(target.read(&self.device)?.into_raw())
+    }
+
+    /// Writes the targets of the pass capture once its frame was submitted, see [`PassCapture`].
+    pub fn save_pass_capture(&mut self) {
+        let Some(capture) = self.pass_capture.take() else {
+            return;
+        };
+        match capture.save(&self.device) {
+            Ok(dir) => println!("Saved the passes of the frame to {}", dir.display()),
+            Err(e) => println!("Failed to save the passes of the frame: {e}"),
+        }
+    }
+
+    /// Replaces the clear color of the forward pass with the environment `cube_map`.
+    pub fn set_skybox(&mut self, cube_map: texture::Texture) {
+        let mut sample_counts = vec![1];
+        if self.msaa.sample_count > 1 {
+            sample_counts.push(self.msaa.sample_count);
+        }
+        self.skybox = Some(Skybox::new(
+            &self.device,
+            self.frame_format(),
+            &sample_counts,
+            cube_map,
+        ));
+    }
+
+    /// Adds a pass that runs at its [`CustomPass::stage`] of every frame.
+    pub fn add_pass(&mut self, pass: Box<dyn CustomPass>) {
+        self.custom_passes.push(pass);
+    }
+
+    /// Starts measuring the GPU time of `pass`, see [`GpuTimer::begin_pass`].
+    pub fn begin_gpu_pass(&mut self, encoder: &mut wgpu::CommandEncoder, pass: &'static str) {
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.begin_pass(encoder, pass);
+        }
+    }
+
+    pub fn end_gpu_pass(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.end_pass(encoder);
+        }
+    }
+
+    /// GPU time of the shadow, blur and forward passes of a recent frame. Empty if the adapter lacks
+    /// [`GPU_TIMER_FEATURES`] or no frame was read back yet. The results lag a few frames behind.
+    pub fn last_pass_timings(&self) -> &[(&'static str, Duration)] {
+        self.gpu_timer
+            .as_ref()
+            .map_or(&[], |gpu_timer| gpu_timer.pass_times())
+    }
+
+    /// Shows the scene in `mode`, replaces the debug view.
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.render_mode = mode;
+        self.debug_view = mode.debug_view();
+        self.scene_graph.material_override = mode.material_override();
+    }
+
+    /// Turns the shadows of all lights on or off. Without them the shadow and blur passes are skipped
+    /// and the forward passes draw with pipeline variants that don't sample the shadow maps, for
+    /// hardware that can't afford shadows.
+    pub fn set_shadows_enabled(&mut self, enabled: bool) {
+        println!("Shadows {}", if enabled { "on" } else { "off" });
+        self.scene_graph.set_shadows_enabled(enabled);
+        self.render_pipeline.set_shadows(enabled);
+        self.msaa.set_shadows(enabled);
+        if let Some(multiview_pipeline) = &mut self.stereo.multiview_pipeline {
+            multiview_pipeline.set_shadows(enabled);
+        }
+        self.custom_materials.set_shadows(enabled);
+    }
+
+    /// Uploads what changed since the last frame before it is encoded, so the passes only record draws:
+    /// the scene graph, the cameras of the shadow map layers if the lights changed and the camera. Also
+    /// creates the pipeline variants of materials added since the last frame.
+    pub fn sync_scene(&mut self) {
+        let eye = self.camera_state.camera.eye;
+        let synced = self.scene_graph.sync(&self.device, &self.queue, eye);
+        if synced.lights && self.layered_shadows.is_none() {
+            self.upload_shadow_cameras();
+        }
+        self.scene_sync = synced;
+        self.upload_camera();
+        self.prepare_pipeline_variants();
+    }
+
+    /// The spot light shadow map layers to draw and blur this frame with
+    /// [`RenderSettings::blur_moved_lights_only`], those of lights that moved since their layer was
+    /// last drawn. All of them if a render node moved, None if all layers are drawn anyway.
+    pub fn moved_shadow_layers(&mut self) -> Option<Vec<u32>> {
+        if !self.settings.blur_moved_lights_only
+            || self.layered_shadows.is_some()
+            || self.gaussian_pass.is_none()
+        {
+            return None;
+        }
+        // moved casters change the shadows of every light
+        if self.scene_sync.matrices > 0 {
+            self.shadow_layer_views.clear();
+        }
+        let origin = self.scene_graph.render_origin();
+        let mut moved = Vec::new();
+        let mut views = HashMap::new();
+        for (light_node, model) in SceneGraphLightNodeIterator::new(&self.scene_graph) {
+            let light = &light_node.light;
+            let Some(layer) = light
+                .shadow_layer
+                .filter(|_| light.cast_shadows && light.kind == LightKind::Spot)
+            else {
+                continue;
+            };
+            let view = (
+                light.calculate_matrix(model, origin),
+                self.scene_graph.shadow_size(light),
+            );
+            if self.shadow_layer_views.get(&layer) != Some(&view) {
+                moved.push(layer);
+            }
+            views.insert(layer, view);
+        }
+        self.shadow_layer_views = views;
+        Some(moved)
+    }
+
+    /// Forgets which shadow map layers are up to date, the next frame draws all of them.
+    pub fn invalidate_shadow_layers(&mut self) {
+        self.shadow_layer_views.clear();
+    }
+
+    /// Writes the camera of each shadow map layer of the lights, the shadow pass draws each layer with
+    /// its own camera.
+    fn upload_shadow_cameras(&self) {
+        let scene_graph = &self.scene_graph;
+        for (light_node, model) in SceneGraphLightNodeIterator::new(scene_graph) {
+            let light = &light_node.light;
+            let Some(layer) = light.shadow_layer else {
+                continue;
+            };
+            // point light faces come after the spot light layers
+            let first_camera = match light.kind {
+                LightKind::Spot => layer,
+                LightKind::Point => scene_graph.shadow_map.layers + layer,
+            };
+            let cameras = light.to_camera_uniforms(model, scene_graph.render_origin());
+            for (face, camera_uniform) in cameras.iter().enumerate() {
+                self.queue.write_buffer(
+                    &self.sp_camera_buffers[first_camera as usize + face],
+                    0,
+                    bytemuck::bytes_of(camera_uniform),
+                );
+            }
+        }
+    }
+
+    fn upload_camera(&mut self) {
+        let origin = self.scene_graph.render_origin();
+        let camera_state = &mut self.camera_state;
+        camera_state
+            .camera_uniform
+            .update(&camera_state.camera.relative_to(origin));
+        camera_state.camera_uniform.debug_view = self.debug_view as u32;
+        self.queue.write_buffer(
+            &camera_state.camera_buffer,
+            0,
+            bytemuck::cast_slice(&[camera_state.camera_uniform]),
+        );
+    }
+
+    /// Loads the models whose OBJ, MTL or texture files `watcher` saw change again and swaps them into
+    /// their render nodes, see asset_reload.rs.
+    pub fn reload_changed_assets(&mut self, watcher: &mut AssetWatcher) {
+        let models = self.scene_graph.model_groups();
+        let changed = watcher.changed(
+            models
+                .iter()
+                .flat_map(|(_, _, files)| files.iter().map(String::as_str)),
+        );
+        if changed.is_empty() {
+            return;
+        }
+        with_resource_cache(&self.device, |cache| {
+            changed.iter().for_each(|file| cache.evict(file))
+        });
+        for (name, source, files) in models {
+            if !files.iter().any(|file| changed.contains(file)) {
+                continue;
+            }
+            // the cached model holds the textures it was loaded with
+            with_resource_cache(&self.device, |cache| cache.evict(&source));
+            let path = Path::new(&source);
+            let directory = path.parent().and_then(Path::to_str).unwrap_or_default();
+            let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let model = pollster::block_on(load_cached_model(
+                directory,
+                file_name,
+                &self.device,
+                &self.queue,
+                DISPLACEMENT_SEGMENTS,
+            ));
+            match model {
+                Ok(model) => {
+                    self.scene_graph.reload_model_node(
+                        &name,
+                        &self.device,
+                        &model,
+                        &self.material_bind_group_layout,
+                    );
+                    println!("Reloaded {source} for {name}");
+                }
+                Err(e) => println!("Keeping {name}, failed to reload {source}: {e}"),
+            }
+        }
+    }
+
+    /// Rebuilds the pipelines of the shaders whose source was saved since the last call, see
+    /// shader_reload.rs.
+    pub fn reload_changed_shaders(&mut self) {
+        let Some(watcher) = &mut self.shader_watcher else {
+            return;
+        };
+        for shader in watcher.changed() {
+            match self.reload_shader(shader) {
+                Ok(()) => println!("Reloaded {}", shader.file_name()),
+                Err(e) => println!("Keeping the previous {}: {e}", shader.file_name()),
+            }
+        }
+    }
+
+    fn reload_shader(&mut self, shader: WatchedShader) -> anyhow::Result<()> {
+        let source = std::fs::read_to_string(shader.path())?;
+        let shadow_mode = self.settings.shadow_mode;
+        let source = match shader {
+            WatchedShader::Forward => shader_compose::compose(
+                &[&source, include_str!("pbr.wgsl"), include_str!("toon.wgsl")],
+                shadow_mode,
+            ),
+            WatchedShader::Shadow if self.device.features().contains(wgpu::Features::MULTIVIEW) => {
+                shader_compose::compose(
+                    &[&source, include_str!("shadow_multiview.wgsl")],
+                    shadow_mode,
+                )
+            }
+            WatchedShader::Shadow => shader_compose::compose(&[&source], shadow_mode),
+            WatchedShader::Gaussian => source,
+        };
+        let device = &self.device;
+        let module = validate(
+            device,
+            || format!("shader module {}", shader.file_name()),
+            || {
+                device.reflect_shader(wgpu::ShaderModuleDescriptor {
+                    label: Some(shader.file_name()),
+                    source: wgpu::ShaderSource::Wgsl(Cow::Owned(source)),
+                })
+            },
+        )?;
+        match shader {
+            WatchedShader::Forward => {
+                let previous = self.forward_shader.replace(module);
+                if let Err(e) = self.rebuild_forward_pipelines() {
+                    self.forward_shader.replace(previous);
+                    self.rebuild_forward_pipelines()?;
+                    return Err(e.into());
+                }
+            }
+            WatchedShader::Shadow => {
+                let previous = self.shadow_shader.replace(module);
+                if let Err(e) = self.rebuild_shadow_pipelines() {
+                    self.shadow_shader.replace(previous);
+                    self.rebuild_shadow_pipelines()?;
+                    return Err(e.into());
+                }
+            }
+            WatchedShader::Gaussian => {
+                if let Some(gaussian_pass) = &mut self.gaussian_pass {
+                    gaussian_pass.blur_pipeline = validate(
+                        device,
+                        || "gaussian_compute_pipeline".to_string(),
+                        || {
+                            GaussianPass::create_pipeline(
+                                device,
+                                &gaussian_pass.bind_group_layout,
+                                &module,
+                                self.settings.blur_radius,
+                                gaussian_pass.workgroup_size,
+                            )
+                        },
+                    )?;
+                }
+            }
+        }
+        // the shadow maps of lights that didn't move are drawn again with the new shaders
+        self.invalidate_shadow_layers();
+        Ok(())
+    }
+
+    fn rebuild_forward_pipelines(&mut self) -> Result<(), RendererError> {
+        self.render_pipeline.rebuild(&self.device)?;
+        self.msaa.rebuild_forward_pipeline(&self.device)
+    }
+
+    fn rebuild_shadow_pipelines(&mut self) -> Result<(), RendererError> {
+        self.shadow_pipeline.rebuild(&self.device)?;
+        match &mut self.layered_shadows {
+            Some(layered_shadows) => layered_shadows.rebuild(&self.device),
+            None => Ok(()),
+        }
+    }
+
+    /// Creates the pipeline variants for depth biases and shadings of materials added since the last frame.
+    fn prepare_pipeline_variants(&mut self) {
+        let keys = self.scene_graph.pipeline_keys();
+        self.render_pipeline
+            .prepare(&self.device, keys.iter().copied());
+        self.shadow_pipeline
+            .prepare(&self.device, keys.iter().copied());
+        if let Some(layered_shadows) = &mut self.layered_shadows {
+            layered_shadows.prepare(&self.device, &keys);
+        }
+        self.refraction
+            .glass_pipeline
+            .prepare(&self.device, keys.iter().copied());
+        if let Some(multiview_pipeline) = &mut self.stereo.multiview_pipeline {
+            multiview_pipeline.prepare(&self.device, keys.iter().copied());
+        }
+        self.msaa.prepare(&self.device, &keys);
+        self.custom_materials.prepare(&self.device, &keys);
+        self.pick
+            .pipeline
+            .prepare(&self.device, keys.iter().copied());
+        shader_cache::save(&self.device);
+    }
+}
+
+/// The shadow pipeline of `shadow_mode`. With `multiview` it renders that many shadow map layers at once,
+/// see [`LayeredShadowPass`].
+pub fn create_shadow_pipeline(
+    device: &Device,
+    shader: ShaderSlot,
+    bind_group_layouts: [BindGroupLayout; 2],
+    shadow_mode: ShadowMode,
+    multiview: Option<NonZeroU32>,
+) -> PipelineVariants {
+    PipelineVariants::new(
+        device,
+        wgpu::DepthBiasState {
+            constant: 2,
+            slope_scale: 2.0,
+            clamp: 0.0005,
+        },
+        // shadows are cast with depth writes, also by transparent nodes
+        move |device, bias, key, multisample| {
+            let mut label =
+                labels::pipeline_variant("shadow_pipeline", key.shading, multisample.count);
+            let vertex_entry = match multiview {
+                Some(views) => {
+                    label += &format!(" ({views} views)");
+                    "vs_shadow_multiview"
+                }
+                None => "vs_shadow",
+            };
+            let layouts = bind_group_layouts.each_ref();
+            let vertex_buffers = [Vertex::desc(), InstanceRaw::desc()];
+            if shadow_mode.is_depth() {
+                // the depth attachment is the shadow map, there is nothing to shade
+                Pipeline::new(
+                    device,
+                    &label,
+                    &shader.borrow(),
+                    &layouts,
+                    vertex_entry,
+                    &vertex_buffers,
+                    None,
+                    &[],
+                    Some(shadow_mode.format()),
+                    Some(bias),
+                    Some(multisample),
+                    multiview,
+                )
+            } else {
+                Pipeline::new(
+                    device,
+                    &label,
+                    &shader.borrow(),
+                    &layouts,
+                    vertex_entry,
+                    &vertex_buffers,
+                    Some("fs_shadow"),
+                    &[Some(wgpu::ColorTargetState {
+                        format: ShadowMap::DEPTH_FORMAT,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    Some(texture::Texture::DEPTH_FORMAT),
+                    Some(bias),
+                    Some(multisample),
+                    multiview,
+                )
+            }
+        },
+    )
+}
+
 pub async fn create_scenegraph(
     device: &Device,
     queue: &Queue,
     material_bind_group_layout: &BindGroupLayout,
     supports_storage_resources: bool,
     shadow_map: ShadowMap,
-) -> SceneGraph {
-    let light_pos = Vec3::new(0.0, 25.0, 30.0);
+    point_shadow_map: ShadowMap,
+    scene: Option<&SceneDescription>,
+) -> Result<SceneGraph, RendererError> {
+    let mut scenegraph = SceneGraph::new(
+        device,
+        supports_storage_resources,
+        shadow_map,
+        point_shadow_map,
+    );
+    scenegraph.clay_material = Material::new("clay", Some([0.8, 0.8, 0.8]), device, queue)?
+        .create_bind_group(device, material_bind_group_layout);
+    if let Some(scene) = scene {
+        scene
+            .build(
+                &mut scenegraph,
+                device,
+                queue,
+                material_bind_group_layout,
+                DISPLACEMENT_SEGMENTS,
+            )
+            .await;
+        return Ok(scenegraph);
+    }
+
+    // placed by its path, see the end of the function
     let light_sun = Light::new(
-        light_pos,
+        Vec3::ZERO,
         wgpu::Color {
             r: 1.0,
             g: 1.0,
             b: 1.0,
             a: 1.0,
         },
-        &shadow_map.texture,
-        0,
     );
     let light_cube_vertices = CUBE_VERTICES
         .iter()
@@ -618,11 +1949,19 @@ pub async fn create_scenegraph(
             material: 0,
             num_elements: CUBE_INDICES.len() as u32,
         }],
-        materials: vec![Material::new("light", Some([1.0, 1.0, 0.0]), device, queue)],
+        materials: vec![Material {
+            // glows by itself instead of only reflecting the light it marks
+            emissive: [1.0, 0.9, 0.4],
+            // lets the light's glow bleed around the marker
+            subsurface: Some(Subsurface {
+                color: [1.0, 0.6, 0.2],
+                wrap: 0.6,
+            }),
+            ..Material::new("light", Some([1.0, 1.0, 0.0]), device, queue)?
+        }],
+        files: Vec::new(),
     };
 
-    let mut scenegraph = SceneGraph::new(supports_storage_resources, shadow_map);
-
     let ground_vertices = [
         Vertex {
             tex_coords: [-1.0, -1.0],
@@ -654,12 +1993,16 @@ pub async fn create_scenegraph(
             material: 0,
             num_elements: ground_indices.len() as u32,
         }],
-        materials: vec![Material::new(
-            "ground",
-            Some([0.4, 0.3, 0.2]),
-            device,
-            queue,
-        )],
+        materials: vec![Material {
+            // pushed behind the floor of the house, which lies in the same plane
+            depth_bias: wgpu::DepthBiasState {
+                constant: 4,
+                slope_scale: 1.0,
+                clamp: 0.0,
+            },
+            ..Material::new("ground", Some([0.4, 0.3, 0.2]), device, queue)?
+        }],
+        files: Vec::new(),
     };
     scenegraph.add_model_node(
         None,
@@ -670,72 +2013,132 @@ pub async fn create_scenegraph(
         Mat4::IDENTITY,
     );
 
-    let model = load_model("assets/All_Files/Example/OBJ", "Example.obj", device, queue);
+    let model = load_model(
+        "assets/All_Files/Example/OBJ",
+        "Example.obj",
+        device,
+        queue,
+        DISPLACEMENT_SEGMENTS,
+    )
+    .await?;
+    // grouped with its file, so a saved scene refers to the model
+    let mut house = GroupNode::new("house".to_string());
+    house.source = Some("assets/All_Files/Example/OBJ/Example.obj".to_string());
+    house.source_files = model.files.clone();
+    scenegraph.insert_group_node(None, house);
     scenegraph.add_model_node(
-        None,
+        Some("house"),
         "house".to_string(),
         device,
-        &model.await.unwrap(),
+        &model,
         material_bind_group_layout,
         Mat4::IDENTITY,
     );
-    scenegraph.add_light_node(None, "light".to_string(), device, light_sun);
+    // a ring of posts around the house, drawn with one instanced draw call
+    let post = Model {
+        meshes: vec![Mesh {
+            name: "post".to_string(),
+            vertices: CUBE_VERTICES.to_vec(),
+            indices: CUBE_INDICES.to_vec(),
+            material: 0,
+            num_elements: CUBE_INDICES.len() as u32,
+        }],
+        // brushed metal, the highlight runs along the posts
+        materials: vec![{
+            let mut material = Material::new("post", Some([0.5, 0.45, 0.4]), device, queue)?;
+            material.material.specular = Some([0.8, 0.8, 0.8]);
+            material.material.shininess = Some(30.0);
+            material.anisotropy = Some(Anisotropy {
+                strength: 0.8,
+                rotation: std::f32::consts::FRAC_PI_2,
+            });
+            material
+        }],
+        files: Vec::new(),
+    };
+    let post_count = 24;
+    let post_transforms = (0..post_count)
+        .map(|i| {
+            let angle = i as f32 / post_count as f32 * std::f32::consts::TAU;
+            Mat4::from_scale_rotation_translation(
+                Vec3::new(0.2, 1.0, 0.2),
+                glam::Quat::from_rotation_y(-angle),
+                Vec3::new(angle.cos() * 20.0, 1.0, angle.sin() * 20.0),
+            )
+        })
+        .collect::<Vec<_>>();
+    scenegraph.add_instanced_model_node(
+        None,
+        "posts".to_string(),
+        device,
+        &post,
+        material_bind_group_layout,
+        &post_transforms,
+    );
+    scenegraph.add_light_node(None, "light".to_string(), light_sun);
+    scenegraph.sun = Some(SunNodes::demo());
+    // dim warm fill light with omnidirectional shadows next to the house, as bright as before the
+    // falloff at 8 units
+    let mut lamp = Light::point(
+        Vec3::new(10.0, 8.0, -5.0),
+        wgpu::Color {
+            r: 0.4,
+            g: 0.3,
+            b: 0.2,
+            a: 1.0,
+        },
+    );
+    lamp.intensity = 64.0;
+    lamp.range = 40.0;
+    scenegraph.add_light_node(None, "lamp".to_string(), lamp);
     scenegraph.add_model_node(
         None,
         "light_model".to_string(),
         device,
         &light_sun_model,
         material_bind_group_layout,
-        Mat4::from_translation(light_pos),
+        Mat4::IDENTITY,
     );
-    scenegraph
-}
-
-pub fn rotate_sun(device: &Device, scene_graph: &mut SceneGraph, time: f32) {
-    let pos;
-    {
-        let node = scene_graph.find_child_mut(Some("light")).unwrap();
-        let light_node = match node {
-            Node::LightNode(light) => light,
-            _ => panic!("Expected a light node"),
-        };
-        let light = &mut light_node.light;
-
-        let radius = 30.0;
-        let speed = 0.5;
-        let angle = time * speed as f32 * std::f32::consts::PI / 2.0;
-
-        let center = Vec3::new(0.0, 0.0, -15.0);
-        light.pos.x = center.x + radius * angle.cos();
-        light.pos.z = center.z + radius * angle.sin();
-        pos = light.pos;
-    }
-
-    {
-        let model_node = scene_graph.find_child_mut(Some("light_model-light"));
-        if model_node.is_none() {
-            return;
-        }
-        let model_node = model_node.unwrap();
-        let light_model_node = match model_node {
-            Node::RenderNode(render) => render,
-            _ => return,
-        };
-
-        light_model_node.set_matrix(Mat4::from_translation(pos), device);
-    };
-
-    scene_graph.update_light_bind_group(device);
+    // the sun circles the house an eighth of a turn per second, its marker turns along the way
+    let sun_orbit = (0..12)
+        .map(|index| {
+            let angle = index as f32 / 12.0 * std::f32::consts::TAU;
+            Vec3::new(0.0, 25.0, -15.0) + 30.0 * Vec3::new(angle.cos(), 0.0, angle.sin())
+        })
+        .collect::<Vec<_>>();
+    let sun_path = SplinePath::new(sun_orbit, true).unwrap();
+    let sun_speed = 30.0 * std::f32::consts::FRAC_PI_4;
+    scenegraph.add_path(PathAnimation::new(
+        "light",
+        sun_path.clone(),
+        sun_speed,
+        false,
+    ));
+    scenegraph.add_path(PathAnimation::new(
+        "light_model-light",
+        sun_path,
+        sun_speed,
+        true,
+    ));
+    Ok(scenegraph)
 }
 
 pub struct RenderProxy {
     event_loop_proxy: Option<EventLoopProxy<Renderer>>,
+    settings_file: Option<String>,
+    scene_file: Option<String>,
 }
 
 impl RenderProxy {
-    pub fn new(event_loop_proxy: EventLoopProxy<Renderer>) -> Self {
+    pub fn new(
+        event_loop_proxy: EventLoopProxy<Renderer>,
+        settings_file: Option<String>,
+        scene_file: Option<String>,
+    ) -> Self {
         Self {
             event_loop_proxy: Some(event_loop_proxy),
+            settings_file,
+            scene_file,
         }
     }
 
@@ -747,17 +2150,32 @@ impl RenderProxy {
 
         #[cfg(target_arch = "wasm32")]
         {
-            let gfx_fut = create_graphics(event_loop);
+            let gfx_fut = create_graphics(
+                event_loop,
+                self.settings_file.take(),
+                self.scene_file.take(),
+            );
             wasm_bindgen_futures::spawn_local(async move {
-                let gfx = gfx_fut.await;
-                assert!(event_loop_proxy.send_event(gfx).is_ok());
+                match gfx_fut.await {
+                    Ok(gfx) => assert!(event_loop_proxy.send_event(gfx).is_ok()),
+                    Err(e) => throw_str(&format!("Failed to create the renderer: {e}")),
+                }
             });
         }
 
         #[cfg(not(target_arch = "wasm32"))]
         {
-            let gfx = pollster::block_on(create_graphics(event_loop));
-            assert!(event_loop_proxy.send_event(gfx).is_ok());
+            match pollster::block_on(create_graphics(
+                event_loop,
+                self.settings_file.take(),
+                self.scene_file.take(),
+            )) {
+                Ok(gfx) => assert!(event_loop_proxy.send_event(gfx).is_ok()),
+                Err(e) => {
+                    println!("Failed to create the renderer: {e}");
+                    event_loop.exit();
+                }
+            }
         }
     }
 }
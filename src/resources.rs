@@ -2,35 +2,96 @@
     Code taken from https://sotrh.github.io/learn-wgpu/beginner/tutorial9-models/#accessing-files-from-wasm
     Making this project ready for rendering on the web is not in the scope of this project, I'd just like to keep this as an option for the future.
  */
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::env;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Arc, OnceLock};
 use cfg_if::cfg_if;
 
+use crate::error::RendererError;
+use crate::model::{load_model, Model};
+use crate::scene::SceneDescription;
+use crate::settings::RenderSettings;
 use crate::texture;
 
-#[cfg(target_arch = "wasm32")]
-fn format_url(file_name: &str) -> reqwest::Url {
-    let window = web_sys::window().unwrap();
-    let location = window.location();
-    let mut origin = location.origin().unwrap();
-    if !origin.ends_with("learn-wgpu") {
-        origin = format!("{}/learn-wgpu", origin);
+/// Where the files of the assets, scenes and settings are loaded from. Natively the paths are relative
+/// to the current directory and on the web to the page, unless a base is given with `--asset-root`, the
+/// `MSM_ASSET_ROOT` environment variable or the `asset_root` parameter of `run_web`.
+#[derive(Debug, Clone, Default)]
+pub struct ResourceConfig {
+    /// Directory, or URL on the web, the paths are relative to.
+    pub base: Option<String>,
+}
+
+static RESOURCE_CONFIG: OnceLock<ResourceConfig> = OnceLock::new();
+
+impl ResourceConfig {
+    pub const ENV_VAR: &'static str = "MSM_ASSET_ROOT";
+
+    /// The config with the base `arg` of the command line, or else of the environment variable.
+    pub fn from_args(arg: Option<String>) -> Self {
+        Self {
+            base: arg.or_else(|| env::var(Self::ENV_VAR).ok()),
+        }
+    }
+
+    /// Makes this the config of all loads, before the first file is loaded. Returns false if a config
+    /// was already used.
+    pub fn init(self) -> bool {
+        if let Some(base) = &self.base {
+            println!("Loading assets from {base}");
+        }
+        RESOURCE_CONFIG.set(self).is_ok()
+    }
+
+    pub fn get() -> &'static ResourceConfig {
+        RESOURCE_CONFIG.get_or_init(ResourceConfig::default)
+    }
+
+    /// The path `file_name` is loaded from.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn path(&self, file_name: &str) -> std::io::Result<std::path::PathBuf> {
+        let base = match &self.base {
+            // a relative base is relative to the current directory too
+            Some(base) => env::current_dir()?.join(base),
+            None => env::current_dir()?,
+        };
+        Ok(base.join(file_name))
+    }
+
+    /// The URL `file_name` is loaded from.
+    #[cfg(target_arch = "wasm32")]
+    pub fn url(&self, file_name: &str) -> anyhow::Result<reqwest::Url> {
+        let page = web_sys::window()
+            .ok_or_else(|| anyhow::anyhow!("no window"))?
+            .location()
+            .href()
+            .map_err(|e| anyhow::anyhow!("no page URL: {e:?}"))?;
+        let mut base = reqwest::Url::parse(&page)?;
+        if let Some(root) = &self.base {
+            // a relative root is relative to the page, a directory needs the trailing slash to be joined
+            let root = if root.ends_with('/') {
+                root.clone()
+            } else {
+                format!("{root}/")
+            };
+            base = base.join(&root)?;
+        }
+        Ok(base.join(file_name)?)
     }
-    let base = reqwest::Url::parse(&format!("{}/", origin,)).unwrap();
-    base.join(file_name).unwrap()
 }
 
 pub async fn load_string(file_name: &str) -> anyhow::Result<String> {
     cfg_if! {
         if #[cfg(target_arch = "wasm32")] {
-            let url = format_url(file_name);
+            let url = ResourceConfig::get().url(file_name)?;
             let txt = reqwest::get(url)
                 .await?
                 .text()
                 .await?;
         } else {
-            let dir = env::current_dir()?;
-            let path = std::path::Path::new(&dir)
-                .join(file_name);
+            let path = ResourceConfig::get().path(file_name)?;
             let txt = std::fs::read_to_string(path)?;
         }
     }
@@ -41,16 +102,14 @@ pub async fn load_string(file_name: &str) -> anyhow::Result<String> {
 pub async fn load_binary(file_name: &str) -> anyhow::Result<Vec<u8>> {
     cfg_if! {
         if #[cfg(target_arch = "wasm32")] {
-            let url = format_url(file_name);
+            let url = ResourceConfig::get().url(file_name)?;
             let data = reqwest::get(url)
                 .await?
                 .bytes()
                 .await?
                 .to_vec();
         } else {
-            let dir = env::current_dir()?;
-            let path = std::path::Path::new(&dir)
-                .join(file_name);
+            let path = ResourceConfig::get().path(file_name)?;
             let data = std::fs::read(path)?;
         }
     }
@@ -59,16 +118,203 @@ pub async fn load_binary(file_name: &str) -> anyhow::Result<Vec<u8>> {
 }
 
 
+/// Textures and models loaded from files, kept per device so loading the same file again shares what was
+/// loaded before instead of uploading a copy, e.g. a texture used by several materials or a model placed
+/// by several nodes. Textures are found by the hash of their file, so copies of an image under other
+/// paths are shared too, models by their path. Evicting an entry only drops the handle of the cache, the
+/// GPU memory is freed once nothing else uses it.
+#[derive(Default)]
+pub struct ResourceCache {
+    /// Hash of the file at each texture path loaded so far.
+    texture_paths: HashMap<String, u64>,
+    /// Textures by the hash of their file and whether they are sRGB.
+    textures: HashMap<(u64, bool), texture::Texture>,
+    /// Models by their path and displacement segments.
+    models: HashMap<(String, u32), Arc<Model>>,
+}
+
+impl ResourceCache {
+    /// Forgets the texture or model loaded from `path`, the next load reads the file again.
+    pub fn evict(&mut self, path: &str) {
+        if let Some(hash) = self.texture_paths.remove(path) {
+            // other paths with the same content keep it
+            if !self.texture_paths.values().any(|other| *other == hash) {
+                self.textures
+                    .retain(|(texture_hash, _), _| *texture_hash != hash);
+            }
+        }
+        self.models.retain(|(model_path, _), _| model_path != path);
+    }
+
+    /// Forgets the models that were not loaded from one of the `used` paths and no caller holds anymore,
+    /// e.g. after their nodes were removed. The render nodes copy the meshes of a model, only the cache
+    /// keeps it alive.
+    pub fn evict_unused_models<'a>(&mut self, used: impl IntoIterator<Item = &'a str>) {
+        let used: Vec<_> = used.into_iter().collect();
+        self.models.retain(|(path, _), model| {
+            used.contains(&path.as_str()) || Arc::strong_count(model) > 1
+        });
+    }
+
+    /// Forgets everything, e.g. when the device is lost and what it holds can't be used anymore.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+thread_local! {
+    static RESOURCE_CACHES: RefCell<HashMap<wgpu::Device, ResourceCache>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Calls `f` with the resource cache of `device`, e.g. to evict entries.
+pub fn with_resource_cache<R>(device: &wgpu::Device, f: impl FnOnce(&mut ResourceCache) -> R) -> R {
+    RESOURCE_CACHES.with_borrow_mut(|caches| f(caches.entry(device.clone()).or_default()))
+}
+
+/// Loads the texture at `file_name`, or shares it if it was loaded before, see [`ResourceCache`]. Radiance
+/// `.hdr` and OpenEXR `.exr` images keep their full range, see [`texture::Texture::from_hdr_image`].
 pub async fn load_texture(
-    file_name: Option<&str>,
+    file_name: &str,
     device: &wgpu::Device,
     queue: &wgpu::Queue,
-) -> anyhow::Result<texture::Texture> {
-    if file_name.is_none() {
-        return Err(anyhow::anyhow!("No file name provided"));
+    srgb: bool,
+) -> Result<texture::Texture, RendererError> {
+    let cached = with_resource_cache(device, |cache| {
+        let hash = cache.texture_paths.get(file_name)?;
+        cache.textures.get(&(*hash, srgb)).cloned()
+    });
+    if let Some(texture) = cached {
+        return Ok(texture);
     }
-    let file_name = file_name.as_ref().unwrap();
-    let data = load_binary(file_name).await?;
-    texture::Texture::from_bytes(device, queue, &data, file_name)
+    let data = load_binary(file_name)
+        .await
+        .map_err(|e| RendererError::asset(file_name, e))?;
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    let hash = hasher.finish();
+    with_resource_cache(device, |cache| {
+        cache.texture_paths.insert(file_name.to_string(), hash);
+        if let Some(texture) = cache.textures.get(&(hash, srgb)) {
+            return Ok(texture.clone());
+        }
+        let texture = if is_hdr_file(file_name) {
+            let img =
+                image::load_from_memory(&data).map_err(|e| RendererError::asset(file_name, e))?;
+            texture::Texture::from_hdr_image(device, queue, &img, Some(file_name), false)?
+        } else {
+            texture::Texture::from_bytes(device, queue, &data, file_name, srgb)?
+        };
+        cache.textures.insert((hash, srgb), texture.clone());
+        Ok(texture)
+    })
+}
+
+fn is_hdr_file(file_name: &str) -> bool {
+    std::path::Path::new(file_name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("hdr") || extension.eq_ignore_ascii_case("exr")
+        })
 }
 
+/// Loads the model `file_name` in the directory `file_path` like [`load_model`], or shares it if it was
+/// loaded with the same displacement segments before, see [`ResourceCache`].
+pub async fn load_cached_model(
+    file_path: &str,
+    file_name: &str,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    displacement_segments: u32,
+) -> Result<Arc<Model>, RendererError> {
+    let key = (
+        std::path::Path::new(file_path)
+            .join(file_name)
+            .to_string_lossy()
+            .into_owned(),
+        displacement_segments,
+    );
+    if let Some(model) = with_resource_cache(device, |cache| cache.models.get(&key).cloned()) {
+        return Ok(model);
+    }
+    let model =
+        Arc::new(load_model(file_path, file_name, device, queue, displacement_segments).await?);
+    with_resource_cache(device, |cache| cache.models.insert(key, model.clone()));
+    Ok(model)
+}
+
+pub async fn load_settings(file_name: &str) -> anyhow::Result<RenderSettings> {
+    RenderSettings::from_toml(&load_string(file_name).await?)
+}
+
+pub async fn load_scene(file_name: &str) -> anyhow::Result<SceneDescription> {
+    SceneDescription::from_json(&load_string(file_name).await?)
+}
+
+/// Face size of cube maps resampled from equirectangular panoramas.
+const SKYBOX_FACE_SIZE: u32 = 1024;
+/// Extensions of the face images of cube maps, in the order they are looked for.
+const FACE_EXTENSIONS: [&str; 3] = ["png", "hdr", "exr"];
+
+/// The decoded images of a cube map, see [`load_cube_map_images`].
+pub enum CubeMapImages {
+    Equirectangular(image::DynamicImage),
+    Faces(Box<[image::DynamicImage; 6]>),
+}
+
+impl CubeMapImages {
+    pub fn upload(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        label: &str,
+    ) -> anyhow::Result<texture::Texture> {
+        match self {
+            Self::Equirectangular(img) => texture::Texture::cube_from_equirectangular(
+                device,
+                queue,
+                img,
+                SKYBOX_FACE_SIZE,
+                label,
+            ),
+            Self::Faces(faces) => texture::Texture::cube_from_faces(device, queue, faces, label),
+        }
+    }
+}
+
+/// Loads an environment cube map, either from an equirectangular image at `path` (e.g. `sky.hdr`) or,
+/// without an extension, from the faces `px`, `nx`, `py`, `ny`, `pz` and `nz` in the directory `path`,
+/// PNG images or HDR ones in `.hdr` or `.exr` files.
+#[allow(dead_code)]
+pub async fn load_cube_map(
+    path: &str,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> anyhow::Result<texture::Texture> {
+    load_cube_map_images(path)
+        .await?
+        .upload(device, queue, path)
+}
+
+/// Reads and decodes the images of the cube map at `path` like [`load_cube_map`], without a device.
+pub async fn load_cube_map_images(path: &str) -> anyhow::Result<CubeMapImages> {
+    if std::path::Path::new(path).extension().is_some() {
+        let img = image::load_from_memory(&load_binary(path).await?)?;
+        return Ok(CubeMapImages::Equirectangular(img));
+    }
+    let mut faces = Vec::with_capacity(6);
+    for face in ["px", "nx", "py", "ny", "pz", "nz"] {
+        let mut data = None;
+        for extension in FACE_EXTENSIONS {
+            if let Ok(face_data) = load_binary(&format!("{path}/{face}.{extension}")).await {
+                data = Some(face_data);
+                break;
+            }
+        }
+        let data = data.ok_or_else(|| anyhow::anyhow!("No cube map face {face} in {path}"))?;
+        faces.push(image::load_from_memory(&data)?);
+    }
+    let faces: [image::DynamicImage; 6] = faces.try_into().unwrap();
+    Ok(CubeMapImages::Faces(Box::new(faces)))
+}
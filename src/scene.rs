@@ -0,0 +1,397 @@
+/*
+ * Scene files.
+ * `--scene <file>` builds the scene graph from a JSON description instead of the built-in demo scene.
+ * Nodes form a hierarchy and each has a transform; a model node becomes a group holding the meshes of an
+ * OBJ file, a light node a spot or point light. Any node can follow a spline path through control points,
+ * light nodes can also be animated with the tracks of light_animation.rs. Constraints of constraint.rs
+ * place a node relative to another node or the camera.
+ * The ambient light lights the whole scene with a sky and a ground color, the sun names the light the
+ * time of day presets move. Custom materials (see custom_material.rs) are WGSL files with a fragment entry,
+ * a node with a `material` draws its meshes and those of its children with one. A group or model node
+ * with an `override` forces `"clay"` or a custom material onto everything below it without replacing the
+ * materials of the nodes, see [`MaterialOverride`]. `SceneGraph::save` writes the same format, without
+ * the materials and overrides:
+ *
+ *     {
+ *         "camera": { "eye": [0.0, 1.0, 30.0], "target": [0.0, 0.0, 0.0] },
+ *         "ambient": { "sky": [0.6, 0.7, 1.0], "ground": [0.4, 0.3, 0.2], "strength": 0.3 },
+ *         "sun": { "light": "lamp" },
+ *         "materials": [{ "name": "lava", "path": "assets/lava.wgsl", "entry": "fs_lava" }],
+ *         "nodes": [
+ *             { "name": "house", "type": "model", "path": "assets/All_Files/Example/OBJ/Example.obj" },
+ *             { "name": "pool", "type": "model", "path": "assets/pool.obj", "material": "lava" },
+ *             { "name": "lamp", "type": "light", "kind": "point", "color": [0.4, 0.3, 0.2],
+ *               "position": [10.0, 8.0, -5.0], "intensity": 64.0, "range": 40.0, "shadow_resolution": 256,
+ *               "animation": { "flicker": { "amount": 0.3, "speed": 8.0 } } },
+ *             { "name": "props", "type": "group", "transform": { "translation": [5.0, 0.0, 0.0] },
+ *               "override": "clay", "children": [] },
+ *             { "name": "drone", "type": "model", "path": "assets/drone.obj",
+ *               "spline": { "points": [[0.0, 5.0, 0.0], [10.0, 6.0, 0.0], [10.0, 5.0, 10.0]], "closed": true,
+ *                           "speed": 4.0, "orient": true } }
+ *         ]
+ *     }
+ */
+use crate::constraint::{Constraint, NodeConstraint};
+use crate::custom_material::CustomMaterials;
+use crate::error::RendererError;
+use crate::light::{Ambient, Light, LightKind};
+use crate::light_animation::{LightAnimation, LightTracks};
+use crate::resources::{load_cached_model, load_string};
+use crate::scenegraph::{GroupNode, MaterialOverride, SceneGraph};
+use crate::spline::{PathAnimation, SplinePath};
+use crate::time_of_day::SunNodes;
+use glam::{DMat4, DVec3, Mat4, Quat, Vec3};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneDescription {
+    #[serde(default)]
+    pub camera: Option<CameraDescription>,
+    /// Hemisphere ambient light, the flat default of [`Ambient`] if the scene has none.
+    #[serde(default)]
+    pub ambient: Option<Ambient>,
+    /// The light moved by the time of day presets, see [`SunNodes`].
+    #[serde(default)]
+    pub sun: Option<SunNodes>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub materials: Vec<MaterialDescription>,
+    #[serde(default)]
+    pub nodes: Vec<NodeDescription>,
+}
+
+/// A custom material shader, see custom_material.rs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MaterialDescription {
+    pub name: String,
+    /// A WGSL file, relative to the working directory (or the page on the web).
+    pub path: String,
+    /// The fragment entry point in the file.
+    pub entry: String,
+}
+
+/// Start view of the camera.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraDescription {
+    pub eye: Vec3,
+    pub target: Vec3,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeDescription {
+    pub name: String,
+    #[serde(default)]
+    pub transform: Transform,
+    /// Moves the node along a path, which replaces the translation and rotation of the transform.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spline: Option<SplineDescription>,
+    /// Sets the transform from a target each frame, see [`Constraint`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub constraint: Option<Constraint>,
+    /// Name of a material of [`SceneDescription::materials`] the meshes of the node and its children are
+    /// drawn with instead of their own materials.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub material: Option<String>,
+    /// `"clay"` or the name of a material of [`SceneDescription::materials`] forced onto everything below a
+    /// group or model node, see [`MaterialOverride`].
+    #[serde(default, rename = "override", skip_serializing_if = "Option::is_none")]
+    pub material_override: Option<String>,
+    #[serde(flatten)]
+    pub content: NodeContent,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum NodeContent {
+    Group {
+        #[serde(default)]
+        children: Vec<NodeDescription>,
+    },
+    /// An OBJ file, relative to the working directory (or the page on the web).
+    Model { path: String },
+    Light {
+        kind: LightKind,
+        /// Linear RGB.
+        color: [f64; 3],
+        position: Vec3,
+        /// See [`Light::intensity`].
+        #[serde(default = "default_intensity")]
+        intensity: f32,
+        /// See [`Light::range`], 0 without distance falloff.
+        #[serde(default)]
+        range: f32,
+        /// See [`Light::cast_shadows`].
+        #[serde(default = "default_cast_shadows")]
+        cast_shadows: bool,
+        /// See [`Light::shadow_resolution`], the whole shadow map without.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        shadow_resolution: Option<u32>,
+        /// Animates the light, see [`LightAnimation`].
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        animation: Option<LightTracks>,
+    },
+}
+
+fn default_intensity() -> f32 {
+    1.0
+}
+
+fn default_cast_shadows() -> bool {
+    true
+}
+
+/// Spline path through control points in the space of the parent of the node, see [`PathAnimation`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SplineDescription {
+    pub points: Vec<Vec3>,
+    /// Runs back from the last point to the first.
+    #[serde(default)]
+    pub closed: bool,
+    /// World units per second.
+    pub speed: f32,
+    /// Turns the -Z axis of the node along the path.
+    #[serde(default)]
+    pub orient: bool,
+}
+
+/// Translation, rotation (a quaternion as `[x, y, z, w]`) and scale of a node relative to its parent.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Transform {
+    /// In f64 for scenes with huge coordinates, see [`SceneGraph::update_render_origin`].
+    pub translation: DVec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: DVec3::ZERO,
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE,
+        }
+    }
+}
+
+impl Transform {
+    pub fn from_matrix(matrix: DMat4) -> Self {
+        let (scale, rotation, translation) = matrix.to_scale_rotation_translation();
+        Self {
+            translation,
+            rotation: rotation.as_quat(),
+            scale: scale.as_vec3(),
+        }
+    }
+
+    pub fn matrix(&self) -> DMat4 {
+        DMat4::from_scale_rotation_translation(
+            self.scale.as_dvec3(),
+            self.rotation.as_dquat(),
+            self.translation,
+        )
+    }
+}
+
+impl SceneDescription {
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Adds the nodes to `scene_graph`. Models that fail to load are left out with a message, the rest of
+    /// the scene is built.
+    pub async fn build(
+        &self,
+        scene_graph: &mut SceneGraph,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        material_bind_group_layout: &wgpu::BindGroupLayout,
+        displacement_segments: u32,
+    ) {
+        // parents are added before their children
+        let mut pending = self
+            .nodes
+            .iter()
+            .rev()
+            .map(|node| (None, node))
+            .collect::<Vec<(Option<&str>, _)>>();
+        while let Some((parent, node)) = pending.pop() {
+            let name = node.name.clone();
+            let matrix = node.transform.matrix();
+            if let Some(spline) = &node.spline {
+                match SplinePath::new(spline.points.clone(), spline.closed) {
+                    Some(path) => scene_graph.add_path(PathAnimation::new(
+                        &name,
+                        path,
+                        spline.speed,
+                        spline.orient,
+                    )),
+                    None => println!("Ignoring the path of {name}, it needs at least two points"),
+                }
+            }
+            if let Some(constraint) = &node.constraint {
+                scene_graph.add_constraint(NodeConstraint::new(&name, constraint.clone()));
+            }
+            match &node.content {
+                NodeContent::Group { children } => {
+                    let mut group = GroupNode::new(name);
+                    group.set_matrix_f64(matrix);
+                    scene_graph.insert_group_node(parent, group);
+                    pending.extend(
+                        children
+                            .iter()
+                            .rev()
+                            .map(|child| (Some(&*node.name), child)),
+                    );
+                }
+                NodeContent::Model { path } => {
+                    let file = Path::new(path);
+                    let directory = file.parent().and_then(Path::to_str).unwrap_or_default();
+                    let file_name = file.file_name().and_then(|name| name.to_str());
+                    let model = match file_name {
+                        Some(file_name) => {
+                            load_cached_model(
+                                directory,
+                                file_name,
+                                device,
+                                queue,
+                                displacement_segments,
+                            )
+                            .await
+                        }
+                        None => Err(RendererError::asset(path, "not a file")),
+                    };
+                    match model {
+                        Ok(model) => {
+                            // the meshes share the transform of their group
+                            let mut group = GroupNode::new(name.clone());
+                            group.set_matrix_f64(matrix);
+                            group.source = Some(path.clone());
+                            group.source_files = model.files.clone();
+                            scene_graph.insert_group_node(parent, group);
+                            scene_graph.add_model_node(
+                                Some(&node.name),
+                                name,
+                                device,
+                                &model,
+                                material_bind_group_layout,
+                                Mat4::IDENTITY,
+                            );
+                        }
+                        Err(e) => {
+                            println!("Skipping {name}, failed to load {path}: {e}")
+                        }
+                    }
+                }
+                NodeContent::Light {
+                    kind,
+                    color,
+                    position,
+                    intensity,
+                    range,
+                    cast_shadows,
+                    shadow_resolution,
+                    animation,
+                } => {
+                    let [r, g, b] = *color;
+                    let color = wgpu::Color { r, g, b, a: 1.0 };
+                    let mut light = match kind {
+                        LightKind::Spot => Light::new(*position, color),
+                        LightKind::Point => Light::point(*position, color),
+                    };
+                    light.intensity = *intensity;
+                    light.range = *range;
+                    light.cast_shadows = *cast_shadows;
+                    light.shadow_resolution = *shadow_resolution;
+                    scene_graph.add_light_node(parent, name.clone(), light);
+                    if let Some(tracks) = animation {
+                        scene_graph.add_light_animation(LightAnimation::new(
+                            &name,
+                            tracks.clone(),
+                            *intensity,
+                        ));
+                    }
+                    if let Some(light_node) = scene_graph.find_child_mut(Some(&node.name)) {
+                        light_node.set_matrix_f64(matrix);
+                    }
+                }
+            }
+        }
+        if let Some(ambient) = self.ambient {
+            scene_graph.set_hemisphere_ambient(ambient.sky, ambient.ground, ambient.strength);
+        }
+        scene_graph.sun = self.sun.clone();
+    }
+
+    /// Adds the materials to `custom_materials` and assigns them and the overrides to the nodes
+    /// [`SceneDescription::build`] added. Materials that fail to load or compile are left out with a message.
+    pub async fn build_materials(
+        &self,
+        scene_graph: &mut SceneGraph,
+        custom_materials: &mut CustomMaterials,
+        device: &wgpu::Device,
+    ) {
+        let mut indices = HashMap::new();
+        for material in &self.materials {
+            let source = match load_string(&material.path).await {
+                Ok(source) => source,
+                Err(e) => {
+                    println!(
+                        "Skipping material {}, failed to load {}: {e}",
+                        material.name, material.path
+                    );
+                    continue;
+                }
+            };
+            match custom_materials.add_scene_material(
+                device,
+                &material.name,
+                &source,
+                &material.entry,
+            ) {
+                Ok(index) => {
+                    indices.insert(&*material.name, index);
+                }
+                Err(e) => println!("Skipping material {}: {e}", material.name),
+            }
+        }
+        // parents before their children, so a child's material replaces the one of its parent
+        let mut pending = self.nodes.iter().collect::<Vec<_>>();
+        while let Some(node) = pending.pop() {
+            if let Some(material) = &node.material {
+                match indices.get(&**material) {
+                    Some(&index) => {
+                        scene_graph.set_custom_material(&node.name, Some(index));
+                    }
+                    None => println!("{} has no material {material}", node.name),
+                }
+            }
+            if let Some(material) = &node.material_override {
+                let material_override = match &**material {
+                    "clay" => Some(MaterialOverride::Clay),
+                    name => indices.get(name).map(|&index| MaterialOverride::Custom(index)),
+                };
+                match material_override {
+                    Some(material_override) => {
+                        if !scene_graph.set_material_override(&node.name, Some(material_override)) {
+                            println!("Ignoring the override of {}, it is not a group", node.name);
+                        }
+                    }
+                    None => println!("{} has no material {material} to override with", node.name),
+                }
+            }
+            if let NodeContent::Group { children } = &node.content {
+                pending.extend(children);
+            }
+        }
+    }
+}
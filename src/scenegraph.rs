@@ -1,26 +1,63 @@
-use crate::light::{Light, LightUniform, ShadowMap};
+use crate::accessibility::AccessibilitySettings;
+use crate::camera::Camera;
+use crate::constraint::{camera_matrix, Constraint, ConstraintTarget, NodeConstraint};
+use crate::culling::{Aabb, Frustum};
+use crate::custom_material::CustomMaterials;
+use crate::error::validate;
+use crate::labels;
+use crate::light::{
+    Ambient, AmbientUniform, Fog, FogUniform, Light, LightKind, LightUniform, ShadowMap,
+};
+use crate::light_animation::LightAnimation;
 use crate::model;
-use crate::model::Vertex;
+use crate::model::{MaterialFeatures, MaterialInfo, Shading, Tangent, Vertex};
+use crate::reflection::ReflectDevice;
+use crate::renderer::{PipelineKey, PipelineVariants};
+use crate::scene::{
+    CameraDescription, NodeContent, NodeDescription, SceneDescription, SplineDescription, Transform,
+};
+use crate::spline::PathAnimation;
+use crate::time_of_day::SunNodes;
 use bytemuck::{Pod, Zeroable};
-use glam::{Mat4, Vec3};
+use glam::{DMat4, Mat3, Mat4, Vec3};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
 use wgpu::util::{DeviceExt};
 use wgpu::{BindGroup, BindGroupLayout, Buffer, Queue, RenderPass};
 
+/// Edge length of the cells the render origin snaps to, see [`SceneGraph::update_render_origin`]. Large
+/// enough that it rarely moves, small enough that positions within a cell keep their precision in f32.
+const RENDER_ORIGIN_CELL: f32 = 1024.0;
+
+/// Identifies a node for as long as it exists, unlike its name, which several nodes may share.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(u64);
+
+static NEXT_NODE_ID: AtomicU64 = AtomicU64::new(0);
+
 #[derive(Debug)]
 pub struct NodeData {
+    id: NodeId,
     name: String,
-    matrix: Mat4,
+    /// Kept in f64 so transforms with huge translations, e.g. of georeferenced or CAD data, compose
+    /// without losing precision. Converted to f32 relative to the render origin for the GPU.
+    matrix: DMat4,
 }
 
 impl NodeData {
     pub fn new(name: String) -> Self {
         Self {
+            id: NodeId(NEXT_NODE_ID.fetch_add(1, Ordering::Relaxed)),
             name,
-            matrix: Mat4::IDENTITY,
+            matrix: DMat4::IDENTITY,
         }
     }
 
     pub fn set_matrix(&mut self, matrix: Mat4) {
+        self.matrix = matrix.as_dmat4();
+    }
+
+    pub fn set_matrix_f64(&mut self, matrix: DMat4) {
         self.matrix = matrix;
     }
 }
@@ -29,6 +66,12 @@ impl NodeData {
 pub struct GroupNode {
     node: NodeData,
     pub children: Vec<Node>,
+    /// The model file the children were loaded from, saved scenes refer to it instead of the meshes.
+    pub source: Option<String>,
+    /// The files the model at `source` was loaded from, see [`model::Model::files`].
+    pub source_files: Vec<String>,
+    /// Material all render nodes below the group are drawn with, see [`SceneGraph::set_material_override`].
+    pub material_override: Option<MaterialOverride>,
 }
 
 impl GroupNode {
@@ -36,6 +79,9 @@ impl GroupNode {
         Self {
             node: NodeData::new(name),
             children: Vec::new(),
+            source: None,
+            source_files: Vec::new(),
+            material_override: None,
         }
     }
 
@@ -43,25 +89,250 @@ impl GroupNode {
         self.node.set_matrix(matrix);
     }
 
+    pub fn set_matrix_f64(&mut self, matrix: DMat4) {
+        self.node.set_matrix_f64(matrix);
+    }
+
     pub fn add_child(&mut self, child: Node) {
         self.children.push(child);
     }
 }
 
+/// A material a group forces onto the render nodes below it instead of their own materials, e.g. to
+/// highlight a selection or to look at the shapes of a model without its textures. The closest group
+/// with an override decides. The nodes keep their shading and material features.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaterialOverride {
+    /// Plain light gray, like an untextured clay model.
+    Clay,
+    /// The [`crate::custom_material::CustomMaterial`] at this index.
+    Custom(usize),
+}
+
+/// Inverse transpose of the upper 3x3 of `model`, which keeps normals perpendicular under non-uniform
+/// scale. A zero scale, e.g. to hide a node in an animation, has no inverse; the identity keeps the
+/// normals finite instead of shading the mesh with NaN.
+fn normal_matrix(model: Mat4) -> Mat3 {
+    let linear = Mat3::from_mat4(model);
+    if linear.determinant().abs() > f32::EPSILON {
+        linear.inverse().transpose()
+    } else {
+        Mat3::IDENTITY
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Pod, Zeroable)]
 pub struct ModelUniform {
-    view_proj: [[f32; 4]; 4],
+    model: [[f32; 4]; 4],
+    // mat3x3 columns are padded to 16 bytes in WGSL uniforms
+    normal: [[f32; 4]; 3],
+}
+
+impl ModelUniform {
+    pub fn new(model: Mat4) -> Self {
+        let normal = normal_matrix(model);
+        Self {
+            model: model.to_cols_array_2d(),
+            normal: [
+                normal.x_axis.extend(0.0).to_array(),
+                normal.y_axis.extend(0.0).to_array(),
+                normal.z_axis.extend(0.0).to_array(),
+            ],
+        }
+    }
+}
+
+/// Per-instance transform, read from a vertex buffer with instance step mode. It is applied in the
+/// node's local space, before the node's model matrix.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct InstanceRaw {
+    model: [[f32; 4]; 4],
+    normal: [[f32; 3]; 3],
+}
+
+impl InstanceRaw {
+    pub fn new(model: Mat4) -> Self {
+        Self {
+            model: model.to_cols_array_2d(),
+            normal: normal_matrix(model).to_cols_array_2d(),
+        }
+    }
+
+    /// Uses the shader locations 3 to 9, after the ones of [`Vertex`].
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 7] = wgpu::vertex_attr_array![
+            3 => Float32x4,
+            4 => Float32x4,
+            5 => Float32x4,
+            6 => Float32x4,
+            7 => Float32x3,
+            8 => Float32x3,
+            9 => Float32x3,
+        ];
+        wgpu::VertexBufferLayout {
+            array_stride: size_of::<InstanceRaw>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &ATTRIBUTES,
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, label: &str, transforms: &[Mat4]) -> Buffer {
+        let instances = transforms
+            .iter()
+            .map(|transform| Self::new(*transform))
+            .collect::<Vec<_>>();
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: bytemuck::cast_slice(&instances),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        })
+    }
+}
+
+/// Per-node model matrices in a single uniform buffer. Every render node owns one slot,
+/// which is selected with a dynamic offset when the node is drawn.
+#[derive(Debug)]
+pub struct ModelMatrices {
+    pub bind_group_layout: BindGroupLayout,
+    pub bind_group: BindGroup,
+    buffer: Buffer,
+    stride: u32,
+    capacity: u32,
+    next_slot: u32,
+    free_slots: Vec<u32>,
+    reallocated: bool,
+}
+
+impl ModelMatrices {
+    const INITIAL_CAPACITY: u32 = 64;
+
+    pub fn new(device: &wgpu::Device) -> Self {
+        let bind_group_layout = device.reflect_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(size_of::<ModelUniform>() as u64),
+                },
+                count: None,
+            }],
+            label: Some("model_matrix_bind_group_layout"),
+        });
+        let alignment = device.limits().min_uniform_buffer_offset_alignment;
+        let stride = (size_of::<ModelUniform>() as u32).div_ceil(alignment) * alignment;
+        let (buffer, bind_group) =
+            Self::create_buffer(device, &bind_group_layout, stride, Self::INITIAL_CAPACITY);
+
+        Self {
+            bind_group_layout,
+            bind_group,
+            buffer,
+            stride,
+            capacity: Self::INITIAL_CAPACITY,
+            next_slot: 0,
+            free_slots: Vec::new(),
+            reallocated: false,
+        }
+    }
+
+    fn create_buffer(
+        device: &wgpu::Device,
+        layout: &BindGroupLayout,
+        stride: u32,
+        capacity: u32,
+    ) -> (Buffer, BindGroup) {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Model Matrix Buffer"),
+            size: (stride * capacity) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(size_of::<ModelUniform>() as u64),
+                }),
+            }],
+            label: Some("model_matrix_bind_group"),
+        });
+        (buffer, bind_group)
+    }
+
+    /// Reserves a slot, doubling the buffer when it is full. A grown buffer starts out empty,
+    /// so all matrices are uploaded again on the next update.
+    fn allocate(&mut self, device: &wgpu::Device) -> u32 {
+        if let Some(slot) = self.free_slots.pop() {
+            return slot;
+        }
+        if self.next_slot == self.capacity {
+            self.capacity *= 2;
+            (self.buffer, self.bind_group) =
+                Self::create_buffer(device, &self.bind_group_layout, self.stride, self.capacity);
+            self.reallocated = true;
+        }
+        let slot = self.next_slot;
+        self.next_slot += 1;
+        slot
+    }
+
+    /// Returns the slot of a removed node for reuse.
+    fn release(&mut self, slot: u32) {
+        self.free_slots.push(slot);
+    }
+
+    pub fn offset(&self, slot: u32) -> u32 {
+        slot * self.stride
+    }
+
+    fn write(&self, queue: &Queue, slot: u32, matrix: Mat4) {
+        queue.write_buffer(
+            &self.buffer,
+            self.offset(slot) as wgpu::BufferAddress,
+            bytemuck::cast_slice(&[ModelUniform::new(matrix)]),
+        );
+    }
 }
 
 #[derive(Debug)]
 pub struct RenderNode {
     node: NodeData,
     pub vertex_buffer: wgpu::Buffer,
+    pub tangent_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
     pub num_elements: u32,
     pub material_bind_group: Option<BindGroup>,
+    /// Depth bias of the node's material, selects the pipeline variant it is drawn with.
+    pub depth_bias: wgpu::DepthBiasState,
+    /// Shading of the node's material, selects the pipeline variant it is drawn with.
+    pub shading: Shading,
+    /// Drawn in the glass pass with refraction, see [`model::Material::is_glass`].
+    pub glass: bool,
+    /// Blended over the opaque scene after it without writing depth, see
+    /// [`model::Material::is_transparent`].
+    pub transparent: bool,
+    /// Shader features of the node's material, select the pipeline variant it is drawn with.
+    pub features: MaterialFeatures,
+    /// Index of the [`crate::custom_material::CustomMaterial`] the node is drawn with instead of its material.
+    pub custom_material: Option<usize>,
+    /// The parts of the node's material an export needs, the material itself only lives on the GPU.
+    pub material_info: MaterialInfo,
+    // per-instance transforms, None draws a single instance with the identity transform
+    instances: Option<(Buffer, Vec<Mat4>)>,
     vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+    // local space bounds of the mesh, including all instances
+    bounds: Aabb,
+    model_slot: u32,
+    // world matrix relative to the render origin currently stored in the node's model matrix slot
+    uploaded_matrix: Option<Mat4>,
 }
 
 #[derive(Debug)]
@@ -77,26 +348,48 @@ impl RenderNode {
         vertices: &[Vertex],
         indices: &[u32],
         material_bind_group: Option<wgpu::BindGroup>,
+        model_slot: u32,
     ) -> Self {
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some(&format!("{} Vertex Buffer", name)),
+            label: Some(&labels::node(&name, "vertices")),
             contents: bytemuck::cast_slice(vertices),
             usage: wgpu::BufferUsages::VERTEX,
         });
 
+        let tangent_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&labels::node(&name, "tangents")),
+            contents: bytemuck::cast_slice(&Tangent::compute(vertices, indices)),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
         let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some(&format!("{} Index Buffer", name)),
+            label: Some(&labels::node(&name, "indices")),
             contents: bytemuck::cast_slice(indices),
             usage: wgpu::BufferUsages::INDEX,
         });
 
+        let bounds = Aabb::from_points(vertices.iter().map(|vertex| Vec3::from(vertex.pos)));
+
         Self {
             node: NodeData::new(name),
             vertex_buffer,
+            tangent_buffer,
             index_buffer,
             num_elements: indices.len() as u32,
             material_bind_group,
+            depth_bias: Default::default(),
+            shading: Default::default(),
+            glass: false,
+            transparent: false,
+            features: Default::default(),
+            material_info: MaterialInfo::default(),
+            instances: None,
             vertices: vertices.to_vec(),
+            indices: indices.to_vec(),
+            bounds,
+            model_slot,
+            custom_material: None,
+            uploaded_matrix: None,
         }
     }
 
@@ -106,36 +399,59 @@ impl RenderNode {
         vertices: &[Vertex],
         indices: &[u32],
         material_bind_group: Option<wgpu::BindGroup>,
+        model_slot: u32,
         matrix: Mat4,
     ) -> Self {
-        let mut render_node = Self::new(name, device, vertices, indices, material_bind_group);
-        render_node.set_matrix(matrix, device);
+        let mut render_node =
+            Self::new(name, device, vertices, indices, material_bind_group, model_slot);
+        render_node.set_matrix(matrix);
         render_node
     }
 
-    pub fn set_matrix(&mut self, matrix: Mat4, device: &wgpu::Device) {
+    /// Only updates the local transform; the GPU copy is refreshed by [`SceneGraph::sync`].
+    pub fn set_matrix(&mut self, matrix: Mat4) {
         self.node.set_matrix(matrix);
-        let transformed_vertices: Vec<Vertex> = self
-            .vertices
-            .iter()
-            .map(|vertex| {
-                let pos =
-                    matrix.transform_point3(Vec3::new(vertex.pos[0], vertex.pos[1], vertex.pos[2]));
-                Vertex {
-                    pos: [pos.x, pos.y, pos.z],
-                    ..*vertex
-                }
-            })
-            .collect();
+    }
 
-        self.vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some(&format!("{} Vertex Buffer", self.node.name)),
-            contents: bytemuck::cast_slice(&transformed_vertices),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
+    pub fn instance_count(&self) -> u32 {
+        self.instances
+            .as_ref()
+            .map_or(1, |(_, transforms)| transforms.len() as u32)
+    }
+
+    pub fn vertices(&self) -> &[Vertex] {
+        &self.vertices
+    }
+
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+
+    /// Transforms of the instances relative to the node, the identity for a node without instances.
+    pub fn instance_transforms(&self) -> &[Mat4] {
+        self.instances
+            .as_ref()
+            .map_or(&[Mat4::IDENTITY], |(_, transforms)| transforms)
+    }
+
+    pub fn pipeline_key(&self) -> PipelineKey {
+        PipelineKey {
+            depth_bias: self.depth_bias,
+            shading: self.shading,
+            transparent: self.transparent,
+            features: self.features,
+            shadowless: false,
+        }
+    }
+
+    /// Center of the mesh in world space, used as the sort key for depth sorting.
+    pub fn world_center(&self, world_matrix: Mat4) -> Vec3 {
+        world_matrix.transform_point3(self.bounds.center())
     }
 }
 
+// scenes hold few group and light nodes, so padding them to the size of a render node is cheap
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 pub enum Node {
     GroupNode(GroupNode),
@@ -143,29 +459,416 @@ pub enum Node {
     LightNode(LightNode),
 }
 
+impl Node {
+    pub fn id(&self) -> NodeId {
+        match self {
+            Node::GroupNode(group) => group.node.id,
+            Node::RenderNode(render) => render.node.id,
+            Node::LightNode(light) => light.node.id,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Node::GroupNode(group) => &group.node.name,
+            Node::RenderNode(render) => &render.node.name,
+            Node::LightNode(light) => &light.node.name,
+        }
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        self.matrix_f64().as_mat4()
+    }
+
+    pub fn matrix_f64(&self) -> DMat4 {
+        match self {
+            Node::GroupNode(group) => group.node.matrix,
+            Node::RenderNode(render) => render.node.matrix,
+            Node::LightNode(light) => light.node.matrix,
+        }
+    }
+
+    pub fn set_matrix(&mut self, matrix: Mat4) {
+        match self {
+            Node::GroupNode(group) => group.set_matrix(matrix),
+            Node::RenderNode(render) => render.set_matrix(matrix),
+            Node::LightNode(light) => light.node.set_matrix(matrix),
+        }
+    }
+
+    /// Like [`Node::set_matrix`] for transforms that need double precision.
+    pub fn set_matrix_f64(&mut self, matrix: DMat4) {
+        match self {
+            Node::GroupNode(group) => group.node.set_matrix_f64(matrix),
+            Node::RenderNode(render) => render.node.set_matrix_f64(matrix),
+            Node::LightNode(light) => light.node.set_matrix_f64(matrix),
+        }
+    }
+
+    /// Calls `f` for this node and all of its descendants.
+    pub fn visit(&self, f: &mut impl FnMut(&Node)) {
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            f(node);
+            if let Node::GroupNode(group) = node {
+                stack.extend(&group.children);
+            }
+        }
+    }
+
+    pub fn visit_mut(&mut self, f: &mut impl FnMut(&mut Node)) {
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            f(node);
+            if let Node::GroupNode(group) = node {
+                stack.extend(&mut group.children);
+            }
+        }
+    }
+}
+
+/// What a [`SceneGraph::sync`] uploaded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SceneSync {
+    /// Render nodes whose model matrix was written.
+    pub matrices: usize,
+    /// Whether the lights were written, because one of them changed or moved or the render origin
+    /// moved.
+    pub lights: bool,
+}
+
 pub struct SceneGraph {
     pub root: Node,
+    pub model_matrices: ModelMatrices,
+    // single identity instance, bound for render nodes without instances
+    identity_instance: Buffer,
     pub light_bind_group: Option<BindGroup>,
     pub light_bind_group_layout: Option<BindGroupLayout>,
+    /// Set when [`SceneGraph::sync`] uploaded the lights this frame, until [`SceneGraph::on_frame_update`].
     pub lights_dirty: bool,
+    light_buffer: Option<Buffer>,
+    light_count_buffer: Buffer,
+    /// The light count and uniforms of the last upload, the lights are uploaded again when they differ.
+    uploaded_lights: Option<(u32, Vec<LightUniform>)>,
+    /// Off leaves the shadows out for all lights, see [`SceneGraph::set_shadows_enabled`].
+    shadows_enabled: bool,
+    /// Set when all lights render their shadows at the full shadow map size, see
+    /// [`SceneGraph::set_full_size_shadows`].
+    full_size_shadows: bool,
     pub supports_storage_resources: bool,
     pub shadow_map: ShadowMap,
+    pub point_shadow_map: ShadowMap,
+    ambient: Ambient,
+    ambient_buffer: Buffer,
+    /// Set when the ambient light changed since it was last uploaded.
+    ambient_dirty: bool,
+    fog: Fog,
+    fog_buffer: Buffer,
+    /// Set when the fog changed since it was last uploaded.
+    fog_dirty: bool,
+    /// Nodes moved along spline paths by [`SceneGraph::animate_paths`].
+    pub paths: Vec<PathAnimation>,
+    /// Lights animated by [`SceneGraph::animate_lights`].
+    pub light_animations: Vec<LightAnimation>,
+    /// Nodes placed by [`SceneGraph::apply_constraints`], in the order they are applied.
+    pub constraints: Vec<NodeConstraint>,
+    /// The light the time of day presets move as the sun, see time_of_day.rs.
+    pub sun: Option<SunNodes>,
+    /// Point the GPU gets every position relative to, see [`SceneGraph::update_render_origin`].
+    render_origin: Vec3,
+    /// Material bind group of [`MaterialOverride::Clay`].
+    pub clay_material: Option<BindGroup>,
+    /// Material all render nodes are drawn with, before the overrides of groups, see
+    /// [`crate::camera::RenderMode`].
+    pub material_override: Option<MaterialOverride>,
     on_frame_update_callback: Option<Box<dyn Fn(&SceneGraph)>>,
 }
 
 impl SceneGraph {
-    pub fn new(supports_storage_resources: bool, shadow_map: ShadowMap) -> Self {
+    pub fn new(
+        device: &wgpu::Device,
+        supports_storage_resources: bool,
+        shadow_map: ShadowMap,
+        point_shadow_map: ShadowMap,
+    ) -> Self {
         Self {
             root: Node::GroupNode(GroupNode::new("root".to_string())),
+            model_matrices: ModelMatrices::new(device),
+            identity_instance: InstanceRaw::create_buffer(
+                device,
+                "Identity Instance Buffer",
+                &[Mat4::IDENTITY],
+            ),
             light_bind_group: None,
-            light_bind_group_layout: None,
+            // the layout does not depend on the number of lights, so pipelines stay compatible
+            light_bind_group_layout: Some(LightUniform::get_bind_group_layout(
+                device,
+                supports_storage_resources,
+                shadow_map.mode,
+            )),
             lights_dirty: false,
+            light_buffer: None,
+            light_count_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Light Count Buffer"),
+                size: size_of::<u32>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            uploaded_lights: None,
+            shadows_enabled: true,
+            full_size_shadows: false,
             supports_storage_resources,
             shadow_map,
+            point_shadow_map,
+            ambient: Ambient::default(),
+            ambient_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Ambient Buffer"),
+                contents: bytemuck::bytes_of(&AmbientUniform::from_ambient(&Ambient::default())),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }),
+            ambient_dirty: false,
+            fog: Fog::default(),
+            fog_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Fog Buffer"),
+                contents: bytemuck::bytes_of(&FogUniform::from_fog(&Fog::default())),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }),
+            fog_dirty: false,
+            paths: Vec::new(),
+            light_animations: Vec::new(),
+            constraints: Vec::new(),
+            sun: None,
+            render_origin: Vec3::ZERO,
+            clay_material: None,
+            material_override: None,
             on_frame_update_callback: None,
         }
     }
 
+    /// Lights every surface with `color` (linear RGB) scaled by `strength`, also where no light reaches.
+    /// Uploaded by the next [`SceneGraph::sync`].
+    #[allow(dead_code)]
+    pub fn set_ambient(&mut self, color: [f32; 3], strength: f32) {
+        self.set_hemisphere_ambient(color, color, strength);
+    }
+
+    /// Like [`SceneGraph::set_ambient`], but surfaces facing up get the `sky` color and surfaces facing
+    /// down the `ground` color.
+    pub fn set_hemisphere_ambient(&mut self, sky: [f32; 3], ground: [f32; 3], strength: f32) {
+        self.ambient = Ambient {
+            sky,
+            ground,
+            strength,
+        };
+        self.ambient_dirty = true;
+    }
+
+    pub fn ambient(&self) -> Ambient {
+        self.ambient
+    }
+
+    /// Fades lit surfaces into `fog` with their distance from the camera, uploaded by the next
+    /// [`SceneGraph::sync`].
+    pub fn set_fog(&mut self, fog: Fog) {
+        self.fog = fog;
+        self.fog_dirty = true;
+    }
+
+    pub fn fog(&self) -> Fog {
+        self.fog
+    }
+
+    /// Turns the shadows of all lights on or off, uploaded by the next [`SceneGraph::sync`]. The shadow
+    /// maps are kept, the renderer stops drawing them while the shadows are off.
+    pub fn set_shadows_enabled(&mut self, enabled: bool) {
+        self.shadows_enabled = enabled;
+    }
+
+    pub fn shadows_enabled(&self) -> bool {
+        self.shadows_enabled
+    }
+
+    /// Ignores the [`Light::shadow_resolution`] of the lights, for shadow passes that draw the layers of
+    /// several lights with the same viewport.
+    pub fn set_full_size_shadows(&mut self, full_size: bool) {
+        self.full_size_shadows = full_size;
+    }
+
+    /// Width and height `light` renders its shadow in, within its shadow map layers.
+    pub fn shadow_size(&self, light: &Light) -> u32 {
+        let map_size = self.shadow_map_for(light.kind).size;
+        if self.full_size_shadows {
+            map_size
+        } else {
+            light.shadow_size(map_size)
+        }
+    }
+
+    pub fn render_origin(&self) -> Vec3 {
+        self.render_origin
+    }
+
+    /// Moves the render origin to the cell of [`RENDER_ORIGIN_CELL`] units around `eye`. Model matrices,
+    /// lights and cameras are given to the GPU relative to the render origin, so the vertex positions and
+    /// shadow lookups stay small and precise with the camera far from the world origin. The model
+    /// matrices and lights are uploaded again by the same sync after it moved.
+    fn update_render_origin(&mut self, eye: Vec3) {
+        let origin = (eye / RENDER_ORIGIN_CELL).round() * RENDER_ORIGIN_CELL;
+        if origin == self.render_origin {
+            return;
+        }
+        println!("Render origin moved to {origin}");
+        self.render_origin = origin;
+    }
+
+    /// Uploads what changed since the last sync in one step before a frame is encoded, so passes only
+    /// bind what is already on the GPU: the render origin follows `eye`, then the model matrices of
+    /// moved render nodes, the lights if any of them changed or moved, and the ambient light and fog if
+    /// they were set are written.
+    pub fn sync(&mut self, device: &wgpu::Device, queue: &Queue, eye: Vec3) -> SceneSync {
+        self.update_render_origin(eye);
+        let matrices = self.update_model_matrices(queue);
+        let lights = self.sync_lights(device, queue);
+        self.update_ambient(queue);
+        SceneSync { matrices, lights }
+    }
+
+    /// Moves the node of `animation` along its path from the next [`SceneGraph::animate_paths`] on.
+    pub fn add_path(&mut self, animation: PathAnimation) {
+        self.paths.push(animation);
+    }
+
+    /// Places the nodes with a path where they are at `time` in seconds. Their scale is kept, the
+    /// translation and rotation come from the path.
+    pub fn animate_paths(&mut self, time: f32) {
+        let paths = std::mem::take(&mut self.paths);
+        for animation in &paths {
+            let Some(node) = self.find_child_mut(Some(&animation.node)) else {
+                continue;
+            };
+            let (scale, _, _) = node.matrix().to_scale_rotation_translation();
+            node.set_matrix(animation.matrix(time) * Mat4::from_scale(scale));
+        }
+        self.paths = paths;
+    }
+
+    /// Animates the light node of `animation` from the next [`SceneGraph::animate_lights`] on.
+    pub fn add_light_animation(&mut self, animation: LightAnimation) {
+        self.light_animations.push(animation);
+    }
+
+    /// Sets the position, color and intensity of the animated lights at `time` in seconds, for the tracks
+    /// they have, with the flashing `limits` allows.
+    pub fn animate_lights(&mut self, time: f32, limits: &AccessibilitySettings) {
+        let animations = std::mem::take(&mut self.light_animations);
+        for animation in &animations {
+            let Some(Node::LightNode(light_node)) = self.find_child_mut(Some(&animation.node))
+            else {
+                continue;
+            };
+            if let Some(matrix) = animation.matrix(light_node.node.matrix.as_mat4(), time) {
+                light_node.node.set_matrix(matrix);
+            }
+            if let Some([r, g, b]) = animation.color(time, limits) {
+                light_node.light.set_color(wgpu::Color { r, g, b, a: 1.0 });
+            }
+            if let Some(intensity) = animation.intensity(time, limits) {
+                light_node.light.intensity = intensity;
+            }
+        }
+        self.light_animations = animations;
+    }
+
+    /// Places the node of `constraint` from the next [`SceneGraph::apply_constraints`] on.
+    pub fn add_constraint(&mut self, constraint: NodeConstraint) {
+        self.constraints.push(constraint);
+    }
+
+    /// Sets the transforms of the constrained nodes from their targets, with the camera at `eye` looking
+    /// at `camera_target`. Constraints whose node or target is missing are skipped.
+    pub fn apply_constraints(&mut self, eye: Vec3, camera_target: Vec3) {
+        let constraints = std::mem::take(&mut self.constraints);
+        for node_constraint in &constraints {
+            let target = match node_constraint.constraint.target() {
+                Some(ConstraintTarget::Node(name)) => {
+                    let (Some((_, matrix)), Some(position)) =
+                        (self.world_matrices(name), self.world_position(name))
+                    else {
+                        continue;
+                    };
+                    let (scale, rotation, _) = matrix.to_scale_rotation_translation();
+                    DMat4::from_scale_rotation_translation(scale, rotation, position.as_dvec3())
+                }
+                _ => camera_matrix(eye, camera_target),
+            };
+            let Some((parent, world)) = self.world_matrices(&node_constraint.node) else {
+                continue;
+            };
+            let Some(node) = self.find_child_mut(Some(&node_constraint.node)) else {
+                continue;
+            };
+            if let Node::LightNode(light_node) = node {
+                // turning the node would swing the light around the origin of the node instead
+                match node_constraint.constraint {
+                    Constraint::LookAt { .. } => {
+                        light_node.light.aim = Some(target.w_axis.truncate().as_vec3());
+                        continue;
+                    }
+                    Constraint::Billboard => continue,
+                    _ => {}
+                }
+            }
+            let world = node_constraint
+                .constraint
+                .world_matrix(world, target, eye.as_dvec3());
+            node.set_matrix_f64(parent.inverse() * world);
+        }
+        self.constraints = constraints;
+    }
+
+    /// The paths in world space, for drawing them.
+    pub fn path_curves(&self) -> Vec<Vec<Vec3>> {
+        if self.paths.is_empty() {
+            return Vec::new();
+        }
+        let mut curves = Vec::new();
+        let mut stack = vec![(&self.root, DMat4::IDENTITY)];
+        while let Some((node, parent_matrix)) = stack.pop() {
+            for animation in self.paths.iter().filter(|path| path.node == node.name()) {
+                let curve = animation.path.polyline();
+                curves.push(
+                    curve
+                        .into_iter()
+                        .map(|point| parent_matrix.transform_point3(point.as_dvec3()).as_vec3())
+                        .collect(),
+                );
+            }
+            if let Node::GroupNode(group) = node {
+                let current_matrix = parent_matrix * group.node.matrix;
+                stack.extend(group.children.iter().map(|child| (child, current_matrix)));
+            }
+        }
+        curves
+    }
+
+    /// Uploads the ambient light and the fog if they changed since the last call.
+    fn update_ambient(&mut self, queue: &Queue) {
+        if std::mem::take(&mut self.ambient_dirty) {
+            queue.write_buffer(
+                &self.ambient_buffer,
+                0,
+                bytemuck::bytes_of(&AmbientUniform::from_ambient(&self.ambient)),
+            );
+        }
+        if std::mem::take(&mut self.fog_dirty) {
+            queue.write_buffer(
+                &self.fog_buffer,
+                0,
+                bytemuck::bytes_of(&FogUniform::from_fog(&self.fog)),
+            );
+        }
+    }
+
     pub fn add_render_node(
         &mut self,
         parent: Option<&str>,
@@ -175,11 +878,54 @@ impl SceneGraph {
         indices: &[u32],
         matrix: Mat4,
     ) {
+        let model_slot = self.model_matrices.allocate(device);
         let render_node =
-            RenderNode::new_with_matrix(name, device, vertices, indices, None, matrix);
+            RenderNode::new_with_matrix(name, device, vertices, indices, None, model_slot, matrix);
         self.add_child(parent, Node::RenderNode(render_node));
     }
 
+    /// Adds an empty group named `name` below the group `parent`, the root if None. Nodes added or moved
+    /// into it follow the transform set with [`SceneGraph::set_group_transform`], e.g. the props of a
+    /// house moving with it. Returns None if `parent` is not a group of the scene.
+    #[allow(dead_code)]
+    pub fn add_group_node(&mut self, parent: Option<NodeId>, name: &str) -> Option<NodeId> {
+        let group = GroupNode::new(name.to_string());
+        let id = group.node.id;
+        let parent = match parent {
+            Some(parent) => self.find_node_mut(parent)?,
+            None => &mut self.root,
+        };
+        let Node::GroupNode(parent) = parent else {
+            return None;
+        };
+        parent.add_child(Node::GroupNode(group));
+        Some(id)
+    }
+
+    /// Adds `group` with its transform and children below the node named `parent`, the root if None.
+    pub fn insert_group_node(&mut self, parent: Option<&str>, group: GroupNode) {
+        self.add_child(parent, Node::GroupNode(group));
+    }
+
+    /// Sets the transform of the group `id` relative to its parent, its children move with it from the
+    /// next [`SceneGraph::sync`] on. Returns false if `id` is not a group of the scene.
+    #[allow(dead_code)]
+    pub fn set_group_transform(&mut self, id: NodeId, matrix: Mat4) -> bool {
+        match self.find_node_mut(id) {
+            Some(Node::GroupNode(group)) => {
+                group.set_matrix(matrix);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Id of the first node named `name`, e.g. to animate the group of a model from a scene file.
+    #[allow(dead_code)]
+    pub fn node_id(&self, name: &str) -> Option<NodeId> {
+        self.find_child(name).map(Node::id)
+    }
+
     pub fn add_model_node(
         &mut self,
         parent: Option<&str>,
@@ -189,35 +935,334 @@ impl SceneGraph {
         bind_group_layout: &BindGroupLayout,
         matrix: Mat4,
     ) {
+        // One bind group per material, shared by all meshes using it, so that
+        // state-sorted draws can skip redundant bind group switches.
+        let bind_groups = model
+            .materials
+            .iter()
+            .map(|material| {
+                validate(
+                    device,
+                    || labels::material(&material.name, "bind_group"),
+                    || material.create_bind_group(device, bind_group_layout),
+                )
+            })
+            .collect::<Vec<_>>();
+
         for mesh in &model.meshes {
-            let material = &model.materials[mesh.material];
-            let bind_group = material.create_bind_group(device, &bind_group_layout);
+            let node_name = format!("{}-{}", name, mesh.name);
+            // meshes whose material or buffers failed are left out, the rest of the model is added
+            let bind_group = match &bind_groups[mesh.material] {
+                Ok(bind_group) => bind_group.clone(),
+                Err(e) => {
+                    println!("Skipping {node_name}: {e}");
+                    continue;
+                }
+            };
+            let model_slot = self.model_matrices.allocate(device);
 
-            let render_node = RenderNode::new_with_matrix(
-                format!("{}-{}", name, mesh.name),
+            let render_node = validate(
                 device,
-                &mesh.vertices,
-                &mesh.indices,
-                bind_group,
-                matrix,
+                || labels::node(&node_name, "buffers"),
+                || {
+                    RenderNode::new_with_matrix(
+                        node_name.clone(),
+                        device,
+                        &mesh.vertices,
+                        &mesh.indices,
+                        bind_group,
+                        model_slot,
+                        matrix,
+                    )
+                },
             );
+            let mut render_node = match render_node {
+                Ok(render_node) => render_node,
+                Err(e) => {
+                    println!("Skipping {node_name}: {e}");
+                    self.model_matrices.release(model_slot);
+                    continue;
+                }
+            };
+            render_node.depth_bias = model.materials[mesh.material].depth_bias;
+            render_node.shading = model.materials[mesh.material].shading;
+            render_node.glass = model.materials[mesh.material].is_glass();
+            render_node.transparent = model.materials[mesh.material].is_transparent();
+            render_node.features = model.materials[mesh.material].features();
+            render_node.material_info = model.materials[mesh.material].info();
             self.add_child(parent, Node::RenderNode(render_node));
         }
     }
 
-    pub fn add_light_node(
+    /// Swaps the meshes and materials of the render nodes below the model group `name` for those of
+    /// `model`, e.g. after its files changed. Render nodes of meshes the model still has keep their node,
+    /// transform and custom material, those of meshes it lost are removed and new meshes are added.
+    /// Returns false if there is no such group.
+    pub fn reload_model_node(
+        &mut self,
+        name: &str,
+        device: &wgpu::Device,
+        model: &model::Model,
+        bind_group_layout: &BindGroupLayout,
+    ) -> bool {
+        let Some(Node::GroupNode(group)) = self.find_child_mut(Some(name)) else {
+            return false;
+        };
+        let (mut previous, others): (Vec<_>, Vec<_>) = std::mem::take(&mut group.children)
+            .into_iter()
+            .partition(|child| matches!(child, Node::RenderNode(_)));
+        self.add_model_node(
+            Some(name),
+            name.to_string(),
+            device,
+            model,
+            bind_group_layout,
+            Mat4::IDENTITY,
+        );
+
+        let Some(Node::GroupNode(group)) = self.find_child_mut(Some(name)) else {
+            return false;
+        };
+        group.source_files = model.files.clone();
+        let mut released_slots = Vec::new();
+        for child in &mut group.children {
+            let Node::RenderNode(render_node) = child else {
+                continue;
+            };
+            let Some(index) = previous
+                .iter()
+                .position(|old| old.name() == render_node.node.name)
+            else {
+                continue;
+            };
+            let Node::RenderNode(old) = previous.swap_remove(index) else {
+                continue;
+            };
+            released_slots.push(render_node.model_slot);
+            render_node.model_slot = old.model_slot;
+            render_node.node = old.node;
+            render_node.custom_material = old.custom_material;
+        }
+        group.children.extend(others);
+        released_slots.extend(previous.iter().filter_map(|old| match old {
+            Node::RenderNode(old) => Some(old.model_slot),
+            _ => None,
+        }));
+        for slot in released_slots {
+            self.model_matrices.release(slot);
+        }
+        true
+    }
+
+    /// Name, source and source files of each group with a model file, see [`GroupNode::source`].
+    pub fn model_groups(&self) -> Vec<(String, String, Vec<String>)> {
+        let mut groups = Vec::new();
+        self.root.visit(&mut |node| {
+            if let Node::GroupNode(group) = node {
+                if let Some(source) = &group.source {
+                    groups.push((
+                        group.node.name.clone(),
+                        source.clone(),
+                        group.source_files.clone(),
+                    ));
+                }
+            }
+        });
+        groups
+    }
+
+    /// Adds a model that is drawn once per transform in `instances`, with a single draw call per mesh.
+    /// Meant for props that appear many times, the transforms are relative to the parent.
+    pub fn add_instanced_model_node(
         &mut self,
         parent: Option<&str>,
         name: String,
         device: &wgpu::Device,
-        light: Light,
+        model: &model::Model,
+        bind_group_layout: &BindGroupLayout,
+        instances: &[Mat4],
     ) {
+        self.add_model_node(
+            parent,
+            name.clone(),
+            device,
+            model,
+            bind_group_layout,
+            Mat4::IDENTITY,
+        );
+        for mesh in &model.meshes {
+            let mesh_name = format!("{}-{}", name, mesh.name);
+            if let Some(Node::RenderNode(render_node)) = self.find_child_mut(Some(&mesh_name)) {
+                let label = labels::node(&mesh_name, "instances");
+                let buffer = InstanceRaw::create_buffer(device, &label, instances);
+                render_node.instances = Some((buffer, instances.to_vec()));
+                let mesh_bounds = render_node.bounds;
+                render_node.bounds = instances
+                    .iter()
+                    .map(|instance| mesh_bounds.transform(*instance))
+                    .reduce(|a, b| a.union(&b))
+                    .unwrap_or(mesh_bounds);
+            }
+        }
+    }
+
+    pub fn add_light_node(&mut self, parent: Option<&str>, name: String, mut light: Light) {
+        match self.free_shadow_layer(light.kind) {
+            Some(layer) => light.set_shadow_layer(&self.shadow_map_for(light.kind).texture, layer),
+            None => println!(
+                "All {} shadow map layers are in use, light {} casts no shadow",
+                self.shadow_map_for(light.kind).layers,
+                name
+            ),
+        }
         let light_node = LightNode {
             node: NodeData::new(name),
             light,
         };
         self.add_child(parent, Node::LightNode(light_node));
-        self.update_light_bind_group(device);
+    }
+
+    /// Vertex buffer with the instance transforms of `render_node`.
+    pub fn instance_buffer<'a>(&'a self, render_node: &'a RenderNode) -> &'a Buffer {
+        render_node
+            .instances
+            .as_ref()
+            .map_or(&self.identity_instance, |(buffer, _)| buffer)
+    }
+
+    /// True if any render node is drawn in the glass pass.
+    pub fn has_glass(&self) -> bool {
+        let mut has_glass = false;
+        self.root.visit(&mut |node| {
+            if let Node::RenderNode(render_node) = node {
+                has_glass |= render_node.glass;
+            }
+        });
+        has_glass
+    }
+
+    /// The distinct pipeline keys of the render nodes, the pipelines need a variant for each of them.
+    pub fn pipeline_keys(&self) -> Vec<PipelineKey> {
+        let mut keys = Vec::new();
+        self.root.visit(&mut |node| {
+            if let Node::RenderNode(render_node) = node {
+                if !keys.contains(&render_node.pipeline_key()) {
+                    keys.push(render_node.pipeline_key());
+                }
+            }
+        });
+        keys
+    }
+
+    /// Size of the scene, for diagnostics.
+    pub fn stats(&self) -> SceneStats {
+        let mut stats = SceneStats::default();
+        self.root.visit(&mut |node| match node {
+            Node::RenderNode(render_node) => {
+                stats.render_nodes += 1;
+                stats.triangles +=
+                    render_node.num_elements as u64 / 3 * render_node.instance_count() as u64;
+            }
+            Node::LightNode(_) => stats.lights += 1,
+            Node::GroupNode(_) => {}
+        });
+        stats
+    }
+
+    /// Cost of each render node seen from `view`, the most expensive first.
+    pub fn node_stats(&self, view: &DrawView) -> Vec<NodeStats> {
+        let mut stats = SceneGraphRenderNodeIterator::new(self)
+            .map(|(render_node, matrix)| {
+                let instances = render_node.instance_count();
+                NodeStats {
+                    name: render_node.node.name.clone(),
+                    triangles: render_node.num_elements as u64 / 3 * instances as u64,
+                    instances,
+                    culled: !view.sees(render_node, matrix),
+                }
+            })
+            .collect::<Vec<_>>();
+        stats.sort_by_key(|node| std::cmp::Reverse(node.drawn_triangles()));
+        stats
+    }
+
+    /// Writes the node hierarchy with the transforms, lights, ambient light and model files as a scene file that
+    /// `--scene` loads, starting at the view of `camera`. Render nodes that were not loaded from a model
+    /// file, like the ground of the demo scene, have no file to refer to and are left out.
+    pub fn save(&self, path: &Path, camera: &Camera) -> anyhow::Result<()> {
+        let Node::GroupNode(root) = &self.root else {
+            unreachable!("the root is a group node");
+        };
+        let mut skipped = Vec::new();
+        let scene = SceneDescription {
+            materials: Vec::new(),
+            camera: Some(CameraDescription {
+                eye: camera.eye,
+                target: camera.target,
+            }),
+            ambient: Some(self.ambient),
+            sun: self.sun.clone(),
+            nodes: root
+                .children
+                .iter()
+                .filter_map(|node| describe_node(node, self, &mut skipped))
+                .collect(),
+        };
+        if !skipped.is_empty() {
+            println!("Not saved, no model file: {}", skipped.join(", "));
+        }
+        std::fs::write(path, scene.to_json()?)?;
+        Ok(())
+    }
+
+    /// Draws the render nodes of the node `name` and its children with the [`crate::custom_material::CustomMaterial`] at `index`,
+    /// or with their own materials again for None. Returns false if there is no such node.
+    pub fn set_custom_material(&mut self, name: &str, index: Option<usize>) -> bool {
+        let Some(node) = self.find_child_mut(Some(name)) else {
+            return false;
+        };
+        node.visit_mut(&mut |node| {
+            if let Node::RenderNode(render_node) = node {
+                render_node.custom_material = index;
+            }
+        });
+        true
+    }
+
+    /// Draws the render nodes below the group `name` with `material_override` instead of their own
+    /// materials, or with their own materials again for None. Returns false if there is no such group.
+    pub fn set_material_override(
+        &mut self,
+        name: &str,
+        material_override: Option<MaterialOverride>,
+    ) -> bool {
+        match self.find_child_mut(Some(name)) {
+            Some(Node::GroupNode(group)) => {
+                group.material_override = material_override;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Shadow map lights of the given kind render into.
+    pub fn shadow_map_for(&self, kind: LightKind) -> &ShadowMap {
+        match kind {
+            LightKind::Spot => &self.shadow_map,
+            LightKind::Point => &self.point_shadow_map,
+        }
+    }
+
+    /// Lowest free block of shadow map layers for a light of the given kind.
+    fn free_shadow_layer(&self, kind: LightKind) -> Option<u32> {
+        let used_layers = SceneGraphLightNodeIterator::new(self)
+            .filter(|(light_node, _)| light_node.light.kind == kind)
+            .filter_map(|(light_node, _)| light_node.light.shadow_layer)
+            .collect::<Vec<_>>();
+        let layers_per_light = kind.shadow_layers();
+        (0..self.shadow_map_for(kind).layers / layers_per_light)
+            .map(|index| index * layers_per_light)
+            .find(|layer| !used_layers.contains(layer))
     }
 
     fn add_child(&mut self, parent: Option<&str>, child: Node) {
@@ -227,6 +1272,105 @@ impl SceneGraph {
         }
     }
 
+    /// Removes the named node together with its children. The root can't be removed.
+    pub fn remove_node(&mut self, name: &str) -> anyhow::Result<()> {
+        let id = self.non_root_node_id(name)?;
+        let node = self
+            .detach_node(id)
+            .expect("a node below the root has a parent");
+
+        node.visit(&mut |node| {
+            if let Node::RenderNode(render) = node {
+                self.model_matrices.release(render.model_slot);
+            }
+        });
+        Ok(())
+    }
+
+    /// Moves the named node with its children below `new_parent` (the root if None), keeping its local
+    /// transform. Fails if either node is missing, the node is the root, or the new parent is not a group
+    /// or lies inside the moved node.
+    pub fn move_node(&mut self, name: &str, new_parent: Option<&str>) -> anyhow::Result<()> {
+        let id = self.non_root_node_id(name)?;
+        let parent_id = match new_parent {
+            Some(new_parent) => match self.find_child(new_parent) {
+                Some(parent @ Node::GroupNode(_)) => parent.id(),
+                Some(_) => anyhow::bail!("{new_parent} is not a group"),
+                None => anyhow::bail!("There is no node {new_parent}"),
+            },
+            None => self.root.id(),
+        };
+        let mut parent_in_subtree = false;
+        self.find_node(id)
+            .expect("the node was just found")
+            .visit(&mut |node| parent_in_subtree |= node.id() == parent_id);
+        if parent_in_subtree {
+            anyhow::bail!("{name} can't be moved below itself");
+        }
+
+        let node = self
+            .detach_node(id)
+            .expect("a node below the root has a parent");
+        let Some(Node::GroupNode(parent)) = self.find_node_mut(parent_id) else {
+            unreachable!("the new parent is a group outside the moved node");
+        };
+        // render nodes and lights pick up their new world matrix in the next sync
+        parent.add_child(node);
+        Ok(())
+    }
+
+    /// Id of the named node, the first one found if several share the name. Fails for the root, which
+    /// has no parent to be taken from.
+    fn non_root_node_id(&self, name: &str) -> anyhow::Result<NodeId> {
+        let Some(node) = self.find_child(name) else {
+            anyhow::bail!("There is no node {name}");
+        };
+        if node.id() == self.root.id() {
+            anyhow::bail!("The root node can't be removed or moved");
+        }
+        Ok(node.id())
+    }
+
+    /// Takes the node out of its parent's children.
+    fn detach_node(&mut self, id: NodeId) -> Option<Node> {
+        let mut stack = vec![&mut self.root];
+        while let Some(node) = stack.pop() {
+            if let Node::GroupNode(group) = node {
+                if let Some(index) = group.children.iter().position(|child| child.id() == id) {
+                    return Some(group.children.remove(index));
+                }
+                stack.extend(&mut group.children);
+            }
+        }
+        None
+    }
+
+    fn find_node(&self, id: NodeId) -> Option<&Node> {
+        let mut stack = vec![&self.root];
+        while let Some(node) = stack.pop() {
+            if node.id() == id {
+                return Some(node);
+            }
+            if let Node::GroupNode(group) = node {
+                stack.extend(&group.children);
+            }
+        }
+        None
+    }
+
+    fn find_node_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        let mut stack = vec![&mut self.root];
+        while let Some(node) = stack.pop() {
+            if node.id() == id {
+                return Some(node);
+            }
+            if let Node::GroupNode(group) = node {
+                stack.extend(&mut group.children);
+            }
+        }
+        None
+    }
+
     pub fn find_child(&self, name: &str) -> Option<&Node> {
         self.find_child_deep(name)
     }
@@ -302,6 +1446,120 @@ impl SceneGraph {
         None
     }
 
+    /// World-space position of a named node: the mesh center for render nodes,
+    /// the light position for light nodes and the origin of the group otherwise.
+    pub fn world_position(&self, name: &str) -> Option<Vec3> {
+        let mut stack = vec![(&self.root, DMat4::IDENTITY)];
+        while let Some((node, parent_matrix)) = stack.pop() {
+            match node {
+                Node::GroupNode(group) => {
+                    let current_matrix = parent_matrix * group.node.matrix;
+                    if group.node.name == name {
+                        return Some(current_matrix.w_axis.truncate().as_vec3());
+                    }
+                    for child in &group.children {
+                        stack.push((child, current_matrix));
+                    }
+                }
+                Node::RenderNode(render) => {
+                    if render.node.name == name {
+                        let current_matrix = parent_matrix * render.node.matrix;
+                        return Some(render.world_center(current_matrix.as_mat4()));
+                    }
+                }
+                Node::LightNode(light) => {
+                    if light.node.name == name {
+                        let current_matrix = parent_matrix * light.node.matrix;
+                        let position = current_matrix.transform_point3(light.light.pos.as_dvec3());
+                        return Some(position.as_vec3());
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// World matrix of the parent of a named node and of the node itself.
+    fn world_matrices(&self, name: &str) -> Option<(DMat4, DMat4)> {
+        let mut stack = vec![(&self.root, DMat4::IDENTITY)];
+        while let Some((node, parent_matrix)) = stack.pop() {
+            let current_matrix = parent_matrix * node.matrix_f64();
+            if node.name() == name {
+                return Some((parent_matrix, current_matrix));
+            }
+            if let Node::GroupNode(group) = node {
+                stack.extend(group.children.iter().map(|child| (child, current_matrix)));
+            }
+        }
+        None
+    }
+
+    /// Uploads the world matrix of every render node whose transform changed since the last call,
+    /// relative to the render origin. Each changed node only writes its own slot of the model matrix
+    /// buffer. Returns the number of written matrices.
+    fn update_model_matrices(&mut self, queue: &Queue) -> usize {
+        let mut written = 0;
+        let reupload_all = std::mem::take(&mut self.model_matrices.reallocated);
+        let model_matrices = &self.model_matrices;
+        let to_render_origin = DMat4::from_translation(-self.render_origin.as_dvec3());
+        let mut stack = vec![(&mut self.root, DMat4::IDENTITY)];
+        while let Some((node, parent_matrix)) = stack.pop() {
+            match node {
+                Node::GroupNode(group) => {
+                    let current_matrix = parent_matrix * group.node.matrix;
+                    for child in &mut group.children {
+                        stack.push((child, current_matrix));
+                    }
+                }
+                Node::RenderNode(render) => {
+                    let current_matrix =
+                        (to_render_origin * parent_matrix * render.node.matrix).as_mat4();
+                    if reupload_all || render.uploaded_matrix != Some(current_matrix) {
+                        model_matrices.write(queue, render.model_slot, current_matrix);
+                        render.uploaded_matrix = Some(current_matrix);
+                        written += 1;
+                    }
+                }
+                Node::LightNode(_) => {}
+            }
+        }
+        written
+    }
+
+    /// World-space bounding box of all render nodes, or None if the scene has no geometry.
+    pub fn bounds(&self) -> Option<(Vec3, Vec3)> {
+        SceneGraphRenderNodeIterator::new(self)
+            .flat_map(|(render, matrix)| {
+                render
+                    .vertices
+                    .iter()
+                    .map(move |vertex| matrix.transform_point3(Vec3::from(vertex.pos)))
+            })
+            .fold(None, |bounds, point| match bounds {
+                None => Some((point, point)),
+                Some((min, max)) => Some((point.min(min), point.max(max))),
+            })
+    }
+
+    /// World-space bounds of each render node, the boxes the draw lists cull with.
+    pub fn render_node_bounds(&self) -> Vec<Aabb> {
+        SceneGraphRenderNodeIterator::new(self)
+            .map(|(render_node, matrix)| render_node.bounds.transform(matrix))
+            .collect()
+    }
+
+    /// View projection of each shadow map layer of the lights that cast shadows, relative to the
+    /// render origin.
+    pub fn shadow_frusta(&self) -> Vec<Mat4> {
+        self.get_light_nodes()
+            .into_iter()
+            .filter(|(light_node, _)| light_node.light.shadow_layer.is_some())
+            .flat_map(|(light_node, model)| {
+                light_node.light.shadow_matrices(model, self.render_origin)
+            })
+            .collect()
+    }
+
     fn get_light_nodes(&self) -> Vec<(&LightNode, Mat4)> {
         SceneGraphLightNodeIterator::new(self).collect::<Vec<(_, _)>>()
     }
@@ -309,62 +1567,119 @@ impl SceneGraph {
     fn get_light_uniforms(&self) -> Vec<LightUniform> {
         let mut uniforms = vec![];
         for light in self.get_light_nodes() {
-            let uniform = LightUniform::from_light(&light.0.light, light.1);
+            let shadow_map_size = self.shadow_map_for(light.0.light.kind).size;
+            let uniform = LightUniform::from_light(
+                &light.0.light,
+                light.1,
+                self.render_origin,
+                self.shadows_enabled,
+                self.shadow_size(&light.0.light) as f32 / shadow_map_size as f32,
+            );
             uniforms.push(uniform);
         }
         uniforms
     }
 
-    pub fn update_light_bind_group_layout(&mut self, device: &wgpu::Device) {
-        let light_count = self.get_light_nodes().len() as u32;
-        if light_count == 0 {
-            self.light_bind_group_layout = None;
+    /// Uploads the lights if they differ from the last upload. The light buffer and bind group are only
+    /// created again when the lights no longer fit the buffer. Returns whether the lights were uploaded.
+    fn sync_lights(&mut self, device: &wgpu::Device, queue: &Queue) -> bool {
+        let mut light_uniforms = self.get_light_uniforms();
+        // uniform arrays have a fixed size and storage buffers can't be empty
+        let len = if self.supports_storage_resources {
+            light_uniforms.len().max(1)
+        } else {
+            if light_uniforms.len() > LightUniform::UNIFORM_ARRAY_SIZE
+                && self.uploaded_lights.is_none()
+            {
+                println!(
+                    "Only {} of {} lights are shaded without storage buffers",
+                    LightUniform::UNIFORM_ARRAY_SIZE,
+                    light_uniforms.len()
+                );
+            }
+            LightUniform::UNIFORM_ARRAY_SIZE
+        };
+        let light_count = light_uniforms.len().min(len) as u32;
+        light_uniforms.resize(len, LightUniform::zeroed());
+        let (fits, unchanged) = match &self.uploaded_lights {
+            Some((count, uploaded)) => (
+                uploaded.len() == len,
+                *count == light_count
+                    && bytemuck::cast_slice::<_, u8>(uploaded)
+                        == bytemuck::cast_slice::<_, u8>(&light_uniforms),
+            ),
+            None => (false, false),
+        };
+        if unchanged {
+            return false;
         }
-        self.light_bind_group_layout = Some(LightUniform::get_bind_group_layout(
-            device,
-            light_count,
-            self.supports_storage_resources,
-        ));
+        queue.write_buffer(
+            &self.light_count_buffer,
+            0,
+            bytemuck::bytes_of(&light_count),
+        );
+        match &self.light_buffer {
+            Some(light_buffer) if fits => {
+                queue.write_buffer(light_buffer, 0, bytemuck::cast_slice(&light_uniforms));
+            }
+            _ => {
+                let light_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Light Buffer"),
+                    contents: bytemuck::cast_slice(&light_uniforms),
+                    usage: if self.supports_storage_resources {
+                        wgpu::BufferUsages::STORAGE
+                    } else {
+                        wgpu::BufferUsages::UNIFORM
+                    } | wgpu::BufferUsages::COPY_SRC
+                        | wgpu::BufferUsages::COPY_DST,
+                });
+                self.light_bind_group = Some(self.create_light_bind_group(device, &light_buffer));
+                self.light_buffer = Some(light_buffer);
+            }
+        }
+        self.uploaded_lights = Some((light_count, light_uniforms));
+        self.lights_dirty = true;
+        true
     }
 
-    pub fn update_light_bind_group(&mut self, device: &wgpu::Device) {
-        self.lights_dirty = true;
-        let light_uniforms = self.get_light_uniforms();
-        let light_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Light Buffer"),
-            contents: bytemuck::cast_slice(&light_uniforms),
-            usage: if self.supports_storage_resources {
-                wgpu::BufferUsages::STORAGE
-            } else {
-                wgpu::BufferUsages::UNIFORM
-            } | wgpu::BufferUsages::COPY_SRC
-                | wgpu::BufferUsages::COPY_DST,
-        });
+    fn create_light_bind_group(&self, device: &wgpu::Device, light_buffer: &Buffer) -> BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: self.light_bind_group_layout.as_ref().unwrap(),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: light_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&self.shadow_map.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.shadow_map.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.light_count_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&self.point_shadow_map.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: self.ambient_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: self.fog_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("Light Bind Group"),
+        })
+    }
 
-        self.update_light_bind_group_layout(device);
-        if let Some(light_bind_group_layout) = &self.light_bind_group_layout {
-            self.light_bind_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
-                layout: &light_bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: light_buffer.as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::TextureView(&self.shadow_map.view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 2,
-                        resource: wgpu::BindingResource::Sampler(&self.shadow_map.sampler),
-                    },
-                ],
-                label: Some("Light Bind Group"),
-            }));
-        }
-    }
-    
-    pub fn set_callback (&mut self, callback: Box<dyn Fn(&SceneGraph)>) {
+    pub fn set_callback(&mut self, callback: Box<dyn Fn(&SceneGraph)>) {
         self.on_frame_update_callback = Some(callback);
     }
 
@@ -376,33 +1691,109 @@ impl SceneGraph {
     }
 }
 
+/// The scene file description of `node` and its children. Render nodes have no description, their names
+/// are added to `skipped`.
+fn describe_node(
+    node: &Node,
+    scene_graph: &SceneGraph,
+    skipped: &mut Vec<String>,
+) -> Option<NodeDescription> {
+    let (node_data, content) = match node {
+        Node::GroupNode(group) => {
+            let content = match &group.source {
+                Some(path) => NodeContent::Model { path: path.clone() },
+                None => NodeContent::Group {
+                    children: group
+                        .children
+                        .iter()
+                        .filter_map(|child| describe_node(child, scene_graph, skipped))
+                        .collect(),
+                },
+            };
+            (&group.node, content)
+        }
+        Node::LightNode(light_node) => {
+            let color = light_node.light.color();
+            let animation = scene_graph
+                .light_animations
+                .iter()
+                .find(|animation| animation.node == light_node.node.name);
+            let content = NodeContent::Light {
+                kind: light_node.light.kind,
+                color: [color.r, color.g, color.b],
+                position: light_node.light.pos,
+                // the flicker only varies the intensity, it is saved without
+                intensity: match animation {
+                    Some(animation) if animation.tracks.flicker.is_some() => {
+                        animation.base_intensity
+                    }
+                    _ => light_node.light.intensity,
+                },
+                range: light_node.light.range,
+                cast_shadows: light_node.light.cast_shadows,
+                shadow_resolution: light_node.light.shadow_resolution,
+                animation: animation.map(|animation| animation.tracks.clone()),
+            };
+            (&light_node.node, content)
+        }
+        Node::RenderNode(render) => {
+            skipped.push(render.node.name.clone());
+            return None;
+        }
+    };
+    let spline = scene_graph
+        .paths
+        .iter()
+        .find(|animation| animation.node == node_data.name)
+        .map(|animation| SplineDescription {
+            points: animation.path.points().to_vec(),
+            closed: animation.path.is_closed(),
+            speed: animation.speed,
+            orient: animation.orient,
+        });
+    let constraint = scene_graph
+        .constraints
+        .iter()
+        .find(|constraint| constraint.node == node_data.name)
+        .map(|constraint| constraint.constraint.clone());
+    Some(NodeDescription {
+        name: node_data.name.clone(),
+        transform: Transform::from_matrix(node_data.matrix),
+        spline,
+        constraint,
+        material: None,
+        material_override: None,
+        content,
+    })
+}
+
 pub struct SceneGraphRenderNodeIterator<'a> {
-    stack: Vec<(&'a Node, Mat4)>,
+    stack: Vec<(&'a Node, DMat4, Option<MaterialOverride>)>,
 }
 
 impl<'a> SceneGraphRenderNodeIterator<'a> {
     pub fn new(scene_graph: &'a SceneGraph) -> Self {
         Self {
-            stack: vec![(&scene_graph.root, Mat4::IDENTITY)],
+            stack: vec![(&scene_graph.root, DMat4::IDENTITY, None)],
         }
     }
-}
 
-impl<'a> Iterator for SceneGraphRenderNodeIterator<'a> {
-    type Item = (&'a RenderNode, Mat4);
-
-    fn next(&mut self) -> Option<Self::Item> {
-        while let Some((node, parent_matrix)) = self.stack.pop() {
+    /// Like [`Iterator::next`], also returns the material override of the closest group above the node.
+    pub fn next_with_override(
+        &mut self,
+    ) -> Option<(&'a RenderNode, Mat4, Option<MaterialOverride>)> {
+        while let Some((node, parent_matrix, material_override)) = self.stack.pop() {
             match node {
                 Node::GroupNode(group) => {
                     let current_matrix = parent_matrix * group.node.matrix;
+                    let material_override = group.material_override.or(material_override);
                     for child in &group.children {
-                        self.stack.push((child, current_matrix));
+                        self.stack.push((child, current_matrix, material_override));
                     }
                 }
                 Node::RenderNode(render) => {
                     let current_matrix = parent_matrix * render.node.matrix;
-                    return Some((render, current_matrix));
+                    return Some((render, current_matrix.as_mat4(), material_override));
                 }
                 _ => {}
             }
@@ -411,14 +1802,23 @@ impl<'a> Iterator for SceneGraphRenderNodeIterator<'a> {
     }
 }
 
+impl<'a> Iterator for SceneGraphRenderNodeIterator<'a> {
+    type Item = (&'a RenderNode, Mat4);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_with_override()
+            .map(|(render, matrix, _)| (render, matrix))
+    }
+}
+
 pub struct SceneGraphLightNodeIterator<'a> {
-    stack: Vec<(&'a Node, Mat4)>,
+    stack: Vec<(&'a Node, DMat4)>,
 }
 
 impl<'a> SceneGraphLightNodeIterator<'a> {
     pub fn new(scene_graph: &'a SceneGraph) -> Self {
         Self {
-            stack: vec![(&scene_graph.root, Mat4::IDENTITY)],
+            stack: vec![(&scene_graph.root, DMat4::IDENTITY)],
         }
     }
 }
@@ -437,7 +1837,7 @@ impl<'a> Iterator for SceneGraphLightNodeIterator<'a> {
                 }
                 Node::LightNode(light) => {
                     let current_matrix = parent_matrix * light.node.matrix;
-                    return Some((light, current_matrix));
+                    return Some((light, current_matrix.as_mat4()));
                 }
                 _ => {}
             }
@@ -446,22 +1846,243 @@ impl<'a> Iterator for SceneGraphLightNodeIterator<'a> {
     }
 }
 
+/// Order in which the draws of a pass are issued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortPolicy {
+    /// Group draws by material bind group to minimize state changes.
+    State,
+    /// Sort draws front-to-back from the view position to maximize early depth rejection.
+    Depth,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DrawStats {
+    pub draws: u32,
+    pub material_switches: u32,
+    /// Render nodes skipped because they are outside of the view frustum.
+    pub culled: u32,
+}
+
+impl std::ops::AddAssign for DrawStats {
+    fn add_assign(&mut self, other: Self) {
+        self.draws += other.draws;
+        self.material_switches += other.material_switches;
+        self.culled += other.culled;
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SceneStats {
+    pub render_nodes: u32,
+    /// Triangles of all render nodes and their instances.
+    pub triangles: u64,
+    pub lights: u32,
+}
+
+/// Cost of a render node in the forward pass of the last frame, see [`SceneGraph::node_stats`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeStats {
+    pub name: String,
+    /// Triangles of the mesh and all of its instances.
+    pub triangles: u64,
+    pub instances: u32,
+    /// Skipped because it was outside of the view frustum.
+    pub culled: bool,
+}
+
+impl NodeStats {
+    pub fn drawn_triangles(&self) -> u64 {
+        if self.culled {
+            0
+        } else {
+            self.triangles
+        }
+    }
+
+    pub fn hud_line(&self) -> String {
+        format!(
+            "{}: {} triangles, {} instances{}",
+            self.name,
+            self.triangles,
+            self.instances,
+            if self.culled { ", culled" } else { "" }
+        )
+    }
+}
+
+/// Which render nodes a pass draws.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrawLayer {
+    All,
+    /// Everything but glass, which is drawn afterwards over a copy of the opaque scene.
+    Opaque,
+    Glass,
+}
+
+impl DrawLayer {
+    fn contains(self, render_node: &RenderNode) -> bool {
+        match self {
+            DrawLayer::All => true,
+            DrawLayer::Opaque => !render_node.glass,
+            DrawLayer::Glass => render_node.glass,
+        }
+    }
+}
+
+/// Where a pass looks from, used for sorting and culling its draws.
+#[derive(Debug, Clone)]
+pub struct DrawView {
+    pub position: Vec3,
+    /// None draws every node, e.g. for passes covering several views.
+    pub frustum: Option<Frustum>,
+    pub layer: DrawLayer,
+}
+
+impl DrawView {
+    pub fn new(position: Vec3, view_proj: Mat4) -> Self {
+        Self {
+            position,
+            frustum: Some(Frustum::from_view_proj(view_proj)),
+            layer: DrawLayer::All,
+        }
+    }
+
+    pub fn unculled(position: Vec3) -> Self {
+        Self {
+            position,
+            frustum: None,
+            layer: DrawLayer::All,
+        }
+    }
+
+    pub fn with_layer(self, layer: DrawLayer) -> Self {
+        Self { layer, ..self }
+    }
+
+    /// Whether `render_node` with the world matrix `matrix` is inside of the view frustum.
+    pub fn sees(&self, render_node: &RenderNode, matrix: Mat4) -> bool {
+        self.frustum
+            .as_ref()
+            .is_none_or(|frustum| frustum.intersects(&render_node.bounds.transform(matrix)))
+    }
+}
+
+pub struct DrawItem<'a> {
+    pub render_node: &'a RenderNode,
+    /// Override of the scene or of a group above the node, see [`SceneGraph::set_material_override`].
+    pub material_override: Option<MaterialOverride>,
+    distance: f32,
+}
+
+impl<'a> DrawItem<'a> {
+    /// Index of the custom material the node is drawn with this frame, if any.
+    pub fn custom_material(&self) -> Option<usize> {
+        match self.material_override {
+            Some(MaterialOverride::Custom(index)) => Some(index),
+            Some(MaterialOverride::Clay) => None,
+            None => self.render_node.custom_material,
+        }
+    }
+
+    /// The material bind group the node is drawn with this frame, if it isn't a custom material.
+    pub fn material_bind_group(&self, scenegraph: &'a SceneGraph) -> Option<&'a BindGroup> {
+        match self.material_override {
+            Some(MaterialOverride::Clay) => scenegraph.clay_material.as_ref(),
+            _ => self.render_node.material_bind_group.as_ref(),
+        }
+    }
+}
+
+// DepthBiasState is not Ord, state sorting only needs equal biases to end up next to each other
+fn compare_depth_bias(a: &wgpu::DepthBiasState, b: &wgpu::DepthBiasState) -> std::cmp::Ordering {
+    a.constant
+        .cmp(&b.constant)
+        .then(a.slope_scale.total_cmp(&b.slope_scale))
+        .then(a.clamp.total_cmp(&b.clamp))
+}
+
+/// The visible render nodes of a pass, ordered according to a [`SortPolicy`]. Transparent nodes come
+/// after all others, from back to front, so that they blend over what is behind them.
+pub struct DrawList<'a> {
+    pub items: Vec<DrawItem<'a>>,
+    pub culled: u32,
+}
+
+impl<'a> DrawList<'a> {
+    pub fn build(scenegraph: &'a SceneGraph, view: &DrawView, policy: SortPolicy) -> Self {
+        let mut culled = 0;
+        let mut nodes = SceneGraphRenderNodeIterator::new(scenegraph);
+        let mut items = std::iter::from_fn(|| nodes.next_with_override())
+            .filter(|(render_node, _, _)| view.layer.contains(render_node))
+            .filter(|(render_node, matrix, _)| {
+                let visible = view.sees(render_node, *matrix);
+                culled += !visible as u32;
+                visible
+            })
+            .map(|(render_node, matrix, material_override)| DrawItem {
+                render_node,
+                material_override: scenegraph.material_override.or(material_override),
+                distance: render_node
+                    .world_center(matrix)
+                    .distance_squared(view.position),
+            })
+            .collect::<Vec<_>>();
+
+        match policy {
+            SortPolicy::State => items.sort_by(|a, b| {
+                compare_depth_bias(&a.render_node.depth_bias, &b.render_node.depth_bias)
+                    .then(a.render_node.shading.cmp(&b.render_node.shading))
+                    .then(
+                        a.material_bind_group(scenegraph)
+                            .cmp(&b.material_bind_group(scenegraph)),
+                    )
+                    .then(a.distance.total_cmp(&b.distance))
+            }),
+            SortPolicy::Depth => items.sort_by(|a, b| a.distance.total_cmp(&b.distance)),
+        }
+        let (mut items, mut transparent): (Vec<_>, Vec<_>) = items
+            .into_iter()
+            .partition(|item| !item.render_node.transparent);
+        transparent.sort_by(|a, b| b.distance.total_cmp(&a.distance));
+        items.append(&mut transparent);
+
+        Self { items, culled }
+    }
+}
+
 pub trait DrawScenegraph<'a> {
     fn draw_scenegraph(
         &mut self,
         scenegraph: &'a SceneGraph,
-        queue: &Queue,
+        pipelines: &'a PipelineVariants,
+        model_bind_group_index: u32,
         material_bind_group_index: u32,
-        model_mat_buffer: &Buffer,
-        camera_position: &Vec3,
-    );
+        view: &DrawView,
+        sort_policy: SortPolicy,
+    ) -> DrawStats;
 
     fn draw_scenegraph_vertices(
         &mut self,
         scenegraph: &'a SceneGraph,
-        queue: &Queue,
-        model_mat_buffer: &Buffer,
-    );
+        pipelines: &'a PipelineVariants,
+        model_bind_group_index: u32,
+        view: &DrawView,
+        sort_policy: SortPolicy,
+    ) -> DrawStats;
+
+    /// Draws the nodes with a custom material, which [`DrawScenegraph::draw_scenegraph`] skips, with the
+    /// pipelines for `sample_count` samples.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_custom_materials(
+        &mut self,
+        scenegraph: &'a SceneGraph,
+        custom_materials: &'a CustomMaterials,
+        sample_count: u32,
+        model_bind_group_index: u32,
+        material_bind_group_index: u32,
+        view: &DrawView,
+        sort_policy: SortPolicy,
+    ) -> DrawStats;
 }
 
 impl<'a, 'b> DrawScenegraph<'b> for RenderPass<'a>
@@ -471,64 +2092,172 @@ where
     fn draw_scenegraph(
         &mut self,
         scenegraph: &'b SceneGraph,
-        queue: &Queue,
+        pipelines: &'b PipelineVariants,
+        model_bind_group_index: u32,
         material_bind_group_index: u32,
-        model_mat_buffer: &Buffer,
-        camera_position: &Vec3,
-    ) {
-        let iterator = SceneGraphRenderNodeIterator::new(scenegraph);
-        let render_nodes: Vec<(&RenderNode, Mat4)> = iterator.collect();
+        view: &DrawView,
+        sort_policy: SortPolicy,
+    ) -> DrawStats {
+        let draw_list = DrawList::build(scenegraph, view, sort_policy);
+        let model_matrices = &scenegraph.model_matrices;
+        let mut stats = DrawStats {
+            culled: draw_list.culled,
+            ..Default::default()
+        };
+        let mut current_material: Option<&BindGroup> = None;
+        let mut current_key = None;
 
-        for render_node in render_nodes {
-            self.set_vertex_buffer(0, render_node.0.vertex_buffer.slice(..));
+        for item in &draw_list.items {
+            let render_node = item.render_node;
+            if item.custom_material().is_some() {
+                continue;
+            }
+            self.push_debug_group(&render_node.node.name);
+            let key = render_node.pipeline_key();
+            if current_key != Some(key) {
+                self.set_pipeline(&pipelines.get(&key).pipeline);
+                current_key = Some(key);
+            }
+            self.set_vertex_buffer(0, render_node.vertex_buffer.slice(..));
+            self.set_vertex_buffer(1, scenegraph.instance_buffer(render_node).slice(..));
+            self.set_vertex_buffer(2, render_node.tangent_buffer.slice(..));
             self.set_index_buffer(
-                render_node.0.index_buffer.slice(..),
+                render_node.index_buffer.slice(..),
                 wgpu::IndexFormat::Uint32,
             );
-            queue.write_buffer(
-                model_mat_buffer,
-                0,
-                bytemuck::cast_slice(&[ModelUniform {
-                    view_proj: render_node.1.to_cols_array_2d(),
-                }]),
+            self.set_bind_group(
+                model_bind_group_index,
+                &model_matrices.bind_group,
+                &[model_matrices.offset(render_node.model_slot)],
             );
-            if let Some(material_bind_group) = &render_node.0.material_bind_group {
-                self.set_bind_group(material_bind_group_index, material_bind_group, &[]);
+            if let Some(material_bind_group) = item.material_bind_group(scenegraph) {
+                if current_material != Some(material_bind_group) {
+                    self.set_bind_group(material_bind_group_index, material_bind_group, &[]);
+                    current_material = Some(material_bind_group);
+                    stats.material_switches += 1;
+                }
             } else {
                 self.set_bind_group(material_bind_group_index, None, &[]);
+                current_material = None;
                 println!(
                     "Material bind group not found for {}",
-                    render_node.0.node.name
+                    render_node.node.name
                 );
             }
-            self.draw_indexed(0..render_node.0.num_elements, 0, 0..1);
+            self.draw_indexed(
+                0..render_node.num_elements,
+                0,
+                0..render_node.instance_count(),
+            );
+            self.pop_debug_group();
+            stats.draws += 1;
         }
+        stats
     }
 
     fn draw_scenegraph_vertices(
         &mut self,
         scenegraph: &'b SceneGraph,
-        queue: &Queue,
-        model_mat_buffer: &Buffer,
-    ) {
-        let iterator = SceneGraphRenderNodeIterator::new(scenegraph);
-        let render_nodes: Vec<(&RenderNode, Mat4)> = iterator.collect();
+        pipelines: &'b PipelineVariants,
+        model_bind_group_index: u32,
+        view: &DrawView,
+        sort_policy: SortPolicy,
+    ) -> DrawStats {
+        let draw_list = DrawList::build(scenegraph, view, sort_policy);
+        let model_matrices = &scenegraph.model_matrices;
+        let mut stats = DrawStats {
+            culled: draw_list.culled,
+            ..Default::default()
+        };
+        let mut current_key = None;
 
-        for render_node in render_nodes {
-            queue.write_buffer(
-                model_mat_buffer,
+        for item in &draw_list.items {
+            self.push_debug_group(&item.render_node.node.name);
+            let key = item.render_node.pipeline_key();
+            if current_key != Some(key) {
+                self.set_pipeline(&pipelines.get(&key).pipeline);
+                current_key = Some(key);
+            }
+            self.set_bind_group(
+                model_bind_group_index,
+                &model_matrices.bind_group,
+                &[model_matrices.offset(item.render_node.model_slot)],
+            );
+            self.set_vertex_buffer(0, item.render_node.vertex_buffer.slice(..));
+            self.set_vertex_buffer(1, scenegraph.instance_buffer(item.render_node).slice(..));
+            self.set_index_buffer(
+                item.render_node.index_buffer.slice(..),
+                wgpu::IndexFormat::Uint32,
+            );
+            self.draw_indexed(
+                0..item.render_node.num_elements,
                 0,
-                bytemuck::cast_slice(&[ModelUniform {
-                    view_proj: render_node.1.to_cols_array_2d(),
-                }]),
+                0..item.render_node.instance_count(),
             );
+            self.pop_debug_group();
+            stats.draws += 1;
+        }
+        stats
+    }
 
-            self.set_vertex_buffer(0, render_node.0.vertex_buffer.slice(..));
+    fn draw_custom_materials(
+        &mut self,
+        scenegraph: &'b SceneGraph,
+        custom_materials: &'b CustomMaterials,
+        sample_count: u32,
+        model_bind_group_index: u32,
+        material_bind_group_index: u32,
+        view: &DrawView,
+        sort_policy: SortPolicy,
+    ) -> DrawStats {
+        let mut stats = DrawStats::default();
+        if custom_materials.materials.is_empty() {
+            return stats;
+        }
+        let draw_list = DrawList::build(scenegraph, view, sort_policy);
+        let model_matrices = &scenegraph.model_matrices;
+        let mut current = None;
+
+        for item in &draw_list.items {
+            let render_node = item.render_node;
+            let Some(index) = item.custom_material() else {
+                continue;
+            };
+            let Some(material) = custom_materials.materials.get(index) else {
+                continue;
+            };
+            self.push_debug_group(&render_node.node.name);
+            let key = render_node.pipeline_key();
+            if current != Some((index, key)) {
+                self.insert_debug_marker(&material.name);
+                let pipeline = material.pipeline(sample_count).get(&key);
+                self.set_pipeline(&pipeline.pipeline);
+                self.set_bind_group(material_bind_group_index, &material.bind_group, &[]);
+                if current.is_none_or(|(current_index, _)| current_index != index) {
+                    stats.material_switches += 1;
+                }
+                current = Some((index, key));
+            }
+            self.set_vertex_buffer(0, render_node.vertex_buffer.slice(..));
+            self.set_vertex_buffer(1, scenegraph.instance_buffer(render_node).slice(..));
+            self.set_vertex_buffer(2, render_node.tangent_buffer.slice(..));
             self.set_index_buffer(
-                render_node.0.index_buffer.slice(..),
+                render_node.index_buffer.slice(..),
                 wgpu::IndexFormat::Uint32,
             );
-            self.draw_indexed(0..render_node.0.num_elements, 0, 0..1);
+            self.set_bind_group(
+                model_bind_group_index,
+                &model_matrices.bind_group,
+                &[model_matrices.offset(render_node.model_slot)],
+            );
+            self.draw_indexed(
+                0..render_node.num_elements,
+                0,
+                0..render_node.instance_count(),
+            );
+            self.pop_debug_group();
+            stats.draws += 1;
         }
+        stats
     }
 }
@@ -0,0 +1,144 @@
+/*
+ * Render settings.
+ * Quality and presentation parameters of the renderer, loaded with `--settings <file>` from a TOML
+ * file. Missing fields keep their defaults, so a file only needs to list what it changes:
+ *
+ *     shadow_map_size = 4096
+ *     blur_radius = 4
+ *     shadow_mode = "pcf3x3"
+ *     clear_color = [0.0, 0.0, 0.0, 1.0]
+ *
+ *     [fog]
+ *     mode = "exponential"
+ *     color = [0.0, 0.0, 0.0]
+ *     density = 0.03
+ *
+ *     [stylize]
+ *     levels = 3
+ *     dither = true
+ *
+ *     [depth_of_field]
+ *     focus = "cursor"
+ *     aperture = 12.0
+ *
+ *     [auto_exposure]
+ *     key = 0.18
+ *     adaptation_speed = 1.5
+ *
+ *     [hdr]
+ *     paper_white = 200.0
+ *     peak_luminance = 1000.0
+ *
+ *     [color_blind]
+ *     deficiency = "deuteranopia"
+ *     filter = "simulate"
+ *
+ *     [accessibility]
+ *     reduced_motion = true
+ *     max_flash_rate = 3.0
+ */
+use crate::accessibility::AccessibilitySettings;
+use crate::color_blind::ColorBlindSettings;
+use crate::depth_of_field::DepthOfFieldSettings;
+use crate::exposure::AutoExposureSettings;
+use crate::hdr::HdrSettings;
+use crate::light::{Fog, ShadowMode};
+use crate::stylize::StylizeSettings;
+use serde::Deserialize;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderSettings {
+    /// Width and height of each layer of the spot light shadow map, the most a light can render its
+    /// shadow in, see [`crate::light::Light::shadow_resolution`].
+    pub shadow_map_size: u32,
+    /// Width and height of the cube faces of point light shadows, kept smaller as each light needs six.
+    pub point_shadow_map_size: u32,
+    /// Number of spot lights with shadows.
+    pub max_lights: u32,
+    /// Number of point lights with shadows.
+    pub max_point_lights: u32,
+    /// Frames per second the application renders at most.
+    pub frame_rate: f64,
+    /// Vertical field of view of the camera in degrees at the start.
+    pub fovy: f32,
+    /// Background behind the scene without a skybox, linear RGBA.
+    pub clear_color: [f64; 4],
+    /// Radius in texels of the box blur that filters the shadow maps.
+    pub blur_radius: u32,
+    /// Width and height of the workgroups of the blur, which are dispatched to cover a shadow map layer.
+    /// At most 16, as a workgroup has at most 256 invocations.
+    pub blur_workgroup_size: u32,
+    /// Only draws and blurs the spot light shadow map layers of lights that moved since they were last
+    /// drawn, the others are kept. All layers are drawn again once a render node moves. For scenes where
+    /// little moves, e.g. on hardware where the blur is expensive.
+    pub blur_moved_lights_only: bool,
+    /// Off skips the shadow and blur passes and draws with pipeline variants without shadows, for
+    /// hardware that can't afford them. Lights can also be kept from casting shadows one by one, see
+    /// [`crate::light::Light::cast_shadows`].
+    pub shadows_enabled: bool,
+    /// `"moments"`, `"hard"`, `"pcf3x3"` or `"pcf5x5"`, see [`ShadowMode`]. Softer filters cost more
+    /// samples per fragment.
+    pub shadow_mode: ShadowMode,
+    /// Distance fog, see [`Fog`]. `mode = "off"` in the `[fog]` table turns it off.
+    pub fog: Fog,
+    /// Posterized output with outlines, see [`StylizeSettings`]. Off without a `[stylize]` table.
+    pub stylize: Option<StylizeSettings>,
+    /// Blur of what lies in front of or behind the focus, see [`DepthOfFieldSettings`]. Off without a
+    /// `[depth_of_field]` table.
+    pub depth_of_field: Option<DepthOfFieldSettings>,
+    /// Eye adaptation to the brightness of the frame, see [`AutoExposureSettings`]. Off without an
+    /// `[auto_exposure]` table.
+    pub auto_exposure: Option<AutoExposureSettings>,
+    /// scRGB output on HDR displays, see [`HdrSettings`]. Off without an `[hdr]` table, and where the
+    /// surface has no HDR format.
+    pub hdr: Option<HdrSettings>,
+    /// Color vision deficiency the frame is simulated or corrected for, see [`ColorBlindSettings`]. Off
+    /// without a `[color_blind]` table, the debug UI can switch it at runtime.
+    pub color_blind: Option<ColorBlindSettings>,
+    /// Limits of camera motion, flashing lights and post-processing, see [`AccessibilitySettings`].
+    /// Nothing is limited without an `[accessibility]` table.
+    pub accessibility: AccessibilitySettings,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            shadow_map_size: 2048,
+            point_shadow_map_size: 512,
+            max_lights: 3,
+            max_point_lights: 1,
+            frame_rate: 60.0,
+            fovy: 45.0,
+            clear_color: [0.1, 0.2, 0.3, 1.0],
+            blur_radius: 8,
+            blur_workgroup_size: 16,
+            blur_moved_lights_only: false,
+            shadows_enabled: true,
+            shadow_mode: ShadowMode::default(),
+            fog: Fog::default(),
+            stylize: None,
+            depth_of_field: None,
+            auto_exposure: None,
+            hdr: None,
+            color_blind: None,
+            accessibility: AccessibilitySettings::default(),
+        }
+    }
+}
+
+impl RenderSettings {
+    pub fn from_toml(toml: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(toml)?)
+    }
+
+    pub fn target_frame_time(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.frame_rate.max(1.0))
+    }
+
+    pub fn clear_color(&self) -> wgpu::Color {
+        let [r, g, b, a] = self.clear_color;
+        wgpu::Color { r, g, b, a }
+    }
+}
@@ -1,187 +1,266 @@
+#import bind_groups
+#import camera
+#import lighting
+#import shadows
+#import math
+
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
     @location(2) normal: vec3<f32>,
+    // tangent and bitangent sign, see Tangent
+    @location(10) tangent: vec4<f32>,
+};
+
+// See InstanceRaw, applied in the node's local space
+struct InstanceInput {
+    @location(3) model_0: vec4<f32>,
+    @location(4) model_1: vec4<f32>,
+    @location(5) model_2: vec4<f32>,
+    @location(6) model_3: vec4<f32>,
+    @location(7) normal_0: vec3<f32>,
+    @location(8) normal_1: vec3<f32>,
+    @location(9) normal_2: vec3<f32>,
 };
 
+fn instance_model(instance: InstanceInput) -> mat4x4<f32> {
+    return mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+}
+
+fn instance_normal(instance: InstanceInput) -> mat3x3<f32> {
+    return mat3x3<f32>(instance.normal_0, instance.normal_1, instance.normal_2);
+}
+
 struct VertexOutput {
     @builtin(position) out_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
     @location(1) world_position: vec4<f32>,
     @location(2) world_normal: vec3<f32>,
+    @location(3) world_tangent: vec4<f32>,
 };
 
-struct Camera {
-    view_proj: mat4x4<f32>,
-    position: vec4<f32>,
-};
-
-@group(0) @binding(0)
-var<uniform> camera: Camera;
-
 struct Model {
     model: mat4x4<f32>,
+    // inverse-transpose of the upper 3x3, keeps normals perpendicular under non-uniform scaling
+    normal: mat3x3<f32>,
 };
 
-@group(1) @binding(0)
+@group(MODEL_GROUP) @binding(0)
 var<uniform> model: Model;
 
 @vertex
 fn vs_main(
     in: VertexInput,
+    instance: InstanceInput,
 ) -> VertexOutput {
-    let world = model.model;
+    let world = model.model * instance_model(instance);
     let world_position = world * vec4<f32>(in.position, 1.0);
     var out = VertexOutput();
     out.out_position = camera.view_proj * world_position;
     out.tex_coords = in.tex_coords;
     out.world_position = world_position;
-    out.world_normal = normalize(mat3x3<f32>(world[0].xyz, world[1].xyz, world[2].xyz) * in.normal);
+    out.world_normal = normalize(model.normal * instance_normal(instance) * in.normal);
+    out.world_tangent = vec4<f32>(normalize((world * vec4<f32>(in.tangent.xyz, 0.0)).xyz), in.tangent.w);
     return out;
 }
 
-struct Light {
-    position: vec4<f32>,
-    color: vec4<f32>,
-    model: mat4x4<f32>,
-    view_proj: mat4x4<f32>,
-}
-@group(3) @binding(0)
-var<storage, read> s_lights: array<Light>;
-@group(3) @binding(0)
-var<uniform> u_lights: array<Light, 10>;
-@group(3) @binding(1) var t_shadow: texture_2d_array<f32>;
-@group(3) @binding(2) var sampler_shadow: sampler;
-
-fn fetch_shadow(light_id: u32, ls_pos: vec4<f32>) -> f32 {
-    if (ls_pos.w <= 0.0) {
-        return 1.0;
-    }
-
-    // compensate for the Y-flip difference between the NDC and texture coordinates
-    let flip_correction = vec2<f32>(0.5, -0.5);
-    // compute texture coordinates for shadow lookup
-    let proj_correction = 1.0 / ls_pos.w;
-    let light_local = ls_pos.xy * flip_correction * proj_correction + vec2<f32>(0.5, 0.5);
-    let depth = ls_pos.z * proj_correction;
-
-    let moments = textureSampleLevel(t_shadow, sampler_shadow, light_local, i32(light_id), 0.0);
-    let reversed_moments = convert_optimized_moments(moments);
-
-    return reduce_light_bleeding(
-        compute_msm_shadow_intensity(reversed_moments, depth),
-        0.0
-    );
-}
-
-// Reverts the projection of the moments done in the shadow pass
-fn convert_optimized_moments(optimized: vec4<f32>) -> vec4<f32> {
-    var adjusted = optimized;
-    adjusted.x -= 0.035955884801;
-
-    let M_inv = mat4x4<f32>(
-        vec4<f32>(0.2227744146,  0.1549679261,  0.1451988946,  0.163127443),
-        vec4<f32>(0.0771972861,  0.1394629426,  0.2120202157,  0.2591432266),
-        vec4<f32>(0.7926986636,  0.7963415838,  0.7258694464,  0.6539092497),
-        vec4<f32>(0.0319417555, -0.1722823173, -0.2758014811, -0.3376131734)
-    );
-
-    return M_inv * adjusted;
-}
-
-fn compute_msm_shadow_intensity(moments: vec4<f32>, fragment_depth: f32) -> f32 {
-    let b = mix(moments, vec4<f32>(0.5), 0.03);
-    var z: vec3<f32>;
-    z.x = fragment_depth;
-
-    // cholesky
-    let l32_d22 = -b.x * b.y + b.z;
-    let d22 = -b.x * b.x + b.y;
-    let squared_depth_var = -b.y * b.y + b.w;
-
-    let d33_d22 = dot(
-        vec2<f32>(squared_depth_var, -l32_d22),
-        vec2<f32>(d22,          l32_d22)
-    );
-
-    let inv_d22 = 1.0 / d22;
-    let l32 = l32_d22 * inv_d22;
-
-    // build quadratic equation to find z1 and z2
-    var c = vec3<f32>(1.0, z.x, z.x * z.x);
-
-    c.y -= b.x;
-    c.z -= b.y + l32 * c.y;
-    c.y *= inv_d22;
-    c.z *= d22 / d33_d22;
-    c.y -= l32 * c.z;
-    c.x -= dot(c.yz, b.xy);
-
-    let p = c.y / c.z;
-    let q = c.x / c.z;
-    let radicand = (p * p * 0.25) - q;
-    let r = sqrt(max(radicand, 0.0));
-    z.y = -0.5 * p - r;
-    z.z = -0.5 * p + r;
-
-    var switch_vals = vec4<f32>(0.0);
-    // z2 < z0  → [ z1, z0, 1, 1 ]
-    if (z.z < z.x) {
-        switch_vals = vec4<f32>(z.y, z.x, 1.0, 1.0);
-    } else if (z.y < z.x) {
-        // z1 < z0  → [ z0, z1, 0, 1 ]
-        switch_vals = vec4<f32>(z.x, z.y, 0.0, 1.0);
-    } else {
-        switch_vals = vec4<f32>(0.0, 0.0, 0.0, 0.0);
-    }
-
-    let numerator = switch_vals.x * z.z - b.x * (switch_vals.x + z.z + b.y);
-    let denominator = (z.z - switch_vals.y) * (z.x - z.y);
-    let quotient = numerator / denominator;
-
-    let raw_light = switch_vals.z + switch_vals.w * quotient;
-    let intensity = clamp(raw_light, 0.0, 1.0);
-
-    return 1.0 - intensity;
-}
-
-fn reduce_light_bleeding(p_max: f32, amount: f32) -> f32 {
-    return clamp((p_max - amount) / (1.0 - amount), 0.0, 1.0);
-}
-
 struct Material {
     ambient: vec4<f32>,
     diffuse: vec4<f32>,
     specular: vec4<f32>,
     shininess: f32,
     dissolve: f32,
+    ior: f32,
+    roughness: f32,
+    // scattering color and wrap factor, see Subsurface
+    subsurface: vec4<f32>,
+    // strength and tangent rotation, see Anisotropy
+    anisotropy: vec2<f32>,
+    // strength and roughness, see Clearcoat
+    clearcoat: vec2<f32>,
+    // metallic factor of the PBR path, see pbr.wgsl
+    metallic: f32,
+    // rim light strength of the toon path, see toon.wgsl
+    toon_rim: f32,
+    // emitted light, above 1 for surfaces brighter than white
+    emissive: vec4<f32>,
 };
 
-@group(2) @binding(0)
+// Material features the pipeline is built for, see MaterialFeatures. The variant of a material without
+// a feature has a constant false in its place, so the feature's code is left out of the shader.
+override ALPHA_TEST: bool = false;
+override ANISOTROPY: bool = true;
+override CLEARCOAT: bool = true;
+// diffuse alpha below which alpha tested fragments are discarded
+const ALPHA_CUTOFF: f32 = 0.5;
+
+@group(MATERIAL_GROUP) @binding(0)
 var t_diffuse: texture_2d<f32>;
-@group(2) @binding(1)
+@group(MATERIAL_GROUP) @binding(1)
 var s_diffuse: sampler;
-@group(2) @binding(2)
+@group(MATERIAL_GROUP) @binding(2)
 var<uniform> material: Material;
+@group(MATERIAL_GROUP) @binding(3)
+var t_specular: texture_2d<f32>;
+@group(MATERIAL_GROUP) @binding(4)
+var t_shininess: texture_2d<f32>;
+@group(MATERIAL_GROUP) @binding(9)
+var t_emissive: texture_2d<f32>;
+
+// Specular parameters of the material at a fragment, after applying the specular and shininess maps.
+struct Surface {
+    specular: vec3<f32>,
+    shininess: f32,
+    roughness: f32,
+};
 
-fn phong (light: Light, normal: vec3<f32>, in: VertexOutput) -> vec3<f32> {
+fn sample_surface(tex_coords: vec2<f32>) -> Surface {
+    let specular_map = textureSample(t_specular, s_diffuse, tex_coords).rgb;
+    let shininess_map = textureSample(t_shininess, s_diffuse, tex_coords).r;
+    var surface: Surface;
+    surface.specular = material.specular.xyz * specular_map;
+    surface.shininess = material.shininess * shininess_map;
+    // a black shininess map is fully rough, a white one keeps the roughness of the material
+    surface.roughness = mix(1.0, material.roughness, shininess_map);
+    return surface;
+}
+
+// Cutout of alpha tested materials, the surface is either there or not.
+fn alpha_test(alpha: f32) {
+    if (ALPHA_TEST && alpha < ALPHA_CUTOFF) {
+        discard;
+    }
+}
+
+// Light the surface gives off by itself, added after the lighting. A bloom pass can take the parts
+// brighter than white from it.
+fn emission(tex_coords: vec2<f32>) -> vec3<f32> {
+    return material.emissive.rgb * textureSample(t_emissive, s_diffuse, tex_coords).rgb;
+}
+
+fn phong (light: Light, normal: vec3<f32>, surface: Surface, in: VertexOutput) -> vec3<f32> {
     let light_world_position = light.model * light.position;
     let light_dir = normalize(light_world_position.xyz - in.world_position.xyz);
 
-    let diffuse = max(0.0, dot(normal, light_dir));
+    let n_dot_l = dot(normal, light_dir);
+    let lambert = max(0.0, n_dot_l);
+    // wrap lighting, the part reaching past the terminator takes the scattering color
+    let wrap = material.subsurface.a;
+    let wrapped = max(0.0, (n_dot_l + wrap) / (1.0 + wrap));
+    let diffuse = lambert + material.subsurface.rgb * (wrapped - lambert);
 
     let view_dir = normalize(camera.position.xyz - in.world_position.xyz);
-    let reflect_dir  = reflect(-light_dir, in.world_normal);
-    let specular = pow(max(0.0, dot(normal, reflect_dir)), (10 * material.shininess));
+    var specular: f32;
+    if (ANISOTROPY && material.anisotropy.x != 0.0) {
+        specular = anisotropic_specular(normal, in.world_tangent, light_dir, view_dir, surface.roughness) * lambert;
+    } else {
+        let reflect_dir  = reflect(-light_dir, in.world_normal);
+        specular = pow(max(0.0, dot(normal, reflect_dir)), (10 * surface.shininess));
+    }
 
-    return diffuse * light.color.xyz + specular * material.specular.xyz;
+    let base = diffuse * light.color.xyz + specular * surface.specular;
+    let falloff = light_falloff(light, in.world_position.xyz);
+    if (CLEARCOAT && material.clearcoat.x > 0.0) {
+        return apply_clearcoat(base, light, normal, light_dir, view_dir) * falloff;
+    }
+    return base * falloff;
+}
+
+// Isotropic GGX lobe of the clearcoat with the fresnel of an index of refraction of 1.5, the light
+// reflected by the coat doesn't reach the base layer.
+fn apply_clearcoat(base: vec3<f32>, light: Light, normal: vec3<f32>, light_dir: vec3<f32>, view_dir: vec3<f32>) -> vec3<f32> {
+    let alpha = max(material.clearcoat.y * material.clearcoat.y, 0.002);
+    let alpha2 = alpha * alpha;
+    let h = normalize(light_dir + view_dir);
+    let nh = max(dot(normal, h), 0.0);
+    let lh = max(dot(light_dir, h), 0.0);
+    let d = nh * nh * (alpha2 - 1.0) + 1.0;
+    let distribution = alpha2 / (PI * d * d);
+    let visibility = 0.25 / max(lh * lh, 0.01);
+    let fresnel = (0.04 + 0.96 * pow(1.0 - lh, 5.0)) * material.clearcoat.x;
+    let coat = distribution * visibility * fresnel * max(dot(normal, light_dir), 0.0);
+    return base * (1.0 - fresnel) + coat * light.color.xyz;
+}
+
+// Anisotropic GGX distribution with the Kelemen visibility term, the highlight is stretched along the
+// tangent by positive and along the bitangent by negative strengths.
+fn anisotropic_specular(normal: vec3<f32>, tangent: vec4<f32>, light_dir: vec3<f32>, view_dir: vec3<f32>, roughness: f32) -> f32 {
+    let t0 = normalize(tangent.xyz - normal * dot(normal, tangent.xyz));
+    let b0 = cross(normal, t0) * tangent.w;
+    let rotation = material.anisotropy.y;
+    let t = cos(rotation) * t0 + sin(rotation) * b0;
+    let b = cross(normal, t) * tangent.w;
+
+    let alpha = max(roughness * roughness, 0.002);
+    let strength = material.anisotropy.x;
+    let alpha_t = max(alpha * (1.0 + strength), 0.002);
+    let alpha_b = max(alpha * (1.0 - strength), 0.002);
+
+    let h = normalize(light_dir + view_dir);
+    let th = dot(t, h) / alpha_t;
+    let bh = dot(b, h) / alpha_b;
+    let nh = dot(normal, h);
+    let denominator = th * th + bh * bh + nh * nh;
+    let distribution = 1.0 / (PI * alpha_t * alpha_b * denominator * denominator);
+    let lh = dot(light_dir, h);
+    return distribution * 0.25 / max(lh * lh, 0.01);
+}
+
+const DEBUG_VIEW_OFF: u32 = 0u;
+const DEBUG_VIEW_NORMALS: u32 = 1u;
+const DEBUG_VIEW_TANGENTS: u32 = 2u;
+const DEBUG_VIEW_DEPTH: u32 = 3u;
+const DEBUG_VIEW_ALBEDO: u32 = 5u;
+const DEBUG_VIEW_UV_CHECKER: u32 = 6u;
+// checker cells along each texture coordinate from 0 to 1
+const DEBUG_CHECKER_CELLS: f32 = 8.0;
+// distance in world units at which the depth view is half as bright as at the camera
+const DEBUG_DEPTH_HALF_DISTANCE: f32 = 10.0;
+
+// Color of the fragment in the debug views, see DebugView
+fn debug_view_color(in: VertexOutput) -> vec4<f32> {
+    switch camera.debug_view {
+        case DEBUG_VIEW_NORMALS: {
+            return vec4<f32>(normalize(in.world_normal) * 0.5 + 0.5, 1.0);
+        }
+        case DEBUG_VIEW_TANGENTS: {
+            return vec4<f32>(in.world_tangent.xyz * 0.5 + 0.5, 1.0);
+        }
+        case DEBUG_VIEW_DEPTH: {
+            let distance = length(camera.position.xyz - in.world_position.xyz);
+            return vec4<f32>(vec3<f32>(DEBUG_DEPTH_HALF_DISTANCE / (DEBUG_DEPTH_HALF_DISTANCE + distance)), 1.0);
+        }
+        case DEBUG_VIEW_ALBEDO: {
+            var albedo = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+            // same fallback to the material color as fs_main
+            if (all(albedo == vec4<f32>(0.0))) {
+                albedo = vec4<f32>(material.diffuse.rgb, material.dissolve);
+            }
+            alpha_test(albedo.a);
+            return albedo;
+        }
+        case DEBUG_VIEW_UV_CHECKER: {
+            let cell = floor(in.tex_coords * DEBUG_CHECKER_CELLS);
+            let checker = abs(cell.x + cell.y) % 2.0;
+            // tinted by the coordinates so that flipped and rotated islands stand out
+            let tint = vec3<f32>(0.5 + 0.5 * fract(in.tex_coords), 1.0);
+            return vec4<f32>(mix(vec3<f32>(0.2), vec3<f32>(0.9), checker) * tint, 1.0);
+        }
+        default: {
+            return vec4<f32>(fract(in.tex_coords), 0.0, 1.0);
+        }
+    }
 }
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    if (camera.debug_view != DEBUG_VIEW_OFF) {
+        return debug_view_color(in);
+    }
     var texture_result = textureSample(t_diffuse, s_diffuse, in.tex_coords);
     var material_color = texture_result;
     let normal = normalize(in.world_normal);
+    let surface = sample_surface(in.tex_coords);
 
     if (texture_result.r == 0.0 && texture_result.g == 0.0 && texture_result.b == 0.0 && texture_result.a == 0.0) {
         material_color = vec4<f32>(
@@ -191,23 +270,28 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
             material.dissolve
         );
     }
-    var light_color: vec3<f32> = vec3<f32>(0.3, 0.3, 0.3);
-    for (var i = 0u; i < 3; i += 1u) {
+    alpha_test(material_color.a);
+    var light_color: vec3<f32> = ambient_light(normal);
+    for (var i = 0u; i < min(light_count, arrayLength(&s_lights)); i += 1u) {
         let light = s_lights[i];
-        let light_proj = light.view_proj * in.world_position;
-        let shadow = fetch_shadow(i, light_proj);
+        let shadow = light_shadow(light, in.world_position);
 
-        light_color += phong(light, normal, in) * shadow;
+        light_color += phong(light, normal, surface, in) * shadow;
     }
 
-    return vec4<f32>(light_color, 1.0) * material_color;
+    let color = vec4<f32>(light_color, 1.0) * material_color;
+    return vec4<f32>(apply_fog(color.rgb + emission(in.tex_coords), in.world_position), color.a);
 }
 
 @fragment
 fn fs_main_without_storage(in: VertexOutput) -> @location(0) vec4<f32> {
+    if (camera.debug_view != DEBUG_VIEW_OFF) {
+        return debug_view_color(in);
+    }
     var texture_result = textureSample(t_diffuse, s_diffuse, in.tex_coords);
         var material_color = texture_result;
         let normal = normalize(in.world_normal);
+        let surface = sample_surface(in.tex_coords);
 
         if (texture_result.r == 0.0 && texture_result.g == 0.0 && texture_result.b == 0.0 && texture_result.a == 0.0) {
             material_color = vec4<f32>(
@@ -217,14 +301,15 @@ fn fs_main_without_storage(in: VertexOutput) -> @location(0) vec4<f32> {
                 material.dissolve
             );
         }
-        var light_color: vec3<f32> = vec3<f32>(0.3, 0.3, 0.3);
-        for (var i = 0u; i < 3; i += 1u) {
+        alpha_test(material_color.a);
+        var light_color: vec3<f32> = ambient_light(normal);
+        for (var i = 0u; i < min(light_count, 10u); i += 1u) {
             let light = u_lights[i];
-            let light_proj = light.view_proj * in.world_position;
-            let shadow = fetch_shadow(i, light_proj);
+            let shadow = light_shadow(light, in.world_position);
 
-            light_color += phong(light, normal, in) * shadow;
+            light_color += phong(light, normal, surface, in) * shadow;
         }
 
-        return vec4<f32>(light_color, 1.0) * material_color;
+        let color = vec4<f32>(light_color, 1.0) * material_color;
+        return vec4<f32>(apply_fog(color.rgb + emission(in.tex_coords), in.world_position), color.a);
 }
\ No newline at end of file
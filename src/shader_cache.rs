@@ -0,0 +1,126 @@
+/*
+ * Shader cache on disk.
+ * Startup creates a pipeline per pass, sample count and material variant, most of its time goes into
+ * compiling their shaders. Where the device supports pipeline caches (Vulkan), the compiled pipelines are
+ * kept in a wgpu pipeline cache that is written to `shader_cache/` under a key of the adapter and driver,
+ * and read back at the next start so the driver can skip compiling them again. The reflection of the WGSL
+ * sources, see `reflection.rs`, is stored next to it under a hash of the source, so naga doesn't parse and
+ * validate the sources on each start either. Entries of other sources or adapters are simply not found,
+ * deleting the directory empties the cache.
+ */
+use crate::reflection::ShaderReflection;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+const CACHE_DIR: &str = "shader_cache";
+
+struct DevicePipelineCache {
+    cache: wgpu::PipelineCache,
+    path: PathBuf,
+    /// Set when pipelines were created with the cache since it was last written.
+    modified: bool,
+}
+
+thread_local! {
+    static PIPELINE_CACHES: RefCell<HashMap<wgpu::Device, DevicePipelineCache>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Creates the pipeline cache of `device` from the data the last run stored for its adapter. Does nothing
+/// if the device lacks [`wgpu::Features::PIPELINE_CACHE`] or its backend has no cache key.
+pub fn init(device: &wgpu::Device, adapter_info: &wgpu::AdapterInfo) {
+    if !device.features().contains(wgpu::Features::PIPELINE_CACHE) {
+        return;
+    }
+    let Some(key) = wgpu::util::pipeline_cache_key(adapter_info) else {
+        return;
+    };
+    let path = Path::new(CACHE_DIR).join(key);
+    let data = std::fs::read(&path).ok();
+    // SAFETY: the data was returned by `PipelineCache::get_data` for an adapter with the same cache key,
+    // unless the file was replaced; with `fallback` the driver validates it and starts empty if it's stale
+    let cache = unsafe {
+        device.create_pipeline_cache(&wgpu::PipelineCacheDescriptor {
+            label: Some("pipeline_cache"),
+            data: data.as_deref(),
+            fallback: true,
+        })
+    };
+    PIPELINE_CACHES.with_borrow_mut(|caches| {
+        caches.insert(
+            device.clone(),
+            DevicePipelineCache {
+                cache,
+                path,
+                modified: false,
+            },
+        )
+    });
+}
+
+/// The cache to create pipelines of `device` with, None if it has none. The cache is written at the next
+/// [`save`].
+pub fn pipeline_cache(device: &wgpu::Device) -> Option<wgpu::PipelineCache> {
+    PIPELINE_CACHES.with_borrow_mut(|caches| {
+        let entry = caches.get_mut(device)?;
+        entry.modified = true;
+        Some(entry.cache.clone())
+    })
+}
+
+/// Writes the pipeline cache of `device` if pipelines were created with it since it was last written.
+pub fn save(device: &wgpu::Device) {
+    PIPELINE_CACHES.with_borrow_mut(|caches| {
+        let Some(entry) = caches.get_mut(device) else {
+            return;
+        };
+        if !std::mem::take(&mut entry.modified) {
+            return;
+        }
+        if let Some(data) = entry.cache.get_data() {
+            if let Err(e) = write(&entry.path, &data) {
+                println!("Failed to write the pipeline cache: {e}");
+            }
+        }
+    });
+}
+
+/// The reflection stored for the WGSL `source`, if an earlier run reflected the same source.
+pub fn load_reflection(source: &str) -> Option<ShaderReflection> {
+    let data = std::fs::read(reflection_path(source)).ok()?;
+    serde_json::from_slice(&data).ok()
+}
+
+pub fn store_reflection(source: &str, reflection: &ShaderReflection) {
+    let result = serde_json::to_vec(reflection)
+        .map_err(std::io::Error::from)
+        .and_then(|data| write(&reflection_path(source), &data));
+    if let Err(e) = result {
+        println!("Failed to write the shader reflection cache: {e}");
+    }
+}
+
+fn reflection_path(source: &str) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    source.hash(&mut hasher);
+    Path::new(CACHE_DIR).join(format!("reflection_{:016x}.json", hasher.finish()))
+}
+
+/// Writes `data` to a temporary file first, so a run that is killed while writing doesn't leave a
+/// truncated entry behind.
+#[cfg(not(target_arch = "wasm32"))]
+fn write(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    std::fs::create_dir_all(CACHE_DIR)?;
+    let temporary = path.with_extension("tmp");
+    std::fs::write(&temporary, data)?;
+    std::fs::rename(temporary, path)
+}
+
+/// There is no file system to cache to on the web.
+#[cfg(target_arch = "wasm32")]
+fn write(_path: &Path, _data: &[u8]) -> std::io::Result<()> {
+    Ok(())
+}
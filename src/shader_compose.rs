@@ -0,0 +1,68 @@
+/*
+ * Shader composition.
+ * Code shared between shaders lives in WGSL snippets that a shader pulls in with `#import <name>` lines,
+ * e.g. `#import shadows` for the shadow sampling of the forward pass. The imports are resolved before the
+ * source is handed to wgpu: each snippet is inserted once per shader, however often it is imported, also
+ * by other snippets. WGSL declarations can come in any order, so the place of an import doesn't matter.
+ * `shadow_mode` is the sampling of the shadow mode the shader is composed for and `bind_groups` the group
+ * indices of bind_groups.rs, the other snippets are the files of the same name:
+ *
+ *     bind_groups   CAMERA_GROUP, MODEL_GROUP, MATERIAL_GROUP and LIGHTS_GROUP
+ *     camera        the camera uniform
+ *     lighting      the lights, the ambient light, the fog and the light falloff
+ *     shadows       the shadows of the lights, sampled through shadow_mode
+ *     msm           moment shadow mapping
+ *     math          PI
+ */
+use crate::bind_groups;
+use crate::light::ShadowMode;
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+fn snippet(name: &str, shadow_mode: ShadowMode) -> Option<Cow<'static, str>> {
+    Some(match name {
+        "bind_groups" => Cow::Owned(bind_groups::wgsl()),
+        "shadow_mode" => Cow::Owned(shadow_mode.wgsl()),
+        "camera" => Cow::Borrowed(include_str!("camera.wgsl")),
+        "lighting" => Cow::Borrowed(include_str!("lighting.wgsl")),
+        "shadows" => Cow::Borrowed(include_str!("shadows.wgsl")),
+        "msm" => Cow::Borrowed(include_str!("msm.wgsl")),
+        "math" => Cow::Borrowed(include_str!("math.wgsl")),
+        _ => return None,
+    })
+}
+
+/// Joins `sources` into one shader and resolves their imports, with the sampling of `shadow_mode`.
+/// Unknown imports are reported and left out, the shader then fails to validate on what they declare.
+pub fn compose(sources: &[&str], shadow_mode: ShadowMode) -> String {
+    let mut imported = HashSet::new();
+    let mut composed = String::new();
+    for source in sources {
+        append(source, shadow_mode, &mut imported, &mut composed);
+    }
+    composed
+}
+
+fn append(
+    source: &str,
+    shadow_mode: ShadowMode,
+    imported: &mut HashSet<String>,
+    composed: &mut String,
+) {
+    for line in source.lines() {
+        let Some(name) = line.trim().strip_prefix("#import ") else {
+            composed.push_str(line);
+            composed.push('\n');
+            continue;
+        };
+        let name = name.trim();
+        // marked before it is inserted, so snippets importing each other don't recurse
+        if !imported.insert(name.to_string()) {
+            continue;
+        }
+        match snippet(name, shadow_mode) {
+            Some(snippet) => append(&snippet, shadow_mode, imported, composed),
+            None => println!("Unknown shader snippet {name}"),
+        }
+    }
+}
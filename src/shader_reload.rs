@@ -0,0 +1,95 @@
+/*
+ * Shader hot reload.
+ * Natively the renderer watches the sources of the forward, shadow and Gaussian blur shaders in `src/`
+ * and rebuilds their pipelines when one of them is saved, so a shader can be changed while the demo runs.
+ * The files are polled for their modification time a few times per second, there is no file watcher
+ * dependency. A source that doesn't compile or a pipeline that fails to build is reported and the
+ * pipelines keep the shader they had. The modules a source imports, and the glass, pick, multiview and
+ * custom material pipelines, keep the sources the binary was built with.
+ */
+use instant::Instant;
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+
+/// A shader module that pipeline build functions read when they build a variant, replaced by a reload.
+pub type ShaderSlot = Rc<RefCell<wgpu::ShaderModule>>;
+
+/// Time between two checks of the modification times.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The shaders that can be reloaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchedShader {
+    Forward,
+    Shadow,
+    Gaussian,
+}
+
+impl WatchedShader {
+    const ALL: [WatchedShader; 3] = [Self::Forward, Self::Shadow, Self::Gaussian];
+
+    pub fn file_name(self) -> &'static str {
+        match self {
+            WatchedShader::Forward => "shader.wgsl",
+            WatchedShader::Shadow => "shadow.wgsl",
+            WatchedShader::Gaussian => "gaussian.wgsl",
+        }
+    }
+
+    /// The source in the source tree the binary was built from.
+    pub fn path(self) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("src")
+            .join(self.file_name())
+    }
+}
+
+/// Polls the watched shader sources for changes.
+pub struct ShaderWatcher {
+    modified: Vec<(WatchedShader, Option<SystemTime>)>,
+    last_poll: Instant,
+}
+
+impl ShaderWatcher {
+    /// None if none of the sources exist, e.g. for a binary that was copied away from its sources.
+    pub fn new() -> Option<Self> {
+        let modified: Vec<_> = WatchedShader::ALL
+            .into_iter()
+            .map(|shader| (shader, modified_time(shader)))
+            .collect();
+        if modified.iter().all(|(_, time)| time.is_none()) {
+            return None;
+        }
+        println!(
+            "Watching the shaders in {} for changes",
+            WatchedShader::Forward.path().parent()?.display()
+        );
+        Some(Self {
+            modified,
+            last_poll: Instant::now(),
+        })
+    }
+
+    /// The shaders whose source was saved since the last call.
+    pub fn changed(&mut self) -> Vec<WatchedShader> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+        let mut changed = Vec::new();
+        for (shader, last_modified) in &mut self.modified {
+            let modified = modified_time(*shader);
+            if modified.is_some() && modified != *last_modified {
+                *last_modified = modified;
+                changed.push(*shader);
+            }
+        }
+        changed
+    }
+}
+
+fn modified_time(shader: WatchedShader) -> Option<SystemTime> {
+    std::fs::metadata(shader.path()).ok()?.modified().ok()
+}
@@ -1,19 +1,26 @@
+#import bind_groups
+
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
     @location(2) normal: vec3<f32>,
 };
 
-struct Camera {
-    view_proj: mat4x4<f32>,
-    position: vec4<f32>,
+// Transform columns of InstanceRaw, the normal matrix is not needed for depth
+struct InstanceInput {
+    @location(3) model_0: vec4<f32>,
+    @location(4) model_1: vec4<f32>,
+    @location(5) model_2: vec4<f32>,
+    @location(6) model_3: vec4<f32>,
 };
 
-@group(0) @binding(0)
-var<uniform> camera: Camera;
+// the layer cameras of shadow_multiview.wgsl have the same stride
+#import camera
 
 struct Model {
     model: mat4x4<f32>,
+    // inverse-transpose of the upper 3x3, keeps normals perpendicular under non-uniform scaling
+    normal: mat3x3<f32>,
 };
 
 struct VertexOutput {
@@ -21,12 +28,13 @@ struct VertexOutput {
     @location(0) depth: f32,
 };
 
-@group(1) @binding(0)
+@group(MODEL_GROUP) @binding(0)
 var<uniform> model: Model;
 
 @vertex
-fn vs_shadow(in: VertexInput) -> VertexOutput {
-    let world_pos = model.model * vec4<f32>(in.position, 1.0);
+fn vs_shadow(in: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let instance_model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    let world_pos = model.model * instance_model * vec4<f32>(in.position, 1.0);
     let view_pos = camera.view_proj * world_pos;
 
     var out: VertexOutput;
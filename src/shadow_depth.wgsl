@@ -0,0 +1,51 @@
+// Shadow map bindings and sampling of ShadowMode::Hard, ShadowMode::Pcf3x3 and ShadowMode::Pcf5x5, imported as shadow_mode by
+// shadows.wgsl. The shadow maps are depth attachments compared with the fragment depth by the comparison
+// sampler.
+// PCF_RADIUS is declared in front, see ShadowMode::pcf_radius. 0 takes a single sample, larger radii
+// average a square of (2 * PCF_RADIUS + 1)^2 bilinear comparisons.
+
+@group(LIGHTS_GROUP) @binding(1) var t_shadow: texture_depth_2d_array;
+@group(LIGHTS_GROUP) @binding(2) var sampler_shadow: sampler_comparison;
+@group(LIGHTS_GROUP) @binding(4) var t_point_shadow: texture_depth_2d_array;
+
+// Keeps surfaces from shadowing themselves, in addition to the slope scaled bias of the shadow pipeline
+const SHADOW_DEPTH_BIAS: f32 = 0.0005;
+
+fn sample_shadow(coords: vec3<f32>, layer: i32) -> f32 {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_shadow));
+    var visibility = 0.0;
+    for (var y = -PCF_RADIUS; y <= PCF_RADIUS; y += 1) {
+        for (var x = -PCF_RADIUS; x <= PCF_RADIUS; x += 1) {
+            let uv = coords.xy + vec2<f32>(f32(x), f32(y)) * texel;
+            visibility += textureSampleCompareLevel(t_shadow, sampler_shadow, uv, layer, coords.z - SHADOW_DEPTH_BIAS);
+        }
+    }
+    let taps = f32((2 * PCF_RADIUS + 1) * (2 * PCF_RADIUS + 1));
+    return visibility / taps;
+}
+
+fn sample_point_shadow(coords: vec3<f32>, layer: i32) -> f32 {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_point_shadow));
+    var visibility = 0.0;
+    for (var y = -PCF_RADIUS; y <= PCF_RADIUS; y += 1) {
+        for (var x = -PCF_RADIUS; x <= PCF_RADIUS; x += 1) {
+            let uv = coords.xy + vec2<f32>(f32(x), f32(y)) * texel;
+            visibility += textureSampleCompareLevel(t_point_shadow, sampler_shadow, uv, layer, coords.z - SHADOW_DEPTH_BIAS);
+        }
+    }
+    let taps = f32((2 * PCF_RADIUS + 1) * (2 * PCF_RADIUS + 1));
+    return visibility / taps;
+}
+
+// The stored depth at `uv` in x, for the pick readout
+fn shadow_texel(uv: vec2<f32>, layer: i32) -> vec4<f32> {
+    let size = textureDimensions(t_shadow);
+    let texel = min(vec2<u32>(uv * vec2<f32>(size)), size - 1u);
+    return vec4<f32>(textureLoad(t_shadow, texel, layer, 0), 0.0, 0.0, 0.0);
+}
+
+fn point_shadow_texel(uv: vec2<f32>, layer: i32) -> vec4<f32> {
+    let size = textureDimensions(t_point_shadow);
+    let texel = min(vec2<u32>(uv * vec2<f32>(size)), size - 1u);
+    return vec4<f32>(textureLoad(t_point_shadow, texel, layer, 0), 0.0, 0.0, 0.0);
+}
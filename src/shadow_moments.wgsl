@@ -0,0 +1,25 @@
+// Shadow map bindings and sampling of ShadowMode::Moments, imported as shadow_mode by shadows.wgsl. The
+// shadow maps hold the optimized moments written by fs_shadow in shadow.wgsl.
+#import msm
+
+@group(LIGHTS_GROUP) @binding(1) var t_shadow: texture_2d_array<f32>;
+@group(LIGHTS_GROUP) @binding(2) var sampler_shadow: sampler;
+@group(LIGHTS_GROUP) @binding(4) var t_point_shadow: texture_2d_array<f32>;
+
+// Light visibility of the shadow map layer at the shadow coordinates `coords`, see shadow_coords
+fn sample_shadow(coords: vec3<f32>, layer: i32) -> f32 {
+    return msm_shadow(shadow_texel(coords.xy, layer), coords.z);
+}
+
+fn sample_point_shadow(coords: vec3<f32>, layer: i32) -> f32 {
+    return msm_shadow(point_shadow_texel(coords.xy, layer), coords.z);
+}
+
+// What the shadow map stores at `uv`, for the pick readout
+fn shadow_texel(uv: vec2<f32>, layer: i32) -> vec4<f32> {
+    return textureSampleLevel(t_shadow, sampler_shadow, uv, layer, 0.0);
+}
+
+fn point_shadow_texel(uv: vec2<f32>, layer: i32) -> vec4<f32> {
+    return textureSampleLevel(t_point_shadow, sampler_shadow, uv, layer, 0.0);
+}
@@ -0,0 +1,20 @@
+// Appended to shadow.wgsl when the adapter supports multiview,
+// so a shadow pass can render up to six shadow map layers at once, one camera per layer.
+@group(CAMERA_GROUP) @binding(0)
+var<uniform> layer_cameras: array<Camera, 6>;
+
+@vertex
+fn vs_shadow_multiview(
+    in: VertexInput,
+    instance: InstanceInput,
+    @builtin(view_index) view_index: i32,
+) -> VertexOutput {
+    let instance_model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    let world_pos = model.model * instance_model * vec4<f32>(in.position, 1.0);
+    let view_pos = layer_cameras[view_index].view_proj * world_pos;
+
+    var out: VertexOutput;
+    out.position = view_pos;
+    out.depth = view_pos.z / view_pos.w;
+    return out;
+}
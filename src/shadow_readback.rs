@@ -0,0 +1,243 @@
+/*
+ * Shadow map readback.
+ * Copies the shadow map layers of a light back to the CPU to check the shadow pass numerically. For moment
+ * shadow maps the depth and its variance are reconstructed from the optimized moments the same way
+ * msm.wgsl does it, so a wrong quantization or blur shows up as negative variances; depth shadow maps are
+ * read as they are. Each layer reports the smallest, largest and mean depth of the texels something was
+ * drawn into and can be saved as a grayscale PNG that stretches that range to black and white, with the
+ * empty texels white. The debug UI inspects a light with its "inspect shadow map" button.
+ */
+use crate::light::ShadowMap;
+use crate::scenegraph::{Node, SceneGraph};
+use glam::{Mat4, Vec4};
+use image::GrayImage;
+use std::fmt;
+use std::path::Path;
+
+/// Offset of the first optimized moment, see `get_optimized_moments` in shadow.wgsl.
+#[allow(clippy::excessive_precision)]
+const MOMENT_OFFSET: f32 = 0.035955884801;
+
+/// Variance below which a reconstructed texel counts as invalid, smaller negative values are rounding.
+const VARIANCE_TOLERANCE: f32 = -1e-4;
+
+/// Statistics of one shadow map layer of a light.
+pub struct ShadowMapStats {
+    pub light: String,
+    /// Cube face of a point light, 0 for spot lights.
+    pub face: u32,
+    /// Layer of the shadow map texture.
+    pub layer: u32,
+    /// Width and height of the region the light renders into.
+    pub size: u32,
+    /// Texels without a caster, cleared and never drawn into.
+    pub empty: u32,
+    /// Texels whose moments don't reconstruct to a depth with a variance of at least zero. The blur
+    /// mixes the empty texels around casters into their edges, so a few of them are expected there.
+    pub invalid: u32,
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    /// Mean variance of the depth of the drawn texels, `None` for depth shadow maps.
+    pub mean_variance: Option<f32>,
+    /// Depth of each texel row by row, `None` where the texel is empty.
+    depths: Vec<Option<f32>>,
+}
+
+impl ShadowMapStats {
+    /// Saves the layer as a grayscale PNG, the depth range of the layer stretched to black and white.
+    pub fn save_png(&self, path: &Path) -> anyhow::Result<()> {
+        let range = (self.max - self.min).max(f32::EPSILON);
+        let pixels = self
+            .depths
+            .iter()
+            .map(|depth| match depth {
+                Some(depth) => (((depth - self.min) / range).clamp(0.0, 1.0) * 255.0).round() as u8,
+                None => u8::MAX,
+            })
+            .collect();
+        GrayImage::from_raw(self.size, self.size, pixels)
+            .ok_or_else(|| anyhow::anyhow!("unexpected size"))?
+            .save(path)?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for ShadowMapStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} face {} (layer {}, {}px): depth {:.5}..{:.5}, mean {:.5}",
+            self.light, self.face, self.layer, self.size, self.min, self.max, self.mean
+        )?;
+        if let Some(variance) = self.mean_variance {
+            write!(
+                f,
+                ", mean variance {variance:.3e}, {} invalid",
+                self.invalid
+            )?;
+        }
+        write!(f, ", {} empty texels", self.empty)
+    }
+}
+
+/// Reads back the shadow map layers of the light named `light`, one for a spot light and six for a point
+/// light. Waits for the GPU, so it is meant for inspection rather than every frame.
+pub fn read_back_light_shadow(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    scene_graph: &SceneGraph,
+    light: &str,
+) -> anyhow::Result<Vec<ShadowMapStats>> {
+    let Some(Node::LightNode(light_node)) = scene_graph.find_child(light) else {
+        anyhow::bail!("there is no light named {light}");
+    };
+    let Some(first_layer) = light_node.light.shadow_layer else {
+        anyhow::bail!("{light} has no shadow map layer");
+    };
+    let kind = light_node.light.kind;
+    let shadow_map = scene_graph.shadow_map_for(kind);
+    let size = scene_graph.shadow_size(&light_node.light);
+    let layers = kind.shadow_layers();
+
+    let format = shadow_map.texture.format();
+    let aspect = if format.is_depth_stencil_format() {
+        wgpu::TextureAspect::DepthOnly
+    } else {
+        wgpu::TextureAspect::All
+    };
+    let bytes_per_texel = format
+        .block_copy_size(Some(aspect))
+        .ok_or_else(|| anyhow::anyhow!("{format:?} can't be copied"))?;
+    let padded_bytes_per_row =
+        (size * bytes_per_texel).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("shadow_readback_buffer"),
+        size: (padded_bytes_per_row * size * layers) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("shadow_readback_encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        wgpu::TexelCopyTextureInfo {
+            texture: &shadow_map.texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d {
+                x: 0,
+                y: 0,
+                z: first_layer,
+            },
+            aspect,
+        },
+        wgpu::TexelCopyBufferInfo {
+            buffer: &buffer,
+            layout: wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_bytes_per_row),
+                rows_per_image: Some(size),
+            },
+        },
+        wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: layers,
+        },
+    );
+    queue.submit(Some(encoder.finish()));
+    buffer.slice(..).map_async(wgpu::MapMode::Read, |_| ());
+    device.poll(wgpu::Maintain::Wait);
+
+    let data = buffer.slice(..).get_mapped_range();
+    let row_bytes = (size * bytes_per_texel) as usize;
+    let layer_bytes = (padded_bytes_per_row * size) as usize;
+    let stats = data
+        .chunks(layer_bytes)
+        .enumerate()
+        .map(|(face, layer_data)| {
+            let texels: Vec<u8> = layer_data
+                .chunks(padded_bytes_per_row as usize)
+                .flat_map(|row| &row[..row_bytes])
+                .copied()
+                .collect();
+            let values = bytemuck::pod_collect_to_vec::<u8, f32>(&texels);
+            let mut stats = layer_stats(shadow_map, &values);
+            stats.light = light.to_string();
+            stats.face = face as u32;
+            stats.layer = first_layer + face as u32;
+            stats.size = size;
+            stats
+        })
+        .collect();
+    drop(data);
+    buffer.unmap();
+    Ok(stats)
+}
+
+/// Statistics of the texel `values` of one layer of `shadow_map`.
+fn layer_stats(shadow_map: &ShadowMap, values: &[f32]) -> ShadowMapStats {
+    let mut depths = Vec::new();
+    let mut invalid = 0;
+    let mut variance_sum = 0.0;
+    if shadow_map.mode.is_depth() {
+        // depth shadow maps are cleared to the far plane
+        depths.extend(values.iter().map(|&depth| (depth < 1.0).then_some(depth)));
+    } else {
+        for moments in values.chunks_exact(4) {
+            let moments = Vec4::from_slice(moments);
+            // moment shadow maps are cleared to zero, which no drawn depth optimizes to
+            if moments == Vec4::ZERO {
+                depths.push(None);
+                continue;
+            }
+            let (depth, variance) = reconstruct_moments(moments);
+            if !depth.is_finite() || variance.is_nan() || variance < VARIANCE_TOLERANCE {
+                invalid += 1;
+            }
+            variance_sum += variance as f64;
+            depths.push(Some(depth));
+        }
+    }
+
+    let drawn: Vec<f32> = depths.iter().flatten().copied().collect();
+    let (min, max, mean) = if drawn.is_empty() {
+        (0.0, 0.0, 0.0)
+    } else {
+        let sum: f64 = drawn.iter().map(|&depth| depth as f64).sum();
+        (
+            drawn.iter().copied().fold(f32::INFINITY, f32::min),
+            drawn.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+            (sum / drawn.len() as f64) as f32,
+        )
+    };
+    let mean_variance =
+        (!shadow_map.mode.is_depth()).then(|| (variance_sum / drawn.len().max(1) as f64) as f32);
+    ShadowMapStats {
+        light: String::new(),
+        face: 0,
+        layer: 0,
+        size: 0,
+        empty: (depths.len() - drawn.len()) as u32,
+        invalid,
+        min,
+        max,
+        mean,
+        mean_variance,
+        depths,
+    }
+}
+
+/// Depth and variance of the optimized `moments` of a texel, like `convert_optimized_moments` in msm.wgsl.
+// the constants digit for digit as the shader has them
+#[allow(clippy::excessive_precision)]
+fn reconstruct_moments(moments: Vec4) -> (f32, f32) {
+    let inverse = Mat4::from_cols(
+        Vec4::new(0.2227744146, 0.1549679261, 0.1451988946, 0.163127443),
+        Vec4::new(0.0771972861, 0.1394629426, 0.2120202157, 0.2591432266),
+        Vec4::new(0.7926986636, 0.7963415838, 0.7258694464, 0.6539092497),
+        Vec4::new(0.0319417555, -0.1722823173, -0.2758014811, -0.3376131734),
+    );
+    let b = inverse * (moments - Vec4::new(MOMENT_OFFSET, 0.0, 0.0, 0.0));
+    (b.x, b.y - b.x * b.x)
+}
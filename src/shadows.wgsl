@@ -0,0 +1,100 @@
+// Shadows of the spot and point lights. The shadow maps are sampled by the snippet of the ShadowMode,
+// imported as shadow_mode.
+#import lighting
+#import shadow_mode
+
+// near and far plane of the point light cube faces, see Light::POINT_SHADOW_NEAR/FAR
+const POINT_SHADOW_NEAR: f32 = 0.5;
+const POINT_SHADOW_FAR: f32 = 50.0;
+
+// Texture coordinates and depth of a light space position, in the `scale` of the shadow map layer the
+// light renders into
+fn shadow_coords(ls_pos: vec4<f32>, scale: f32) -> vec3<f32> {
+    // compensate for the Y-flip difference between the NDC and texture coordinates
+    let flip_correction = vec2<f32>(0.5, -0.5);
+    // compute texture coordinates for shadow lookup
+    let proj_correction = 1.0 / ls_pos.w;
+    let light_local = ls_pos.xy * flip_correction * proj_correction + vec2<f32>(0.5, 0.5);
+    let depth = ls_pos.z * proj_correction;
+    // outside of the light frustum the edge of its corner is sampled, not the rest of the layer
+    return vec3<f32>(clamp(light_local, vec2<f32>(0.0), vec2<f32>(1.0)) * scale, depth);
+}
+
+fn fetch_shadow(shadow_layer: i32, scale: f32, ls_pos: vec4<f32>) -> f32 {
+    if (shadow_layer < 0 || ls_pos.w <= 0.0) {
+        return 1.0;
+    }
+
+    return sample_shadow(shadow_coords(ls_pos, scale), shadow_layer);
+}
+
+struct PointShadowFace {
+    face: i32,
+    ls_pos: vec4<f32>,
+};
+
+// Cube face and face space position of a point light shadow, `to_fragment` points from the light to the
+// fragment. The cube face is picked by the major axis, in the order +X, -X, +Y, -Y, +Z, -Z like
+// Light::shadow_matrices.
+fn point_shadow_face(to_fragment: vec3<f32>) -> PointShadowFace {
+    let a = abs(to_fragment);
+    var face: i32;
+    var forward: vec3<f32>;
+    var up: vec3<f32>;
+    if (a.x >= a.y && a.x >= a.z) {
+        face = select(1, 0, to_fragment.x > 0.0);
+        forward = vec3<f32>(sign(to_fragment.x), 0.0, 0.0);
+        up = vec3<f32>(0.0, 1.0, 0.0);
+    } else if (a.y >= a.z) {
+        face = select(3, 2, to_fragment.y > 0.0);
+        forward = vec3<f32>(0.0, sign(to_fragment.y), 0.0);
+        up = vec3<f32>(0.0, 0.0, sign(to_fragment.y));
+    } else {
+        face = select(5, 4, to_fragment.z > 0.0);
+        forward = vec3<f32>(0.0, 0.0, sign(to_fragment.z));
+        up = vec3<f32>(0.0, 1.0, 0.0);
+    }
+
+    // same as the face's look_at_rh and 90 degree perspective_rh matrices
+    let side = normalize(cross(forward, up));
+    let true_up = cross(side, forward);
+    let distance = dot(to_fragment, forward);
+    let r = POINT_SHADOW_FAR / (POINT_SHADOW_NEAR - POINT_SHADOW_FAR);
+    var out: PointShadowFace;
+    out.face = face;
+    out.ls_pos = vec4<f32>(
+        dot(side, to_fragment),
+        dot(true_up, to_fragment),
+        r * (POINT_SHADOW_NEAR - distance),
+        distance
+    );
+    return out;
+}
+
+// Shadow of a point light, `to_fragment` points from the light to the fragment.
+fn fetch_point_shadow(first_layer: i32, scale: f32, to_fragment: vec3<f32>) -> f32 {
+    if (first_layer < 0) {
+        return 1.0;
+    }
+
+    let point_face = point_shadow_face(to_fragment);
+    return sample_point_shadow(shadow_coords(point_face.ls_pos, scale), first_layer + point_face.face);
+}
+
+// False in the pipelines of a renderer with its shadows turned off, see PipelineVariants::set_shadows
+override SHADOWS: bool = true;
+
+fn light_shadow(light: Light, world_position: vec4<f32>) -> f32 {
+    if (!SHADOWS) {
+        return 1.0;
+    }
+    if (light.kind == LIGHT_KIND_POINT) {
+        let light_world_position = light.model * light.position;
+        return fetch_point_shadow(
+            light.shadow_layer,
+            light.shadow_scale,
+            world_position.xyz - light_world_position.xyz
+        );
+    }
+    return fetch_shadow(light.shadow_layer, light.shadow_scale, light.view_proj * world_position);
+}
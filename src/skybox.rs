@@ -0,0 +1,133 @@
+/*
+ * Skybox drawn from an environment cube map.
+ * The sky is a fullscreen triangle on the far plane, drawn first in the forward pass without writing
+ * depth, so the scene covers it. Each pixel looks up the cube map along its view ray, reconstructed
+ * from the inverse view projection of the camera. The multiview stereo pass keeps the clear color.
+ */
+use crate::camera::CameraUniform;
+use crate::reflection::ReflectDevice;
+use crate::shader_cache;
+use crate::texture;
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+pub struct Skybox {
+    /// One pipeline per sample count of the forward targets.
+    pipelines: HashMap<u32, wgpu::RenderPipeline>,
+    bind_group: wgpu::BindGroup,
+    #[allow(unused)]
+    cube_map: texture::Texture,
+}
+
+impl Skybox {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        sample_counts: &[u32],
+        cube_map: texture::Texture,
+    ) -> Self {
+        let bind_group_layout = device.reflect_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("skybox_bind_group_layout"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&cube_map.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&cube_map.sampler),
+                },
+            ],
+            label: Some("skybox_bind_group"),
+        });
+
+        let shader = device.reflect_shader(wgpu::ShaderModuleDescriptor {
+            label: Some("skybox"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("skybox.wgsl"))),
+        });
+        let camera_bind_group_layout = CameraUniform::get_bind_group_layout(device);
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("skybox_pipeline_layout"),
+            bind_group_layouts: &[&camera_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipelines = sample_counts
+            .iter()
+            .map(|&sample_count| {
+                let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("skybox_pipeline"),
+                    layout: Some(&layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: Some("vs_sky"),
+                        compilation_options: Default::default(),
+                        buffers: &[],
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: Some("fs_sky"),
+                        compilation_options: Default::default(),
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format,
+                            blend: None,
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                    }),
+                    primitive: wgpu::PrimitiveState::default(),
+                    // the triangle lies exactly on the cleared depth of 1
+                    depth_stencil: Some(wgpu::DepthStencilState {
+                        format: texture::Texture::DEPTH_FORMAT,
+                        depth_write_enabled: false,
+                        depth_compare: wgpu::CompareFunction::LessEqual,
+                        stencil: wgpu::StencilState::default(),
+                        bias: wgpu::DepthBiasState::default(),
+                    }),
+                    multisample: wgpu::MultisampleState {
+                        count: sample_count,
+                        ..Default::default()
+                    },
+                    multiview: None,
+                    cache: shader_cache::pipeline_cache(device).as_ref(),
+                });
+                (sample_count, pipeline)
+            })
+            .collect();
+
+        Self {
+            pipelines,
+            bind_group,
+            cube_map,
+        }
+    }
+
+    /// Draws the sky into a forward pass, the camera bind group must be set at group 0.
+    pub fn draw(&self, rpass: &mut wgpu::RenderPass, sample_count: u32) {
+        let Some(pipeline) = self.pipelines.get(&sample_count) else {
+            return;
+        };
+        rpass.set_pipeline(pipeline);
+        rpass.set_bind_group(1, &self.bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
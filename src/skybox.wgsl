@@ -0,0 +1,34 @@
+struct Camera {
+    view_proj: mat4x4<f32>,
+    position: vec4<f32>,
+    inv_view_proj: mat4x4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(1) @binding(0) var t_sky: texture_cube<f32>;
+@group(1) @binding(1) var s_sky: sampler;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+@vertex
+fn vs_sky(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // a single triangle covering the whole viewport, on the far plane behind everything else
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    let ndc = uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0);
+    var out: VertexOutput;
+    out.position = vec4<f32>(ndc, 1.0, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+@fragment
+fn fs_sky(in: VertexOutput) -> @location(0) vec4<f32> {
+    // view ray through the pixel, from the near to the far plane
+    let near = camera.inv_view_proj * vec4<f32>(in.ndc, 0.0, 1.0);
+    let far = camera.inv_view_proj * vec4<f32>(in.ndc, 1.0, 1.0);
+    let dir = normalize(far.xyz / far.w - near.xyz / near.w);
+    return vec4<f32>(textureSample(t_sky, s_sky, dir).rgb, 1.0);
+}
@@ -0,0 +1,168 @@
+/*
+ * Spline paths.
+ * A node can follow a path through control points at a constant speed, optionally turned to face along
+ * the path. The path is a Catmull-Rom spline, it passes through every control point, and it is walked by
+ * arc length so the speed stays the same on long and short spans. Paths are given in the space of the
+ * parent of the node, like its transform. Open paths start over at the first point once the end is
+ * reached, closed paths run back from the last point to the first.
+ */
+use glam::{Mat3, Mat4, Quat, Vec3};
+
+/// Straight pieces each span between two control points is measured and drawn with.
+const SEGMENTS_PER_SPAN: usize = 16;
+
+#[derive(Debug, Clone)]
+pub struct SplinePath {
+    points: Vec<Vec3>,
+    closed: bool,
+    /// Arc length from the start to the end of each segment, see SEGMENTS_PER_SPAN.
+    lengths: Vec<f32>,
+}
+
+impl SplinePath {
+    /// None for fewer than two points.
+    pub fn new(points: Vec<Vec3>, closed: bool) -> Option<Self> {
+        if points.len() < 2 {
+            return None;
+        }
+        let mut path = Self {
+            points,
+            closed,
+            lengths: Vec::new(),
+        };
+        let mut length = 0.0;
+        let mut previous = path.point_at(0.0);
+        for segment in 1..=path.spans() * SEGMENTS_PER_SPAN {
+            let point = path.point_at(segment as f32 / SEGMENTS_PER_SPAN as f32);
+            length += point.distance(previous);
+            path.lengths.push(length);
+            previous = point;
+        }
+        Some(path)
+    }
+
+    pub fn points(&self) -> &[Vec3] {
+        &self.points
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn length(&self) -> f32 {
+        self.lengths.last().copied().unwrap_or(0.0)
+    }
+
+    fn spans(&self) -> usize {
+        if self.closed {
+            self.points.len()
+        } else {
+            self.points.len() - 1
+        }
+    }
+
+    /// Control point `index`, wrapped around for closed paths and clamped to the ends for open ones.
+    fn control_point(&self, index: isize) -> Vec3 {
+        let count = self.points.len() as isize;
+        let index = if self.closed {
+            index.rem_euclid(count)
+        } else {
+            index.clamp(0, count - 1)
+        };
+        self.points[index as usize]
+    }
+
+    /// Point at `t` from 0 at the first control point to 1 at the second and so on.
+    fn point_at(&self, t: f32) -> Vec3 {
+        let span = (t.floor() as isize).clamp(0, self.spans() as isize - 1);
+        let local = t - span as f32;
+        let [p0, p1, p2, p3] = [-1, 0, 1, 2].map(|offset| self.control_point(span + offset));
+        let (t2, t3) = (local * local, local * local * local);
+        0.5 * (2.0 * p1
+            + (p2 - p0) * local
+            + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+            + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+    }
+
+    /// Position and direction of travel `distance` along the path.
+    pub fn sample(&self, distance: f32) -> (Vec3, Vec3) {
+        let length = self.length();
+        let distance = if length > 0.0 {
+            distance.rem_euclid(length)
+        } else {
+            0.0
+        };
+        let segment = self.lengths.partition_point(|&end| end < distance);
+        let segment = segment.min(self.lengths.len() - 1);
+        let start = if segment == 0 {
+            0.0
+        } else {
+            self.lengths[segment - 1]
+        };
+        let segment_length = self.lengths[segment] - start;
+        let fraction = if segment_length > 0.0 {
+            (distance - start) / segment_length
+        } else {
+            0.0
+        };
+        let t0 = segment as f32 / SEGMENTS_PER_SPAN as f32;
+        let t1 = (segment + 1) as f32 / SEGMENTS_PER_SPAN as f32;
+        let (from, to) = (self.point_at(t0), self.point_at(t1));
+        (from.lerp(to, fraction), (to - from).normalize_or_zero())
+    }
+
+    /// Points along the whole path, for drawing it.
+    pub fn polyline(&self) -> Vec<Vec3> {
+        (0..=self.spans() * SEGMENTS_PER_SPAN)
+            .map(|segment| self.point_at(segment as f32 / SEGMENTS_PER_SPAN as f32))
+            .collect()
+    }
+}
+
+/// Moves the node `node` along `path`.
+#[derive(Debug, Clone)]
+pub struct PathAnimation {
+    pub node: String,
+    pub path: SplinePath,
+    /// World units per second.
+    pub speed: f32,
+    /// Turns the -Z axis of the node along the path, with +Y up.
+    pub orient: bool,
+}
+
+impl PathAnimation {
+    pub fn new(node: &str, path: SplinePath, speed: f32, orient: bool) -> Self {
+        Self {
+            node: node.to_string(),
+            path,
+            speed,
+            orient,
+        }
+    }
+
+    /// Translation and rotation of the node at `time` in seconds.
+    pub fn matrix(&self, time: f32) -> Mat4 {
+        let (position, direction) = self.path.sample(time * self.speed);
+        let rotation = if self.orient {
+            orientation(direction)
+        } else {
+            Quat::IDENTITY
+        };
+        Mat4::from_rotation_translation(rotation, position)
+    }
+}
+
+/// Rotation turning -Z to `direction` without rolling around it.
+pub fn orientation(direction: Vec3) -> Quat {
+    let right = direction.cross(Vec3::Y);
+    if direction == Vec3::ZERO {
+        return Quat::IDENTITY;
+    }
+    if right.length_squared() < 1e-6 {
+        // straight up or down, there is no roll to keep
+        return Quat::from_rotation_arc(Vec3::NEG_Z, direction);
+    }
+    let right = right.normalize();
+    let up = right.cross(direction);
+    Quat::from_mat3(&Mat3::from_cols(right, up, -direction))
+}
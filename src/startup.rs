@@ -0,0 +1,53 @@
+/*
+ * Startup timing.
+ * Creating the renderer is split into phases, from loading the settings to the pipelines and the skybox,
+ * and the time of each is printed once the renderer is ready, to see where startup time goes. The
+ * scene shaders are compiled in parallel, and the skybox is decoded on a thread of its own while the
+ * device, scene and pipelines are created, so its phase is only the rest of the decoding and the upload.
+ */
+use instant::Instant;
+use std::time::Duration;
+
+pub struct StartupTimer {
+    start: Instant,
+    phase_start: Instant,
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl StartupTimer {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            start: now,
+            phase_start: now,
+            phases: Vec::new(),
+        }
+    }
+
+    /// Records the time since the previous phase (or the start) as the time of `phase`.
+    pub fn lap(&mut self, phase: &'static str) {
+        let now = Instant::now();
+        self.phases.push((phase, now - self.phase_start));
+        self.phase_start = now;
+    }
+
+    /// The phases in the order they ran.
+    #[allow(dead_code)]
+    pub fn phases(&self) -> &[(&'static str, Duration)] {
+        &self.phases
+    }
+
+    /// e.g. `Startup took 812 ms: device 95 ms, shaders 40 ms, ...`
+    pub fn summary(&self) -> String {
+        let phases = self
+            .phases
+            .iter()
+            .map(|(phase, time)| format!("{phase} {} ms", time.as_millis()))
+            .collect::<Vec<_>>();
+        format!(
+            "Startup took {} ms: {}",
+            self.start.elapsed().as_millis(),
+            phases.join(", ")
+        )
+    }
+}
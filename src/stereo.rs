@@ -0,0 +1,342 @@
+/*
+ * Stereo rendering into a two-layer texture array, one layer per eye.
+ * The layers are what a head-mounted display integration would consume; there is no headset runtime in
+ * this crate yet, the eyes are placed by the `StereoRig` and the window shows a mirror of the left eye.
+ * The eyes can also be composited as a red-cyan anaglyph or side by side.
+ * Where the adapter supports multiview, both eyes are drawn in a single pass.
+ */
+use crate::camera::{Camera, CameraUniform};
+use crate::input::Action;
+use crate::reflection::ReflectDevice;
+use crate::renderer::{Pipeline, PipelineVariants};
+use crate::texture;
+use glam::{Mat4, Vec4};
+use std::borrow::Cow;
+
+pub const EYE_COUNT: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StereoMode {
+    Off,
+    /// The window mirrors the left eye layer.
+    Mirror,
+    /// Red-cyan anaglyph for colored glasses.
+    Anaglyph,
+    /// Both eyes squeezed into the left and right half of the window.
+    SideBySide,
+}
+
+impl StereoMode {
+    pub fn next(self) -> Self {
+        match self {
+            StereoMode::Off => StereoMode::Mirror,
+            StereoMode::Mirror => StereoMode::Anaglyph,
+            StereoMode::Anaglyph => StereoMode::SideBySide,
+            StereoMode::SideBySide => StereoMode::Off,
+        }
+    }
+}
+
+pub struct StereoRig {
+    /// Inter-pupillary distance in world units.
+    pub ipd: f32,
+    /// Distance of the zero-parallax plane. Objects at this distance appear on the screen surface,
+    /// closer objects pop out of it.
+    pub convergence: f32,
+}
+
+impl StereoRig {
+    /// Camera uniforms for the left and right eye. The eyes are offset along the camera's right vector
+    /// with parallel view axes, and converge through an off-axis projection shift.
+    pub fn eye_uniforms(&self, camera: &Camera, aspect: f32) -> [CameraUniform; EYE_COUNT as usize] {
+        let half_ipd = self.ipd * 0.5;
+        let projection = Mat4::perspective_rh(camera.fovy.to_radians(), aspect, camera.znear, camera.zfar);
+        [-1.0f32, 1.0].map(|side| {
+            let offset = camera.right() * side * half_ipd;
+            let eye = camera.eye + offset;
+            let view = Mat4::look_at_rh(eye, camera.target + offset, camera.up);
+            // shift clip-space x by a multiple of w, which moves the convergence plane to the screen center
+            let shift = projection.x_axis.x * side * half_ipd / self.convergence;
+            let convergence_shift = Mat4::from_cols(Vec4::X, Vec4::Y, Vec4::Z, Vec4::new(shift, 0.0, 0.0, 1.0));
+            CameraUniform::new(convergence_shift * projection * view, eye)
+        })
+    }
+}
+
+pub struct StereoTarget {
+    pub width: u32,
+    pub height: u32,
+    pub color_view: wgpu::TextureView,
+    pub color_eye_views: Vec<wgpu::TextureView>,
+    pub depth_view: wgpu::TextureView,
+    pub depth_eye_views: Vec<wgpu::TextureView>,
+    pub composite_bind_group: wgpu::BindGroup,
+}
+
+pub struct StereoPass {
+    pub rig: StereoRig,
+    pub mode: StereoMode,
+    /// Forward pipeline rendering both eyes at once, if the adapter supports multiview.
+    pub multiview_pipeline: Option<PipelineVariants>,
+    eye_buffers: Vec<wgpu::Buffer>,
+    pub eye_bind_groups: Vec<wgpu::BindGroup>,
+    multiview_buffer: wgpu::Buffer,
+    pub multiview_bind_group: wgpu::BindGroup,
+    mirror_pipeline: Pipeline,
+    anaglyph_pipeline: Pipeline,
+    side_by_side_pipeline: Pipeline,
+    composite_bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    format: wgpu::TextureFormat,
+    pub target: Option<StereoTarget>,
+}
+
+impl StereoPass {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        multiview_pipeline: Option<PipelineVariants>,
+    ) -> Self {
+        let camera_bind_group_layout = CameraUniform::get_bind_group_layout(device);
+        let create_camera_buffer = |label: &str, count: u64| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: count * size_of::<CameraUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        };
+        let create_camera_bind_group = |label: &str, buffer: &wgpu::Buffer| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &camera_bind_group_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                }],
+                label: Some(label),
+            })
+        };
+
+        let eye_buffers = (0..EYE_COUNT)
+            .map(|eye| create_camera_buffer(&format!("Eye {eye} Camera Buffer"), 1))
+            .collect::<Vec<_>>();
+        let eye_bind_groups = eye_buffers
+            .iter()
+            .enumerate()
+            .map(|(eye, buffer)| create_camera_bind_group(&format!("eye_{eye}_camera_bind_group"), buffer))
+            .collect();
+        let multiview_buffer = create_camera_buffer("Multiview Camera Buffer", EYE_COUNT as u64);
+        let multiview_bind_group =
+            create_camera_bind_group("multiview_camera_bind_group", &multiview_buffer);
+
+        let composite_bind_group_layout = device.reflect_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("stereo_composite_bind_group_layout"),
+        });
+        let shader = device.reflect_shader(wgpu::ShaderModuleDescriptor {
+            label: Some("stereo"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("stereo.wgsl"))),
+        });
+        let create_composite_pipeline = |fragment_entry: &str| {
+            Pipeline::new(
+                device,
+                &format!("stereo_composite_pipeline {fragment_entry}"),
+                &shader,
+                &[&composite_bind_group_layout],
+                "vs_fullscreen",
+                &[],
+                Some(fragment_entry),
+                &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                None,
+                None,
+                None,
+                None,
+            )
+        };
+        let mirror_pipeline = create_composite_pipeline("fs_mirror");
+        let anaglyph_pipeline = create_composite_pipeline("fs_anaglyph");
+        let side_by_side_pipeline = create_composite_pipeline("fs_side_by_side");
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("stereo_sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            rig: StereoRig {
+                ipd: 0.3,
+                convergence: 10.0,
+            },
+            mode: StereoMode::Off,
+            multiview_pipeline,
+            eye_buffers,
+            eye_bind_groups,
+            multiview_buffer,
+            multiview_bind_group,
+            mirror_pipeline,
+            anaglyph_pipeline,
+            side_by_side_pipeline,
+            composite_bind_group_layout,
+            sampler,
+            format,
+            target: None,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.mode != StereoMode::Off
+    }
+
+    /// Pipeline compositing the eye layers into the window for the current mode.
+    pub fn composite_pipeline(&self) -> &Pipeline {
+        match self.mode {
+            StereoMode::Anaglyph => &self.anaglyph_pipeline,
+            StereoMode::SideBySide => &self.side_by_side_pipeline,
+            StereoMode::Off | StereoMode::Mirror => &self.mirror_pipeline,
+        }
+    }
+
+    /// Size of a single eye layer for a window of the given size.
+    pub fn eye_size(&self, width: u32, height: u32) -> (u32, u32) {
+        match self.mode {
+            StereoMode::SideBySide => (width / 2, height),
+            _ => (width, height),
+        }
+    }
+
+    pub fn update_cameras(&self, queue: &wgpu::Queue, camera: &Camera) {
+        let (width, height) = match &self.target {
+            Some(target) => (target.width, target.height),
+            None => return,
+        };
+        let eye_uniforms = self.rig.eye_uniforms(camera, width as f32 / height as f32);
+        for (buffer, uniform) in self.eye_buffers.iter().zip(&eye_uniforms) {
+            queue.write_buffer(buffer, 0, bytemuck::cast_slice(&[*uniform]));
+        }
+        queue.write_buffer(&self.multiview_buffer, 0, bytemuck::cast_slice(&eye_uniforms));
+    }
+
+    /// Applies the stereo actions of the [`crate::input::ActionMap`], by default V cycles the stereo mode,
+    /// [ and ] change the eye distance, comma and period the convergence distance. Each `pressed` steps
+    /// once, held keys don't repeat. Returns whether `action` is a stereo action.
+    pub fn process_action(&mut self, action: Action, pressed: bool) -> bool {
+        match action {
+            Action::CycleStereoMode if pressed => self.mode = self.mode.next(),
+            Action::NarrowEyeDistance if pressed => self.rig.ipd = (self.rig.ipd * 0.8).max(0.001),
+            Action::WidenEyeDistance if pressed => self.rig.ipd *= 1.25,
+            Action::ConvergeNearer if pressed => {
+                self.rig.convergence = (self.rig.convergence * 0.8).max(0.5)
+            }
+            Action::ConvergeFarther if pressed => self.rig.convergence *= 1.25,
+            Action::CycleStereoMode
+            | Action::NarrowEyeDistance
+            | Action::WidenEyeDistance
+            | Action::ConvergeNearer
+            | Action::ConvergeFarther => {}
+            _ => return false,
+        }
+        true
+    }
+
+    /// (Re-)creates the eye textures if the requested size differs from the current one.
+    pub fn ensure_target(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        let (width, height) = (width.max(1), height.max(1));
+        if let Some(target) = &self.target {
+            if target.width == width && target.height == height {
+                return;
+            }
+        }
+
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: EYE_COUNT,
+        };
+        let create_layers = |label: &str, format: wgpu::TextureFormat, usage| {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage,
+                view_formats: &[],
+            });
+            let array_view = texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some(label),
+                dimension: Some(wgpu::TextureViewDimension::D2Array),
+                ..Default::default()
+            });
+            let eye_views = (0..EYE_COUNT)
+                .map(|eye| {
+                    texture.create_view(&wgpu::TextureViewDescriptor {
+                        label: Some(&format!("{label}_eye_{eye}")),
+                        dimension: Some(wgpu::TextureViewDimension::D2),
+                        base_array_layer: eye,
+                        array_layer_count: Some(1),
+                        ..Default::default()
+                    })
+                })
+                .collect::<Vec<_>>();
+            (array_view, eye_views)
+        };
+
+        let (color_view, color_eye_views) = create_layers(
+            "stereo_color",
+            self.format,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        );
+        let (depth_view, depth_eye_views) = create_layers(
+            "stereo_depth",
+            texture::Texture::DEPTH_FORMAT,
+            wgpu::TextureUsages::RENDER_ATTACHMENT,
+        );
+        let composite_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.composite_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&color_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+            label: Some("stereo_composite_bind_group"),
+        });
+
+        self.target = Some(StereoTarget {
+            width,
+            height,
+            color_view,
+            color_eye_views,
+            depth_view,
+            depth_eye_views,
+            composite_bind_group,
+        });
+    }
+}
@@ -0,0 +1,41 @@
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // a single triangle covering the whole viewport
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@group(0) @binding(0) var t_eyes: texture_2d_array<f32>;
+@group(0) @binding(1) var s_eyes: sampler;
+
+// Desktop mirror of the left eye
+@fragment
+fn fs_mirror(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(t_eyes, s_eyes, in.uv, 0);
+}
+
+// Half-color red-cyan anaglyph: the red channel carries the left eye's luminance to reduce
+// retinal rivalry, green and blue come from the right eye.
+@fragment
+fn fs_anaglyph(in: VertexOutput) -> @location(0) vec4<f32> {
+    let left = textureSample(t_eyes, s_eyes, in.uv, 0);
+    let right = textureSample(t_eyes, s_eyes, in.uv, 1);
+    let left_luminance = dot(left.rgb, vec3<f32>(0.299, 0.587, 0.114));
+    return vec4<f32>(left_luminance, right.g, right.b, 1.0);
+}
+
+// Left eye in the left half of the viewport, right eye in the right half
+@fragment
+fn fs_side_by_side(in: VertexOutput) -> @location(0) vec4<f32> {
+    let eye = select(0, 1, in.uv.x >= 0.5);
+    let uv = vec2<f32>(fract(in.uv.x * 2.0), in.uv.y);
+    return textureSample(t_eyes, s_eyes, uv, eye);
+}
@@ -0,0 +1,227 @@
+/*
+ * Stylized output.
+ * A custom pass after the forward pass that posterizes the frame to a few brightness levels per channel,
+ * optionally with ordered dithering between them, and draws black outlines where the Sobel filter of the
+ * luminance finds edges, for a toon or retro look. It is added from the `[stylize]` table of the render
+ * settings and shows how an effect is built on the custom pass API: it copies the frame, which needs the
+ * frame texture to allow copies, and draws over it with the copy as input.
+ */
+use crate::custom_pass::{CustomPass, PassContext, PassStage};
+use crate::reflection::ReflectDevice;
+use crate::renderer::Pipeline;
+use serde::Deserialize;
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StylizeSettings {
+    /// Brightness levels per color channel.
+    pub levels: u32,
+    pub outlines: bool,
+    /// Sobel magnitude of the luminance above which a pixel is outlined.
+    pub outline_threshold: f32,
+    /// Dithers between the levels with a 4x4 Bayer pattern instead of banding.
+    pub dither: bool,
+    /// Blend of the stylized and the unchanged frame, 1 shows only the stylized one.
+    pub intensity: f32,
+}
+
+impl Default for StylizeSettings {
+    fn default() -> Self {
+        Self {
+            levels: 4,
+            outlines: true,
+            outline_threshold: 0.4,
+            dither: false,
+            intensity: 1.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct StylizeUniform {
+    levels: f32,
+    outline_threshold: f32,
+    dither: u32,
+    intensity: f32,
+}
+
+impl StylizeUniform {
+    fn from_settings(settings: &StylizeSettings) -> Self {
+        Self {
+            levels: settings.levels.max(2) as f32,
+            outline_threshold: if settings.outlines {
+                settings.outline_threshold
+            } else {
+                0.0
+            },
+            dither: settings.dither as u32,
+            intensity: settings.intensity.clamp(0.0, 1.0),
+        }
+    }
+}
+
+pub struct StylizePass {
+    pipeline: Pipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    uniform_buffer: wgpu::Buffer,
+    /// Copy of the frame the pass reads from and its bind group, created for the size of the frame.
+    frame_copy: Option<(wgpu::Texture, wgpu::BindGroup)>,
+    /// Set once it was reported that the frame texture can't be copied.
+    reported: bool,
+}
+
+impl StylizePass {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        settings: &StylizeSettings,
+    ) -> Self {
+        let bind_group_layout = device.reflect_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("stylize_bind_group_layout"),
+        });
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Stylize Buffer"),
+            contents: bytemuck::cast_slice(&[StylizeUniform::from_settings(settings)]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let shader = device.reflect_shader(wgpu::ShaderModuleDescriptor {
+            label: Some("stylize"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("stylize.wgsl"))),
+        });
+        let pipeline = Pipeline::new(
+            device,
+            "stylize_pipeline",
+            &shader,
+            &[&bind_group_layout],
+            "vs_fullscreen",
+            &[],
+            Some("fs_stylize"),
+            &[Some(wgpu::ColorTargetState {
+                format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            None,
+            None,
+            None,
+            None,
+        );
+
+        Self {
+            pipeline,
+            bind_group_layout,
+            uniform_buffer,
+            frame_copy: None,
+            reported: false,
+        }
+    }
+
+    /// Creates the copy of the frame for a frame texture like `frame`, again when its size changed.
+    fn prepare_frame_copy(&mut self, device: &wgpu::Device, frame: &wgpu::Texture) {
+        // read through the sRGB view of the frame, like the passes draw into it
+        let format = frame.format().add_srgb_suffix();
+        let fits = self
+            .frame_copy
+            .as_ref()
+            .is_some_and(|(copy, _)| copy.size() == frame.size() && copy.format() == format);
+        if fits {
+            return;
+        }
+        let copy = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("stylize_frame_copy"),
+            size: frame.size(),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(
+                        &copy.create_view(&Default::default()),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("stylize_bind_group"),
+        });
+        self.frame_copy = Some((copy, bind_group));
+    }
+}
+
+impl CustomPass for StylizePass {
+    fn stage(&self) -> PassStage {
+        PassStage::AfterForward
+    }
+
+    fn render(&mut self, context: PassContext) {
+        let frame = context.color_texture;
+        if !frame.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+            if !std::mem::replace(&mut self.reported, true) {
+                println!("Stylized output is off, the frame texture can't be copied");
+            }
+            return;
+        }
+        self.prepare_frame_copy(&context.renderer.device, frame);
+        let Some((copy, bind_group)) = &self.frame_copy else {
+            return;
+        };
+        context.encoder.copy_texture_to_texture(
+            frame.as_image_copy(),
+            copy.as_image_copy(),
+            frame.size(),
+        );
+
+        let mut rpass = context
+            .encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("stylize_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: context.color,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+        rpass.set_pipeline(&self.pipeline.pipeline);
+        rpass.set_bind_group(0, bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,72 @@
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+};
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // a single triangle covering the whole viewport
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    return out;
+}
+
+struct Stylize {
+    levels: f32,
+    // Sobel magnitude of the luminance above which a pixel is outlined, 0 without outlines
+    outline_threshold: f32,
+    dither: u32,
+    // blend of the stylized and the unchanged color
+    intensity: f32,
+};
+
+@group(0) @binding(0) var t_frame: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> stylize: Stylize;
+
+// 4x4 Bayer matrix, thresholds in sixteenths
+const BAYER: array<f32, 16> = array<f32, 16>(
+    0.0, 8.0, 2.0, 10.0,
+    12.0, 4.0, 14.0, 6.0,
+    3.0, 11.0, 1.0, 9.0,
+    15.0, 7.0, 13.0, 5.0,
+);
+
+fn luminance(pixel: vec2<i32>) -> f32 {
+    let size = vec2<i32>(textureDimensions(t_frame));
+    let color = textureLoad(t_frame, clamp(pixel, vec2<i32>(0), size - 1), 0).rgb;
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+fn sobel(pixel: vec2<i32>) -> f32 {
+    let tl = luminance(pixel + vec2<i32>(-1, -1));
+    let t = luminance(pixel + vec2<i32>(0, -1));
+    let tr = luminance(pixel + vec2<i32>(1, -1));
+    let l = luminance(pixel + vec2<i32>(-1, 0));
+    let r = luminance(pixel + vec2<i32>(1, 0));
+    let bl = luminance(pixel + vec2<i32>(-1, 1));
+    let b = luminance(pixel + vec2<i32>(0, 1));
+    let br = luminance(pixel + vec2<i32>(1, 1));
+    let gx = (tr + 2.0 * r + br) - (tl + 2.0 * l + bl);
+    let gy = (bl + 2.0 * b + br) - (tl + 2.0 * t + tr);
+    return length(vec2<f32>(gx, gy));
+}
+
+@fragment
+fn fs_stylize(in: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(in.position.xy);
+    let color = textureLoad(t_frame, pixel, 0).rgb;
+    // quantized in gamma space, so the bands are evenly spaced in brightness
+    let levels = max(stylize.levels - 1.0, 1.0);
+    var offset = 0.5;
+    if stylize.dither != 0u {
+        let index = u32(pixel.y % 4) * 4u + u32(pixel.x % 4);
+        offset = (BAYER[index] + 0.5) / 16.0;
+    }
+    let encoded = pow(color, vec3<f32>(1.0 / 2.2));
+    let quantized = floor(encoded * levels + offset) / levels;
+    var result = pow(clamp(quantized, vec3<f32>(0.0), vec3<f32>(1.0)), vec3<f32>(2.2));
+    if stylize.outline_threshold > 0.0 && sobel(pixel) > stylize.outline_threshold {
+        result = vec3<f32>(0.0);
+    }
+    return vec4<f32>(mix(color, result, stylize.intensity), 1.0);
+}
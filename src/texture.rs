@@ -1,7 +1,99 @@
+use crate::error::{validate, RendererError};
+use crate::reflection::ReflectDevice;
+use crate::renderer::Pipeline;
 use anyhow::*;
+use glam::Vec3;
 use image::{DynamicImage, GenericImageView};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::f32::consts::PI;
 
-#[derive(Debug)]
+/// What a color texture shows, which decides how it is filtered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureContent {
+    /// Few colors on a small grid, kept crisp with nearest filtering.
+    PixelArt,
+    /// Interface elements with transparent surroundings, filtered linearly without repeating at the edges.
+    Ui,
+    /// Everything else, filtered trilinearly between the mip levels and anisotropically for surfaces seen
+    /// at grazing angles, where the backend supports it.
+    Photo,
+}
+
+impl TextureContent {
+    /// Largest side of a texture still considered for pixel art.
+    const PIXEL_ART_MAX_SIZE: u32 = 128;
+    /// Most distinct colors of a texture still considered pixel art.
+    const PIXEL_ART_MAX_COLORS: usize = 32;
+    /// Share of fully transparent pixels from which a texture is considered part of an interface.
+    const UI_MIN_TRANSPARENT: f32 = 0.25;
+    const ANISOTROPY: u16 = 16;
+
+    /// Guesses the content from the pixels: small images with a handful of colors are pixel art, images
+    /// with large transparent areas are interface elements.
+    pub fn classify(img: &DynamicImage) -> Self {
+        let rgba = img.to_rgba8();
+        let (width, height) = rgba.dimensions();
+        if width.max(height) <= Self::PIXEL_ART_MAX_SIZE {
+            let mut colors = std::collections::HashSet::new();
+            let few_colors = rgba.pixels().all(|pixel| {
+                colors.insert(pixel.0);
+                colors.len() <= Self::PIXEL_ART_MAX_COLORS
+            });
+            if few_colors {
+                return TextureContent::PixelArt;
+            }
+        }
+        let transparent = rgba.pixels().filter(|pixel| pixel.0[3] == 0).count();
+        if transparent as f32 >= (width * height) as f32 * Self::UI_MIN_TRANSPARENT {
+            TextureContent::Ui
+        } else {
+            TextureContent::Photo
+        }
+    }
+
+    pub fn sampler_descriptor(self, label: Option<&str>) -> wgpu::SamplerDescriptor<'_> {
+        let (filter, address_mode, anisotropy_clamp) = match self {
+            TextureContent::PixelArt => (wgpu::FilterMode::Nearest, wgpu::AddressMode::Repeat, 1),
+            TextureContent::Ui => (wgpu::FilterMode::Linear, wgpu::AddressMode::ClampToEdge, 1),
+            TextureContent::Photo => (
+                wgpu::FilterMode::Linear,
+                wgpu::AddressMode::Repeat,
+                Self::ANISOTROPY,
+            ),
+        };
+        wgpu::SamplerDescriptor {
+            label,
+            address_mode_u: address_mode,
+            address_mode_v: address_mode,
+            address_mode_w: address_mode,
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_filter: filter,
+            anisotropy_clamp,
+            ..Default::default()
+        }
+    }
+}
+
+impl std::str::FromStr for TextureContent {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "pixelart" | "pixel" => std::result::Result::Ok(TextureContent::PixelArt),
+            "ui" => std::result::Result::Ok(TextureContent::Ui),
+            "photo" => std::result::Result::Ok(TextureContent::Photo),
+            _ => Err(format!(
+                "Unknown texture content {name}, expected pixelart, ui or photo"
+            )),
+        }
+    }
+}
+
+/// Cloning shares the GPU texture, see [`crate::resources::ResourceCache`].
+#[derive(Debug, Clone)]
 pub struct Texture {
     #[allow(unused)]
     pub texture: wgpu::Texture,
@@ -30,6 +122,17 @@ impl Texture {
         width: u32,
         height: u32,
         label: &str,
+    ) -> Self {
+        Self::create_multisampled_depth_texture(device, width, height, 1, label)
+    }
+
+    /// Depth texture for a multisampled color target, `sample_count` must match the color target.
+    pub fn create_multisampled_depth_texture(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+        label: &str,
     ) -> Self {
         let size = wgpu::Extent3d {
             width,
@@ -40,15 +143,26 @@ impl Texture {
             label: Some(label),
             size,
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
             format: Self::DEPTH_FORMAT,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            // sampled by the depth visualization, copied by pass captures
+            usage: if sample_count > 1 {
+                wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+            } else {
+                wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC
+            },
             view_formats: &[],
         };
         let texture = device.create_texture(&desc);
-        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some(label),
+            ..Default::default()
+        });
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(label),
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
             address_mode_w: wgpu::AddressMode::ClampToEdge,
@@ -72,18 +186,21 @@ impl Texture {
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         bytes: &[u8],
-        label: &str
-    ) -> Result<Self> {
-        let img = image::load_from_memory(bytes)?;
-        Self::from_image(device, queue, &img, Some(label))
+        label: &str,
+        srgb: bool,
+    ) -> Result<Self, RendererError> {
+        let img = image::load_from_memory(bytes).map_err(|e| RendererError::asset(label, e))?;
+        Self::from_image(device, queue, &img, Some(label), srgb)
     }
 
     pub fn from_image(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         img: &image::DynamicImage,
-        label: Option<&str>
-    ) -> Result<Self> {
+        label: Option<&str>,
+        // color maps are stored in sRGB, data maps like shininess are linear
+        srgb: bool,
+    ) -> Result<Self, RendererError> {
         let rgba = img.to_rgba8();
         let dimensions = img.dimensions();
 
@@ -92,18 +209,30 @@ impl Texture {
             height: dimensions.1,
             depth_or_array_layers: 1,
         };
-        let texture = device.create_texture(
-            &wgpu::TextureDescriptor {
-                label,
-                size,
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Rgba8UnormSrgb,
-                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-                view_formats: &[],
-            }
-        );
+        let desc = wgpu::TextureDescriptor {
+            label,
+            size,
+            // a full chain, minified surfaces sample the level closest to their size instead of shimmering
+            mip_level_count: size.max_mips(wgpu::TextureDimension::D2),
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: if srgb {
+                wgpu::TextureFormat::Rgba8UnormSrgb
+            } else {
+                wgpu::TextureFormat::Rgba8Unorm
+            },
+            // the levels below the first are drawn by generate_mipmaps
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        };
+        // fails e.g. for images larger than the device's texture size limit
+        let texture = validate(
+            device,
+            || format!("texture {}", label.unwrap_or_default()),
+            || device.create_texture(&desc),
+        )?;
 
         queue.write_texture(
             wgpu::TexelCopyTextureInfo {
@@ -120,25 +249,452 @@ impl Texture {
             },
             size,
         );
+        generate_mipmaps(device, queue, &texture);
 
-        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(
-            &wgpu::SamplerDescriptor {
-                address_mode_u: wgpu::AddressMode::Repeat,
-                address_mode_v: wgpu::AddressMode::Repeat,
-                address_mode_w: wgpu::AddressMode::Repeat,
-                mag_filter: wgpu::FilterMode::Linear,
-                min_filter: wgpu::FilterMode::Linear,
-                mipmap_filter: wgpu::FilterMode::Linear,
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label,
+            ..Default::default()
+        });
+        let sampler =
+            device.create_sampler(&TextureContent::classify(img).sampler_descriptor(label));
+
+        std::result::Result::Ok(Self { texture, view, sampler })
+    }
+
+    /// Texture of a high dynamic range image, e.g. a Radiance `.hdr` or OpenEXR `.exr` file, that keeps
+    /// the values above 1 of an environment map or IBL source. It is stored in `Rgba16Float`, filtered and
+    /// mipmapped like a photo, or with `full_precision` in `Rgba32Float`, which most adapters can only
+    /// sample without filtering. Images without float channels are taken as sRGB.
+    pub fn from_hdr_image(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &DynamicImage,
+        label: Option<&str>,
+        full_precision: bool,
+    ) -> Result<Self, RendererError> {
+        let (width, height) = img.dimensions();
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let pixels = linear_rgba32f(img).into_raw();
+        let (format, data, mip_level_count) = if full_precision {
+            (
+                wgpu::TextureFormat::Rgba32Float,
+                bytemuck::cast_slice(&pixels).to_vec(),
+                1,
+            )
+        } else {
+            let halfs: Vec<u16> = pixels
+                .iter()
+                .map(|&value| half::f16::from_f32(value).to_bits())
+                .collect();
+            (
+                wgpu::TextureFormat::Rgba16Float,
+                bytemuck::cast_slice(&halfs).to_vec(),
+                size.max_mips(wgpu::TextureDimension::D2),
+            )
+        };
+        let desc = wgpu::TextureDescriptor {
+            label,
+            size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            // the mip levels are drawn, see generate_mipmaps
+            usage: if mip_level_count > 1 {
+                wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_DST
+                    | wgpu::TextureUsages::RENDER_ATTACHMENT
+            } else {
+                wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST
+            },
+            view_formats: &[],
+        };
+        let texture = validate(
+            device,
+            || format!("HDR texture {}", label.unwrap_or_default()),
+            || device.create_texture(&desc),
+        )?;
+        let bytes_per_pixel = format.block_copy_size(None).unwrap_or(8);
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                aspect: wgpu::TextureAspect::All,
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            &data,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_pixel * width),
+                rows_per_image: Some(height),
+            },
+            size,
+        );
+        generate_mipmaps(device, queue, &texture);
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label,
+            ..Default::default()
+        });
+        // nearest filtering like pixel art where the format can't be filtered
+        let content = if full_precision {
+            TextureContent::PixelArt
+        } else {
+            TextureContent::Photo
+        };
+        let sampler = device.create_sampler(&content.sampler_descriptor(label));
+
+        std::result::Result::Ok(Self {
+            texture,
+            view,
+            sampler,
+        })
+    }
+
+    /// Replaces the sampler with the one for `content`, e.g. when a material states what its texture shows.
+    pub fn set_content(
+        &mut self,
+        device: &wgpu::Device,
+        content: TextureContent,
+        label: Option<&str>,
+    ) {
+        self.sampler = device.create_sampler(&content.sampler_descriptor(label));
+    }
+
+    /// Cube map from six square faces in the order +X, -X, +Y, -Y, +Z, -Z.
+    pub fn cube_from_faces(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        faces: &[DynamicImage; 6],
+        label: &str,
+    ) -> Result<Self> {
+        let face_size = faces[0].width();
+        if let Some(face) = faces
+            .iter()
+            .find(|face| face.dimensions() != (face_size, face_size))
+        {
+            bail!(
+                "Cube map faces must be square and of equal size, expected {face_size}x{face_size}, got {:?}",
+                face.dimensions()
+            );
+        }
+        // HDR faces keep their range, like HDR panoramas
+        if faces.iter().any(is_hdr) {
+            let data: Vec<u8> = faces
+                .iter()
+                .flat_map(|face| linear_rgba32f(face).into_raw())
+                .map(|channel| half::f16::from_f32(channel).to_bits())
+                .flat_map(u16::to_ne_bytes)
+                .collect();
+            return Ok(Self::create_cube(
+                device,
+                queue,
+                wgpu::TextureFormat::Rgba16Float,
+                face_size,
+                &data,
+                label,
+            ));
+        }
+        let data: Vec<u8> = faces
+            .iter()
+            .flat_map(|face| face.to_rgba8().into_raw())
+            .collect();
+        Ok(Self::create_cube(
+            device,
+            queue,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            face_size,
+            &data,
+            label,
+        ))
+    }
+
+    /// Cube map resampled from an equirectangular panorama, laid out like the panorama capture.
+    /// HDR images keep their range in a float texture, other images are treated as sRGB.
+    pub fn cube_from_equirectangular(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &DynamicImage,
+        face_size: u32,
+        label: &str,
+    ) -> Result<Self> {
+        let panorama = linear_rgba32f(img);
+
+        let data: Vec<u8> = (0..6)
+            .flat_map(|face| {
+                let panorama = &panorama;
+                (0..face_size * face_size).flat_map(move |i| {
+                    // texel centers in [-1, 1], t points down
+                    let s = 2.0 * ((i % face_size) as f32 + 0.5) / face_size as f32 - 1.0;
+                    let t = 2.0 * ((i / face_size) as f32 + 0.5) / face_size as f32 - 1.0;
+                    let dir = match face {
+                        0 => Vec3::new(1.0, -t, -s),
+                        1 => Vec3::new(-1.0, -t, s),
+                        2 => Vec3::new(s, 1.0, t),
+                        3 => Vec3::new(s, -1.0, -t),
+                        4 => Vec3::new(s, -t, 1.0),
+                        _ => Vec3::new(-s, -t, -1.0),
+                    }
+                    .normalize();
+                    // inverse of the mapping in panorama.wgsl, the image center looks down -Z
+                    let u = dir.x.atan2(-dir.z) / (2.0 * PI) + 0.5;
+                    let v = 0.5 - dir.y.asin() / PI;
+                    let texel = image::imageops::sample_bilinear(panorama, u, v.clamp(0.0, 1.0))
+                        .unwrap_or(image::Rgba([0.0, 0.0, 0.0, 1.0]));
+                    texel
+                        .0
+                        .map(|channel| half::f16::from_f32(channel).to_bits())
+                })
+            })
+            .flat_map(u16::to_ne_bytes)
+            .collect();
+        Ok(Self::create_cube(
+            device,
+            queue,
+            wgpu::TextureFormat::Rgba16Float,
+            face_size,
+            &data,
+            label,
+        ))
+    }
+
+    fn create_cube(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        format: wgpu::TextureFormat,
+        face_size: u32,
+        data: &[u8],
+        label: &str,
+    ) -> Self {
+        let size = wgpu::Extent3d {
+            width: face_size,
+            height: face_size,
+            depth_or_array_layers: 6,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let bytes_per_pixel = format.block_copy_size(None).unwrap_or(4);
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                aspect: wgpu::TextureAspect::All,
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            data,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_pixel * face_size),
+                rows_per_image: Some(face_size),
+            },
+            size,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some(label),
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(label),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+}
+
+/// Pipeline that draws a mip level from the one above it, with its bind group layout and sampler.
+struct MipmapPipeline {
+    pipeline: Pipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+}
+
+impl MipmapPipeline {
+    fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let bind_group_layout = device.reflect_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("mipmap_bind_group_layout"),
+        });
+        let shader = device.reflect_shader(wgpu::ShaderModuleDescriptor {
+            label: Some("mipmap"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("mipmap.wgsl"))),
+        });
+        let pipeline = Pipeline::new(
+            device,
+            "mipmap_pipeline",
+            &shader,
+            &[&bind_group_layout],
+            "vs_fullscreen",
+            &[],
+            Some("fs_downsample"),
+            &[Some(wgpu::ColorTargetState {
+                format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            None,
+            None,
+            None,
+            None,
+        );
+        // the four texels of the level above blend into one, sRGB textures in linear space
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("mipmap_sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Self {
+            pipeline,
+            bind_group_layout,
+            sampler,
+        }
+    }
+}
+
+thread_local! {
+    static MIPMAP_PIPELINES: RefCell<HashMap<(wgpu::Device, wgpu::TextureFormat), MipmapPipeline>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Draws each mip level of `texture` below the first from the level above it. The texture needs
+/// [`wgpu::TextureUsages::RENDER_ATTACHMENT`] and its first level must already be uploaded.
+pub fn generate_mipmaps(device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture) {
+    if texture.mip_level_count() < 2 {
+        return;
+    }
+    let format = texture.format();
+    MIPMAP_PIPELINES.with_borrow_mut(|pipelines| {
+        let mipmap = pipelines
+            .entry((device.clone(), format))
+            .or_insert_with(|| MipmapPipeline::new(device, format));
+        let level_view = |level| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("mipmap_level"),
+                base_mip_level: level,
+                mip_level_count: Some(1),
+                ..Default::default()
+            })
+        };
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("mipmap_encoder"),
+        });
+        for level in 1..texture.mip_level_count() {
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &mipmap.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&level_view(level - 1)),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&mipmap.sampler),
+                    },
+                ],
+                label: Some("mipmap_bind_group"),
+            });
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("mipmap_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &level_view(level),
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
                 ..Default::default()
+            });
+            rpass.set_pipeline(&mipmap.pipeline.pipeline);
+            rpass.set_bind_group(0, &bind_group, &[]);
+            rpass.draw(0..3, 0..1);
+        }
+        queue.submit(Some(encoder.finish()));
+    });
+}
+
+/// Whether `img` has float channels, as decoded from `.hdr` and `.exr` files.
+fn is_hdr(img: &DynamicImage) -> bool {
+    matches!(
+        img,
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+    )
+}
+
+/// The linear colors of `img`, HDR images as they are and other images decoded from sRGB.
+fn linear_rgba32f(img: &DynamicImage) -> image::Rgba32FImage {
+    let mut pixels = img.to_rgba32f();
+    if !is_hdr(img) {
+        for pixel in pixels.pixels_mut() {
+            for channel in &mut pixel.0[..3] {
+                *channel = srgb_to_linear(*channel);
             }
-        );
+        }
+    }
+    pixels
+}
 
-        Ok(Self { texture, view, sampler })
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
     }
 }
 
 pub fn get_default_texture() -> DynamicImage {
     // Create a 1x1 transparent texture
     DynamicImage::new_rgba8(1, 1)
+}
+
+/// Light ramp of toon materials without one of their own: a dark, a mid and a lit band, in sRGB.
+pub fn get_toon_ramp_texture() -> DynamicImage {
+    let bands = [100, 190, 255].map(|value| image::Rgba([value, value, value, 255]));
+    DynamicImage::ImageRgba8(image::RgbaImage::from_fn(3, 1, |x, _| bands[x as usize]))
+}
+
+pub fn get_white_texture() -> DynamicImage {
+    // Create a 1x1 white texture, which leaves the values it is multiplied with unchanged
+    DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba([255; 4])))
 }
\ No newline at end of file
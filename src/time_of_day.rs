@@ -0,0 +1,326 @@
+/*
+ * Time of day.
+ * Named lighting presets for dawn, noon, dusk and night set the direction and color of the sun, the
+ * ambient light and the fog. Switching to a preset blends from the current lighting over a few seconds.
+ * The sun is the light node named by the `sun` of the scene, "light" in the demo scene, placed on a
+ * sphere around the scene center in the direction of the preset, and its markers, e.g. the cube of the
+ * demo scene, move with it; the first switch takes them off their paths and ends the light animation of
+ * the sun. Without a sun the presets only change the ambient light and the fog.
+ * T cycles through the presets, the web build can also pick one from the page with `set_time_of_day`.
+ */
+use crate::light::{Ambient, Fog, FogMode};
+use crate::scenegraph::{Node, SceneGraph};
+use glam::{Mat4, Vec3};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Point the sun looks at, see Light::calculate_matrix.
+const SCENE_CENTER: Vec3 = Vec3::new(0.0, 0.0, -15.0);
+/// Distance of the sun from the scene center, within the far plane of its shadow map.
+const SUN_DISTANCE: f32 = 38.0;
+/// Seconds the lighting takes to blend to a preset picked with a key.
+pub const TRANSITION_SECONDS: f32 = 3.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeOfDay {
+    Dawn,
+    Noon,
+    Dusk,
+    Night,
+}
+
+impl TimeOfDay {
+    pub const ALL: [TimeOfDay; 4] = [
+        TimeOfDay::Dawn,
+        TimeOfDay::Noon,
+        TimeOfDay::Dusk,
+        TimeOfDay::Night,
+    ];
+
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|preset| *preset == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    pub fn lighting(self) -> Lighting {
+        match self {
+            TimeOfDay::Dawn => Lighting {
+                sun_direction: Vec3::new(1.0, 0.3, 0.4),
+                sun_color: [1.0, 0.6, 0.4],
+                ambient: Ambient {
+                    sky: [0.7, 0.55, 0.55],
+                    ground: [0.35, 0.3, 0.25],
+                    strength: 0.3,
+                },
+                fog: Fog {
+                    mode: FogMode::Linear,
+                    color: [0.6, 0.5, 0.5],
+                    density: 0.03,
+                    start: 20.0,
+                    end: 80.0,
+                },
+            },
+            TimeOfDay::Noon => Lighting {
+                sun_direction: Vec3::new(0.2, 1.0, 0.3),
+                sun_color: [1.0, 1.0, 0.95],
+                ambient: Ambient {
+                    sky: [0.6, 0.7, 1.0],
+                    ground: [0.45, 0.4, 0.35],
+                    strength: 0.4,
+                },
+                fog: Fog {
+                    mode: FogMode::Linear,
+                    color: [0.45, 0.55, 0.7],
+                    density: 0.01,
+                    start: 50.0,
+                    end: 120.0,
+                },
+            },
+            TimeOfDay::Dusk => Lighting {
+                sun_direction: Vec3::new(-1.0, 0.25, 0.3),
+                sun_color: [0.9, 0.4, 0.2],
+                ambient: Ambient {
+                    sky: [0.55, 0.4, 0.5],
+                    ground: [0.3, 0.2, 0.2],
+                    strength: 0.25,
+                },
+                fog: Fog {
+                    mode: FogMode::Linear,
+                    color: [0.4, 0.25, 0.3],
+                    density: 0.025,
+                    start: 30.0,
+                    end: 90.0,
+                },
+            },
+            // the sun stands in for the moon
+            TimeOfDay::Night => Lighting {
+                sun_direction: Vec3::new(-0.3, 0.8, -0.4),
+                sun_color: [0.15, 0.2, 0.35],
+                ambient: Ambient {
+                    sky: [0.2, 0.25, 0.45],
+                    ground: [0.05, 0.05, 0.08],
+                    strength: 0.15,
+                },
+                fog: Fog {
+                    mode: FogMode::Linear,
+                    color: [0.02, 0.03, 0.06],
+                    density: 0.04,
+                    start: 15.0,
+                    end: 60.0,
+                },
+            },
+        }
+    }
+}
+
+impl FromStr for TimeOfDay {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|preset| format!("{preset:?}").eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                format!("Unknown time of day {name}, expected dawn, noon, dusk or night")
+            })
+    }
+}
+
+/// The nodes the presets move: the light that stands in for the sun and the nodes placed at it, e.g.
+/// a model marking where the light is. Set with the `sun` of a scene file, e.g.
+/// `"sun": { "light": "lamp", "markers": ["lamp_model"] }`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SunNodes {
+    pub light: String,
+    #[serde(default)]
+    pub markers: Vec<String>,
+}
+
+impl SunNodes {
+    /// The sun of the demo scene and its marker cube.
+    pub fn demo() -> Self {
+        Self {
+            light: "light".to_string(),
+            markers: vec!["light_model-light".to_string()],
+        }
+    }
+
+    fn contains(&self, node: &str) -> bool {
+        self.light == node || self.markers.iter().any(|marker| marker == node)
+    }
+}
+
+/// The lighting a preset sets.
+#[derive(Debug, Clone, Copy)]
+pub struct Lighting {
+    /// From the scene center towards the sun.
+    pub sun_direction: Vec3,
+    /// Linear RGB.
+    pub sun_color: [f32; 3],
+    pub ambient: Ambient,
+    /// Color and reach of the fog, its mode stays the one of the scene so fog that is off stays off.
+    pub fog: Fog,
+}
+
+impl Lighting {
+    /// The lighting of `scene_graph` as it is, with the preset's sun if the scene has none.
+    fn current(scene_graph: &SceneGraph, preset: &Lighting) -> Self {
+        let sun = scene_graph.sun.as_ref().and_then(|sun| {
+            let position = scene_graph.world_position(&sun.light)?;
+            let Some(Node::LightNode(light_node)) = scene_graph.find_child(&sun.light) else {
+                return None;
+            };
+            let color = light_node.light.color();
+            Some((
+                position - SCENE_CENTER,
+                [color.r as f32, color.g as f32, color.b as f32],
+            ))
+        });
+        let (sun_direction, sun_color) = sun.unwrap_or((preset.sun_direction, preset.sun_color));
+        Self {
+            sun_direction,
+            sun_color,
+            ambient: scene_graph.ambient(),
+            fog: scene_graph.fog(),
+        }
+    }
+
+    fn blend(&self, other: &Self, t: f32) -> Self {
+        let mix = |a: [f32; 3], b: [f32; 3]| Vec3::from(a).lerp(Vec3::from(b), t).to_array();
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+        let from = self.sun_direction.normalize_or_zero();
+        let to = other.sun_direction.normalize_or_zero();
+        Self {
+            sun_direction: from.lerp(to, t).normalize_or(to),
+            sun_color: mix(self.sun_color, other.sun_color),
+            ambient: Ambient {
+                sky: mix(self.ambient.sky, other.ambient.sky),
+                ground: mix(self.ambient.ground, other.ambient.ground),
+                strength: lerp(self.ambient.strength, other.ambient.strength),
+            },
+            fog: Fog {
+                mode: other.fog.mode,
+                color: mix(self.fog.color, other.fog.color),
+                density: lerp(self.fog.density, other.fog.density),
+                start: lerp(self.fog.start, other.fog.start),
+                end: lerp(self.fog.end, other.fog.end),
+            },
+        }
+    }
+}
+
+struct Transition {
+    from: Option<Lighting>,
+    to: TimeOfDay,
+    duration: f32,
+    /// Time the transition started at, set by the first update after it was requested.
+    start: Option<f32>,
+}
+
+/// Blends the lighting of the scene to the presets it is switched to.
+#[derive(Default)]
+pub struct DayCycle {
+    /// The preset the lighting is at or blending to, None before the first switch.
+    current: Option<TimeOfDay>,
+    transition: Option<Transition>,
+}
+
+impl DayCycle {
+    pub fn current(&self) -> Option<TimeOfDay> {
+        self.current
+    }
+
+    /// Blends to `preset` over `duration` seconds, starting at the next [`DayCycle::update`].
+    pub fn switch_to(&mut self, preset: TimeOfDay, duration: f32) {
+        println!("Time of day: {preset:?}");
+        self.current = Some(preset);
+        self.transition = Some(Transition {
+            from: None,
+            to: preset,
+            duration,
+            start: None,
+        });
+    }
+
+    /// Applies the lighting at `time` in seconds while a transition runs.
+    pub fn update(&mut self, scene_graph: &mut SceneGraph, time: f32) {
+        let Some(transition) = &mut self.transition else {
+            return;
+        };
+        let target = transition.to.lighting();
+        if transition.start.is_none() {
+            transition.start = Some(time);
+            // blends on from where a running transition was
+            transition.from = Some(Lighting::current(scene_graph, &target));
+            // the presets place the sun from now on
+            if let Some(sun) = &scene_graph.sun {
+                let sun = sun.clone();
+                scene_graph
+                    .paths
+                    .retain(|animation| !sun.contains(&animation.node));
+                scene_graph
+                    .light_animations
+                    .retain(|animation| animation.node != sun.light);
+            }
+        }
+        let elapsed = time - transition.start.unwrap_or(time);
+        let t = (elapsed / transition.duration.max(f32::EPSILON)).clamp(0.0, 1.0);
+        let lighting = match &transition.from {
+            Some(from) => from.blend(&target, t * t * (3.0 - 2.0 * t)),
+            None => target,
+        };
+        if t >= 1.0 {
+            self.transition = None;
+        }
+        apply(scene_graph, &lighting);
+    }
+}
+
+fn apply(scene_graph: &mut SceneGraph, lighting: &Lighting) {
+    let ambient = lighting.ambient;
+    scene_graph.set_hemisphere_ambient(ambient.sky, ambient.ground, ambient.strength);
+    scene_graph.set_fog(Fog {
+        mode: scene_graph.fog().mode,
+        ..lighting.fog
+    });
+
+    let Some(sun) = scene_graph.sun.clone() else {
+        return;
+    };
+    let position = SCENE_CENTER + lighting.sun_direction.normalize_or(Vec3::Y) * SUN_DISTANCE;
+    if let Some(Node::LightNode(light_node)) = scene_graph.find_child_mut(Some(&sun.light)) {
+        let [r, g, b] = lighting.sun_color.map(f64::from);
+        light_node.light.set_color(wgpu::Color { r, g, b, a: 1.0 });
+        // the node moves the light, its own position stays where it was
+        let offset = light_node.light.pos;
+        light_node
+            .node
+            .set_matrix(Mat4::from_translation(position - offset));
+    }
+    for marker in &sun.markers {
+        if let Some(node) = scene_graph.find_child_mut(Some(marker)) {
+            node.set_matrix(Mat4::from_translation(position));
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+thread_local! {
+    static REQUESTED: std::cell::Cell<Option<(TimeOfDay, f32)>> = const { std::cell::Cell::new(None) };
+}
+
+/// Switches to the preset `name` (dawn, noon, dusk or night) over `seconds`, from the hosting page.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen::prelude::wasm_bindgen]
+pub fn set_time_of_day(name: &str, seconds: f32) -> Result<(), wasm_bindgen::JsValue> {
+    let preset = name.parse::<TimeOfDay>()?;
+    REQUESTED.with(|requested| requested.set(Some((preset, seconds))));
+    Ok(())
+}
+
+/// The preset the page asked for since the last call.
+#[cfg(target_arch = "wasm32")]
+pub fn take_requested() -> Option<(TimeOfDay, f32)> {
+    REQUESTED.with(|requested| requested.take())
+}
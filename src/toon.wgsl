@@ -0,0 +1,76 @@
+// Appended to shader.wgsl and pbr.wgsl for materials with Shading::Toon, see model.rs.
+// Cel shading: the half-Lambert term, darkened by the shadow, looks up a band of the ramp texture, so
+// shadow edges fall into the bands as well. The highlight is cut off hard and a rim light traces the
+// silhouette on the lit side.
+@group(MATERIAL_GROUP) @binding(8)
+var t_ramp: texture_2d<f32>;
+
+// Band of the ramp at 0 for unlit to 1 for fully lit, without filtering between the texels.
+fn toon_ramp(lighting: f32) -> vec3<f32> {
+    let width = textureDimensions(t_ramp).x;
+    let texel = min(u32(clamp(lighting, 0.0, 1.0) * f32(width)), width - 1u);
+    return textureLoad(t_ramp, vec2<u32>(texel, 0u), 0).rgb;
+}
+
+fn toon(light: Light, normal: vec3<f32>, surface: Surface, in: VertexOutput, shadow: f32) -> vec3<f32> {
+    let light_world_position = light.model * light.position;
+    let light_dir = normalize(light_world_position.xyz - in.world_position.xyz);
+    let view_dir = normalize(camera.position.xyz - in.world_position.xyz);
+    let n_dot_l = dot(normal, light_dir);
+
+    let band = toon_ramp((n_dot_l * 0.5 + 0.5) * shadow);
+    let h = normalize(light_dir + view_dir);
+    let highlight = step(0.5, pow(max(dot(normal, h), 0.0), 10.0 * surface.shininess)) * shadow;
+    let lit = step(0.0, n_dot_l) * shadow;
+    let rim = material.toon_rim * smoothstep(0.6, 0.7, 1.0 - max(dot(normal, view_dir), 0.0)) * lit;
+
+    let color = band * light.color.xyz + (highlight * surface.specular + rim * light.color.xyz);
+    return color * light_falloff(light, in.world_position.xyz);
+}
+
+@fragment
+fn fs_toon(in: VertexOutput) -> @location(0) vec4<f32> {
+    if (camera.debug_view != DEBUG_VIEW_OFF) {
+        return debug_view_color(in);
+    }
+    var material_color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    // same fallback to the material color as fs_main
+    if (all(material_color == vec4<f32>(0.0))) {
+        material_color = vec4<f32>(material.diffuse.rgb, material.dissolve);
+    }
+    alpha_test(material_color.a);
+    let normal = normalize(in.world_normal);
+    let surface = sample_surface(in.tex_coords);
+
+    var light_color = ambient_light(normal);
+    for (var i = 0u; i < min(light_count, arrayLength(&s_lights)); i += 1u) {
+        let light = s_lights[i];
+        light_color += toon(light, normal, surface, in, light_shadow(light, in.world_position));
+    }
+
+    let color = vec4<f32>(light_color, 1.0) * material_color;
+    return vec4<f32>(apply_fog(color.rgb + emission(in.tex_coords), in.world_position), color.a);
+}
+
+@fragment
+fn fs_toon_without_storage(in: VertexOutput) -> @location(0) vec4<f32> {
+    if (camera.debug_view != DEBUG_VIEW_OFF) {
+        return debug_view_color(in);
+    }
+    var material_color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    if (all(material_color == vec4<f32>(0.0))) {
+        material_color = vec4<f32>(material.diffuse.rgb, material.dissolve);
+    }
+    alpha_test(material_color.a);
+    let normal = normalize(in.world_normal);
+    let surface = sample_surface(in.tex_coords);
+
+    var light_color = ambient_light(normal);
+    for (var i = 0u; i < min(light_count, 10u); i += 1u) {
+        let light = u_lights[i];
+        light_color += toon(light, normal, surface, in, light_shadow(light, in.world_position));
+    }
+
+    let color = vec4<f32>(light_color, 1.0) * material_color;
+    return vec4<f32>(apply_fog(color.rgb + emission(in.tex_coords), in.world_position), color.a);
+}
@@ -0,0 +1,246 @@
+/*
+ * Motion trails.
+ * A trail follows a node of the scene graph by name and records the world positions it passes, one each
+ * time it moved a bit further. The positions are drawn as a ribbon facing the camera that narrows and
+ * fades out towards the oldest position, e.g. to show the orbit of the sun. Like the debug lines, the
+ * ribbons are drawn over the frame without the depth of the scene.
+ */
+use crate::reflection::ReflectDevice;
+use crate::scenegraph::SceneGraph;
+use crate::shader_cache;
+use glam::Vec3;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+/// Distance the target has to move before another position is recorded.
+const MIN_SPACING: f32 = 0.25;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct TrailVertex {
+    position: [f32; 3],
+    color: [f32; 4],
+}
+
+impl TrailVertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 2] =
+        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x4];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: size_of::<TrailVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+pub struct Trail {
+    /// Name of the followed node, see [`SceneGraph::world_position`].
+    pub target: String,
+    /// Linear RGB.
+    pub color: [f32; 3],
+    /// Width of the ribbon at the target, in world units.
+    pub width: f32,
+    /// Number of positions kept, older ones are dropped.
+    pub length: usize,
+    /// Recorded positions, the oldest first.
+    positions: VecDeque<Vec3>,
+}
+
+impl Trail {
+    pub fn new(target: &str, color: [f32; 3], width: f32, length: usize) -> Self {
+        Self {
+            target: target.to_string(),
+            color,
+            width,
+            length,
+            positions: VecDeque::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.positions.clear();
+    }
+
+    fn record(&mut self, position: Vec3) {
+        if self
+            .positions
+            .back()
+            .is_some_and(|last| last.distance(position) < MIN_SPACING)
+        {
+            return;
+        }
+        self.positions.push_back(position);
+        while self.positions.len() > self.length.max(2) {
+            self.positions.pop_front();
+        }
+    }
+
+    /// Triangle strip of the ribbon seen from `eye`, relative to the render `origin`.
+    fn ribbon(&self, eye: Vec3, origin: Vec3) -> Vec<TrailVertex> {
+        let count = self.positions.len();
+        if count < 2 {
+            return Vec::new();
+        }
+        let [r, g, b] = self.color;
+        let mut vertices = Vec::with_capacity(count * 2);
+        for (index, &position) in self.positions.iter().enumerate() {
+            let tangent = self.positions[(index + 1).min(count - 1)]
+                - self.positions[index.saturating_sub(1)];
+            // 0 at the oldest position and 1 at the target
+            let age = index as f32 / (count - 1) as f32;
+            let side = tangent.cross(eye - position).normalize_or_zero() * self.width * 0.5 * age;
+            for corner in [position - side, position + side] {
+                vertices.push(TrailVertex {
+                    position: (corner - origin).to_array(),
+                    color: [r, g, b, age],
+                });
+            }
+        }
+        vertices
+    }
+}
+
+pub struct Trails {
+    pub enabled: bool,
+    pub trails: Vec<Trail>,
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    /// Number of vertices that fit into the vertex buffer.
+    capacity: usize,
+}
+
+impl Trails {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let shader = device.reflect_shader(wgpu::ShaderModuleDescriptor {
+            label: Some("trails"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("trails.wgsl"))),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("trails_pipeline_layout"),
+            bind_group_layouts: &[camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("trails_pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_trail"),
+                compilation_options: Default::default(),
+                buffers: &[TrailVertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_trail"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: shader_cache::pipeline_cache(device).as_ref(),
+        });
+        let capacity = 256;
+
+        Self {
+            enabled: true,
+            trails: Vec::new(),
+            pipeline,
+            vertex_buffer: Self::create_vertex_buffer(device, capacity),
+            capacity,
+        }
+    }
+
+    fn create_vertex_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("trails_vertex_buffer"),
+            size: (capacity * size_of::<TrailVertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Adds a trail, it starts with the next [`Trails::record`].
+    pub fn add(&mut self, trail: Trail) {
+        self.trails.push(trail);
+    }
+
+    /// Records the current positions of the targets, trails of missing targets are cleared.
+    pub fn record(&mut self, scene_graph: &SceneGraph) {
+        for trail in &mut self.trails {
+            match scene_graph.world_position(&trail.target) {
+                Some(position) => trail.record(position),
+                None => trail.clear(),
+            }
+        }
+    }
+
+    /// Draws the trails over `view`, seen from `eye` through the camera of `camera_bind_group` which is
+    /// relative to the render `origin`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        eye: Vec3,
+        origin: Vec3,
+        camera_bind_group: &wgpu::BindGroup,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        // each trail is a strip of its own in the shared vertex buffer
+        let mut vertices = Vec::new();
+        let mut strips = Vec::new();
+        for trail in &self.trails {
+            let start = vertices.len() as u32;
+            vertices.extend(trail.ribbon(eye, origin));
+            if vertices.len() as u32 > start {
+                strips.push(start..vertices.len() as u32);
+            }
+        }
+        if vertices.is_empty() {
+            return;
+        }
+        if vertices.len() > self.capacity {
+            self.capacity = vertices.len().next_power_of_two();
+            self.vertex_buffer = Self::create_vertex_buffer(device, self.capacity);
+        }
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("trails_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, camera_bind_group, &[]);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        for strip in strips {
+            rpass.draw(strip, 0..1);
+        }
+    }
+}
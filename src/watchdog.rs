@@ -0,0 +1,92 @@
+/*
+ * Watchdog for long frames.
+ * Frames that take longer than a threshold, e.g. because of a huge model or a giant blur kernel, are
+ * logged with the time each pass took and the size of the scene. Without timestamp queries the passes
+ * are timed on the CPU: recording a pass is usually quick, a GPU that falls behind shows up as time spent
+ * acquiring the next surface texture, submitting and presenting.
+ */
+use crate::scenegraph::{DrawStats, SceneStats};
+use instant::Instant;
+use std::time::Duration;
+
+/// Long frames after a logged one are only counted for this long, so slow content doesn't flood the log.
+const LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+pub struct FrameWatchdog {
+    /// Frames taking longer are logged.
+    pub threshold: Duration,
+    frame_start: Option<Instant>,
+    lap_start: Instant,
+    passes: Vec<(&'static str, Duration)>,
+    last_log: Option<Instant>,
+    /// Long frames since the last logged one.
+    suppressed: u32,
+}
+
+impl FrameWatchdog {
+    pub fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            frame_start: None,
+            lap_start: Instant::now(),
+            passes: Vec::new(),
+            last_log: None,
+            suppressed: 0,
+        }
+    }
+
+    pub fn begin_frame(&mut self) {
+        let now = Instant::now();
+        self.frame_start = Some(now);
+        self.lap_start = now;
+        self.passes.clear();
+    }
+
+    /// Records the time since the previous pass (or the start of the frame) as the time of `pass`.
+    /// Does nothing outside of a frame, e.g. while rendering the golden views.
+    pub fn lap(&mut self, pass: &'static str) {
+        if self.frame_start.is_none() {
+            return;
+        }
+        let now = Instant::now();
+        self.passes.push((pass, now - self.lap_start));
+        self.lap_start = now;
+    }
+
+    /// Ends the frame and logs it if it took longer than the threshold.
+    pub fn end_frame(&mut self, scene: SceneStats, draws: DrawStats) {
+        let Some(frame_start) = self.frame_start.take() else {
+            return;
+        };
+        let frame_time = frame_start.elapsed();
+        if frame_time <= self.threshold {
+            return;
+        }
+        if self
+            .last_log
+            .is_some_and(|last_log| last_log.elapsed() < LOG_INTERVAL)
+        {
+            self.suppressed += 1;
+            return;
+        }
+        self.last_log = Some(Instant::now());
+
+        println!(
+            "Long frame: {:.1} ms (threshold {:.1} ms), {} more since the last report",
+            frame_time.as_secs_f64() * 1000.0,
+            self.threshold.as_secs_f64() * 1000.0,
+            std::mem::take(&mut self.suppressed)
+        );
+        for (pass, time) in &self.passes {
+            println!("  {pass:<10} {:8.2} ms", time.as_secs_f64() * 1000.0);
+        }
+        println!(
+            "  scene: {} render nodes, {} triangles, {} lights",
+            scene.render_nodes, scene.triangles, scene.lights
+        );
+        println!(
+            "  forward pass: {} draws, {} material switches, {} culled",
+            draws.draws, draws.material_switches, draws.culled
+        );
+    }
+}